@@ -1,9 +1,10 @@
 use clap::{App, Arg};
-use easy_fs::{BlockDevice, EasyFileSystem};
+use easy_fs::{set_clock, BlockDevice, EasyFileSystem};
 use std::fs::{read_dir, File, OpenOptions};
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 const BLOCK_SZ: usize = 512;
 
@@ -59,9 +60,16 @@ fn easy_fs_pack() -> std::io::Result<()> {
             .write(true)
             .create(true)
             .open(format!("{}{}", target_path, "fs.img"))?;
-        f.set_len(32 * 2048 * 512).unwrap();
+        // the kernel uses the 64MiB after the file system as its swap area
+        f.set_len((32 + 64) * 2048 * 512).unwrap();
         f
     })));
+    set_clock(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as u32
+    });
     // 32MiB, at most 4095 files
     let efs = EasyFileSystem::create(block_file, 32 * 2048, 1);
     let root_inode = Arc::new(EasyFileSystem::root_inode(&efs));
@@ -91,8 +99,13 @@ fn easy_fs_pack() -> std::io::Result<()> {
     Ok(())
 }
 
+/// All tests share `target/fs.img` and the global block cache, so run them one by one.
+#[cfg(test)]
+static EFS_TEST_LOCK: Mutex<()> = Mutex::new(());
+
 #[test]
 fn efs_test() -> std::io::Result<()> {
+    let _guard = EFS_TEST_LOCK.lock().unwrap();
     let block_file = Arc::new(BlockFile(Mutex::new({
         let f = OpenOptions::new()
             .read(true)
@@ -153,3 +166,486 @@ fn efs_test() -> std::io::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn efs_dir_test() -> std::io::Result<()> {
+    let _guard = EFS_TEST_LOCK.lock().unwrap();
+    let block_file = Arc::new(BlockFile(Mutex::new({
+        let f = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open("target/fs.img")?;
+        f.set_len(8192 * 512).unwrap();
+        f
+    })));
+    EasyFileSystem::create(block_file.clone(), 4096, 1);
+    let efs = EasyFileSystem::open(block_file.clone());
+    let root_inode = Arc::new(EasyFileSystem::root_inode(&efs));
+    assert_eq!(root_inode.ls(), vec![".", ".."]);
+    assert_eq!(root_inode.find("..").unwrap().inode_id(), 0);
+
+    // mkdir and path lookup
+    let dira = root_inode.mkdir("a").unwrap();
+    assert!(dira.is_dir());
+    assert!(root_inode.mkdir("a").is_none());
+    let dirb = dira.mkdir("b").unwrap();
+    let filec = dirb.create("c").unwrap();
+    assert!(!filec.is_dir());
+    assert!(filec.create("d").is_none());
+    filec.write_at(0, "hello".as_bytes());
+    let found = root_inode.find_path("/a/b/c").unwrap();
+    assert_eq!(found.inode_id(), filec.inode_id());
+    assert_eq!(
+        root_inode.find_path("a/./b/../b//c").unwrap().inode_id(),
+        filec.inode_id()
+    );
+    assert_eq!(
+        root_inode.find_path("a/b/..").unwrap().inode_id(),
+        dira.inode_id()
+    );
+    assert!(root_inode.find_path("a/b/c/d").is_none());
+    assert!(root_inode.find_path("a/x").is_none());
+
+    // rename within a directory and across directories
+    assert!(dirb.rename("c", &dirb, "e"));
+    assert!(dirb.find("c").is_none());
+    assert!(dirb.rename("e", &root_inode, "f"));
+    let mut buffer = [0u8; 16];
+    let filef = root_inode.find("f").unwrap();
+    let len = filef.read_at(0, &mut buffer);
+    assert_eq!(&buffer[..len], "hello".as_bytes());
+    // move a directory and check that ".." follows it
+    assert!(dira.rename("b", &root_inode, "b"));
+    assert_eq!(
+        root_inode.find_path("b/..").unwrap().inode_id(),
+        root_inode.inode_id()
+    );
+    assert!(!root_inode.rename("a", &dira, "a"));
+    assert!(!root_inode.rename("b", &root_inode, "f"));
+
+    // unlink/rmdir free the inode once it is not open, so that it is reused
+    let f_id = filef.inode_id();
+    drop((filec, found, filef));
+    assert!(!root_inode.unlink("a"));
+    assert!(root_inode.unlink("f"));
+    assert!(root_inode.find("f").is_none());
+    assert!(!root_inode.unlink("f"));
+    let g = root_inode.create("g").unwrap();
+    assert_eq!(g.inode_id(), f_id);
+    assert_eq!(g.read_at(0, &mut buffer), 0);
+    root_inode.find("b").unwrap().create("h");
+    assert!(!root_inode.rmdir("b"));
+    assert!(root_inode.find("b").unwrap().unlink("h"));
+    assert!(root_inode.rmdir("b"));
+    assert!(!root_inode.rmdir("g"));
+    assert!(!root_inode.rmdir("."));
+    assert!(root_inode.rmdir("a"));
+    assert_eq!(root_inode.ls(), vec![".", "..", "g"]);
+
+    // a socket file is neither a file nor a directory, and is unlinked as a file
+    let sock = root_inode.create_socket("s").unwrap();
+    assert!(sock.is_socket() && !sock.is_dir());
+    assert!(!g.is_socket());
+    assert!(root_inode.create_socket("s").is_none());
+    assert!(root_inode.create_socket("g").is_none());
+    assert!(root_inode.unlink("s"));
+    assert!(root_inode.find("s").is_none());
+
+    Ok(())
+}
+
+#[cfg(test)]
+static TEST_TIME: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+
+#[test]
+fn efs_metadata_test() -> std::io::Result<()> {
+    use easy_fs::DiskInodeType;
+    use std::sync::atomic::Ordering;
+    let _guard = EFS_TEST_LOCK.lock().unwrap();
+    let block_file = Arc::new(BlockFile(Mutex::new({
+        let f = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open("target/fs.img")?;
+        f.set_len(8192 * 512).unwrap();
+        f
+    })));
+    set_clock(|| TEST_TIME.load(Ordering::Relaxed));
+    TEST_TIME.store(100, Ordering::Relaxed);
+    EasyFileSystem::create(block_file.clone(), 4096, 1);
+    let efs = EasyFileSystem::open(block_file.clone());
+    let root_inode = Arc::new(EasyFileSystem::root_inode(&efs));
+    let root = root_inode.metadata();
+    assert_eq!((root.inode_id, root.type_), (0, DiskInodeType::Directory));
+    assert_eq!((root.mode, root.nlink), (0o755, 2));
+    assert_eq!((root.atime, root.mtime, root.ctime), (100, 100, 100));
+
+    // a file starts empty, and a subdirectory links its parent
+    TEST_TIME.store(200, Ordering::Relaxed);
+    let file = root_inode.create("file").unwrap();
+    let dir = root_inode.mkdir("dir").unwrap();
+    let meta = file.metadata();
+    assert_eq!(meta.type_, DiskInodeType::File);
+    assert_eq!(
+        (meta.mode, meta.nlink, meta.size, meta.blocks),
+        (0o644, 1, 0, 0)
+    );
+    assert_eq!(meta.mtime, 200);
+    assert_eq!(dir.metadata().nlink, 2);
+    let root = root_inode.metadata();
+    assert_eq!((root.nlink, root.mtime, root.atime), (3, 200, 100));
+
+    // writes change the data and the inode, reads only access it
+    TEST_TIME.store(300, Ordering::Relaxed);
+    file.write_at(0, &[1u8; 30 * BLOCK_SZ]);
+    let meta = file.metadata();
+    assert_eq!((meta.size, meta.blocks), (30 * BLOCK_SZ as u32, 31));
+    assert_eq!((meta.atime, meta.mtime, meta.ctime), (200, 300, 300));
+    TEST_TIME.store(400, Ordering::Relaxed);
+    let mut buffer = [0u8; 16];
+    file.read_at(0, &mut buffer);
+    let meta = file.metadata();
+    assert_eq!((meta.atime, meta.mtime, meta.ctime), (400, 300, 300));
+
+    // moving a directory moves the link of its ".."
+    TEST_TIME.store(500, Ordering::Relaxed);
+    let sub = dir.mkdir("sub").unwrap();
+    assert_eq!(dir.metadata().nlink, 3);
+    assert!(dir.rename("sub", &root_inode, "sub"));
+    assert_eq!(dir.metadata().nlink, 2);
+    assert_eq!(root_inode.metadata().nlink, 4);
+    assert_eq!(sub.metadata().ctime, 500);
+    assert!(root_inode.rmdir("sub"));
+    assert_eq!(root_inode.metadata().nlink, 3);
+
+    // the metadata lives in the disk inode, not in the handle
+    drop((file, dir, sub, root_inode));
+    let efs = EasyFileSystem::open(block_file.clone());
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let meta = root_inode.find("file").unwrap().metadata();
+    assert_eq!(
+        (meta.size, meta.atime, meta.mtime),
+        (30 * BLOCK_SZ as u32, 400, 300)
+    );
+    Ok(())
+}
+
+#[test]
+fn efs_link_test() -> std::io::Result<()> {
+    let _guard = EFS_TEST_LOCK.lock().unwrap();
+    let block_file = Arc::new(BlockFile(Mutex::new({
+        let f = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open("target/fs.img")?;
+        f.set_len(8192 * 512).unwrap();
+        f
+    })));
+    EasyFileSystem::create(block_file.clone(), 4096, 1);
+    let efs = EasyFileSystem::open(block_file.clone());
+    let root_inode = Arc::new(EasyFileSystem::root_inode(&efs));
+    let dir = root_inode.mkdir("dir").unwrap();
+    let file = dir.create("file").unwrap();
+    file.write_at(0, "linked".as_bytes());
+
+    // hard links share the inode, which is freed with its last link
+    assert!(root_inode.link("hard", &file));
+    assert!(!root_inode.link("hard", &file));
+    assert!(!root_inode.link("dir2", &dir));
+    assert_eq!(file.metadata().nlink, 2);
+    assert_eq!(root_inode.find("hard").unwrap().inode_id(), file.inode_id());
+    assert!(dir.unlink("file"));
+    assert_eq!(file.metadata().nlink, 1);
+    let mut buffer = [0u8; 16];
+    let hard = root_inode.find("hard").unwrap();
+    let len = hard.read_at(0, &mut buffer);
+    assert_eq!(&buffer[..len], "linked".as_bytes());
+    // renaming over a link drops it as well
+    let other = root_inode.create("other").unwrap();
+    assert!(root_inode.link("other2", &other));
+    assert!(root_inode.rename("hard", &root_inode, "other"));
+    assert_eq!(other.metadata().nlink, 1);
+    assert!(root_inode.unlink("other2"));
+    let other_id = other.inode_id();
+    drop(other);
+    let reused = root_inode.create("reused").unwrap();
+    assert_eq!(reused.inode_id(), other_id);
+
+    // symlinks are followed from the directory holding them, or the root
+    let link = root_inode.symlink("rel", "dir/../other").unwrap();
+    assert!(link.is_symlink());
+    assert_eq!(link.readlink().unwrap(), "dir/../other");
+    assert_eq!(link.metadata().size, 12);
+    assert!(root_inode.find("other").unwrap().readlink().is_none());
+    assert_eq!(
+        root_inode.find_path("rel").unwrap().inode_id(),
+        hard.inode_id()
+    );
+    dir.symlink("abs", "/rel").unwrap();
+    dir.symlink("up", "..").unwrap();
+    assert_eq!(
+        root_inode.find_path("dir/abs").unwrap().inode_id(),
+        hard.inode_id()
+    );
+    assert_eq!(
+        root_inode.find_path("dir/up/dir/up").unwrap().inode_id(),
+        root_inode.inode_id()
+    );
+    assert!(dir.symlink("abs", "x").is_none());
+    assert!(dir.symlink("empty", "").is_none());
+    // a dangling link resolves to nothing, and a loop is given up
+    dir.symlink("dangling", "nowhere").unwrap();
+    assert!(root_inode.find_path("dir/dangling").is_none());
+    root_inode.symlink("loop_a", "loop_b").unwrap();
+    root_inode.symlink("loop_b", "loop_a").unwrap();
+    assert!(root_inode.find_path("loop_a").is_none());
+    // unlinking a symlink leaves its target
+    assert!(dir.unlink("abs"));
+    assert!(root_inode.find("other").is_some());
+    Ok(())
+}
+
+#[test]
+fn efs_truncate_test() -> std::io::Result<()> {
+    let _guard = EFS_TEST_LOCK.lock().unwrap();
+    let block_file = Arc::new(BlockFile(Mutex::new({
+        let f = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open("target/fs.img")?;
+        f.set_len(8192 * 512).unwrap();
+        f
+    })));
+    EasyFileSystem::create(block_file.clone(), 4096, 1);
+    let efs = EasyFileSystem::open(block_file.clone());
+    let root_inode = Arc::new(EasyFileSystem::root_inode(&efs));
+    let file = root_inode.create("file").unwrap();
+    // 22 direct blocks, then 128 through indirect1, the rest through indirect2
+    const BLOCK_SZ: usize = 512;
+    const DIRECT: usize = 22;
+    const INDIRECT1: usize = DIRECT + 128;
+    const LARGE: usize = 2600;
+    let total_blocks = |size: usize| {
+        let data_blocks = size.div_ceil(BLOCK_SZ);
+        let mut total = data_blocks;
+        if data_blocks > DIRECT {
+            total += 1;
+        }
+        if data_blocks > INDIRECT1 {
+            total += 1 + (data_blocks - INDIRECT1).div_ceil(128);
+        }
+        total as u32
+    };
+    let pattern = |offset: usize| (offset % 251) as u8;
+    let data: Vec<u8> = (0..LARGE * BLOCK_SZ).map(pattern).collect();
+    let mut buffer = vec![0u8; LARGE * BLOCK_SZ];
+    // the data area only holds one large file, so leaked blocks run it out
+    for new_size in [
+        0,
+        100,
+        DIRECT * BLOCK_SZ,
+        DIRECT * BLOCK_SZ + 1,
+        INDIRECT1 * BLOCK_SZ,
+        INDIRECT1 * BLOCK_SZ + 300,
+        (INDIRECT1 + 128) * BLOCK_SZ,
+        (INDIRECT1 + 128) * BLOCK_SZ + 1,
+        (INDIRECT1 + 1000) * BLOCK_SZ - 7,
+    ] {
+        assert_eq!(file.write_at(0, &data), data.len());
+        assert!(file.truncate(new_size as u32));
+        let metadata = file.metadata();
+        assert_eq!(metadata.size as usize, new_size);
+        assert_eq!(metadata.blocks, total_blocks(new_size));
+        assert_eq!(file.read_at(0, &mut buffer), new_size);
+        assert_eq!(&buffer[..new_size], &data[..new_size]);
+        // growing again reads zeros past the old end
+        let grown = new_size + 2 * BLOCK_SZ;
+        assert!(file.truncate(grown as u32));
+        assert_eq!(file.read_at(0, &mut buffer), grown);
+        assert_eq!(&buffer[..new_size], &data[..new_size]);
+        assert!(buffer[new_size..grown].iter().all(|byte| *byte == 0));
+        assert!(file.truncate(LARGE as u32 * BLOCK_SZ as u32));
+    }
+    file.clear();
+    // the blocks freed are found by the next file
+    let other = root_inode.create("other").unwrap();
+    assert_eq!(other.write_at(0, &data), data.len());
+    assert!(!root_inode.truncate(0));
+    Ok(())
+}
+
+#[test]
+fn efs_sparse_test() -> std::io::Result<()> {
+    let _guard = EFS_TEST_LOCK.lock().unwrap();
+    let block_file = Arc::new(BlockFile(Mutex::new({
+        let f = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open("target/fs.img")?;
+        f.set_len(8192 * 512).unwrap();
+        f
+    })));
+    EasyFileSystem::create(block_file.clone(), 4096, 1);
+    let efs = EasyFileSystem::open(block_file.clone());
+    let root_inode = Arc::new(EasyFileSystem::root_inode(&efs));
+    const BLOCK_SZ: usize = 512;
+    // far more than the image holds, were the holes allocated
+    let files: Vec<_> = (0..100)
+        .map(|i| root_inode.create(format!("sparse{}", i).as_str()).unwrap())
+        .collect();
+    for file in files.iter() {
+        assert_eq!(file.write_at(easy_fs::MAX_FILE_SIZE - 1, &[1]), 1);
+    }
+    let file = &files[0];
+    let metadata = file.metadata();
+    assert_eq!(metadata.size as usize, easy_fs::MAX_FILE_SIZE);
+    // one data block, indirect2 and a low-level indirect1
+    assert_eq!(metadata.blocks, 3);
+    assert_eq!(file.write_at(easy_fs::MAX_FILE_SIZE, &[1]), 0);
+
+    // holes read zeros
+    let mut buffer = [0xffu8; 3 * BLOCK_SZ];
+    assert_eq!(file.read_at(0, &mut buffer), buffer.len());
+    assert!(buffer.iter().all(|byte| *byte == 0));
+    assert_eq!(file.read_at(easy_fs::MAX_FILE_SIZE - 2, &mut buffer), 2);
+    assert_eq!(&buffer[..2], &[0, 1]);
+    // writing into a hole fills just its block, across two blocks here
+    let offset = 30 * BLOCK_SZ - 2;
+    assert_eq!(file.write_at(offset, &[2, 3, 4, 5]), 4);
+    assert_eq!(file.metadata().blocks, 6);
+    assert_eq!(file.read_at(offset - 1, &mut buffer[..6]), 6);
+    assert_eq!(&buffer[..6], &[0, 2, 3, 4, 5, 0]);
+
+    // shrinking frees what is held past the end, growing leaves a hole
+    assert!(file.truncate((30 * BLOCK_SZ) as u32));
+    assert_eq!(file.metadata().blocks, 2);
+    assert!(file.truncate((200 * BLOCK_SZ) as u32));
+    assert_eq!(file.metadata().blocks, 2);
+    assert_eq!(file.read_at(offset, &mut buffer[..4]), 4);
+    assert_eq!(&buffer[..4], &[2, 3, 0, 0]);
+    assert!(!file.truncate(easy_fs::MAX_FILE_SIZE as u32 + 1));
+    assert!(file.truncate(0));
+    assert_eq!(file.metadata().blocks, 0);
+
+    // removing sparse files frees their blocks for a dense one
+    drop(files);
+    for i in 0..100 {
+        assert!(root_inode.unlink(format!("sparse{}", i).as_str()));
+    }
+    let dense = root_inode.create("dense").unwrap();
+    let data = vec![6u8; 2600 * BLOCK_SZ];
+    assert_eq!(dense.write_at(0, &data), data.len());
+    Ok(())
+}
+
+#[test]
+fn efs_orphan_test() -> std::io::Result<()> {
+    let _guard = EFS_TEST_LOCK.lock().unwrap();
+    let block_file = Arc::new(BlockFile(Mutex::new({
+        let f = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open("target/fs.img")?;
+        f.set_len(8192 * 512).unwrap();
+        f
+    })));
+    EasyFileSystem::create(block_file.clone(), 4096, 1);
+    let efs = EasyFileSystem::open(block_file.clone());
+    let root_inode = Arc::new(EasyFileSystem::root_inode(&efs));
+    let mut buffer = [0u8; 16];
+
+    // an unlinked file is kept while it is open
+    let file = root_inode.create("file").unwrap();
+    file.write_at(0, "orphan".as_bytes());
+    let file_id = file.inode_id();
+    assert!(root_inode.unlink("file"));
+    assert!(root_inode.find("file").is_none());
+    assert_eq!(file.metadata().nlink, 0);
+    let other = root_inode.create("other").unwrap();
+    assert_ne!(other.inode_id(), file_id);
+    other.write_at(0, "other".as_bytes());
+    let len = file.read_at(0, &mut buffer);
+    assert_eq!(&buffer[..len], "orphan".as_bytes());
+    assert_eq!(file.write_at(len, "!".as_bytes()), 1);
+    // and can not be linked again
+    assert!(!root_inode.link("file", &file));
+    // it is freed after its last Inode is dropped
+    let copy = Arc::clone(&file);
+    drop(file);
+    assert_ne!(root_inode.create("a").unwrap().inode_id(), file_id);
+    drop(copy);
+    let reused = root_inode.create("reused").unwrap();
+    assert_eq!(reused.inode_id(), file_id);
+    assert_eq!(reused.read_at(0, &mut buffer), 0);
+    let len = other.read_at(0, &mut buffer);
+    assert_eq!(&buffer[..len], "other".as_bytes());
+
+    // a file replaced by rename is kept while it is open as well
+    let replaced_id = other.inode_id();
+    assert!(root_inode.rename("reused", &root_inode, "other"));
+    assert_ne!(root_inode.create("b").unwrap().inode_id(), replaced_id);
+    let len = other.read_at(0, &mut buffer);
+    assert_eq!(&buffer[..len], "other".as_bytes());
+    drop(other);
+    assert_eq!(root_inode.create("c").unwrap().inode_id(), replaced_id);
+
+    // a removed directory can not get new entries
+    let dir = root_inode.mkdir("dir").unwrap();
+    let dir_id = dir.inode_id();
+    assert!(root_inode.rmdir("dir"));
+    assert!(dir.create("x").is_none());
+    assert!(!root_inode.rename("c", &dir, "c"));
+    assert_ne!(root_inode.create("d").unwrap().inode_id(), dir_id);
+    drop(dir);
+    assert_eq!(root_inode.create("e").unwrap().inode_id(), dir_id);
+    Ok(())
+}
+
+#[test]
+fn efs_orphan_mount_test() -> std::io::Result<()> {
+    let _guard = EFS_TEST_LOCK.lock().unwrap();
+    let block_file = Arc::new(BlockFile(Mutex::new({
+        let f = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open("target/fs.img")?;
+        f.set_len(8192 * 512).unwrap();
+        f
+    })));
+    EasyFileSystem::create(block_file.clone(), 4096, 1);
+    let efs = EasyFileSystem::open(block_file.clone());
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let kept = root_inode.create("kept").unwrap();
+    kept.write_at(0, "kept".as_bytes());
+    let lost = root_inode.create("lost").unwrap();
+    let lost_id = lost.inode_id();
+    lost.write_at(0, &[1u8; 3 * BLOCK_SZ]);
+    // the first data block after those of the files
+    let next_block = efs.lock().alloc_data();
+    efs.lock().dealloc_data(next_block);
+    assert!(root_inode.unlink("lost"));
+    // the system goes down while the unlinked file is still open
+    std::mem::forget(lost);
+    drop(kept);
+    drop(root_inode);
+
+    // the orphan is freed on the next mount
+    let efs = EasyFileSystem::open(block_file.clone());
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    assert!(efs.lock().alloc_data() < next_block);
+    let reused = root_inode.create("reused").unwrap();
+    assert_eq!(reused.inode_id(), lost_id);
+    let mut buffer = [0u8; 16];
+    assert_eq!(reused.read_at(0, &mut buffer), 0);
+    let kept = root_inode.find("kept").unwrap();
+    let len = kept.read_at(0, &mut buffer);
+    assert_eq!(&buffer[..len], "kept".as_bytes());
+    Ok(())
+}
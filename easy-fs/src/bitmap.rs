@@ -1,5 +1,6 @@
 use super::{get_block_cache, BlockDevice, BLOCK_SZ};
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 
 type BitmapBlock = [u64; 64];
 
@@ -63,6 +64,23 @@ impl Bitmap {
             });
     }
 
+    /// All bits allocated, in order.
+    pub fn allocated(&self, block_device: &Arc<dyn BlockDevice>) -> Vec<usize> {
+        let mut bits = Vec::new();
+        for block_id in 0..self.blocks {
+            get_block_cache(block_id + self.start_block_id, Arc::clone(block_device))
+                .lock()
+                .read(0, |bitmap_block: &BitmapBlock| {
+                    for (bits64_pos, bits64) in bitmap_block.iter().enumerate() {
+                        for inner_pos in (0..64).filter(|pos| bits64 & (1u64 << pos) != 0) {
+                            bits.push(block_id * BLOCK_BITS + bits64_pos * 64 + inner_pos);
+                        }
+                    }
+                });
+        }
+        bits
+    }
+
     pub fn maximum(&self) -> usize {
         self.blocks * BLOCK_BITS
     }
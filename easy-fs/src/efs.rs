@@ -1,9 +1,11 @@
 use super::{
-    block_cache_sync_all, get_block_cache, Bitmap, BlockDevice, DiskInode, DiskInodeType, Inode,
-    SuperBlock,
+    block_cache_sync_all, get_block_cache, Bitmap, BlockDevice, DirEntry, DiskInode, DiskInodeType,
+    Inode, SuperBlock, DIRENT_SZ,
 };
 use crate::BLOCK_SZ;
+use alloc::collections::{BTreeMap, BTreeSet};
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use spin::Mutex;
 
 pub struct EasyFileSystem {
@@ -12,10 +14,62 @@ pub struct EasyFileSystem {
     pub data_bitmap: Bitmap,
     inode_area_start_block: u32,
     data_area_start_block: u32,
+    pub(crate) open_inodes: Arc<Mutex<OpenInodes>>,
+}
+
+/// The `Inode`s in memory of each inode id. An inode losing its last link
+/// while some of them exist is an orphan, which is kept until they are
+/// all dropped.
+#[derive(Default)]
+pub(crate) struct OpenInodes {
+    counts: BTreeMap<u32, usize>,
+    orphans: BTreeSet<u32>,
+    /// orphans no `Inode` refers to any more, to be freed
+    unreferenced: Vec<u32>,
+}
+
+impl OpenInodes {
+    pub(crate) fn open(&mut self, inode_id: u32) {
+        *self.counts.entry(inode_id).or_insert(0) += 1;
+    }
+
+    pub(crate) fn close(&mut self, inode_id: u32) {
+        let count = self.counts.get_mut(&inode_id).unwrap();
+        *count -= 1;
+        if *count == 0 {
+            self.counts.remove(&inode_id);
+            if self.orphans.remove(&inode_id) {
+                self.unreferenced.push(inode_id);
+            }
+        }
+    }
+
+    /// Mark an inode without links as an orphan, an `Inode` of it is held
+    /// by the caller.
+    pub(crate) fn orphan(&mut self, inode_id: u32) {
+        self.orphans.insert(inode_id);
+    }
 }
 
 type DataBlock = [u8; BLOCK_SZ];
 
+fn no_clock() -> u32 {
+    0
+}
+
+/// The clock stamping inodes, in seconds since the epoch. The file system
+/// has no clock of its own, so inodes are stamped 0 until one is set.
+static CLOCK: Mutex<fn() -> u32> = Mutex::new(no_clock);
+
+pub fn set_clock(clock: fn() -> u32) {
+    *CLOCK.lock() = clock;
+}
+
+/// The time of the clock set.
+pub fn now() -> u32 {
+    (CLOCK.lock())()
+}
+
 impl EasyFileSystem {
     pub fn create(
         block_device: Arc<dyn BlockDevice>,
@@ -41,6 +95,7 @@ impl EasyFileSystem {
             data_bitmap,
             inode_area_start_block: 1 + inode_bitmap_blocks,
             data_area_start_block: 1 + inode_total_blocks + data_bitmap_blocks,
+            open_inodes: Arc::new(Mutex::new(OpenInodes::default())),
         };
         // clear all blocks
         for i in 0..total_blocks {
@@ -68,25 +123,34 @@ impl EasyFileSystem {
         // write back immediately
         // create a inode for root node "/"
         assert_eq!(efs.alloc_inode(), 0);
+        let root_data_block = efs.alloc_data();
         let (root_inode_block_id, root_inode_offset) = efs.get_disk_inode_pos(0);
         get_block_cache(root_inode_block_id as usize, Arc::clone(&block_device))
             .lock()
             .modify(root_inode_offset, |disk_inode: &mut DiskInode| {
-                disk_inode.initialize(DiskInodeType::Directory);
+                disk_inode.initialize(DiskInodeType::Directory, now());
+                // both "." and ".." of the root directory point to itself
+                disk_inode.increase_size((2 * DIRENT_SZ) as u32);
+                disk_inode.alloc_blocks(0, 2 * DIRENT_SZ, &mut || root_data_block, &block_device);
+                disk_inode.write_at(0, DirEntry::new(".", 0).as_bytes(), &block_device);
+                disk_inode.write_at(DIRENT_SZ, DirEntry::new("..", 0).as_bytes(), &block_device);
             });
         block_cache_sync_all();
         Arc::new(Mutex::new(efs))
     }
 
+    /// Open the efs on `block_device`. Inodes unlinked while they were still
+    /// open, which are left over if the system went down before they were
+    /// closed, are freed.
     pub fn open(block_device: Arc<dyn BlockDevice>) -> Arc<Mutex<Self>> {
         // read SuperBlock
-        get_block_cache(0, Arc::clone(&block_device))
-            .lock()
-            .read(0, |super_block: &SuperBlock| {
+        let mut efs = get_block_cache(0, Arc::clone(&block_device)).lock().read(
+            0,
+            |super_block: &SuperBlock| {
                 assert!(super_block.is_valid(), "Error loading EFS!");
                 let inode_total_blocks =
                     super_block.inode_bitmap_blocks + super_block.inode_area_blocks;
-                let efs = Self {
+                Self {
                     block_device,
                     inode_bitmap: Bitmap::new(1, super_block.inode_bitmap_blocks as usize),
                     data_bitmap: Bitmap::new(
@@ -95,17 +159,42 @@ impl EasyFileSystem {
                     ),
                     inode_area_start_block: 1 + super_block.inode_bitmap_blocks,
                     data_area_start_block: 1 + inode_total_blocks + super_block.data_bitmap_blocks,
-                };
-                Arc::new(Mutex::new(efs))
+                    open_inodes: Arc::new(Mutex::new(OpenInodes::default())),
+                }
+            },
+        );
+        let orphans: Vec<u32> = efs
+            .inode_bitmap
+            .allocated(&efs.block_device)
+            .into_iter()
+            .map(|inode_id| inode_id as u32)
+            .filter(|&inode_id| {
+                let (block_id, block_offset) = efs.get_disk_inode_pos(inode_id);
+                get_block_cache(block_id as usize, Arc::clone(&efs.block_device))
+                    .lock()
+                    .read(block_offset, |disk_inode: &DiskInode| disk_inode.nlink == 0)
             })
+            .collect();
+        efs.open_inodes.lock().unreferenced = orphans;
+        efs.free_orphans();
+        block_cache_sync_all();
+        Arc::new(Mutex::new(efs))
     }
 
     pub fn root_inode(efs: &Arc<Mutex<Self>>) -> Inode {
         let block_device = Arc::clone(&efs.lock().block_device);
+        let open_inodes = Arc::clone(&efs.lock().open_inodes);
         // acquire efs lock temporarily
         let (block_id, block_offset) = efs.lock().get_disk_inode_pos(0);
         // release efs lock
-        Inode::new(block_id, block_offset, Arc::clone(efs), block_device)
+        Inode::new(
+            0,
+            block_id,
+            block_offset,
+            Arc::clone(efs),
+            block_device,
+            open_inodes,
+        )
     }
 
     pub fn get_disk_inode_pos(&self, inode_id: u32) -> (u32, usize) {
@@ -126,6 +215,11 @@ impl EasyFileSystem {
         self.inode_bitmap.alloc(&self.block_device).unwrap() as u32
     }
 
+    pub fn dealloc_inode(&mut self, inode_id: u32) {
+        self.inode_bitmap
+            .dealloc(&self.block_device, inode_id as usize)
+    }
+
     /// Return a block ID not ID in the data area.
     pub fn alloc_data(&mut self) -> u32 {
         self.data_bitmap.alloc(&self.block_device).unwrap() as u32 + self.data_area_start_block
@@ -144,4 +238,22 @@ impl EasyFileSystem {
             (block_id - self.data_area_start_block) as usize,
         )
     }
+
+    /// Free the orphans whose last `Inode` has been dropped, it is done
+    /// when no block cache is locked.
+    pub(crate) fn free_orphans(&mut self) {
+        let orphans = core::mem::take(&mut self.open_inodes.lock().unreferenced);
+        for inode_id in orphans {
+            let (block_id, block_offset) = self.get_disk_inode_pos(inode_id);
+            let data_blocks = get_block_cache(block_id as usize, Arc::clone(&self.block_device))
+                .lock()
+                .modify(block_offset, |disk_inode: &mut DiskInode| {
+                    disk_inode.clear_size(&self.block_device)
+                });
+            for data_block in data_blocks {
+                self.dealloc_data(data_block);
+            }
+            self.dealloc_inode(inode_id);
+        }
+    }
 }
@@ -4,18 +4,23 @@ use alloc::vec::Vec;
 use core::fmt::{Debug, Formatter, Result};
 
 const EFS_MAGIC: u32 = 0x3b800001;
-const INODE_DIRECT_COUNT: usize = 28;
-const NAME_LENGTH_LIMIT: usize = 27;
+/// Bumped whenever the on-disk layout changes, version 2 added the
+/// metadata of `DiskInode` and version 3 holes, a block id of 0.
+const EFS_VERSION: u32 = 3;
+const INODE_DIRECT_COUNT: usize = 22;
+pub const NAME_LENGTH_LIMIT: usize = 27;
 const INODE_INDIRECT1_COUNT: usize = BLOCK_SZ / 4;
 const INODE_INDIRECT2_COUNT: usize = INODE_INDIRECT1_COUNT * INODE_INDIRECT1_COUNT;
 const DIRECT_BOUND: usize = INODE_DIRECT_COUNT;
 const INDIRECT1_BOUND: usize = DIRECT_BOUND + INODE_INDIRECT1_COUNT;
-#[allow(unused)]
 const INDIRECT2_BOUND: usize = INDIRECT1_BOUND + INODE_INDIRECT2_COUNT;
+/// The largest file the indices can address.
+pub const MAX_FILE_SIZE: usize = INDIRECT2_BOUND * BLOCK_SZ;
 
 #[repr(C)]
 pub struct SuperBlock {
     magic: u32,
+    version: u32,
     pub total_blocks: u32,
     pub inode_bitmap_blocks: u32,
     pub inode_area_blocks: u32,
@@ -26,6 +31,7 @@ pub struct SuperBlock {
 impl Debug for SuperBlock {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         f.debug_struct("SuperBlock")
+            .field("version", &self.version)
             .field("total_blocks", &self.total_blocks)
             .field("inode_bitmap_blocks", &self.inode_bitmap_blocks)
             .field("inode_area_blocks", &self.inode_area_blocks)
@@ -46,6 +52,7 @@ impl SuperBlock {
     ) {
         *self = Self {
             magic: EFS_MAGIC,
+            version: EFS_VERSION,
             total_blocks,
             inode_bitmap_blocks,
             inode_area_blocks,
@@ -54,14 +61,18 @@ impl SuperBlock {
         }
     }
     pub fn is_valid(&self) -> bool {
-        self.magic == EFS_MAGIC
+        self.magic == EFS_MAGIC && self.version == EFS_VERSION
     }
 }
 
-#[derive(PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum DiskInodeType {
     File,
     Directory,
+    /// names a Unix domain socket, and holds no data
+    Socket,
+    /// holds the path it refers to as its data
+    Symlink,
 }
 
 type IndirectBlock = [u32; BLOCK_SZ / 4];
@@ -73,18 +84,57 @@ pub struct DiskInode {
     pub direct: [u32; INODE_DIRECT_COUNT],
     pub indirect1: u32,
     pub indirect2: u32,
+    /// permission bits
+    pub mode: u16,
+    /// the directory entries referring to the inode
+    pub nlink: u16,
+    pub uid: u32,
+    pub gid: u32,
+    /// times in seconds since the epoch, of the last read, change of data
+    /// and change of the inode itself
+    pub atime: u32,
+    pub mtime: u32,
+    pub ctime: u32,
     type_: DiskInodeType,
 }
 
+// a block holds a whole number of inodes
+const _: () = assert!(core::mem::size_of::<DiskInode>() == 128);
+
 impl DiskInode {
     /// indirect1 and indirect2 block are allocated only when they are needed.
-    pub fn initialize(&mut self, type_: DiskInodeType) {
+    pub fn initialize(&mut self, type_: DiskInodeType, now: u32) {
         self.size = 0;
         self.direct.iter_mut().for_each(|v| *v = 0);
         self.indirect1 = 0;
         self.indirect2 = 0;
+        self.mode = match type_ {
+            DiskInodeType::Directory => 0o755,
+            // the permissions of the target apply
+            DiskInodeType::Symlink => 0o777,
+            _ => 0o644,
+        };
+        // a directory is also referred to by its own "."
+        self.nlink = if type_ == DiskInodeType::Directory {
+            2
+        } else {
+            1
+        };
+        self.uid = 0;
+        self.gid = 0;
+        self.atime = now;
+        self.mtime = now;
+        self.ctime = now;
         self.type_ = type_;
     }
+    pub fn type_(&self) -> DiskInodeType {
+        self.type_
+    }
+    /// The data has changed, and so has the inode.
+    pub fn touch(&mut self, now: u32) {
+        self.mtime = now;
+        self.ctime = now;
+    }
     pub fn is_dir(&self) -> bool {
         self.type_ == DiskInodeType::Directory
     }
@@ -92,6 +142,12 @@ impl DiskInode {
     pub fn is_file(&self) -> bool {
         self.type_ == DiskInodeType::File
     }
+    pub fn is_socket(&self) -> bool {
+        self.type_ == DiskInodeType::Socket
+    }
+    pub fn is_symlink(&self) -> bool {
+        self.type_ == DiskInodeType::Symlink
+    }
     /// Return block number correspond to size.
     pub fn data_blocks(&self) -> u32 {
         Self::_data_blocks(self.size)
@@ -99,44 +155,59 @@ impl DiskInode {
     fn _data_blocks(size: u32) -> u32 {
         (size + BLOCK_SZ as u32 - 1) / BLOCK_SZ as u32
     }
-    /// Return number of blocks needed include indirect1/2.
-    pub fn total_blocks(size: u32) -> u32 {
-        let data_blocks = Self::_data_blocks(size) as usize;
-        let mut total = data_blocks as usize;
+    /// Return number of blocks held for data and indices, holes hold none.
+    pub fn allocated_blocks(&self, block_device: &Arc<dyn BlockDevice>) -> u32 {
+        let held = |block_ids: &[u32]| block_ids.iter().filter(|id| **id != 0).count();
+        let mut total = held(&self.direct);
         // indirect1
-        if data_blocks > INODE_DIRECT_COUNT {
-            total += 1;
+        if self.indirect1 != 0 {
+            total += 1 + get_block_cache(self.indirect1 as usize, Arc::clone(block_device))
+                .lock()
+                .read(0, |indirect1: &IndirectBlock| held(indirect1));
         }
         // indirect2
-        if data_blocks > INDIRECT1_BOUND {
+        if self.indirect2 != 0 {
             total += 1;
-            // sub indirect1
-            total +=
-                (data_blocks - INDIRECT1_BOUND + INODE_INDIRECT1_COUNT - 1) / INODE_INDIRECT1_COUNT;
+            get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
+                .lock()
+                .read(0, |indirect2: &IndirectBlock| {
+                    // sub indirect1
+                    for entry in indirect2.iter().filter(|id| **id != 0) {
+                        total += 1 + get_block_cache(*entry as usize, Arc::clone(block_device))
+                            .lock()
+                            .read(0, |indirect1: &IndirectBlock| held(indirect1));
+                    }
+                });
         }
         total as u32
     }
-    pub fn blocks_num_needed(&self, new_size: u32) -> u32 {
-        assert!(new_size >= self.size);
-        Self::total_blocks(new_size) - Self::total_blocks(self.size)
-    }
+    /// Return the data block of `inner_id`, 0 if it lies in a hole.
     pub fn get_block_id(&self, inner_id: u32, block_device: &Arc<dyn BlockDevice>) -> u32 {
         let inner_id = inner_id as usize;
         if inner_id < INODE_DIRECT_COUNT {
             self.direct[inner_id]
         } else if inner_id < INDIRECT1_BOUND {
+            if self.indirect1 == 0 {
+                return 0;
+            }
             get_block_cache(self.indirect1 as usize, Arc::clone(block_device))
                 .lock()
                 .read(0, |indirect_block: &IndirectBlock| {
                     indirect_block[inner_id - INODE_DIRECT_COUNT]
                 })
         } else {
+            if self.indirect2 == 0 {
+                return 0;
+            }
             let last = inner_id - INDIRECT1_BOUND;
             let indirect1 = get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
                 .lock()
                 .read(0, |indirect2: &IndirectBlock| {
                     indirect2[last / INODE_INDIRECT1_COUNT]
                 });
+            if indirect1 == 0 {
+                return 0;
+            }
             get_block_cache(indirect1 as usize, Arc::clone(block_device))
                 .lock()
                 .read(0, |indirect1: &IndirectBlock| {
@@ -144,76 +215,72 @@ impl DiskInode {
                 })
         }
     }
-    pub fn increase_size(
+    /// Grow size to `new_size`, the part grown is a hole.
+    pub fn increase_size(&mut self, new_size: u32) {
+        assert!(new_size >= self.size && new_size as usize <= MAX_FILE_SIZE);
+        self.size = new_size;
+    }
+    /// Give the holes in `[offset, end)` data blocks from `new_block`, as
+    /// well as the index blocks they need.
+    ///
+    /// Blocks from `new_block` must be zeroed.
+    pub fn alloc_blocks(
         &mut self,
-        new_size: u32,
-        new_blocks: Vec<u32>,
+        offset: usize,
+        end: usize,
+        new_block: &mut impl FnMut() -> u32,
         block_device: &Arc<dyn BlockDevice>,
     ) {
-        let mut current_blocks = self.data_blocks();
-        self.size = new_size;
-        let mut total_blocks = self.data_blocks();
-        let mut new_blocks = new_blocks.into_iter();
-        // fill direct
-        while current_blocks < total_blocks.min(INODE_DIRECT_COUNT as u32) {
-            self.direct[current_blocks as usize] = new_blocks.next().unwrap();
-            current_blocks += 1;
-        }
-        // alloc indirect1
-        if total_blocks > INODE_DIRECT_COUNT as u32 {
-            if current_blocks == INODE_DIRECT_COUNT as u32 {
-                self.indirect1 = new_blocks.next().unwrap();
+        assert!(end <= self.size as usize);
+        for inner_id in offset / BLOCK_SZ..end.div_ceil(BLOCK_SZ) {
+            if self.get_block_id(inner_id as u32, block_device) == 0 {
+                self.map_block(inner_id, new_block, block_device);
             }
-            current_blocks -= INODE_DIRECT_COUNT as u32;
-            total_blocks -= INODE_DIRECT_COUNT as u32;
-        } else {
+        }
+    }
+    fn map_block(
+        &mut self,
+        inner_id: usize,
+        new_block: &mut impl FnMut() -> u32,
+        block_device: &Arc<dyn BlockDevice>,
+    ) {
+        // direct
+        if inner_id < DIRECT_BOUND {
+            self.direct[inner_id] = new_block();
             return;
         }
-        // fill indirect1
-        get_block_cache(self.indirect1 as usize, Arc::clone(block_device))
-            .lock()
-            .modify(0, |indirect1: &mut IndirectBlock| {
-                while current_blocks < total_blocks.min(INODE_INDIRECT1_COUNT as u32) {
-                    indirect1[current_blocks as usize] = new_blocks.next().unwrap();
-                    current_blocks += 1;
-                }
-            });
-        // alloc indirect2
-        if total_blocks > INODE_INDIRECT1_COUNT as u32 {
-            if current_blocks == INODE_INDIRECT1_COUNT as u32 {
-                self.indirect2 = new_blocks.next().unwrap();
+        // indirect1
+        if inner_id < INDIRECT1_BOUND {
+            if self.indirect1 == 0 {
+                self.indirect1 = new_block();
             }
-            current_blocks -= INODE_INDIRECT1_COUNT as u32;
-            total_blocks -= INODE_INDIRECT1_COUNT as u32;
-        } else {
+            let block_id = new_block();
+            get_block_cache(self.indirect1 as usize, Arc::clone(block_device))
+                .lock()
+                .modify(0, |indirect1: &mut IndirectBlock| {
+                    indirect1[inner_id - DIRECT_BOUND] = block_id;
+                });
             return;
         }
-        // fill indirect2 from (a0, b0) -> (a1, b1)
-        let mut a0 = current_blocks as usize / INODE_INDIRECT1_COUNT;
-        let mut b0 = current_blocks as usize % INODE_INDIRECT1_COUNT;
-        let a1 = total_blocks as usize / INODE_INDIRECT1_COUNT;
-        let b1 = total_blocks as usize % INODE_INDIRECT1_COUNT;
-        // alloc low-level indirect1
+        // indirect2
+        if self.indirect2 == 0 {
+            self.indirect2 = new_block();
+        }
+        let last = inner_id - INDIRECT1_BOUND;
         get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
             .lock()
             .modify(0, |indirect2: &mut IndirectBlock| {
-                while (a0 < a1) || (a0 == a1 && b0 < b1) {
-                    if b0 == 0 {
-                        indirect2[a0] = new_blocks.next().unwrap();
-                    }
-                    // fill current
-                    get_block_cache(indirect2[a0] as usize, Arc::clone(block_device))
-                        .lock()
-                        .modify(0, |indirect1: &mut IndirectBlock| {
-                            indirect1[b0] = new_blocks.next().unwrap();
-                        });
-                    // move to next
-                    b0 += 1;
-                    if b0 == INODE_INDIRECT1_COUNT {
-                        b0 = 0;
-                        a0 += 1;
-                    }
+                // low-level indirect1
+                let entry = &mut indirect2[last / INODE_INDIRECT1_COUNT];
+                if *entry == 0 {
+                    *entry = new_block();
                 }
+                let block_id = new_block();
+                get_block_cache(*entry as usize, Arc::clone(block_device))
+                    .lock()
+                    .modify(0, |indirect1: &mut IndirectBlock| {
+                        indirect1[last % INODE_INDIRECT1_COUNT] = block_id;
+                    });
             });
     }
 
@@ -221,74 +288,95 @@ impl DiskInode {
     ///
     /// We will clear the block contents to zero later.
     pub fn clear_size(&mut self, block_device: &Arc<dyn BlockDevice>) -> Vec<u32> {
+        self.decrease_size(0, block_device)
+    }
+    /// Shrink size to `new_size` and return blocks that should be
+    /// deallocated, including index blocks left empty.
+    ///
+    /// The rest of the last block kept is cleared, so a file grown again
+    /// reads zeros there.
+    pub fn decrease_size(
+        &mut self,
+        new_size: u32,
+        block_device: &Arc<dyn BlockDevice>,
+    ) -> Vec<u32> {
+        assert!(new_size <= self.size);
+        let old_blocks = self.data_blocks() as usize;
+        let new_blocks = Self::_data_blocks(new_size) as usize;
+        let tail = new_size as usize % BLOCK_SZ;
+        let tail_block = match tail {
+            0 => 0,
+            _ => self.get_block_id(new_blocks as u32 - 1, block_device),
+        };
+        if tail_block != 0 {
+            get_block_cache(tail_block as usize, Arc::clone(block_device))
+                .lock()
+                .modify(0, |data_block: &mut DataBlock| {
+                    data_block[tail..].iter_mut().for_each(|byte| *byte = 0);
+                });
+        }
+        self.size = new_size;
         let mut v: Vec<u32> = Vec::new();
-        let mut data_blocks = self.data_blocks() as usize;
-        self.size = 0;
-        let mut current_blocks = 0usize;
+        // holes hold no block to free
+        let mut free = |entry: &mut u32| {
+            if *entry != 0 {
+                v.push(*entry);
+                *entry = 0;
+            }
+        };
         // direct
-        while current_blocks < data_blocks.min(INODE_DIRECT_COUNT) {
-            v.push(self.direct[current_blocks]);
-            self.direct[current_blocks] = 0;
-            current_blocks += 1;
-        }
-        // indirect1 block
-        if data_blocks > INODE_DIRECT_COUNT {
-            v.push(self.indirect1);
-            data_blocks -= INODE_DIRECT_COUNT;
-            current_blocks = 0;
-        } else {
-            return v;
+        for entry in self
+            .direct
+            .iter_mut()
+            .take(old_blocks.min(DIRECT_BOUND))
+            .skip(new_blocks.min(DIRECT_BOUND))
+        {
+            free(entry);
         }
         // indirect1
-        get_block_cache(self.indirect1 as usize, Arc::clone(block_device))
-            .lock()
-            .modify(0, |indirect1: &mut IndirectBlock| {
-                while current_blocks < data_blocks.min(INODE_INDIRECT1_COUNT) {
-                    v.push(indirect1[current_blocks]);
-                    //indirect1[current_blocks] = 0;
-                    current_blocks += 1;
-                }
-            });
-        self.indirect1 = 0;
-        // indirect2 block
-        if data_blocks > INODE_INDIRECT1_COUNT {
-            v.push(self.indirect2);
-            data_blocks -= INODE_INDIRECT1_COUNT;
-        } else {
-            return v;
+        if old_blocks > DIRECT_BOUND && self.indirect1 != 0 {
+            let start = new_blocks.clamp(DIRECT_BOUND, INDIRECT1_BOUND) - DIRECT_BOUND;
+            let end = old_blocks.min(INDIRECT1_BOUND) - DIRECT_BOUND;
+            get_block_cache(self.indirect1 as usize, Arc::clone(block_device))
+                .lock()
+                .modify(0, |indirect1: &mut IndirectBlock| {
+                    indirect1[start..end].iter_mut().for_each(&mut free);
+                });
+            if new_blocks <= DIRECT_BOUND {
+                free(&mut self.indirect1);
+            }
         }
         // indirect2
-        assert!(data_blocks <= INODE_INDIRECT2_COUNT);
-        let a1 = data_blocks / INODE_INDIRECT1_COUNT;
-        let b1 = data_blocks % INODE_INDIRECT1_COUNT;
-        get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
-            .lock()
-            .modify(0, |indirect2: &mut IndirectBlock| {
-                // full indirect1 blocks
-                for entry in indirect2.iter_mut().take(a1) {
-                    v.push(*entry);
-                    get_block_cache(*entry as usize, Arc::clone(block_device))
-                        .lock()
-                        .modify(0, |indirect1: &mut IndirectBlock| {
-                            for entry in indirect1.iter() {
-                                v.push(*entry);
-                            }
-                        });
-                }
-                // last indirect1 block
-                if b1 > 0 {
-                    v.push(indirect2[a1]);
-                    get_block_cache(indirect2[a1] as usize, Arc::clone(block_device))
-                        .lock()
-                        .modify(0, |indirect1: &mut IndirectBlock| {
-                            for entry in indirect1.iter().take(b1) {
-                                v.push(*entry);
-                            }
-                        });
-                    //indirect2[a1] = 0;
-                }
-            });
-        self.indirect2 = 0;
+        if old_blocks > INDIRECT1_BOUND && self.indirect2 != 0 {
+            let start = new_blocks.max(INDIRECT1_BOUND) - INDIRECT1_BOUND;
+            let end = old_blocks - INDIRECT1_BOUND;
+            get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
+                .lock()
+                .modify(0, |indirect2: &mut IndirectBlock| {
+                    let a0 = start / INODE_INDIRECT1_COUNT;
+                    let a1 = end.div_ceil(INODE_INDIRECT1_COUNT);
+                    for (a, entry) in indirect2.iter_mut().enumerate().take(a1).skip(a0) {
+                        if *entry == 0 {
+                            continue;
+                        }
+                        let first = a * INODE_INDIRECT1_COUNT;
+                        let b0 = start.saturating_sub(first);
+                        let b1 = (end - first).min(INODE_INDIRECT1_COUNT);
+                        get_block_cache(*entry as usize, Arc::clone(block_device))
+                            .lock()
+                            .modify(0, |indirect1: &mut IndirectBlock| {
+                                indirect1[b0..b1].iter_mut().for_each(&mut free);
+                            });
+                        // a low-level indirect1 block left empty
+                        if b0 == 0 {
+                            free(entry);
+                        }
+                    }
+                });
+            if new_blocks <= INDIRECT1_BOUND {
+                free(&mut self.indirect2);
+            }
+        }
         v
     }
     pub fn read_at(
@@ -311,15 +399,16 @@ impl DiskInode {
             // read and update read size
             let block_read_size = end_current_block - start;
             let dst = &mut buf[read_size..read_size + block_read_size];
-            get_block_cache(
-                self.get_block_id(start_block as u32, block_device) as usize,
-                Arc::clone(block_device),
-            )
-            .lock()
-            .read(0, |data_block: &DataBlock| {
-                let src = &data_block[start % BLOCK_SZ..start % BLOCK_SZ + block_read_size];
-                dst.copy_from_slice(src);
-            });
+            match self.get_block_id(start_block as u32, block_device) {
+                // a hole reads zeros
+                0 => dst.fill(0),
+                block_id => get_block_cache(block_id as usize, Arc::clone(block_device))
+                    .lock()
+                    .read(0, |data_block: &DataBlock| {
+                        let src = &data_block[start % BLOCK_SZ..start % BLOCK_SZ + block_read_size];
+                        dst.copy_from_slice(src);
+                    }),
+            }
             read_size += block_read_size;
             // move to next block
             if end_current_block == end {
@@ -330,7 +419,8 @@ impl DiskInode {
         }
         read_size
     }
-    /// File size must be adjusted before.
+    /// File size must be adjusted and blocks allocated for the holes
+    /// written before.
     pub fn write_at(
         &mut self,
         offset: usize,
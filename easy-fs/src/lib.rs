@@ -13,6 +13,8 @@ pub const BLOCK_SZ: usize = 512;
 use bitmap::Bitmap;
 use block_cache::{block_cache_sync_all, get_block_cache};
 pub use block_dev::BlockDevice;
-pub use efs::EasyFileSystem;
+use efs::{now, OpenInodes};
+pub use efs::{set_clock, EasyFileSystem};
 use layout::*;
-pub use vfs::Inode;
+pub use layout::{DiskInodeType, MAX_FILE_SIZE};
+pub use vfs::{Inode, Metadata};
@@ -1,35 +1,69 @@
 use super::{
-    block_cache_sync_all, get_block_cache, BlockDevice, DirEntry, DiskInode, DiskInodeType,
-    EasyFileSystem, DIRENT_SZ,
+    block_cache_sync_all, get_block_cache, now, BlockDevice, DirEntry, DiskInode, DiskInodeType,
+    EasyFileSystem, OpenInodes, DIRENT_SZ, MAX_FILE_SIZE, NAME_LENGTH_LIMIT,
 };
+use alloc::collections::VecDeque;
 use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec;
 use alloc::vec::Vec;
 use spin::{Mutex, MutexGuard};
 
+/// Symlinks followed in one path walk at most, as SYMLOOP_MAX of Linux.
+const SYMLINK_LIMIT: usize = 40;
+
+/// The metadata of an inode, as `stat` reports it.
+#[derive(Clone, Copy, Debug)]
+pub struct Metadata {
+    pub inode_id: u32,
+    pub type_: DiskInodeType,
+    /// permission bits
+    pub mode: u16,
+    pub nlink: u16,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u32,
+    /// blocks held for data and indices
+    pub blocks: u32,
+    pub atime: u32,
+    pub mtime: u32,
+    pub ctime: u32,
+}
+
 pub struct Inode {
+    inode_id: u32,
     block_id: usize,
     block_offset: usize,
     fs: Arc<Mutex<EasyFileSystem>>,
     block_device: Arc<dyn BlockDevice>,
+    open_inodes: Arc<Mutex<OpenInodes>>,
 }
 
 impl Inode {
     /// We should not acquire efs lock here.
-    pub fn new(
+    pub(crate) fn new(
+        inode_id: u32,
         block_id: u32,
         block_offset: usize,
         fs: Arc<Mutex<EasyFileSystem>>,
         block_device: Arc<dyn BlockDevice>,
+        open_inodes: Arc<Mutex<OpenInodes>>,
     ) -> Self {
+        open_inodes.lock().open(inode_id);
         Self {
+            inode_id,
             block_id: block_id as usize,
             block_offset,
             fs,
             block_device,
+            open_inodes,
         }
     }
 
+    pub fn inode_id(&self) -> u32 {
+        self.inode_id
+    }
+
     fn read_disk_inode<V>(&self, f: impl FnOnce(&DiskInode) -> V) -> V {
         get_block_cache(self.block_id, Arc::clone(&self.block_device))
             .lock()
@@ -42,9 +76,32 @@ impl Inode {
             .modify(self.block_offset, f)
     }
 
+    /// Build the inode with id `inode_id`, the efs lock should be held by the caller.
+    fn get_inode(&self, inode_id: u32, fs: &MutexGuard<EasyFileSystem>) -> Arc<Inode> {
+        let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
+        Arc::new(Self::new(
+            inode_id,
+            block_id,
+            block_offset,
+            self.fs.clone(),
+            self.block_device.clone(),
+            fs.open_inodes.clone(),
+        ))
+    }
+
+    /// Lock the efs to change it, the orphans whose last `Inode` has been
+    /// dropped since are freed first.
+    fn lock_fs(&self) -> MutexGuard<'_, EasyFileSystem> {
+        let mut fs = self.fs.lock();
+        fs.free_orphans();
+        fs
+    }
+
     fn find_inode_id(&self, name: &str, disk_inode: &DiskInode) -> Option<u32> {
-        // assert it is a directory
-        assert!(disk_inode.is_dir());
+        // only directories have entries, and an empty name marks a free slot
+        if !disk_inode.is_dir() || name.is_empty() {
+            return None;
+        }
         let file_count = (disk_inode.size as usize) / DIRENT_SZ;
         let mut dirent = DirEntry::empty();
         for i in 0..file_count {
@@ -59,128 +116,509 @@ impl Inode {
         None
     }
 
+    /// Names of all entries in a directory, including "." and "..".
+    fn dirent_names(&self, disk_inode: &DiskInode) -> Vec<String> {
+        let mut v: Vec<String> = Vec::new();
+        if !disk_inode.is_dir() {
+            return v;
+        }
+        let file_count = (disk_inode.size as usize) / DIRENT_SZ;
+        for i in 0..file_count {
+            let mut dirent = DirEntry::empty();
+            assert_eq!(
+                disk_inode.read_at(i * DIRENT_SZ, dirent.as_bytes_mut(), &self.block_device,),
+                DIRENT_SZ,
+            );
+            if !dirent.name().is_empty() {
+                v.push(String::from(dirent.name()));
+            }
+        }
+        v
+    }
+
+    /// Write a dirent into the first free slot of a directory, or append it.
+    fn add_dirent(
+        &self,
+        name: &str,
+        inode_id: u32,
+        disk_inode: &mut DiskInode,
+        fs: &mut MutexGuard<EasyFileSystem>,
+    ) {
+        let file_count = (disk_inode.size as usize) / DIRENT_SZ;
+        let mut dirent = DirEntry::empty();
+        let mut slot = file_count;
+        for i in 0..file_count {
+            disk_inode.read_at(i * DIRENT_SZ, dirent.as_bytes_mut(), &self.block_device);
+            if dirent.name().is_empty() {
+                slot = i;
+                break;
+            }
+        }
+        self.alloc_range(slot * DIRENT_SZ, (slot + 1) * DIRENT_SZ, disk_inode, fs);
+        let dirent = DirEntry::new(name, inode_id);
+        disk_inode.write_at(slot * DIRENT_SZ, dirent.as_bytes(), &self.block_device);
+    }
+
+    /// Free the slot of dirent `name` and return the inode id it refers to.
+    fn remove_dirent(&self, name: &str, disk_inode: &mut DiskInode) -> Option<u32> {
+        if name.is_empty() {
+            return None;
+        }
+        let file_count = (disk_inode.size as usize) / DIRENT_SZ;
+        let mut dirent = DirEntry::empty();
+        for i in 0..file_count {
+            disk_inode.read_at(i * DIRENT_SZ, dirent.as_bytes_mut(), &self.block_device);
+            if dirent.name() == name {
+                disk_inode.write_at(
+                    i * DIRENT_SZ,
+                    DirEntry::empty().as_bytes(),
+                    &self.block_device,
+                );
+                return Some(dirent.inode_number());
+            }
+        }
+        None
+    }
+
     pub fn find(&self, name: &str) -> Option<Arc<Inode>> {
         let fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| self.find_inode_id(name, disk_inode))
+            .map(|inode_id| self.get_inode(inode_id, &fs))
+    }
+
+    /// Walk a path relative to this inode, e.g. "a/b/../c".
+    ///
+    /// Empty components are ignored, so "a//b/" refers to the same inode as "a/b".
+    /// Symlinks are followed, relative targets from the directory holding
+    /// the link and absolute ones from the root, and a walk following more
+    /// than `SYMLINK_LIMIT` of them fails as a loop.
+    pub fn find_path(self: &Arc<Self>, path: &str) -> Option<Arc<Inode>> {
+        let split = |path: &str| -> Vec<String> {
+            path.split('/')
+                .filter(|name| !name.is_empty())
+                .map(String::from)
+                .collect()
+        };
+        let mut names: VecDeque<String> = split(path).into();
+        let mut dir = Arc::clone(self);
+        let mut followed = 0;
+        while let Some(name) = names.pop_front() {
+            let inode = dir.find(&name)?;
+            match inode.readlink() {
+                Some(target) => {
+                    followed += 1;
+                    if followed > SYMLINK_LIMIT {
+                        return None;
+                    }
+                    if target.starts_with('/') {
+                        let fs = self.fs.lock();
+                        dir = self.get_inode(0, &fs);
+                    }
+                    for name in split(&target).into_iter().rev() {
+                        names.push_front(name);
+                    }
+                }
+                None => dir = inode,
+            }
+        }
+        Some(dir)
+    }
+
+    pub fn is_dir(&self) -> bool {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.is_dir())
+    }
+
+    pub fn is_socket(&self) -> bool {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.is_socket())
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.is_symlink())
+    }
+
+    /// The path a symlink refers to, None if this is not a symlink.
+    pub fn readlink(&self) -> Option<String> {
+        let _fs = self.fs.lock();
         self.read_disk_inode(|disk_inode| {
-            self.find_inode_id(name, disk_inode).map(|inode_id| {
-                let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
-                Arc::new(Self::new(
-                    block_id,
-                    block_offset,
-                    self.fs.clone(),
-                    self.block_device.clone(),
-                ))
-            })
+            if !disk_inode.is_symlink() {
+                return None;
+            }
+            let mut target = vec![0u8; disk_inode.size as usize];
+            disk_inode.read_at(0, &mut target, &self.block_device);
+            String::from_utf8(target).ok()
         })
     }
 
-    fn increase_size(
+    /// Grow the file to hold `[offset, end)` and allocate blocks for the
+    /// holes in it, which is done before writing there.
+    fn alloc_range(
         &self,
-        new_size: u32,
+        offset: usize,
+        end: usize,
         disk_inode: &mut DiskInode,
         fs: &mut MutexGuard<EasyFileSystem>,
     ) {
-        if new_size < disk_inode.size {
-            return;
+        if end as u32 > disk_inode.size {
+            disk_inode.increase_size(end as u32);
         }
-        let blocks_needed = disk_inode.blocks_num_needed(new_size);
-        let mut v: Vec<u32> = Vec::new();
-        for _ in 0..blocks_needed {
-            v.push(fs.alloc_data());
-        }
-        disk_inode.increase_size(new_size, v, &self.block_device);
+        disk_inode.alloc_blocks(offset, end, &mut || fs.alloc_data(), &self.block_device);
     }
 
-    pub fn create(&self, name: &str) -> Option<Arc<Inode>> {
-        let mut fs = self.fs.lock();
-        let op = |root_inode: &mut DiskInode| {
-            // assert it is a directory
-            assert!(root_inode.is_dir());
-            // has the file been created?
-            self.find_inode_id(name, root_inode)
+    fn create_inode(&self, name: &str, type_: DiskInodeType) -> Option<Arc<Inode>> {
+        if name.is_empty() || name.len() > NAME_LENGTH_LIMIT || name.contains('/') {
+            return None;
+        }
+        let mut fs = self.lock_fs();
+        let op = |dir_inode: &DiskInode| {
+            // only a directory not removed can hold a new file, has the file been created?
+            !dir_inode.is_dir()
+                || dir_inode.nlink == 0
+                || self.find_inode_id(name, dir_inode).is_some()
         };
-        if self.modify_disk_inode(op).is_some() {
+        if self.read_disk_inode(op) {
             return None;
         }
         // create a new file
         // alloc a inode with an indirect block
         let new_inode_id = fs.alloc_inode();
+        let is_dir = type_ == DiskInodeType::Directory;
         // initialize inode
         let (new_inode_block_id, new_inode_block_offset) = fs.get_disk_inode_pos(new_inode_id);
         get_block_cache(new_inode_block_id as usize, Arc::clone(&self.block_device))
             .lock()
             .modify(new_inode_block_offset, |new_inode: &mut DiskInode| {
-                new_inode.initialize(DiskInodeType::File);
+                new_inode.initialize(type_, now());
+                if is_dir {
+                    // a new directory starts with "." and ".."
+                    self.alloc_range(0, 2 * DIRENT_SZ, new_inode, &mut fs);
+                    let dot = DirEntry::new(".", new_inode_id);
+                    new_inode.write_at(0, dot.as_bytes(), &self.block_device);
+                    let dotdot = DirEntry::new("..", self.inode_id);
+                    new_inode.write_at(DIRENT_SZ, dotdot.as_bytes(), &self.block_device);
+                }
             });
-        self.modify_disk_inode(|root_inode| {
-            // append file in the dirent
-            let file_count = (root_inode.size as usize) / DIRENT_SZ;
-            let new_size = (file_count + 1) * DIRENT_SZ;
-            // increase size
-            self.increase_size(new_size as u32, root_inode, &mut fs);
-            // write dirent
-            let dirent = DirEntry::new(name, new_inode_id);
-            root_inode.write_at(
-                file_count * DIRENT_SZ,
-                dirent.as_bytes(),
-                &self.block_device,
-            );
+        self.modify_disk_inode(|dir_inode| {
+            self.add_dirent(name, new_inode_id, dir_inode, &mut fs);
+            // ".." of a new directory refers to this one
+            if is_dir {
+                dir_inode.nlink += 1;
+            }
+            dir_inode.touch(now());
         });
 
-        let (block_id, block_offset) = fs.get_disk_inode_pos(new_inode_id);
         block_cache_sync_all();
         // return inode
-        Some(Arc::new(Self::new(
-            block_id,
-            block_offset,
-            self.fs.clone(),
-            self.block_device.clone(),
-        )))
+        Some(self.get_inode(new_inode_id, &fs))
         // release efs lock automatically by compiler
     }
 
+    pub fn create(&self, name: &str) -> Option<Arc<Inode>> {
+        self.create_inode(name, DiskInodeType::File)
+    }
+
+    pub fn mkdir(&self, name: &str) -> Option<Arc<Inode>> {
+        self.create_inode(name, DiskInodeType::Directory)
+    }
+
+    /// Create the file a Unix domain socket is bound to.
+    pub fn create_socket(&self, name: &str) -> Option<Arc<Inode>> {
+        self.create_inode(name, DiskInodeType::Socket)
+    }
+
+    /// Create a symlink referring to `target`, which need not exist.
+    pub fn symlink(&self, name: &str, target: &str) -> Option<Arc<Inode>> {
+        if target.is_empty() {
+            return None;
+        }
+        let inode = self.create_inode(name, DiskInodeType::Symlink)?;
+        inode.write_at(0, target.as_bytes());
+        Some(inode)
+    }
+
+    /// Add entry `name` referring to `inode`, which may not be a directory.
+    pub fn link(&self, name: &str, inode: &Inode) -> bool {
+        if name.is_empty() || name.len() > NAME_LENGTH_LIMIT || name.contains('/') {
+            return false;
+        }
+        let mut fs = self.lock_fs();
+        let op = |dir_inode: &DiskInode| {
+            dir_inode.is_dir()
+                && dir_inode.nlink > 0
+                && self.find_inode_id(name, dir_inode).is_none()
+        };
+        // an orphan can not be linked again
+        if !self.read_disk_inode(op)
+            || inode.read_disk_inode(|disk_inode| disk_inode.is_dir() || disk_inode.nlink == 0)
+        {
+            return false;
+        }
+        let now = now();
+        self.modify_disk_inode(|dir_inode| {
+            self.add_dirent(name, inode.inode_id, dir_inode, &mut fs);
+            dir_inode.touch(now);
+        });
+        inode.modify_disk_inode(|disk_inode| {
+            disk_inode.nlink += 1;
+            disk_inode.ctime = now;
+        });
+        block_cache_sync_all();
+        true
+    }
+
+    /// Drop a link to a non-directory, which becomes an orphan with its
+    /// last link.
+    fn drop_link(&self) {
+        let now = now();
+        let unlinked = self.modify_disk_inode(|disk_inode| {
+            disk_inode.nlink -= 1;
+            disk_inode.ctime = now;
+            disk_inode.nlink == 0
+        });
+        if unlinked {
+            self.open_inodes.lock().orphan(self.inode_id);
+        }
+    }
+
+    /// Remove a non-directory entry. The inode is freed with its last link,
+    /// but not before the last `Inode` of it is dropped, so an open file
+    /// stays usable.
+    pub fn unlink(&self, name: &str) -> bool {
+        let mut fs = self.lock_fs();
+        let inode = match self.read_disk_inode(|disk_inode| self.find_inode_id(name, disk_inode)) {
+            Some(inode_id) => self.get_inode(inode_id, &fs),
+            None => return false,
+        };
+        if inode.read_disk_inode(|disk_inode| disk_inode.is_dir()) {
+            return false;
+        }
+        self.modify_disk_inode(|dir_inode| {
+            self.remove_dirent(name, dir_inode);
+            dir_inode.touch(now());
+        });
+        inode.drop_link();
+        drop(inode);
+        fs.free_orphans();
+        block_cache_sync_all();
+        true
+    }
+
+    /// Remove an empty directory, i.e. one holding nothing but "." and "..".
+    /// It is freed after the last `Inode` of it is dropped, as an unlinked file.
+    pub fn rmdir(&self, name: &str) -> bool {
+        if name == "." || name == ".." {
+            return false;
+        }
+        let mut fs = self.lock_fs();
+        let inode = match self.read_disk_inode(|disk_inode| self.find_inode_id(name, disk_inode)) {
+            Some(inode_id) => self.get_inode(inode_id, &fs),
+            None => return false,
+        };
+        let removable = inode.read_disk_inode(|disk_inode| {
+            disk_inode.is_dir()
+                && inode
+                    .dirent_names(disk_inode)
+                    .iter()
+                    .all(|name| name == "." || name == "..")
+        });
+        if !removable {
+            return false;
+        }
+        self.modify_disk_inode(|dir_inode| {
+            self.remove_dirent(name, dir_inode);
+            dir_inode.nlink -= 1;
+            dir_inode.touch(now());
+        });
+        inode.modify_disk_inode(|disk_inode| disk_inode.nlink = 0);
+        self.open_inodes.lock().orphan(inode.inode_id);
+        drop(inode);
+        fs.free_orphans();
+        block_cache_sync_all();
+        true
+    }
+
+    /// Move entry `old_name` of this directory to `new_name` under `new_dir`.
+    ///
+    /// An existing `new_name` is replaced only if neither side is a directory.
+    pub fn rename(&self, old_name: &str, new_dir: &Inode, new_name: &str) -> bool {
+        let is_special = |name: &str| name == "." || name == "..";
+        if is_special(old_name)
+            || is_special(new_name)
+            || new_name.is_empty()
+            || new_name.len() > NAME_LENGTH_LIMIT
+            || new_name.contains('/')
+        {
+            return false;
+        }
+        let mut fs = self.lock_fs();
+        let inode =
+            match self.read_disk_inode(|disk_inode| self.find_inode_id(old_name, disk_inode)) {
+                Some(inode_id) => self.get_inode(inode_id, &fs),
+                None => return false,
+            };
+        if !new_dir.read_disk_inode(|disk_inode| disk_inode.is_dir() && disk_inode.nlink > 0) {
+            return false;
+        }
+        let is_dir = inode.read_disk_inode(|disk_inode| disk_inode.is_dir());
+        if is_dir {
+            // a directory cannot be moved into its own subtree
+            let mut ancestor = new_dir.inode_id;
+            loop {
+                if ancestor == inode.inode_id {
+                    return false;
+                }
+                if ancestor == 0 {
+                    break;
+                }
+                let ancestor_inode = self.get_inode(ancestor, &fs);
+                ancestor = ancestor_inode
+                    .read_disk_inode(|disk_inode| ancestor_inode.find_inode_id("..", disk_inode))
+                    .unwrap();
+            }
+        }
+        if let Some(replaced_id) =
+            new_dir.read_disk_inode(|disk_inode| new_dir.find_inode_id(new_name, disk_inode))
+        {
+            if replaced_id == inode.inode_id {
+                return true;
+            }
+            let replaced = self.get_inode(replaced_id, &fs);
+            if is_dir || replaced.read_disk_inode(|disk_inode| disk_inode.is_dir()) {
+                return false;
+            }
+            new_dir.modify_disk_inode(|dir_inode| new_dir.remove_dirent(new_name, dir_inode));
+            replaced.drop_link();
+        }
+        let now = now();
+        let moves_dir = is_dir && new_dir.inode_id != self.inode_id;
+        self.modify_disk_inode(|dir_inode| {
+            self.remove_dirent(old_name, dir_inode);
+            if moves_dir {
+                dir_inode.nlink -= 1;
+            }
+            dir_inode.touch(now);
+        });
+        new_dir.modify_disk_inode(|dir_inode| {
+            new_dir.add_dirent(new_name, inode.inode_id, dir_inode, &mut fs);
+            if moves_dir {
+                dir_inode.nlink += 1;
+            }
+            dir_inode.touch(now);
+        });
+        inode.modify_disk_inode(|disk_inode| {
+            if moves_dir {
+                // point ".." of the moved directory to its new parent
+                inode.remove_dirent("..", disk_inode);
+                inode.add_dirent("..", new_dir.inode_id, disk_inode, &mut fs);
+            }
+            disk_inode.ctime = now;
+        });
+        // the replaced inode is freed here if it is not open
+        fs.free_orphans();
+        block_cache_sync_all();
+        true
+    }
+
     pub fn ls(&self) -> Vec<String> {
         let _fs = self.fs.lock();
-        self.read_disk_inode(|disk_inode| {
-            let file_count = (disk_inode.size as usize) / DIRENT_SZ;
-            let mut v: Vec<String> = Vec::new();
-            for i in 0..file_count {
-                let mut dirent = DirEntry::empty();
-                assert_eq!(
-                    disk_inode.read_at(i * DIRENT_SZ, dirent.as_bytes_mut(), &self.block_device,),
-                    DIRENT_SZ,
-                );
-                v.push(String::from(dirent.name()));
-            }
-            v
+        self.read_disk_inode(|disk_inode| self.dirent_names(disk_inode))
+    }
+
+    pub fn size(&self) -> usize {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.size as usize)
+    }
+
+    pub fn metadata(&self) -> Metadata {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| Metadata {
+            inode_id: self.inode_id,
+            type_: disk_inode.type_(),
+            mode: disk_inode.mode,
+            nlink: disk_inode.nlink,
+            uid: disk_inode.uid,
+            gid: disk_inode.gid,
+            size: disk_inode.size,
+            blocks: disk_inode.allocated_blocks(&self.block_device),
+            atime: disk_inode.atime,
+            mtime: disk_inode.mtime,
+            ctime: disk_inode.ctime,
         })
     }
 
     pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
         let _fs = self.fs.lock();
-        self.read_disk_inode(|disk_inode| disk_inode.read_at(offset, buf, &self.block_device))
+        self.modify_disk_inode(|disk_inode| {
+            disk_inode.atime = now();
+            disk_inode.read_at(offset, buf, &self.block_device)
+        })
     }
 
+    /// Write `buf` at `offset`, blocks are only allocated for the part
+    /// written, so skipping past the end leaves a hole. Nothing is written
+    /// past `MAX_FILE_SIZE`.
     pub fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
-        let mut fs = self.fs.lock();
+        let end = (offset + buf.len()).min(MAX_FILE_SIZE);
+        if offset >= end {
+            return 0;
+        }
+        let mut fs = self.lock_fs();
         let size = self.modify_disk_inode(|disk_inode| {
-            self.increase_size((offset + buf.len()) as u32, disk_inode, &mut fs);
-            disk_inode.write_at(offset, buf, &self.block_device)
+            self.alloc_range(offset, end, disk_inode, &mut fs);
+            disk_inode.touch(now());
+            disk_inode.write_at(offset, &buf[..end - offset], &self.block_device)
         });
         block_cache_sync_all();
         size
     }
 
-    pub fn clear(&self) {
-        let mut fs = self.fs.lock();
+    /// Free all data blocks, the efs lock should be held by the caller.
+    fn clear_data(&self, fs: &mut MutexGuard<EasyFileSystem>) {
         self.modify_disk_inode(|disk_inode| {
-            let size = disk_inode.size;
             let data_blocks_dealloc = disk_inode.clear_size(&self.block_device);
-            assert!(data_blocks_dealloc.len() == DiskInode::total_blocks(size) as usize);
             for data_block in data_blocks_dealloc.into_iter() {
                 fs.dealloc_data(data_block);
             }
         });
+    }
+
+    /// Shrink or grow the file to `new_size`, the part grown is a hole.
+    /// A directory can not be truncated.
+    pub fn truncate(&self, new_size: u32) -> bool {
+        let mut fs = self.lock_fs();
+        let truncated = self.modify_disk_inode(|disk_inode| {
+            if disk_inode.is_dir() || new_size as usize > MAX_FILE_SIZE {
+                return false;
+            }
+            if new_size < disk_inode.size {
+                let data_blocks_dealloc = disk_inode.decrease_size(new_size, &self.block_device);
+                for data_block in data_blocks_dealloc.into_iter() {
+                    fs.dealloc_data(data_block);
+                }
+            } else {
+                disk_inode.increase_size(new_size);
+            }
+            disk_inode.touch(now());
+            true
+        });
+        block_cache_sync_all();
+        truncated
+    }
+
+    pub fn clear(&self) {
+        let mut fs = self.lock_fs();
+        self.clear_data(&mut fs);
+        self.modify_disk_inode(|disk_inode| disk_inode.touch(now()));
         block_cache_sync_all();
     }
 }
+
+impl Drop for Inode {
+    /// An orphan is not freed here since the efs may be locked, but the
+    /// next time the efs is changed.
+    fn drop(&mut self) {
+        self.open_inodes.lock().close(self.inode_id);
+    }
+}
@@ -2,11 +2,26 @@
 
 pub const USER_STACK_SIZE: usize = 4096 * 2;
 pub const KERNEL_STACK_SIZE: usize = 4096 * 2;
+/// Each hart has a boot stack of this size in entry.asm.
+pub const BOOT_STACK_SIZE: usize = 4096 * 16;
 pub const KERNEL_HEAP_SIZE: usize = 0x100_0000;
 pub const PAGE_SIZE: usize = 0x1000;
 pub const PAGE_SIZE_BITS: usize = 0xc;
 
+/// `mmap` only places mappings in [MMAP_BASE, MMAP_TOP) of the user space,
+/// the program break cannot grow beyond MMAP_BASE.
+pub const MMAP_BASE: usize = 0x2000_0000;
+pub const MMAP_TOP: usize = 0x3f_0000_0000;
+/// User stacks of threads start from here, after a guard page.
+pub const USER_STACK_BASE: usize = MMAP_TOP + PAGE_SIZE;
+
+/// Swapped out pages are kept in the swap area of the block device, which
+/// follows the 32MiB file system in the image built by easy-fs-fuse.
+pub const SWAP_START_BLOCK: usize = 32 * 2048;
+/// Number of pages the swap area holds (64MiB).
+pub const SWAP_PAGES: usize = 16384;
+
 pub const TRAMPOLINE: usize = usize::MAX - PAGE_SIZE + 1;
 pub const TRAP_CONTEXT_BASE: usize = TRAMPOLINE - PAGE_SIZE;
 
-pub use crate::board::{CLOCK_FREQ, MEMORY_END, MMIO};
+pub use crate::board::{CLOCK_FREQ, MAX_HARTS, MEMORY_END, MMIO};
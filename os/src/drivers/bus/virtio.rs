@@ -2,14 +2,14 @@ use crate::mm::{
     FrameTracker, PageTable, PhysAddr, PhysPageNum, StepByOne, VirtAddr, frame_alloc_more,
     frame_dealloc, kernel_token,
 };
-use crate::sync::UPIntrFreeCell;
+use crate::sync::SpinIntrFreeCell;
 use alloc::vec::Vec;
 use lazy_static::*;
 use virtio_drivers::Hal;
 
 lazy_static! {
-    static ref QUEUE_FRAMES: UPIntrFreeCell<Vec<FrameTracker>> =
-        unsafe { UPIntrFreeCell::new(Vec::new()) };
+    static ref QUEUE_FRAMES: SpinIntrFreeCell<Vec<FrameTracker>> =
+        unsafe { SpinIntrFreeCell::new(Vec::new()) };
 }
 
 pub struct VirtioHal;
@@ -2,9 +2,10 @@
 ///! Ref: ns16550a datasheet: https://datasheetspdf.com/pdf-file/605590/NationalSemiconductor/NS16550A/1
 ///! Ref: ns16450 datasheet: https://datasheetspdf.com/pdf-file/1311818/NationalSemiconductor/NS16450/1
 use super::CharDevice;
-use crate::sync::{Condvar, UPIntrFreeCell};
+use crate::sync::{Condvar, PollWaiter, SpinIntrFreeCell};
 use crate::task::schedule;
 use alloc::collections::VecDeque;
+use alloc::sync::Arc;
 use bitflags::*;
 use volatile::{ReadOnly, Volatile, WriteOnly};
 
@@ -125,7 +126,7 @@ struct NS16550aInner {
 }
 
 pub struct NS16550a<const BASE_ADDR: usize> {
-    inner: UPIntrFreeCell<NS16550aInner>,
+    inner: SpinIntrFreeCell<NS16550aInner>,
     condvar: Condvar,
 }
 
@@ -137,7 +138,7 @@ impl<const BASE_ADDR: usize> NS16550a<BASE_ADDR> {
         };
         //inner.ns16550a.init();
         Self {
-            inner: unsafe { UPIntrFreeCell::new(inner) },
+            inner: unsafe { SpinIntrFreeCell::new(inner) },
             condvar: Condvar::new(),
         }
     }
@@ -146,6 +147,11 @@ impl<const BASE_ADDR: usize> NS16550a<BASE_ADDR> {
         self.inner
             .exclusive_session(|inner| inner.read_buffer.is_empty())
     }
+
+    /// Have a poll woken when a character arrives.
+    pub fn register_poller(&self, waiter: &Arc<PollWaiter>) {
+        self.condvar.register_poller(waiter);
+    }
 }
 
 impl<const BASE_ADDR: usize> CharDevice for NS16550a<BASE_ADDR> {
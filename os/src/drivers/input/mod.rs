@@ -1,5 +1,5 @@
 use crate::drivers::bus::virtio::VirtioHal;
-use crate::sync::{Condvar, UPIntrFreeCell};
+use crate::sync::{Condvar, PollWaiter, SpinIntrFreeCell};
 use crate::task::schedule;
 use alloc::collections::VecDeque;
 use alloc::sync::Arc;
@@ -15,14 +15,18 @@ struct VirtIOInputInner {
 }
 
 struct VirtIOInputWrapper {
-    inner: UPIntrFreeCell<VirtIOInputInner>,
+    inner: SpinIntrFreeCell<VirtIOInputInner>,
     condvar: Condvar,
 }
 
 pub trait InputDevice: Send + Sync + Any {
     fn read_event(&self) -> u64;
+    /// Take an event without waiting, None if there is none.
+    fn try_read_event(&self) -> Option<u64>;
     fn handle_irq(&self);
     fn is_empty(&self) -> bool;
+    /// Have a poll woken when an event arrives.
+    fn register_poller(&self, waiter: &Arc<PollWaiter>);
 }
 
 lazy_static::lazy_static!(
@@ -39,7 +43,7 @@ impl VirtIOInputWrapper {
             events: VecDeque::new(),
         };
         Self {
-            inner: unsafe { UPIntrFreeCell::new(inner) },
+            inner: unsafe { SpinIntrFreeCell::new(inner) },
             condvar: Condvar::new(),
         }
     }
@@ -50,6 +54,14 @@ impl InputDevice for VirtIOInputWrapper {
         self.inner.exclusive_access().events.is_empty()
     }
 
+    fn try_read_event(&self) -> Option<u64> {
+        self.inner.exclusive_access().events.pop_front()
+    }
+
+    fn register_poller(&self, waiter: &Arc<PollWaiter>) {
+        self.condvar.register_poller(waiter);
+    }
+
     fn read_event(&self) -> u64 {
         loop {
             let mut inner = self.inner.exclusive_access();
@@ -0,0 +1,307 @@
+//! A virtio-net driver over the legacy MMIO transport. Receive buffers stay
+//! posted to the device, so frames arrive with an interrupt and are taken
+//! without blocking.
+
+use super::{DEFAULT_MAC, NetDevice};
+use crate::config::PAGE_SIZE;
+use crate::drivers::bus::virtio::VirtioHal;
+use crate::sync::SpinIntrFreeCell;
+use alloc::vec::Vec;
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{Ordering, fence};
+use virtio_drivers::Hal;
+
+const VIRTIO4: usize = 0x10004000;
+
+// registers of the legacy MMIO transport
+const MAGIC_VALUE: usize = 0x000;
+const VERSION: usize = 0x004;
+const DEVICE_ID: usize = 0x008;
+const HOST_FEATURES: usize = 0x010;
+const GUEST_FEATURES: usize = 0x020;
+const GUEST_PAGE_SIZE: usize = 0x028;
+const QUEUE_SEL: usize = 0x030;
+const QUEUE_NUM_MAX: usize = 0x034;
+const QUEUE_NUM: usize = 0x038;
+const QUEUE_ALIGN: usize = 0x03c;
+const QUEUE_PFN: usize = 0x040;
+const QUEUE_NOTIFY: usize = 0x050;
+const INTERRUPT_STATUS: usize = 0x060;
+const INTERRUPT_ACK: usize = 0x064;
+const STATUS: usize = 0x070;
+/// `struct virtio_net_config` of the device
+const CONFIG: usize = 0x100;
+
+const STATUS_ACKNOWLEDGE: u32 = 1;
+const STATUS_DRIVER: u32 = 2;
+const STATUS_DRIVER_OK: u32 = 4;
+
+const DEVICE_NET: u32 = 1;
+const VIRTIO_NET_F_MAC: u32 = 1 << 5;
+
+const QUEUE_RECEIVE: u32 = 0;
+const QUEUE_TRANSMIT: u32 = 1;
+const QUEUE_SIZE: usize = 16;
+
+const VIRTQ_DESC_F_WRITE: u16 = 2;
+const VIRTQ_AVAIL_F_NO_INTERRUPT: u16 = 1;
+
+/// `struct virtio_net_hdr` without mergeable receive buffers.
+const NET_HDR_SIZE: usize = 10;
+/// A header and an ethernet frame of the full MTU fit in a buffer.
+const BUF_SIZE: usize = 2048;
+
+#[repr(C)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+/// A virtqueue in the legacy layout: the descriptor table and the available
+/// ring, then the used ring at the next page.
+struct VirtQueue {
+    base: usize,
+    /// index of the next entry in the available ring
+    avail_idx: u16,
+    /// index of the next entry in the used ring to be taken
+    last_used_idx: u16,
+}
+
+impl VirtQueue {
+    const AVAIL_OFFSET: usize = 16 * QUEUE_SIZE;
+    const USED_OFFSET: usize = PAGE_SIZE;
+
+    fn new() -> Self {
+        Self {
+            base: VirtioHal::phys_to_virt(VirtioHal::dma_alloc(2)),
+            avail_idx: 0,
+            last_used_idx: 0,
+        }
+    }
+    fn desc(&self, id: usize) -> *mut Descriptor {
+        (self.base + 16 * id) as *mut Descriptor
+    }
+    fn avail_flags(&self) -> *mut u16 {
+        (self.base + Self::AVAIL_OFFSET) as *mut u16
+    }
+    fn avail_idx_ptr(&self) -> *mut u16 {
+        (self.base + Self::AVAIL_OFFSET + 2) as *mut u16
+    }
+    fn avail_ring(&self, slot: usize) -> *mut u16 {
+        (self.base + Self::AVAIL_OFFSET + 4 + 2 * slot) as *mut u16
+    }
+    fn used_idx_ptr(&self) -> *const u16 {
+        (self.base + Self::USED_OFFSET + 2) as *const u16
+    }
+    /// Id and written length of an entry in the used ring.
+    fn used_elem(&self, slot: usize) -> (usize, usize) {
+        let elem = self.base + Self::USED_OFFSET + 4 + 8 * slot;
+        unsafe {
+            (
+                read_volatile(elem as *const u32) as usize,
+                read_volatile((elem + 4) as *const u32) as usize,
+            )
+        }
+    }
+    /// Make a descriptor available to the device.
+    fn push_avail(&mut self, id: usize) {
+        unsafe {
+            write_volatile(
+                self.avail_ring(self.avail_idx as usize % QUEUE_SIZE),
+                id as u16,
+            );
+            // the ring entry is visible before the index
+            fence(Ordering::SeqCst);
+            self.avail_idx = self.avail_idx.wrapping_add(1);
+            write_volatile(self.avail_idx_ptr(), self.avail_idx);
+            fence(Ordering::SeqCst);
+        }
+    }
+    /// Take a descriptor which the device has finished with.
+    fn pop_used(&mut self) -> Option<(usize, usize)> {
+        fence(Ordering::SeqCst);
+        if unsafe { read_volatile(self.used_idx_ptr()) } == self.last_used_idx {
+            return None;
+        }
+        let elem = self.used_elem(self.last_used_idx as usize % QUEUE_SIZE);
+        self.last_used_idx = self.last_used_idx.wrapping_add(1);
+        Some(elem)
+    }
+}
+
+struct VirtIONetInner {
+    rx_queue: VirtQueue,
+    tx_queue: VirtQueue,
+    /// physical address of `QUEUE_SIZE` receive buffers
+    rx_buffers: usize,
+    tx_buffers: usize,
+    /// transmit descriptors which are not in use
+    tx_free: Vec<usize>,
+}
+
+pub struct VirtIONet {
+    inner: SpinIntrFreeCell<VirtIONetInner>,
+    mac: [u8; 6],
+}
+
+fn read_reg(offset: usize) -> u32 {
+    unsafe { read_volatile((VIRTIO4 + offset) as *const u32) }
+}
+
+fn write_reg(offset: usize, value: u32) {
+    unsafe { write_volatile((VIRTIO4 + offset) as *mut u32, value) }
+}
+
+fn setup_queue(index: u32, queue: &VirtQueue) {
+    write_reg(QUEUE_SEL, index);
+    assert!(read_reg(QUEUE_NUM_MAX) as usize >= QUEUE_SIZE);
+    write_reg(QUEUE_NUM, QUEUE_SIZE as u32);
+    write_reg(QUEUE_ALIGN, PAGE_SIZE as u32);
+    write_reg(
+        QUEUE_PFN,
+        (VirtioHal::virt_to_phys(queue.base) / PAGE_SIZE) as u32,
+    );
+}
+
+impl VirtIONet {
+    /// Whether a legacy virtio-net device is there, the slot is empty if
+    /// QEMU is given no netdev.
+    pub fn probe() -> bool {
+        read_reg(MAGIC_VALUE) == 0x7472_6976
+            && read_reg(VERSION) == 1
+            && read_reg(DEVICE_ID) == DEVICE_NET
+    }
+
+    pub fn new() -> Self {
+        assert_eq!(read_reg(MAGIC_VALUE), 0x7472_6976, "not a virtio device");
+        assert_eq!(
+            read_reg(VERSION),
+            1,
+            "only the legacy transport is supported"
+        );
+        assert_eq!(read_reg(DEVICE_ID), DEVICE_NET, "not a virtio-net device");
+        // reset, then acknowledge the device
+        write_reg(STATUS, 0);
+        write_reg(STATUS, STATUS_ACKNOWLEDGE);
+        write_reg(STATUS, STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+        let features = read_reg(HOST_FEATURES) & VIRTIO_NET_F_MAC;
+        write_reg(GUEST_FEATURES, features);
+        let mut mac = DEFAULT_MAC;
+        if features & VIRTIO_NET_F_MAC != 0 {
+            for (i, byte) in mac.iter_mut().enumerate() {
+                *byte = unsafe { read_volatile((VIRTIO4 + CONFIG + i) as *const u8) };
+            }
+        }
+        write_reg(GUEST_PAGE_SIZE, PAGE_SIZE as u32);
+
+        let mut rx_queue = VirtQueue::new();
+        let tx_queue = VirtQueue::new();
+        setup_queue(QUEUE_RECEIVE, &rx_queue);
+        setup_queue(QUEUE_TRANSMIT, &tx_queue);
+        let pages = QUEUE_SIZE * BUF_SIZE / PAGE_SIZE;
+        let rx_buffers = VirtioHal::dma_alloc(pages);
+        let tx_buffers = VirtioHal::dma_alloc(pages);
+        // all receive buffers are given to the device at once
+        for id in 0..QUEUE_SIZE {
+            unsafe {
+                write_volatile(
+                    rx_queue.desc(id),
+                    Descriptor {
+                        addr: (rx_buffers + id * BUF_SIZE) as u64,
+                        len: BUF_SIZE as u32,
+                        flags: VIRTQ_DESC_F_WRITE,
+                        next: 0,
+                    },
+                );
+            }
+            rx_queue.push_avail(id);
+        }
+        // transmitted buffers are reclaimed when sending, no interrupt is needed
+        unsafe {
+            write_volatile(tx_queue.avail_flags(), VIRTQ_AVAIL_F_NO_INTERRUPT);
+        }
+        write_reg(
+            STATUS,
+            STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_DRIVER_OK,
+        );
+        write_reg(QUEUE_NOTIFY, QUEUE_RECEIVE);
+        let inner = VirtIONetInner {
+            rx_queue,
+            tx_queue,
+            rx_buffers,
+            tx_buffers,
+            tx_free: (0..QUEUE_SIZE).collect(),
+        };
+        Self {
+            inner: unsafe { SpinIntrFreeCell::new(inner) },
+            mac,
+        }
+    }
+}
+
+impl NetDevice for VirtIONet {
+    fn transmit(&self, data: &[u8]) {
+        assert!(data.len() <= BUF_SIZE - NET_HDR_SIZE);
+        let mut inner = self.inner.exclusive_access();
+        let id = loop {
+            while let Some((id, _)) = inner.tx_queue.pop_used() {
+                inner.tx_free.push(id);
+            }
+            if let Some(id) = inner.tx_free.pop() {
+                break id;
+            }
+            // all buffers are being sent
+            core::hint::spin_loop();
+        };
+        let buffer = VirtioHal::phys_to_virt(inner.tx_buffers + id * BUF_SIZE);
+        unsafe {
+            // no offload is used, so the header is all zero
+            core::ptr::write_bytes(buffer as *mut u8, 0, NET_HDR_SIZE);
+            core::ptr::copy_nonoverlapping(
+                data.as_ptr(),
+                (buffer + NET_HDR_SIZE) as *mut u8,
+                data.len(),
+            );
+            write_volatile(
+                inner.tx_queue.desc(id),
+                Descriptor {
+                    addr: (inner.tx_buffers + id * BUF_SIZE) as u64,
+                    len: (NET_HDR_SIZE + data.len()) as u32,
+                    flags: 0,
+                    next: 0,
+                },
+            );
+        }
+        inner.tx_queue.push_avail(id);
+        write_reg(QUEUE_NOTIFY, QUEUE_TRANSMIT);
+    }
+
+    fn receive(&self, data: &mut [u8]) -> Option<usize> {
+        let mut inner = self.inner.exclusive_access();
+        let (id, len) = inner.rx_queue.pop_used()?;
+        let len = len.saturating_sub(NET_HDR_SIZE).min(data.len());
+        let buffer = VirtioHal::phys_to_virt(inner.rx_buffers + id * BUF_SIZE);
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                (buffer + NET_HDR_SIZE) as *const u8,
+                data.as_mut_ptr(),
+                len,
+            );
+        }
+        // the buffer is given back to the device
+        inner.rx_queue.push_avail(id);
+        write_reg(QUEUE_NOTIFY, QUEUE_RECEIVE);
+        Some(len)
+    }
+
+    fn mac(&self) -> [u8; 6] {
+        self.mac
+    }
+
+    fn handle_irq(&self) {
+        let _inner = self.inner.exclusive_access();
+        write_reg(INTERRUPT_ACK, read_reg(INTERRUPT_STATUS));
+    }
+}
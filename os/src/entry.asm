@@ -1,12 +1,29 @@
     .section .text.entry
     .globl _start
 _start:
-    la sp, boot_stack_top
+    # a0 = hart id of the boot hart
+    call set_boot_stack
     call rust_main
 
+    .globl _start_secondary
+_start_secondary:
+    # a0 = hart id of the hart started through SBI HSM
+    call set_boot_stack
+    call rust_main_secondary
+
+# every hart has its own boot stack, and keeps its hart id in tp
+set_boot_stack:
+    mv tp, a0
+    addi t0, a0, 1
+    slli t0, t0, 16
+    la sp, boot_stack_lower_bound
+    add sp, sp, t0
+    ret
+
     .section .bss.stack
     .globl boot_stack_lower_bound
 boot_stack_lower_bound:
-    .space 4096 * 16
+    # 64KiB for each of the 4 harts
+    .space 4096 * 16 * 4
     .globl boot_stack_top
 boot_stack_top:
@@ -1,17 +1,19 @@
-use super::File;
+use super::{File, SeekFrom, Stat};
 use crate::drivers::BLOCK_DEVICE;
 use crate::mm::UserBuffer;
-use crate::sync::UPIntrFreeCell;
+use crate::sync::SpinIntrFreeCell;
+use crate::timer::get_real_time_sec;
+use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use bitflags::*;
-use easy_fs::{EasyFileSystem, Inode};
+use easy_fs::{EasyFileSystem, Inode, set_clock};
 use lazy_static::*;
 
 pub struct OSInode {
     readable: bool,
     writable: bool,
-    inner: UPIntrFreeCell<OSInodeInner>,
+    inner: SpinIntrFreeCell<OSInodeInner>,
 }
 
 pub struct OSInodeInner {
@@ -24,27 +26,14 @@ impl OSInode {
         Self {
             readable,
             writable,
-            inner: unsafe { UPIntrFreeCell::new(OSInodeInner { offset: 0, inode }) },
+            inner: unsafe { SpinIntrFreeCell::new(OSInodeInner { offset: 0, inode }) },
         }
     }
-    pub fn read_all(&self) -> Vec<u8> {
-        let mut inner = self.inner.exclusive_access();
-        let mut buffer = [0u8; 512];
-        let mut v: Vec<u8> = Vec::new();
-        loop {
-            let len = inner.inode.read_at(inner.offset, &mut buffer);
-            if len == 0 {
-                break;
-            }
-            inner.offset += len;
-            v.extend_from_slice(&buffer[..len]);
-        }
-        v
-    }
 }
 
 lazy_static! {
     pub static ref ROOT_INODE: Arc<Inode> = {
+        set_clock(get_real_time_sec);
         let efs = EasyFileSystem::open(BLOCK_DEVICE.clone());
         Arc::new(EasyFileSystem::root_inode(&efs))
     };
@@ -53,7 +42,9 @@ lazy_static! {
 pub fn list_apps() {
     println!("/**** APPS ****");
     for app in ROOT_INODE.ls() {
-        println!("{}", app);
+        if app != "." && app != ".." {
+            println!("{}", app);
+        }
     }
     println!("**************/")
 }
@@ -82,27 +73,204 @@ impl OpenFlags {
     }
 }
 
-pub fn open_file(name: &str, flags: OpenFlags) -> Option<Arc<OSInode>> {
+/// Split a path into its parent part and last component, e.g. "/a/b/c" -> ("/a/b/", "c").
+fn split_path(path: &str) -> (&str, &str) {
+    let path = path.trim_end_matches('/');
+    match path.rfind('/') {
+        Some(idx) => (&path[..=idx], &path[idx + 1..]),
+        None => ("", path),
+    }
+}
+
+/// Resolve `path` against the working directory `cwd` into an absolute path
+/// without "." components, e.g. ("/a/b", "./../c") -> "/a/b/../c".
+///
+/// ".." is kept, since it leads to the parent of the directory a symlink
+/// before it refers to, rather than to the directory holding the symlink.
+pub fn absolute_path(cwd: &str, path: &str) -> String {
+    let base = if path.starts_with('/') { "" } else { cwd };
+    let names = base
+        .split('/')
+        .chain(path.split('/'))
+        .filter(|name| !name.is_empty() && *name != ".");
+    let mut abs_path = String::new();
+    for name in names {
+        abs_path.push('/');
+        abs_path.push_str(name);
+    }
+    if abs_path.is_empty() {
+        abs_path.push('/');
+    }
+    abs_path
+}
+
+/// Paths given to the functions below are resolved from the root directory,
+/// following symlinks.
+fn find_inode(path: &str) -> Option<Arc<Inode>> {
+    ROOT_INODE.find_path(path)
+}
+
+/// As `find_inode`, but a symlink at the end of `path` is not followed.
+fn find_inode_nofollow(path: &str) -> Option<Arc<Inode>> {
+    match find_parent(path)? {
+        (parent, "") => Some(parent),
+        (parent, name) => parent.find(name),
+    }
+}
+
+/// Return the directory that holds the last component of `path` and that component.
+fn find_parent(path: &str) -> Option<(Arc<Inode>, &str)> {
+    let (parent, name) = split_path(path);
+    find_inode(parent).map(|inode| (inode, name))
+}
+
+pub fn open_file(path: &str, flags: OpenFlags) -> Option<Arc<OSInode>> {
     let (readable, writable) = flags.read_write();
-    if flags.contains(OpenFlags::CREATE) {
-        if let Some(inode) = ROOT_INODE.find(name) {
-            // clear size
-            inode.clear();
-            Some(Arc::new(OSInode::new(readable, writable, inode)))
+    let inode = if flags.contains(OpenFlags::CREATE) {
+        if let Some(inode) = find_inode(path) {
+            inode
         } else {
             // create file
-            ROOT_INODE
-                .create(name)
-                .map(|inode| Arc::new(OSInode::new(readable, writable, inode)))
+            let (parent, name) = find_parent(path)?;
+            parent.create(name)?
         }
     } else {
-        ROOT_INODE.find(name).map(|inode| {
-            if flags.contains(OpenFlags::TRUNC) {
-                inode.clear();
-            }
-            Arc::new(OSInode::new(readable, writable, inode))
-        })
+        find_inode(path)?
+    };
+    // a socket file is reached by connecting to it
+    if inode.is_socket() {
+        return None;
     }
+    if inode.is_dir() {
+        // a directory can only be opened for reading its entries
+        if writable || flags.intersects(OpenFlags::CREATE | OpenFlags::TRUNC) {
+            return None;
+        }
+    } else if flags.intersects(OpenFlags::CREATE | OpenFlags::TRUNC) {
+        // clear size
+        inode.clear();
+    }
+    Some(Arc::new(OSInode::new(readable, writable, inode)))
+}
+
+/// The metadata of the file at `path`, or of the symlink itself there
+/// unless `follow_symlink`.
+pub fn stat(path: &str, follow_symlink: bool) -> Option<Stat> {
+    let inode = if follow_symlink {
+        find_inode(path)
+    } else {
+        find_inode_nofollow(path)
+    };
+    inode.map(|inode| Stat::from_metadata(&inode.metadata()))
+}
+
+/// The path of the directory at `path` without symlinks or "..", found by
+/// walking up through "..". It is kept as the working directory.
+pub fn real_dir_path(path: &str) -> Option<String> {
+    let mut dir = find_inode(path).filter(|inode| inode.is_dir())?;
+    let mut names = Vec::new();
+    while dir.inode_id() != ROOT_INODE.inode_id() {
+        let parent = dir.find("..")?;
+        // a directory has a single name since it can not be hard linked
+        let name = parent.ls().into_iter().find(|name| {
+            name != "."
+                && name != ".."
+                && parent
+                    .find(name)
+                    .is_some_and(|child| child.inode_id() == dir.inode_id())
+        })?;
+        names.push(name);
+        dir = parent;
+    }
+    let mut real_path = String::new();
+    for name in names.iter().rev() {
+        real_path.push('/');
+        real_path.push_str(name);
+    }
+    if real_path.is_empty() {
+        real_path.push('/');
+    }
+    Some(real_path)
+}
+
+pub fn mkdir(path: &str) -> bool {
+    find_parent(path).is_some_and(|(parent, name)| parent.mkdir(name).is_some())
+}
+
+/// Create the socket file at `path`, return its inode id.
+pub fn make_socket_file(path: &str) -> Option<u32> {
+    let (parent, name) = find_parent(path)?;
+    parent.create_socket(name).map(|inode| inode.inode_id())
+}
+
+/// The inode id of the socket file at `path`.
+pub fn find_socket_file(path: &str) -> Option<u32> {
+    find_inode(path)
+        .filter(|inode| inode.is_socket())
+        .map(|inode| inode.inode_id())
+}
+
+/// Add `new_path` referring to the file at `old_path`, which is not
+/// followed if it is a symlink.
+pub fn link(old_path: &str, new_path: &str) -> bool {
+    match (find_inode_nofollow(old_path), find_parent(new_path)) {
+        (Some(inode), Some((parent, name))) => parent.link(name, &inode),
+        _ => false,
+    }
+}
+
+pub fn symlink(target: &str, path: &str) -> bool {
+    find_parent(path).is_some_and(|(parent, name)| parent.symlink(name, target).is_some())
+}
+
+pub fn readlink(path: &str) -> Option<String> {
+    find_inode_nofollow(path)?.readlink()
+}
+
+/// Shrink or grow the file at `path` to `len`, directories are refused.
+pub fn truncate(path: &str, len: u32) -> bool {
+    find_inode(path).is_some_and(|inode| inode.truncate(len))
+}
+
+pub fn unlink(path: &str) -> bool {
+    find_parent(path).is_some_and(|(parent, name)| parent.unlink(name))
+}
+
+pub fn rmdir(path: &str) -> bool {
+    find_parent(path).is_some_and(|(parent, name)| parent.rmdir(name))
+}
+
+pub fn rename(old_path: &str, new_path: &str) -> bool {
+    match (find_parent(old_path), find_parent(new_path)) {
+        (Some((old_parent, old_name)), Some((new_parent, new_name))) => {
+            old_parent.rename(old_name, &new_parent, new_name)
+        }
+        _ => false,
+    }
+}
+
+/// Read from `offset` of `inode` into `buf`, return the size read.
+fn read_buf(inode: &Inode, offset: usize, mut buf: UserBuffer) -> usize {
+    let mut total_read_size = 0usize;
+    for slice in buf.buffers.iter_mut() {
+        let read_size = inode.read_at(offset + total_read_size, *slice);
+        if read_size == 0 {
+            break;
+        }
+        total_read_size += read_size;
+    }
+    total_read_size
+}
+
+/// Write `buf` to `offset` of `inode`, which grows as needed.
+fn write_buf(inode: &Inode, offset: usize, buf: UserBuffer) -> usize {
+    let mut total_write_size = 0usize;
+    for slice in buf.buffers.iter() {
+        let write_size = inode.write_at(offset + total_write_size, *slice);
+        assert_eq!(write_size, slice.len());
+        total_write_size += write_size;
+    }
+    total_write_size
 }
 
 impl File for OSInode {
@@ -112,28 +280,38 @@ impl File for OSInode {
     fn writable(&self) -> bool {
         self.writable
     }
-    fn read(&self, mut buf: UserBuffer) -> usize {
+    fn read(&self, buf: UserBuffer) -> usize {
         let mut inner = self.inner.exclusive_access();
-        let mut total_read_size = 0usize;
-        for slice in buf.buffers.iter_mut() {
-            let read_size = inner.inode.read_at(inner.offset, *slice);
-            if read_size == 0 {
-                break;
-            }
-            inner.offset += read_size;
-            total_read_size += read_size;
-        }
-        total_read_size
+        let read_size = read_buf(&inner.inode, inner.offset, buf);
+        inner.offset += read_size;
+        read_size
     }
     fn write(&self, buf: UserBuffer) -> usize {
         let mut inner = self.inner.exclusive_access();
-        let mut total_write_size = 0usize;
-        for slice in buf.buffers.iter() {
-            let write_size = inner.inode.write_at(inner.offset, *slice);
-            assert_eq!(write_size, slice.len());
-            inner.offset += write_size;
-            total_write_size += write_size;
-        }
-        total_write_size
+        let write_size = write_buf(&inner.inode, inner.offset, buf);
+        inner.offset += write_size;
+        write_size
+    }
+    /// The offset may move past the end, a write there grows the file.
+    fn seek(&self, pos: SeekFrom) -> Option<usize> {
+        let mut inner = self.inner.exclusive_access();
+        let offset = match pos {
+            SeekFrom::Start(offset) => offset,
+            SeekFrom::Current(delta) => inner.offset.checked_add_signed(delta)?,
+            SeekFrom::End(delta) => inner.inode.size().checked_add_signed(delta)?,
+        };
+        inner.offset = offset;
+        Some(offset)
+    }
+    fn read_at(&self, offset: usize, buf: UserBuffer) -> Option<usize> {
+        let inode = self.inner.exclusive_access().inode.clone();
+        Some(read_buf(&inode, offset, buf))
+    }
+    fn write_at(&self, offset: usize, buf: UserBuffer) -> Option<usize> {
+        let inode = self.inner.exclusive_access().inode.clone();
+        Some(write_buf(&inode, offset, buf))
+    }
+    fn inode(&self) -> Option<Arc<Inode>> {
+        Some(self.inner.exclusive_access().inode.clone())
     }
 }
@@ -0,0 +1,75 @@
+use super::{File, PollEvents, S_IFCHR, Stat};
+use crate::drivers::{KEYBOARD_DEVICE, MOUSE_DEVICE};
+use crate::mm::UserBuffer;
+use crate::sync::PollWaiter;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+const EVENT_SIZE: usize = core::mem::size_of::<u64>();
+
+/// The events of the keyboard and the mouse, read as u64 values in the
+/// format of `sys_event_get`. A read waits for an event, and returns as
+/// many whole events as are ready and fit.
+pub struct InputEvents;
+
+impl InputEvents {
+    fn try_read_event(&self) -> Option<u64> {
+        KEYBOARD_DEVICE
+            .try_read_event()
+            .or_else(|| MOUSE_DEVICE.try_read_event())
+    }
+}
+
+impl File for InputEvents {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    fn read(&self, buf: UserBuffer) -> usize {
+        let count = buf.len() / EVENT_SIZE;
+        if count == 0 {
+            return 0;
+        }
+        let mut events = Vec::with_capacity(count);
+        while events.is_empty() {
+            // registered before checking, so no event is missed
+            let waiter = PollWaiter::new();
+            self.register_waiter(&waiter);
+            while events.len() < count {
+                match self.try_read_event() {
+                    Some(event) => events.push(event),
+                    None => break,
+                }
+            }
+            if events.is_empty() {
+                waiter.wait();
+            }
+        }
+        let bytes = events.iter().flat_map(|event| event.to_ne_bytes());
+        for (dst, byte) in buf.into_iter().zip(bytes) {
+            unsafe {
+                *dst = byte;
+            }
+        }
+        events.len() * EVENT_SIZE
+    }
+    fn write(&self, _buf: UserBuffer) -> usize {
+        0
+    }
+    fn poll_ready(&self) -> PollEvents {
+        if KEYBOARD_DEVICE.is_empty() && MOUSE_DEVICE.is_empty() {
+            PollEvents::empty()
+        } else {
+            PollEvents::IN
+        }
+    }
+    fn register_waiter(&self, waiter: &Arc<PollWaiter>) {
+        KEYBOARD_DEVICE.register_poller(waiter);
+        MOUSE_DEVICE.register_poller(waiter);
+    }
+    fn stat(&self) -> Stat {
+        Stat::special(S_IFCHR)
+    }
+}
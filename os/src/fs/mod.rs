@@ -1,16 +1,85 @@
 mod inode;
+mod input;
 mod pipe;
+mod poll;
+mod stat;
 mod stdio;
+mod unix;
 
 use crate::mm::UserBuffer;
+use crate::net::Socket;
+use crate::sync::PollWaiter;
+use alloc::sync::Arc;
+use easy_fs::Inode;
+
+/// Where the offset given to `File::seek` counts from.
+pub enum SeekFrom {
+    Start(usize),
+    Current(isize),
+    End(isize),
+}
 
 pub trait File: Send + Sync {
     fn readable(&self) -> bool;
     fn writable(&self) -> bool;
     fn read(&self, buf: UserBuffer) -> usize;
     fn write(&self, buf: UserBuffer) -> usize;
+    /// Move the offset of the file and return it, None if the file is
+    /// not seekable or the offset would be negative.
+    fn seek(&self, _pos: SeekFrom) -> Option<usize> {
+        None
+    }
+    /// Read at `offset` leaving the offset of the file alone, None if the
+    /// file is not seekable.
+    fn read_at(&self, _offset: usize, _buf: UserBuffer) -> Option<usize> {
+        None
+    }
+    /// Write at `offset` leaving the offset of the file alone, None if the
+    /// file is not seekable.
+    fn write_at(&self, _offset: usize, _buf: UserBuffer) -> Option<usize> {
+        None
+    }
+    /// The easy-fs inode behind the file, if there is one.
+    fn inode(&self) -> Option<Arc<Inode>> {
+        None
+    }
+    /// The socket operations, if the file is a socket.
+    fn socket(&self) -> Option<&dyn Socket> {
+        None
+    }
+    /// The metadata of the inode behind the file, other files only tell
+    /// their type.
+    fn stat(&self) -> Stat {
+        match self.inode() {
+            Some(inode) => Stat::from_metadata(&inode.metadata()),
+            None if self.socket().is_some() => Stat::special(S_IFSOCK),
+            None => Stat::special(0),
+        }
+    }
+    /// The events ready now, a file which never blocks is always ready
+    /// to be read or written as it is opened.
+    fn poll_ready(&self) -> PollEvents {
+        let mut events = PollEvents::empty();
+        events.set(PollEvents::IN, self.readable());
+        events.set(PollEvents::OUT, self.writable());
+        events
+    }
+    /// Have `waiter` woken when the events ready may change, a file
+    /// which never blocks has nothing to register.
+    fn register_waiter(&self, _waiter: &Arc<PollWaiter>) {}
+    /// The interest list, if the file is an epoll instance.
+    fn epoll(&self) -> Option<&Epoll> {
+        None
+    }
 }
 
-pub use inode::{OpenFlags, list_apps, open_file};
+pub use inode::{
+    OpenFlags, absolute_path, link, list_apps, mkdir, open_file, readlink, real_dir_path, rename,
+    rmdir, stat, symlink, truncate, unlink,
+};
+pub use input::InputEvents;
 pub use pipe::make_pipe;
+pub use poll::{Epoll, PollEvents, wait_ready};
+pub use stat::{S_IFCHR, S_IFIFO, S_IFSOCK, Stat};
 pub use stdio::{Stdin, Stdout};
+pub use unix::{UnixSocket, UnixType, make_socket_pair};
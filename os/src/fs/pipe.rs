@@ -1,6 +1,6 @@
-use super::File;
+use super::{File, PollEvents, S_IFIFO, Stat};
 use crate::mm::UserBuffer;
-use crate::sync::UPIntrFreeCell;
+use crate::sync::{Condvar, PollWaiter, SpinIntrFreeCell};
 use alloc::sync::{Arc, Weak};
 
 use crate::task::suspend_current_and_run_next;
@@ -8,18 +8,18 @@ use crate::task::suspend_current_and_run_next;
 pub struct Pipe {
     readable: bool,
     writable: bool,
-    buffer: Arc<UPIntrFreeCell<PipeRingBuffer>>,
+    buffer: Arc<SpinIntrFreeCell<PipeRingBuffer>>,
 }
 
 impl Pipe {
-    pub fn read_end_with_buffer(buffer: Arc<UPIntrFreeCell<PipeRingBuffer>>) -> Self {
+    pub fn read_end_with_buffer(buffer: Arc<SpinIntrFreeCell<PipeRingBuffer>>) -> Self {
         Self {
             readable: true,
             writable: false,
             buffer,
         }
     }
-    pub fn write_end_with_buffer(buffer: Arc<UPIntrFreeCell<PipeRingBuffer>>) -> Self {
+    pub fn write_end_with_buffer(buffer: Arc<SpinIntrFreeCell<PipeRingBuffer>>) -> Self {
         Self {
             readable: false,
             writable: true,
@@ -43,6 +43,8 @@ pub struct PipeRingBuffer {
     tail: usize,
     status: RingBufferStatus,
     write_end: Option<Weak<Pipe>>,
+    /// only polls wait on it, readers and writers yield
+    condvar: Condvar,
 }
 
 impl PipeRingBuffer {
@@ -53,6 +55,7 @@ impl PipeRingBuffer {
             tail: 0,
             status: RingBufferStatus::Empty,
             write_end: None,
+            condvar: Condvar::new(),
         }
     }
     pub fn set_write_end(&mut self, write_end: &Arc<Pipe>) {
@@ -98,7 +101,7 @@ impl PipeRingBuffer {
 
 /// Return (read_end, write_end)
 pub fn make_pipe() -> (Arc<Pipe>, Arc<Pipe>) {
-    let buffer = Arc::new(unsafe { UPIntrFreeCell::new(PipeRingBuffer::new()) });
+    let buffer = Arc::new(unsafe { SpinIntrFreeCell::new(PipeRingBuffer::new()) });
     let read_end = Arc::new(Pipe::read_end_with_buffer(buffer.clone()));
     let write_end = Arc::new(Pipe::write_end_with_buffer(buffer.clone()));
     buffer.exclusive_access().set_write_end(&write_end);
@@ -135,12 +138,15 @@ impl File for Pipe {
                     }
                     already_read += 1;
                     if already_read == want_to_read {
+                        ring_buffer.condvar.broadcast();
                         return want_to_read;
                     }
                 } else {
+                    ring_buffer.condvar.broadcast();
                     return already_read;
                 }
             }
+            ring_buffer.condvar.broadcast();
         }
     }
     fn write(&self, buf: UserBuffer) -> usize {
@@ -162,12 +168,50 @@ impl File for Pipe {
                     ring_buffer.write_byte(unsafe { *byte_ref });
                     already_write += 1;
                     if already_write == want_to_write {
+                        ring_buffer.condvar.broadcast();
                         return want_to_write;
                     }
                 } else {
+                    ring_buffer.condvar.broadcast();
                     return already_write;
                 }
             }
+            ring_buffer.condvar.broadcast();
+        }
+    }
+    /// The read end is hung up once the write end is closed and the data
+    /// is read. The write end is ready when there is room.
+    fn poll_ready(&self) -> PollEvents {
+        let ring_buffer = self.buffer.exclusive_access();
+        let mut events = PollEvents::empty();
+        if self.readable {
+            if ring_buffer.available_read() > 0 {
+                events |= PollEvents::IN;
+            } else if ring_buffer.all_write_ends_closed() {
+                events |= PollEvents::HUP;
+            }
+        }
+        if self.writable && ring_buffer.available_write() > 0 {
+            events |= PollEvents::OUT;
+        }
+        events
+    }
+    fn register_waiter(&self, waiter: &Arc<PollWaiter>) {
+        self.buffer
+            .exclusive_access()
+            .condvar
+            .register_poller(waiter);
+    }
+    fn stat(&self) -> Stat {
+        Stat::special(S_IFIFO)
+    }
+}
+
+impl Drop for Pipe {
+    /// Polls of the read end see the hang up.
+    fn drop(&mut self) {
+        if self.writable {
+            self.buffer.exclusive_access().condvar.broadcast();
         }
     }
 }
@@ -0,0 +1,200 @@
+//! Waiting for any of several files to become ready, shared by poll,
+//! select and epoll. A poll registers a waiter on every file, checks them,
+//! and sleeps until a file or the timeout wakes the waiter.
+
+use super::File;
+use crate::mm::UserBuffer;
+use crate::sync::{PollWaiter, SpinIntrFreeCell};
+use crate::timer::{add_poll_timer, get_time_ms};
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use bitflags::*;
+
+bitflags! {
+    /// The events of poll, epoll uses the same bits.
+    pub struct PollEvents: u16 {
+        /// a read does not block
+        const IN = 1 << 0;
+        const PRI = 1 << 1;
+        /// a write does not block
+        const OUT = 1 << 2;
+        /// only reported, never asked
+        const ERR = 1 << 3;
+        /// the peer or the writers are gone, only reported
+        const HUP = 1 << 4;
+        /// the fd is not open, only reported by poll
+        const NVAL = 1 << 5;
+    }
+}
+
+impl PollEvents {
+    /// The events reported whether they are asked or not.
+    pub fn always() -> Self {
+        Self::ERR | Self::HUP
+    }
+}
+
+/// Wait until `check` finds something ready among `files`, it is called
+/// after the files have registered a waiter, so nothing ready is missed.
+/// Return None if `timeout_ms` passes first, or at once for a timeout of 0.
+pub fn wait_ready<T>(
+    files: &[Arc<dyn File>],
+    timeout_ms: Option<usize>,
+    mut check: impl FnMut() -> Option<T>,
+) -> Option<T> {
+    let expire_ms = timeout_ms.map(|timeout_ms| get_time_ms() + timeout_ms);
+    loop {
+        let waiter = PollWaiter::new();
+        for file in files {
+            file.register_waiter(&waiter);
+        }
+        if let Some(ready) = check() {
+            return Some(ready);
+        }
+        match expire_ms {
+            Some(expire_ms) if get_time_ms() >= expire_ms => return None,
+            Some(expire_ms) => add_poll_timer(expire_ms, waiter.clone()),
+            None => {}
+        }
+        waiter.wait();
+    }
+}
+
+/// The events of interest of a file and the data reported with them.
+struct Interest {
+    /// the file is not kept open by epoll, the interest is dropped with it
+    file: Weak<dyn File>,
+    events: PollEvents,
+    data: u64,
+}
+
+/// The interest list of an epoll instance, by fd. Only level-triggered
+/// notification is supported, and an epoll instance may not be watched
+/// by another.
+pub struct Epoll {
+    interests: SpinIntrFreeCell<BTreeMap<usize, Interest>>,
+}
+
+impl Epoll {
+    pub fn new() -> Self {
+        Self {
+            interests: unsafe { SpinIntrFreeCell::new(BTreeMap::new()) },
+        }
+    }
+
+    /// Watch `events` of `file` at `fd`, fail if the fd is watched already.
+    pub fn add(&self, fd: usize, file: &Arc<dyn File>, events: PollEvents, data: u64) -> bool {
+        if file.epoll().is_some() {
+            return false;
+        }
+        let mut interests = self.interests.exclusive_access();
+        // the file watched may have been closed and the fd reused
+        if interests
+            .get(&fd)
+            .is_some_and(|interest| interest.file.strong_count() > 0)
+        {
+            return false;
+        }
+        interests.insert(
+            fd,
+            Interest {
+                file: Arc::downgrade(file),
+                events,
+                data,
+            },
+        );
+        true
+    }
+
+    pub fn modify(&self, fd: usize, file: &Arc<dyn File>, events: PollEvents, data: u64) -> bool {
+        let mut interests = self.interests.exclusive_access();
+        match interests.get_mut(&fd) {
+            Some(interest) if interest.file.ptr_eq(&Arc::downgrade(file)) => {
+                interest.events = events;
+                interest.data = data;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn delete(&self, fd: usize, file: &Arc<dyn File>) -> bool {
+        let mut interests = self.interests.exclusive_access();
+        match interests.get(&fd) {
+            Some(interest) if interest.file.ptr_eq(&Arc::downgrade(file)) => {
+                interests.remove(&fd);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// The files watched, the interests of closed files are dropped.
+    pub fn files(&self) -> Vec<Arc<dyn File>> {
+        let mut interests = self.interests.exclusive_access();
+        interests.retain(|_, interest| interest.file.strong_count() > 0);
+        interests
+            .values()
+            .filter_map(|interest| interest.file.upgrade())
+            .collect()
+    }
+
+    /// The events ready with their data, at most `max` of them.
+    pub fn ready(&self, max: usize) -> Vec<(PollEvents, u64)> {
+        // the files are checked with the interest list unlocked
+        let interests: Vec<_> = self.interests.exclusive_session(|interests| {
+            interests
+                .values()
+                .filter_map(|interest| {
+                    Some((interest.file.upgrade()?, interest.events, interest.data))
+                })
+                .collect()
+        });
+        interests
+            .into_iter()
+            .filter_map(|(file, events, data)| {
+                let ready = file.poll_ready() & (events | PollEvents::always());
+                (!ready.is_empty()).then_some((ready, data))
+            })
+            .take(max)
+            .collect()
+    }
+}
+
+impl File for Epoll {
+    fn readable(&self) -> bool {
+        false
+    }
+
+    fn writable(&self) -> bool {
+        false
+    }
+
+    fn read(&self, _buf: UserBuffer) -> usize {
+        0
+    }
+
+    fn write(&self, _buf: UserBuffer) -> usize {
+        0
+    }
+
+    /// Readable when some file watched is ready.
+    fn poll_ready(&self) -> PollEvents {
+        if self.ready(1).is_empty() {
+            PollEvents::empty()
+        } else {
+            PollEvents::IN
+        }
+    }
+
+    fn register_waiter(&self, waiter: &Arc<PollWaiter>) {
+        for file in self.files() {
+            file.register_waiter(waiter);
+        }
+    }
+
+    fn epoll(&self) -> Option<&Epoll> {
+        Some(self)
+    }
+}
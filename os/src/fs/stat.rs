@@ -0,0 +1,74 @@
+//! The metadata reported by `fstat` and `stat`.
+
+use easy_fs::{DiskInodeType, Metadata};
+
+/// The file types in `Stat::mode`, above the permission bits.
+pub const S_IFIFO: u32 = 0o010000;
+pub const S_IFCHR: u32 = 0o020000;
+pub const S_IFDIR: u32 = 0o040000;
+pub const S_IFREG: u32 = 0o100000;
+pub const S_IFLNK: u32 = 0o120000;
+pub const S_IFSOCK: u32 = 0o140000;
+
+/// Files are stored in blocks of this size, which `Stat::blocks` counts.
+const STAT_BLOCK_SIZE: u32 = 512;
+
+/// `struct stat` of Linux on RISC-V, times are in seconds since the epoch.
+#[repr(C)]
+#[derive(Default)]
+pub struct Stat {
+    pub dev: u64,
+    pub ino: u64,
+    pub mode: u32,
+    pub nlink: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub rdev: u64,
+    __pad1: u64,
+    pub size: i64,
+    pub blksize: u32,
+    __pad2: u32,
+    pub blocks: u64,
+    pub atime: i64,
+    pub atime_nsec: u64,
+    pub mtime: i64,
+    pub mtime_nsec: u64,
+    pub ctime: i64,
+    pub ctime_nsec: u64,
+    __unused: [u32; 2],
+}
+
+impl Stat {
+    /// A file of type `mode` without an inode, such as a pipe.
+    pub fn special(mode: u32) -> Self {
+        Self {
+            mode: mode | 0o600,
+            nlink: 1,
+            blksize: STAT_BLOCK_SIZE,
+            ..Default::default()
+        }
+    }
+
+    pub fn from_metadata(metadata: &Metadata) -> Self {
+        let file_type = match metadata.type_ {
+            DiskInodeType::File => S_IFREG,
+            DiskInodeType::Directory => S_IFDIR,
+            DiskInodeType::Socket => S_IFSOCK,
+            DiskInodeType::Symlink => S_IFLNK,
+        };
+        Self {
+            ino: metadata.inode_id as u64,
+            mode: file_type | metadata.mode as u32,
+            nlink: metadata.nlink as u32,
+            uid: metadata.uid,
+            gid: metadata.gid,
+            size: metadata.size as i64,
+            blksize: STAT_BLOCK_SIZE,
+            blocks: metadata.blocks as u64,
+            atime: metadata.atime as i64,
+            mtime: metadata.mtime as i64,
+            ctime: metadata.ctime as i64,
+            ..Default::default()
+        }
+    }
+}
@@ -1,7 +1,9 @@
-use super::File;
+use super::{File, PollEvents, S_IFCHR, Stat};
 use crate::drivers::chardev::CharDevice;
 use crate::drivers::chardev::UART;
 use crate::mm::UserBuffer;
+use crate::sync::PollWaiter;
+use alloc::sync::Arc;
 
 pub struct Stdin;
 pub struct Stdout;
@@ -25,6 +27,19 @@ impl File for Stdin {
     fn write(&self, _user_buf: UserBuffer) -> usize {
         panic!("Cannot write to stdin!");
     }
+    fn poll_ready(&self) -> PollEvents {
+        if UART.read_buffer_is_empty() {
+            PollEvents::empty()
+        } else {
+            PollEvents::IN
+        }
+    }
+    fn register_waiter(&self, waiter: &Arc<PollWaiter>) {
+        UART.register_poller(waiter);
+    }
+    fn stat(&self) -> Stat {
+        Stat::special(S_IFCHR)
+    }
 }
 
 impl File for Stdout {
@@ -43,4 +58,7 @@ impl File for Stdout {
         }
         user_buf.len()
     }
+    fn stat(&self) -> Stat {
+        Stat::special(S_IFCHR)
+    }
 }
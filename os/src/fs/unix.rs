@@ -0,0 +1,715 @@
+//! Unix domain sockets, for bidirectional IPC between processes of this
+//! host. A socket is named by a socket file of easy-fs, and open files can
+//! be passed along with the data, as SCM_RIGHTS of Linux does.
+
+use super::inode::{find_socket_file, make_socket_file};
+use super::{File, PollEvents};
+use crate::mm::UserBuffer;
+use crate::net::{Shutdown, SockAddr, Socket};
+use crate::sync::{Condvar, PollWaiter, SpinIntrFreeCell};
+use crate::task::schedule;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// Bytes a queue holds at most, writers wait for readers beyond it.
+const QUEUE_CAPACITY: usize = 16 * 1024;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum UnixType {
+    Stream,
+    Datagram,
+}
+
+/// Data written at once, with the files passed along.
+struct Message {
+    data: Vec<u8>,
+    /// bytes of `data` read already, a stream reads a message in parts
+    offset: usize,
+    files: Vec<Arc<dyn File>>,
+    /// the name of the sender of a datagram
+    source: Option<String>,
+}
+
+struct QueueInner {
+    messages: VecDeque<Message>,
+    /// bytes of the messages not read yet
+    len: usize,
+    /// no more messages come, readers get the end of file
+    write_closed: bool,
+    /// nobody reads the messages any more, writers fail
+    read_closed: bool,
+    /// readers and writers wait on it
+    condvar: Condvar,
+}
+
+/// Messages of one direction, to a stream socket or to a datagram socket.
+struct Queue {
+    inner: SpinIntrFreeCell<QueueInner>,
+}
+
+impl Queue {
+    fn new() -> Arc<Self> {
+        Arc::new(Self {
+            inner: unsafe {
+                SpinIntrFreeCell::new(QueueInner {
+                    messages: VecDeque::new(),
+                    len: 0,
+                    write_closed: false,
+                    read_closed: false,
+                    condvar: Condvar::new(),
+                })
+            },
+        })
+    }
+
+    /// Append a message, waiting for room. A message larger than the
+    /// capacity goes into an empty queue. Return false if nobody reads it.
+    fn push(&self, message: Message) -> bool {
+        loop {
+            let mut inner = self.inner.exclusive_access();
+            if inner.read_closed {
+                return false;
+            }
+            if inner.len == 0 || inner.len + message.data.len() <= QUEUE_CAPACITY {
+                inner.len += message.data.len();
+                inner.messages.push_back(message);
+                inner.condvar.broadcast();
+                return true;
+            }
+            let task_cx_ptr = inner.condvar.wait_no_sched();
+            drop(inner);
+            schedule(task_cx_ptr);
+        }
+    }
+
+    /// Take data, waiting for a message. A datagram is taken whole and
+    /// truncated to the buffer, while a stream read goes on to the next
+    /// messages, but stops before one passing files. 0 is returned at the
+    /// end of file.
+    fn pop(&self, buf: UserBuffer, stream: bool) -> (usize, Vec<Arc<dyn File>>, Option<String>) {
+        let mut guard = loop {
+            let inner = self.inner.exclusive_access();
+            if !inner.messages.is_empty() || inner.write_closed || inner.read_closed {
+                break inner;
+            }
+            let task_cx_ptr = inner.condvar.wait_no_sched();
+            drop(inner);
+            schedule(task_cx_ptr);
+        };
+        let inner = &mut *guard;
+        let mut dst = buf.into_iter();
+        let mut len = 0;
+        let mut files = Vec::new();
+        let mut source = None;
+        while let Some(message) = inner.messages.front_mut() {
+            if len > 0 && !message.files.is_empty() {
+                break;
+            }
+            files.append(&mut message.files);
+            source = message.source.take();
+            let remaining = message.data.len() - message.offset;
+            let mut copied = 0;
+            for (byte, ptr) in message.data[message.offset..].iter().zip(&mut dst) {
+                unsafe {
+                    *ptr = *byte;
+                }
+                copied += 1;
+            }
+            len += copied;
+            if stream && copied < remaining {
+                message.offset += copied;
+                inner.len -= copied;
+                break;
+            }
+            inner.len -= remaining;
+            inner.messages.pop_front();
+            if !stream {
+                break;
+            }
+        }
+        // writers may wait for room
+        inner.condvar.broadcast();
+        (len, files, source)
+    }
+
+    fn close_write(&self) {
+        let mut inner = self.inner.exclusive_access();
+        inner.write_closed = true;
+        inner.condvar.broadcast();
+    }
+
+    /// Whether a pop does not wait.
+    fn can_pop(&self) -> bool {
+        let inner = self.inner.exclusive_access();
+        !inner.messages.is_empty() || inner.write_closed || inner.read_closed
+    }
+
+    /// Whether a push of a small message does not wait.
+    fn can_push(&self) -> bool {
+        let inner = self.inner.exclusive_access();
+        !inner.read_closed && inner.len < QUEUE_CAPACITY
+    }
+
+    /// Whether no more messages come.
+    fn is_write_closed(&self) -> bool {
+        self.inner.exclusive_access().write_closed
+    }
+
+    fn is_read_closed(&self) -> bool {
+        self.inner.exclusive_access().read_closed
+    }
+
+    fn register_poller(&self, waiter: &Arc<PollWaiter>) {
+        self.inner
+            .exclusive_access()
+            .condvar
+            .register_poller(waiter);
+    }
+
+    /// Drop the messages queued, and those sent later.
+    fn close_read(&self) {
+        let messages = {
+            let mut inner = self.inner.exclusive_access();
+            inner.read_closed = true;
+            inner.len = 0;
+            inner.condvar.broadcast();
+            core::mem::take(&mut inner.messages)
+        };
+        // the files passed may be sockets whose drop comes back to this queue
+        drop(messages);
+    }
+}
+
+struct BacklogInner {
+    connections: VecDeque<Arc<UnixSocket>>,
+    limit: usize,
+    closed: bool,
+    condvar: Condvar,
+}
+
+/// Connections of a listening socket waiting to be accepted.
+struct Backlog {
+    inner: SpinIntrFreeCell<BacklogInner>,
+}
+
+impl Backlog {
+    fn new(limit: usize) -> Arc<Self> {
+        Arc::new(Self {
+            inner: unsafe {
+                SpinIntrFreeCell::new(BacklogInner {
+                    connections: VecDeque::new(),
+                    limit: limit.max(1),
+                    closed: false,
+                    condvar: Condvar::new(),
+                })
+            },
+        })
+    }
+
+    /// Return false if the backlog is full or the listener is closed.
+    fn push(&self, connection: Arc<UnixSocket>) -> bool {
+        let mut inner = self.inner.exclusive_access();
+        if inner.closed || inner.connections.len() >= inner.limit {
+            return false;
+        }
+        inner.connections.push_back(connection);
+        inner.condvar.signal();
+        true
+    }
+
+    /// Whether a pop does not wait.
+    fn can_pop(&self) -> bool {
+        let inner = self.inner.exclusive_access();
+        !inner.connections.is_empty() || inner.closed
+    }
+
+    fn register_poller(&self, waiter: &Arc<PollWaiter>) {
+        self.inner
+            .exclusive_access()
+            .condvar
+            .register_poller(waiter);
+    }
+
+    /// Wait for a connection, None if the listener is closed.
+    fn pop(&self) -> Option<Arc<UnixSocket>> {
+        loop {
+            let mut inner = self.inner.exclusive_access();
+            if let Some(connection) = inner.connections.pop_front() {
+                return Some(connection);
+            }
+            if inner.closed {
+                return None;
+            }
+            let task_cx_ptr = inner.condvar.wait_no_sched();
+            drop(inner);
+            schedule(task_cx_ptr);
+        }
+    }
+
+    /// The connections not accepted are closed.
+    fn close(&self) {
+        let connections = {
+            let mut inner = self.inner.exclusive_access();
+            inner.closed = true;
+            inner.condvar.broadcast();
+            core::mem::take(&mut inner.connections)
+        };
+        drop(connections);
+    }
+}
+
+/// What a socket file names.
+enum Binding {
+    Listener(Weak<Backlog>),
+    Datagram(Weak<Queue>),
+}
+
+lazy_static! {
+    /// Bound sockets by the inode ids of their socket files.
+    static ref BINDINGS: SpinIntrFreeCell<BTreeMap<u32, Binding>> =
+        unsafe { SpinIntrFreeCell::new(BTreeMap::new()) };
+}
+
+/// The listener named by `path`.
+fn find_listener(path: &str) -> Option<Arc<Backlog>> {
+    let inode_id = find_socket_file(path)?;
+    match BINDINGS.exclusive_access().get(&inode_id)? {
+        Binding::Listener(backlog) => backlog.upgrade(),
+        Binding::Datagram(_) => None,
+    }
+}
+
+/// The queue of the datagram socket named by `path`.
+fn find_datagram(path: &str) -> Option<Arc<Queue>> {
+    let inode_id = find_socket_file(path)?;
+    match BINDINGS.exclusive_access().get(&inode_id)? {
+        Binding::Datagram(queue) => queue.upgrade(),
+        Binding::Listener(_) => None,
+    }
+}
+
+#[derive(Clone)]
+enum UnixState {
+    /// a stream socket not connected yet
+    Idle,
+    Listening(Arc<Backlog>),
+    /// a connected stream, which receives from `rx` and sends to `tx`
+    Connected {
+        rx: Arc<Queue>,
+        tx: Arc<Queue>,
+    },
+    /// a datagram socket, which sends to `peer` by default
+    Datagram {
+        rx: Arc<Queue>,
+        peer: Option<Weak<Queue>>,
+    },
+}
+
+struct UnixSocketInner {
+    state: UnixState,
+    /// the path bound to, or that of the listener for an accepted socket
+    name: Option<String>,
+    /// the inode id of the socket file this socket is bound to
+    inode_id: Option<u32>,
+    peer_name: Option<String>,
+    write_shutdown: bool,
+}
+
+pub struct UnixSocket {
+    socket_type: UnixType,
+    inner: SpinIntrFreeCell<UnixSocketInner>,
+}
+
+impl UnixSocket {
+    fn with_state(socket_type: UnixType, state: UnixState) -> Self {
+        Self {
+            socket_type,
+            inner: unsafe {
+                SpinIntrFreeCell::new(UnixSocketInner {
+                    state,
+                    name: None,
+                    inode_id: None,
+                    peer_name: None,
+                    write_shutdown: false,
+                })
+            },
+        }
+    }
+
+    pub fn new(socket_type: UnixType) -> Self {
+        let state = match socket_type {
+            UnixType::Stream => UnixState::Idle,
+            UnixType::Datagram => UnixState::Datagram {
+                rx: Queue::new(),
+                peer: None,
+            },
+        };
+        Self::with_state(socket_type, state)
+    }
+
+    /// The queues are used with the socket unlocked.
+    fn state(&self) -> UnixState {
+        self.inner.exclusive_access().state.clone()
+    }
+
+    fn name(&self) -> Option<String> {
+        self.inner.exclusive_access().name.clone()
+    }
+}
+
+/// Return a pair of sockets connected to each other.
+pub fn make_socket_pair(socket_type: UnixType) -> (Arc<UnixSocket>, Arc<UnixSocket>) {
+    let (a_to_b, b_to_a) = (Queue::new(), Queue::new());
+    let (a, b) = match socket_type {
+        UnixType::Stream => (
+            UnixState::Connected {
+                rx: b_to_a.clone(),
+                tx: a_to_b.clone(),
+            },
+            UnixState::Connected {
+                rx: a_to_b,
+                tx: b_to_a,
+            },
+        ),
+        UnixType::Datagram => (
+            UnixState::Datagram {
+                rx: b_to_a.clone(),
+                peer: Some(Arc::downgrade(&a_to_b)),
+            },
+            UnixState::Datagram {
+                rx: a_to_b,
+                peer: Some(Arc::downgrade(&b_to_a)),
+            },
+        ),
+    };
+    (
+        Arc::new(UnixSocket::with_state(socket_type, a)),
+        Arc::new(UnixSocket::with_state(socket_type, b)),
+    )
+}
+
+impl Socket for UnixSocket {
+    /// Create the socket file at the path, which must not exist.
+    fn bind(&self, addr: SockAddr) -> bool {
+        let SockAddr::Unix(path) = addr else {
+            return false;
+        };
+        if path.is_empty() || self.inner.exclusive_access().name.is_some() {
+            return false;
+        }
+        // the file system may block, so the socket is not locked meanwhile
+        let inode_id = match make_socket_file(&path) {
+            Some(inode_id) => inode_id,
+            None => return false,
+        };
+        let mut inner = self.inner.exclusive_access();
+        if inner.name.is_some() {
+            return false;
+        }
+        if let UnixState::Datagram { rx, .. } = &inner.state {
+            BINDINGS
+                .exclusive_access()
+                .insert(inode_id, Binding::Datagram(Arc::downgrade(rx)));
+        }
+        inner.name = Some(path);
+        inner.inode_id = Some(inode_id);
+        true
+    }
+
+    /// Only a bound stream socket listens.
+    fn listen(&self, backlog: usize) -> bool {
+        let mut inner = self.inner.exclusive_access();
+        let inode_id = match (&inner.state, inner.inode_id) {
+            (UnixState::Idle, Some(inode_id)) => inode_id,
+            _ => return false,
+        };
+        let backlog = Backlog::new(backlog);
+        BINDINGS
+            .exclusive_access()
+            .insert(inode_id, Binding::Listener(Arc::downgrade(&backlog)));
+        inner.state = UnixState::Listening(backlog);
+        true
+    }
+
+    fn accept(&self) -> Option<(Arc<dyn File>, SockAddr)> {
+        let backlog = match &self.inner.exclusive_access().state {
+            UnixState::Listening(backlog) => backlog.clone(),
+            _ => return None,
+        };
+        let connection = backlog.pop()?;
+        let peer = connection.peer_addr().unwrap();
+        Some((connection as Arc<dyn File>, peer))
+    }
+
+    /// A stream is connected as soon as the listener has room for it, and
+    /// a datagram socket only records its peer.
+    fn connect(&self, addr: SockAddr) -> bool {
+        let SockAddr::Unix(path) = addr else {
+            return false;
+        };
+        match self.socket_type {
+            UnixType::Stream => {
+                if !matches!(self.inner.exclusive_access().state, UnixState::Idle) {
+                    return false;
+                }
+                let backlog = match find_listener(&path) {
+                    Some(backlog) => backlog,
+                    None => return false,
+                };
+                let (to_server, to_client) = (Queue::new(), Queue::new());
+                let server = UnixSocket::with_state(
+                    UnixType::Stream,
+                    UnixState::Connected {
+                        rx: to_server.clone(),
+                        tx: to_client.clone(),
+                    },
+                );
+                {
+                    let mut server_inner = server.inner.exclusive_access();
+                    server_inner.name = Some(path.clone());
+                    server_inner.peer_name = self.name();
+                }
+                if !backlog.push(Arc::new(server)) {
+                    return false;
+                }
+                let mut inner = self.inner.exclusive_access();
+                inner.state = UnixState::Connected {
+                    rx: to_client,
+                    tx: to_server,
+                };
+                inner.peer_name = Some(path);
+                true
+            }
+            UnixType::Datagram => {
+                let queue = match find_datagram(&path) {
+                    Some(queue) => queue,
+                    None => return false,
+                };
+                let mut inner = self.inner.exclusive_access();
+                if let UnixState::Datagram { peer, .. } = &mut inner.state {
+                    *peer = Some(Arc::downgrade(&queue));
+                }
+                inner.peer_name = Some(path);
+                true
+            }
+        }
+    }
+
+    fn send_to(&self, buf: UserBuffer, addr: Option<SockAddr>) -> Option<usize> {
+        self.send_msg(buf, addr, Vec::new())
+    }
+
+    fn recv_from(&self, buf: UserBuffer) -> Option<(usize, Option<SockAddr>)> {
+        self.recv_msg(buf).map(|(len, source, _)| (len, source))
+    }
+
+    /// A stream sends to its peer, while a datagram goes to `addr` or to
+    /// the connected peer. A stream passes files only with some data.
+    fn send_msg(
+        &self,
+        buf: UserBuffer,
+        addr: Option<SockAddr>,
+        files: Vec<Arc<dyn File>>,
+    ) -> Option<usize> {
+        let (queue, source) = {
+            let inner = self.inner.exclusive_access();
+            if inner.write_shutdown {
+                return None;
+            }
+            let queue = match (&inner.state, &addr) {
+                (UnixState::Connected { tx, .. }, _) => Some(tx.clone()),
+                // the socket file is looked up with the socket unlocked
+                (UnixState::Datagram { .. }, Some(_)) => None,
+                (UnixState::Datagram { peer, .. }, None) => Some(peer.as_ref()?.upgrade()?),
+                _ => return None,
+            };
+            (queue, inner.name.clone())
+        };
+        let queue = match (queue, addr) {
+            (Some(queue), _) => queue,
+            (None, Some(SockAddr::Unix(path))) => find_datagram(&path)?,
+            _ => return None,
+        };
+        // only datagrams tell their senders
+        let source = match self.socket_type {
+            UnixType::Stream => None,
+            UnixType::Datagram => source,
+        };
+        let len = buf.len();
+        if self.socket_type == UnixType::Stream && len == 0 {
+            return files.is_empty().then_some(0);
+        }
+        if self.socket_type == UnixType::Datagram && len > QUEUE_CAPACITY {
+            return None;
+        }
+        let mut data = Vec::with_capacity(len);
+        for buffer in buf.buffers.iter() {
+            data.extend_from_slice(buffer);
+        }
+        let message = Message {
+            data,
+            offset: 0,
+            files,
+            source,
+        };
+        queue.push(message).then_some(len)
+    }
+
+    fn recv_msg(&self, buf: UserBuffer) -> Option<(usize, Option<SockAddr>, Vec<Arc<dyn File>>)> {
+        let (queue, stream) = match &self.inner.exclusive_access().state {
+            UnixState::Connected { rx, .. } => (rx.clone(), true),
+            UnixState::Datagram { rx, .. } => (rx.clone(), false),
+            _ => return None,
+        };
+        let (len, files, source) = queue.pop(buf, stream);
+        let source = (!stream).then(|| SockAddr::Unix(source.unwrap_or_default()));
+        Some((len, source, files))
+    }
+
+    fn shutdown(&self, how: Shutdown) -> bool {
+        let (read, write) = match how {
+            Shutdown::Read => (true, false),
+            Shutdown::Write => (false, true),
+            Shutdown::Both => (true, true),
+        };
+        let mut inner = self.inner.exclusive_access();
+        match &inner.state {
+            UnixState::Connected { rx, tx } => {
+                if read {
+                    rx.close_read();
+                }
+                if write {
+                    tx.close_write();
+                }
+            }
+            UnixState::Datagram { rx, .. } => {
+                if read {
+                    rx.close_read();
+                }
+            }
+            _ => return false,
+        }
+        inner.write_shutdown |= write;
+        true
+    }
+
+    fn local_addr(&self) -> Option<SockAddr> {
+        Some(SockAddr::Unix(self.name().unwrap_or_default()))
+    }
+
+    /// An unnamed peer has an empty path.
+    fn peer_addr(&self) -> Option<SockAddr> {
+        let inner = self.inner.exclusive_access();
+        match &inner.state {
+            UnixState::Connected { .. } => {}
+            UnixState::Datagram { peer: Some(_), .. } => {}
+            _ => return None,
+        }
+        Some(SockAddr::Unix(inner.peer_name.clone().unwrap_or_default()))
+    }
+}
+
+impl Drop for UnixSocket {
+    /// The peer reads the end of file and fails to write, and the name is
+    /// released. The socket file stays until it is unlinked.
+    fn drop(&mut self) {
+        let inner = self.inner.exclusive_access();
+        let owner = match &inner.state {
+            UnixState::Connected { rx, tx } => {
+                tx.close_write();
+                rx.close_read();
+                return;
+            }
+            UnixState::Listening(backlog) => {
+                backlog.close();
+                Binding::Listener(Arc::downgrade(backlog))
+            }
+            UnixState::Datagram { rx, .. } => {
+                rx.close_read();
+                Binding::Datagram(Arc::downgrade(rx))
+            }
+            UnixState::Idle => return,
+        };
+        let Some(inode_id) = inner.inode_id else {
+            return;
+        };
+        drop(inner);
+        let mut bindings = BINDINGS.exclusive_access();
+        let owned = match (bindings.get(&inode_id), &owner) {
+            (Some(Binding::Listener(a)), Binding::Listener(b)) => a.ptr_eq(b),
+            (Some(Binding::Datagram(a)), Binding::Datagram(b)) => a.ptr_eq(b),
+            _ => false,
+        };
+        if owned {
+            bindings.remove(&inode_id);
+        }
+    }
+}
+
+impl File for UnixSocket {
+    fn readable(&self) -> bool {
+        true
+    }
+
+    fn writable(&self) -> bool {
+        true
+    }
+
+    /// Files passed along are closed.
+    fn read(&self, buf: UserBuffer) -> usize {
+        self.recv_from(buf).map_or(0, |(len, _)| len)
+    }
+
+    fn write(&self, buf: UserBuffer) -> usize {
+        self.send_to(buf, None).unwrap_or(0)
+    }
+
+    /// A stream is hung up once the peer is closed, and a stream not
+    /// connected is hung up as well.
+    fn poll_ready(&self) -> PollEvents {
+        let write_shutdown = self.inner.exclusive_access().write_shutdown;
+        let mut events = PollEvents::empty();
+        match self.state() {
+            UnixState::Idle => events |= PollEvents::HUP,
+            UnixState::Listening(backlog) => events.set(PollEvents::IN, backlog.can_pop()),
+            UnixState::Connected { rx, tx } => {
+                events.set(PollEvents::IN, rx.can_pop());
+                events.set(PollEvents::OUT, !write_shutdown && tx.can_push());
+                events.set(PollEvents::HUP, rx.is_write_closed() && tx.is_read_closed());
+            }
+            UnixState::Datagram { rx, peer } => {
+                events.set(PollEvents::IN, rx.can_pop());
+                let writable = match peer {
+                    Some(peer) => peer.upgrade().is_some_and(|peer| peer.can_push()),
+                    None => true,
+                };
+                events.set(PollEvents::OUT, !write_shutdown && writable);
+            }
+        }
+        events
+    }
+
+    fn register_waiter(&self, waiter: &Arc<PollWaiter>) {
+        match self.state() {
+            UnixState::Idle => {}
+            UnixState::Listening(backlog) => backlog.register_poller(waiter),
+            UnixState::Connected { rx, tx } => {
+                rx.register_poller(waiter);
+                tx.register_poller(waiter);
+            }
+            UnixState::Datagram { rx, peer } => {
+                rx.register_poller(waiter);
+                if let Some(peer) = peer.and_then(|peer| peer.upgrade()) {
+                    peer.register_poller(waiter);
+                }
+            }
+        }
+    }
+
+    fn socket(&self) -> Option<&dyn Socket> {
+        Some(self)
+    }
+}
@@ -3,7 +3,7 @@
 #![feature(alloc_error_handler)]
 
 //use crate::drivers::{GPU_DEVICE, KEYBOARD_DEVICE, MOUSE_DEVICE, INPUT_CONDVAR};
-use crate::drivers::{GPU_DEVICE, KEYBOARD_DEVICE, MOUSE_DEVICE};
+use crate::drivers::{GPU_DEVICE, KEYBOARD_DEVICE, MOUSE_DEVICE, NET_DEVICE};
 extern crate alloc;
 
 #[macro_use]
@@ -47,15 +47,15 @@ fn clear_bss() {
 }
 
 use lazy_static::*;
-use sync::UPIntrFreeCell;
+use sync::SpinIntrFreeCell;
 
 lazy_static! {
-    pub static ref DEV_NON_BLOCKING_ACCESS: UPIntrFreeCell<bool> =
-        unsafe { UPIntrFreeCell::new(false) };
+    pub static ref DEV_NON_BLOCKING_ACCESS: SpinIntrFreeCell<bool> =
+        unsafe { SpinIntrFreeCell::new(false) };
 }
 
 #[unsafe(no_mangle)]
-pub fn rust_main() -> ! {
+pub fn rust_main(boot_hart_id: usize) -> ! {
     clear_bss();
     logging::init();
     mm::init();
@@ -66,14 +66,43 @@ pub fn rust_main() -> ! {
     let _keyboard = KEYBOARD_DEVICE.clone();
     info!("KERN: init mouse");
     let _mouse = MOUSE_DEVICE.clone();
+    info!("KERN: init net");
+    let _net = NET_DEVICE.clone();
     info!("KERN: init trap");
     trap::init();
     trap::enable_timer_interrupt();
+    trap::enable_software_interrupt();
     timer::set_next_trigger();
     board::device_init();
+    info!("KERN: configure net by dhcp");
+    net::dhcp::start();
     fs::list_apps();
     task::add_initproc();
     *DEV_NON_BLOCKING_ACCESS.exclusive_access() = true;
+    start_other_harts(boot_hart_id);
     task::run_tasks();
     panic!("Unreachable in rust_main!");
 }
+
+fn start_other_harts(boot_hart_id: usize) {
+    unsafe extern "C" {
+        safe fn _start_secondary();
+    }
+    for hart_id in (0..config::MAX_HARTS).filter(|&hart_id| hart_id != boot_hart_id) {
+        // harts not given to the machine fail to start
+        if sbi::hart_start(hart_id, _start_secondary as usize) {
+            info!("KERN: start hart {}", hart_id);
+        }
+    }
+}
+
+#[unsafe(no_mangle)]
+pub fn rust_main_secondary() -> ! {
+    mm::KERNEL_SPACE.exclusive_access().activate();
+    trap::init();
+    trap::enable_timer_interrupt();
+    trap::enable_software_interrupt();
+    timer::set_next_trigger();
+    task::run_tasks();
+    panic!("Unreachable in rust_main_secondary!");
+}
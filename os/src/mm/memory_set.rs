@@ -1,13 +1,20 @@
+use super::page_cache::{PageKey, cache_frame, cached_frame};
+use super::swap::{swap_dup, swap_free, swap_in, swap_out};
 use super::{FrameTracker, frame_alloc};
 use super::{PTEFlags, PageTable, PageTableEntry};
 use super::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
 use super::{StepByOne, VPNRange};
-use crate::config::{MEMORY_END, MMIO, PAGE_SIZE, TRAMPOLINE};
-use crate::sync::UPIntrFreeCell;
+use crate::config::{
+    MEMORY_END, MMAP_BASE, MMAP_TOP, MMIO, PAGE_SIZE, TRAMPOLINE, USER_STACK_BASE,
+};
+use crate::sbi::remote_sfence_vma_all;
+use crate::sync::SpinIntrFreeCell;
 use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
+use alloc::vec;
 use alloc::vec::Vec;
 use core::arch::asm;
+use easy_fs::Inode;
 use lazy_static::*;
 use riscv::register::satp;
 
@@ -24,9 +31,12 @@ unsafe extern "C" {
     safe fn strampoline();
 }
 
+/// Size of the header of a 64-bit elf file.
+const ELF_HEADER_SIZE: usize = 64;
+
 lazy_static! {
-    pub static ref KERNEL_SPACE: Arc<UPIntrFreeCell<MemorySet>> =
-        Arc::new(unsafe { UPIntrFreeCell::new(MemorySet::new_kernel()) });
+    pub static ref KERNEL_SPACE: Arc<SpinIntrFreeCell<MemorySet>> =
+        Arc::new(unsafe { SpinIntrFreeCell::new(MemorySet::new_kernel()) });
 }
 
 pub fn kernel_token() -> usize {
@@ -36,6 +46,11 @@ pub fn kernel_token() -> usize {
 pub struct MemorySet {
     page_table: PageTable,
     areas: Vec<MapArea>,
+    /// the heap area lies in [heap_bottom, brk) rounded up to whole pages
+    heap_bottom: usize,
+    brk: usize,
+    /// the last page swapped out, pages after it are checked first
+    clock_hand: VirtPageNum,
 }
 
 impl MemorySet {
@@ -43,6 +58,9 @@ impl MemorySet {
         Self {
             page_table: PageTable::new(),
             areas: Vec::new(),
+            heap_bottom: 0,
+            brk: 0,
+            clock_hand: VirtPageNum(0),
         }
     }
     pub fn token(&self) -> usize {
@@ -60,6 +78,165 @@ impl MemorySet {
             None,
         );
     }
+    /// Insert a user mapping created by `mmap`. Shared mappings keep sharing
+    /// their frames after `fork`. File mappings are loaded lazily, shared
+    /// anonymous mappings come with `frames` already zeroed.
+    pub fn insert_mmap_area(
+        &mut self,
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        permission: MapPermission,
+        shared: bool,
+        file: Option<MapFile>,
+        frames: Vec<FrameTracker>,
+    ) {
+        let mut map_area = MapArea::new(start_va, end_va, MapType::Framed, permission);
+        map_area.shared = shared;
+        map_area.file = file;
+        for (vpn, frame) in map_area.vpn_range.into_iter().zip(frames) {
+            map_area.map_frame(&mut self.page_table, vpn, Arc::new(frame));
+        }
+        self.push_lazy(map_area);
+    }
+    /// Find `pages` free pages for `mmap`, `hint` is used if it is free.
+    pub fn find_free_area(&self, hint: VirtPageNum, pages: usize) -> Option<VirtPageNum> {
+        let base: VirtPageNum = VirtAddr::from(MMAP_BASE).floor();
+        if hint >= base && self.is_free_for_mmap(hint, VirtPageNum(hint.0 + pages)) {
+            return Some(hint);
+        }
+        let mut areas: Vec<_> = self
+            .areas
+            .iter()
+            .map(|area| area.vpn_range)
+            .filter(|range| range.get_end() > base)
+            .collect();
+        areas.sort_by_key(|range| range.get_start().0);
+        let mut start = base;
+        for range in areas {
+            if range.get_start().0 >= start.0 + pages {
+                break;
+            }
+            start = start.max(range.get_end());
+        }
+        self.is_free_for_mmap(start, VirtPageNum(start.0 + pages))
+            .then_some(start)
+    }
+    /// Whether [start, end) lies in the mmap region without overlapping any area.
+    pub fn is_free_for_mmap(&self, start: VirtPageNum, end: VirtPageNum) -> bool {
+        Self::in_mmap_region(start, end) && self.is_free(start, end)
+    }
+    fn is_free(&self, start: VirtPageNum, end: VirtPageNum) -> bool {
+        !self
+            .areas
+            .iter()
+            .any(|area| area.vpn_range.get_start() < end && start < area.vpn_range.get_end())
+    }
+    fn in_mmap_region(start: VirtPageNum, end: VirtPageNum) -> bool {
+        let base: VirtPageNum = VirtAddr::from(MMAP_BASE).floor();
+        let top: VirtPageNum = VirtAddr::from(MMAP_TOP).floor();
+        base <= start && start < end && end <= top
+    }
+    /// Split the area containing `vpn` so that an area starts at `vpn`.
+    fn split_area_at(&mut self, vpn: VirtPageNum) {
+        if let Some(area) = self
+            .areas
+            .iter_mut()
+            .find(|area| area.vpn_range.get_start() < vpn && vpn < area.vpn_range.get_end())
+        {
+            let another = area.split_off(vpn);
+            self.areas.push(another);
+        }
+    }
+    /// Remove the mappings in [start, end) of the mmap region, return the dirty
+    /// pages of shared file mappings to be written back or None if the range is invalid.
+    pub fn munmap(&mut self, start: VirtPageNum, end: VirtPageNum) -> Option<Vec<FilePage>> {
+        if !Self::in_mmap_region(start, end) {
+            return None;
+        }
+        self.split_area_at(start);
+        self.split_area_at(end);
+        let mut file_pages = Vec::new();
+        let page_table = &mut self.page_table;
+        self.areas.retain_mut(|area| {
+            if area.vpn_range.get_start() < start || area.vpn_range.get_end() > end {
+                return true;
+            }
+            file_pages.extend(area.shared_file_pages(page_table));
+            area.unmap(page_table);
+            false
+        });
+        self.flush_tlb();
+        Some(file_pages)
+    }
+    /// Change the permission of the mappings in [start, end) of the mmap region,
+    /// return false if a part of the range is not mapped.
+    pub fn mprotect(&mut self, start: VirtPageNum, end: VirtPageNum, perm: MapPermission) -> bool {
+        if !Self::in_mmap_region(start, end) {
+            return false;
+        }
+        let mut ranges: Vec<_> = self
+            .areas
+            .iter()
+            .map(|area| area.vpn_range)
+            .filter(|range| range.get_start() < end && start < range.get_end())
+            .collect();
+        ranges.sort_by_key(|range| range.get_start().0);
+        let mut covered = start;
+        for range in ranges {
+            if range.get_start() > covered {
+                break;
+            }
+            covered = covered.max(range.get_end());
+        }
+        if covered < end {
+            return false;
+        }
+        self.split_area_at(start);
+        self.split_area_at(end);
+        for area in self.areas.iter_mut() {
+            if start <= area.vpn_range.get_start() && area.vpn_range.get_end() <= end {
+                area.set_perm(&mut self.page_table, perm);
+            }
+        }
+        self.flush_tlb();
+        true
+    }
+    pub fn brk(&self) -> usize {
+        self.brk
+    }
+    /// Move the program break, the heap area grows or shrinks page by page
+    /// and new pages are zero-filled on the first access.
+    /// Return false if the heap cannot be moved there.
+    pub fn set_brk(&mut self, brk: usize) -> bool {
+        if brk < self.heap_bottom || brk > MMAP_BASE {
+            return false;
+        }
+        let old_end = VirtAddr::from(self.brk).ceil();
+        let new_end = VirtAddr::from(brk).ceil();
+        if new_end > old_end && !self.is_free(old_end, new_end) {
+            return false;
+        }
+        let heap_start = VirtAddr::from(self.heap_bottom).floor();
+        let heap = self
+            .areas
+            .iter_mut()
+            .find(|area| area.vpn_range.get_start() == heap_start)
+            .unwrap();
+        heap.resize(&mut self.page_table, new_end);
+        if new_end < old_end {
+            self.flush_tlb();
+        }
+        self.brk = brk;
+        true
+    }
+    /// Dirty pages of all shared file mappings, which should be written back
+    /// before the address space goes away.
+    pub fn shared_file_pages(&self) -> Vec<FilePage> {
+        self.areas
+            .iter()
+            .flat_map(|area| area.shared_file_pages(&self.page_table))
+            .collect()
+    }
     pub fn remove_area_with_start_vpn(&mut self, start_vpn: VirtPageNum) {
         if let Some((idx, area)) = self
             .areas
@@ -81,6 +258,10 @@ impl MemorySet {
         }
         self.areas.push(map_area);
     }
+    /// Add a new MapArea whose pages are loaded on the first access.
+    fn push_lazy(&mut self, map_area: MapArea) {
+        self.areas.push(map_area);
+    }
     /// Mention that trampoline is not collected by areas.
     fn map_trampoline(&mut self) {
         self.page_table.map(
@@ -168,12 +349,22 @@ impl MemorySet {
     }
     /// Include sections in elf and trampoline,
     /// also returns user_sp_base and entry point.
-    pub fn from_elf(elf_data: &[u8]) -> (Self, usize, usize) {
+    /// Sections are loaded from `elf_inode` on page faults.
+    pub fn from_elf(elf_inode: &Arc<Inode>) -> (Self, usize, usize) {
         let mut memory_set = Self::new_bare();
         // map trampoline
         memory_set.map_trampoline();
+        // only read the elf header and program headers
+        let mut elf_data = vec![0u8; ELF_HEADER_SIZE];
+        elf_inode.read_at(0, &mut elf_data);
+        let ph_end = {
+            let pt2 = xmas_elf::ElfFile::new(&elf_data).unwrap().header.pt2;
+            pt2.ph_offset() as usize + pt2.ph_count() as usize * pt2.ph_entry_size() as usize
+        };
+        elf_data.resize(ph_end, 0);
+        elf_inode.read_at(0, &mut elf_data);
         // map program headers of elf, with U flag
-        let elf = xmas_elf::ElfFile::new(elf_data).unwrap();
+        let elf = xmas_elf::ElfFile::new(&elf_data).unwrap();
         let elf_header = elf.header;
         let magic = elf_header.pt1.magic;
         assert_eq!(magic, [0x7f, 0x45, 0x4c, 0x46], "invalid elf!");
@@ -195,30 +386,54 @@ impl MemorySet {
                 if ph_flags.is_execute() {
                     map_perm |= MapPermission::X;
                 }
-                let map_area = MapArea::new(start_va, end_va, MapType::Framed, map_perm);
+                let mut map_area = MapArea::new(start_va, end_va, MapType::Framed, map_perm);
                 max_end_vpn = map_area.vpn_range.get_end();
-                memory_set.push(
-                    map_area,
-                    Some(&elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize]),
-                );
+                // the file range starts from the beginning of the first page
+                let page_offset = start_va.page_offset();
+                map_area.file = Some(MapFile {
+                    inode: elf_inode.clone(),
+                    offset: ph.offset() as usize - page_offset,
+                    len: ph.file_size() as usize + page_offset,
+                });
+                memory_set.push_lazy(map_area);
             }
         }
+        // the heap starts empty right after the last section
         let max_end_va: VirtAddr = max_end_vpn.into();
-        let mut user_stack_base: usize = max_end_va.into();
-        user_stack_base += PAGE_SIZE;
+        memory_set.heap_bottom = max_end_va.into();
+        memory_set.brk = memory_set.heap_bottom;
+        memory_set.push(
+            MapArea::new(
+                max_end_va,
+                max_end_va,
+                MapType::Framed,
+                MapPermission::R | MapPermission::W | MapPermission::U,
+            ),
+            None,
+        );
         (
             memory_set,
-            user_stack_base,
+            USER_STACK_BASE,
             elf.header.pt2.entry_point() as usize,
         )
     }
-    pub fn from_existed_user(user_space: &MemorySet) -> MemorySet {
+    /// User pages are shared copy-on-write with `user_space`, pages that
+    /// are only accessed by the kernel (trap contexts) are copied at once.
+    pub fn from_existed_user(user_space: &mut MemorySet) -> MemorySet {
         let mut memory_set = Self::new_bare();
+        memory_set.heap_bottom = user_space.heap_bottom;
+        memory_set.brk = user_space.brk;
         // map trampoline
         memory_set.map_trampoline();
-        // copy data sections/trap_context/user_stack
         for area in user_space.areas.iter() {
-            let new_area = MapArea::from_another(area);
+            let mut new_area = MapArea::from_another(area);
+            if area.map_type == MapType::Framed && area.map_perm.contains(MapPermission::U) {
+                // share data sections/user_stack
+                new_area.map_cow(area, &mut user_space.page_table, &mut memory_set.page_table);
+                memory_set.areas.push(new_area);
+                continue;
+            }
+            // copy trap_context
             memory_set.push(new_area, None);
             // copy data from another space
             for vpn in area.vpn_range {
@@ -231,6 +446,136 @@ impl MemorySet {
         }
         memory_set
     }
+    /// Give the current process its own writable copy of a copy-on-write page,
+    /// return false if `vpn` is not such a page.
+    pub fn handle_cow_fault(&mut self, vpn: VirtPageNum) -> bool {
+        match self.translate(vpn) {
+            Some(pte) if pte.is_cow() => {}
+            // copied by another thread in the meantime
+            Some(pte) if pte.is_valid() && pte.writable() => return true,
+            _ => return false,
+        }
+        let area = self
+            .areas
+            .iter_mut()
+            .find(|area| area.data_frames.contains_key(&vpn))
+            .unwrap();
+        area.copy_on_write(&mut self.page_table, vpn);
+        // other threads must not read the shared frame any more
+        self.flush_tlb();
+        true
+    }
+    /// Return how to fill `vpn` if it is a page of a framed area
+    /// which has not been loaded yet or has been swapped out.
+    pub fn lazy_page(&self, vpn: VirtPageNum) -> Option<LazyPage> {
+        if self.translate(vpn).is_some_and(|pte| pte.is_valid()) {
+            return None;
+        }
+        let area = self
+            .areas
+            .iter()
+            .find(|area| area.map_type == MapType::Framed && area.contains(vpn))?;
+        if let Some(&slot) = area.swapped.get(&vpn) {
+            return Some(LazyPage::Swap(slot));
+        }
+        let page_index = vpn.0 - area.vpn_range.get_start().0;
+        Some(match &area.file {
+            Some(file) if area.shared => LazyPage::Shared(file.page(page_index)),
+            Some(file) => LazyPage::File(file.page(page_index)),
+            None => LazyPage::Zero,
+        })
+    }
+    /// Map `frame` filled according to `lazy_page` at `vpn`,
+    /// return false if `vpn` does not belong to any area.
+    /// The frame is dropped if the page has been changed in the meantime.
+    pub fn map_lazy_page(
+        &mut self,
+        vpn: VirtPageNum,
+        frame: Arc<FrameTracker>,
+        lazy_page: &LazyPage,
+    ) -> bool {
+        if self.translate(vpn).is_some_and(|pte| pte.is_valid()) {
+            // loaded by another thread in the meantime
+            return true;
+        }
+        let area = match self.areas.iter_mut().find(|area| area.contains(vpn)) {
+            Some(area) => area,
+            None => return false,
+        };
+        match (lazy_page, area.swapped.get(&vpn).copied()) {
+            (LazyPage::Swap(slot), Some(swapped)) if *slot == swapped => {
+                area.swapped.remove(&vpn);
+                swap_free(*slot);
+            }
+            // swapped in and out again by others, the page is resolved once more
+            (LazyPage::Swap(_), _) | (_, Some(_)) => return true,
+            _ => {}
+        }
+        area.map_frame(&mut self.page_table, vpn, frame);
+        true
+    }
+    /// Swap out at most `max` user pages picked by the enhanced clock algorithm,
+    /// return the number of pages swapped out. Pages neither accessed nor dirty
+    /// are picked in rounds 0 and 2, accessed bits of the pages passed by are
+    /// cleared in rounds 1 and 3 where dirty pages are picked as well.
+    /// Frames shared with other processes are not swapped out.
+    pub fn swap_out_pages(&mut self, round: usize, max: usize) -> usize {
+        let mut pages: Vec<VirtPageNum> = self
+            .areas
+            .iter()
+            .filter(|area| area.swappable())
+            .flat_map(|area| {
+                area.data_frames
+                    .iter()
+                    .filter(|(_, frame)| Arc::strong_count(frame) == 1)
+                    .map(|(&vpn, _)| vpn)
+            })
+            .collect();
+        pages.sort_by_key(|vpn| vpn.0);
+        // start from the page after the clock hand
+        let hand = pages.partition_point(|vpn| vpn.0 <= self.clock_hand.0);
+        pages.rotate_left(hand);
+        let clear_accessed = round % 2 == 1;
+        let mut count = 0;
+        for vpn in pages {
+            if count == max {
+                break;
+            }
+            let flags = self.page_table.translate(vpn).unwrap().flags();
+            if flags.contains(PTEFlags::A) {
+                if clear_accessed {
+                    self.page_table.clear_accessed(vpn);
+                }
+                continue;
+            }
+            if !clear_accessed && flags.contains(PTEFlags::D) {
+                continue;
+            }
+            let area = self
+                .areas
+                .iter_mut()
+                .find(|area| area.contains(vpn))
+                .unwrap();
+            let frame = area.data_frames.remove(&vpn).unwrap();
+            let slot = match swap_out(frame.clone()) {
+                Some(slot) => slot,
+                None => {
+                    // the swap area is full
+                    area.data_frames.insert(vpn, frame);
+                    break;
+                }
+            };
+            area.swapped.insert(vpn, slot);
+            self.page_table.unmap(vpn);
+            self.clock_hand = vpn;
+            count += 1;
+        }
+        if count > 0 || clear_accessed {
+            // the frames must not be written any more before they are swapped out
+            self.flush_tlb();
+        }
+        count
+    }
     pub fn activate(&self) {
         let satp = self.page_table.token();
         unsafe {
@@ -238,6 +583,11 @@ impl MemorySet {
             asm!("sfence.vma");
         }
     }
+    /// Flush the TLB of all harts after mappings are removed or restricted,
+    /// since other threads of the process may be running on them.
+    fn flush_tlb(&self) {
+        remote_sfence_vma_all();
+    }
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
         self.page_table.translate(vpn)
     }
@@ -249,9 +599,16 @@ impl MemorySet {
 
 pub struct MapArea {
     vpn_range: VPNRange,
-    data_frames: BTreeMap<VirtPageNum, FrameTracker>,
+    /// frames may be shared with the areas of forked processes
+    data_frames: BTreeMap<VirtPageNum, Arc<FrameTracker>>,
     map_type: MapType,
     map_perm: MapPermission,
+    /// initial content of the area, pages not mapped yet are loaded on page faults
+    file: Option<MapFile>,
+    /// frames of shared areas are not copied on write after `fork`
+    shared: bool,
+    /// swap slots of pages swapped out
+    swapped: BTreeMap<VirtPageNum, usize>,
 }
 
 impl MapArea {
@@ -268,6 +625,9 @@ impl MapArea {
             data_frames: BTreeMap::new(),
             map_type,
             map_perm,
+            file: None,
+            shared: false,
+            swapped: BTreeMap::new(),
         }
     }
     pub fn from_another(another: &MapArea) -> Self {
@@ -276,8 +636,18 @@ impl MapArea {
             data_frames: BTreeMap::new(),
             map_type: another.map_type,
             map_perm: another.map_perm,
+            file: another.file.clone(),
+            shared: another.shared,
+            swapped: BTreeMap::new(),
         }
     }
+    pub fn contains(&self, vpn: VirtPageNum) -> bool {
+        self.vpn_range.get_start() <= vpn && vpn < self.vpn_range.get_end()
+    }
+    /// Whether pages of the area may be swapped out.
+    fn swappable(&self) -> bool {
+        self.map_type == MapType::Framed && self.map_perm.contains(MapPermission::U) && !self.shared
+    }
     pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
         let ppn: PhysPageNum;
         match self.map_type {
@@ -287,7 +657,7 @@ impl MapArea {
             MapType::Framed => {
                 let frame = frame_alloc().unwrap();
                 ppn = frame.ppn;
-                self.data_frames.insert(vpn, frame);
+                self.data_frames.insert(vpn, Arc::new(frame));
             }
             MapType::Linear(pn_offset) => {
                 // check for sv39
@@ -295,12 +665,71 @@ impl MapArea {
                 ppn = PhysPageNum((vpn.0 as isize + pn_offset) as usize);
             }
         }
-        let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
+        let pte_flags = PTEFlags::from_bits(self.map_perm.bits as u16).unwrap();
         page_table.map(vpn, ppn, pte_flags);
     }
+    /// Share all frames of `another` with this area. Writable pages become
+    /// read-only copy-on-write pages in both page tables, unless the area is
+    /// shared, whose pages keep their dirty bits to be written back by both.
+    pub fn map_cow(
+        &mut self,
+        another: &MapArea,
+        src_page_table: &mut PageTable,
+        page_table: &mut PageTable,
+    ) {
+        let mut pte_flags = PTEFlags::from_bits(self.map_perm.bits as u16).unwrap();
+        if !self.shared && pte_flags.contains(PTEFlags::W) {
+            pte_flags = (pte_flags - PTEFlags::W) | PTEFlags::COW;
+        }
+        for (&vpn, frame) in another.data_frames.iter() {
+            if self.shared {
+                let dirty = src_page_table.translate(vpn).unwrap().flags() & PTEFlags::D;
+                page_table.map(vpn, frame.ppn, pte_flags | dirty);
+            } else {
+                src_page_table.remap(vpn, frame.ppn, pte_flags);
+                page_table.map(vpn, frame.ppn, pte_flags);
+            }
+            self.data_frames.insert(vpn, frame.clone());
+        }
+        for (&vpn, &slot) in another.swapped.iter() {
+            swap_dup(slot);
+            self.swapped.insert(vpn, slot);
+        }
+    }
+    /// Make a copy-on-write page writable, the frame is copied only if
+    /// it is still shared with other areas.
+    pub fn copy_on_write(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
+        let frame = self.data_frames.get_mut(&vpn).unwrap();
+        if Arc::strong_count(frame) > 1 {
+            let new_frame = frame_alloc().unwrap();
+            new_frame
+                .ppn
+                .get_bytes_array()
+                .copy_from_slice(frame.ppn.get_bytes_array());
+            *frame = Arc::new(new_frame);
+        }
+        let pte_flags = PTEFlags::from_bits(self.map_perm.bits as u16).unwrap();
+        page_table.remap(vpn, frame.ppn, pte_flags);
+    }
+    /// Map a frame of a page loaded on a page fault.
+    pub fn map_frame(
+        &mut self,
+        page_table: &mut PageTable,
+        vpn: VirtPageNum,
+        frame: Arc<FrameTracker>,
+    ) {
+        assert_eq!(self.map_type, MapType::Framed);
+        let pte_flags = PTEFlags::from_bits(self.map_perm.bits as u16).unwrap();
+        page_table.map(vpn, frame.ppn, pte_flags);
+        self.data_frames.insert(vpn, frame);
+    }
     pub fn unmap_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
-        if self.map_type == MapType::Framed {
-            self.data_frames.remove(&vpn);
+        if self.map_type == MapType::Framed && self.data_frames.remove(&vpn).is_none() {
+            // the page has never been loaded or has been swapped out
+            if let Some(slot) = self.swapped.remove(&vpn) {
+                swap_free(slot);
+            }
+            return;
         }
         page_table.unmap(vpn);
     }
@@ -314,6 +743,64 @@ impl MapArea {
             self.unmap_one(page_table, vpn);
         }
     }
+    /// Move the end of a framed area to `new_end`,
+    /// new pages are loaded on the first access.
+    pub fn resize(&mut self, page_table: &mut PageTable, new_end: VirtPageNum) {
+        let start = self.vpn_range.get_start();
+        let end = self.vpn_range.get_end();
+        if new_end < end {
+            for vpn in VPNRange::new(new_end, end) {
+                self.unmap_one(page_table, vpn);
+            }
+        }
+        self.vpn_range = VPNRange::new(start, new_end);
+    }
+    /// Split the area at `vpn`, this area keeps the pages before `vpn`
+    /// and the rest are returned as a new area.
+    pub fn split_off(&mut self, vpn: VirtPageNum) -> MapArea {
+        let mut another = MapArea::from_another(self);
+        let pages = vpn.0 - self.vpn_range.get_start().0;
+        another.vpn_range = VPNRange::new(vpn, self.vpn_range.get_end());
+        another.data_frames = self.data_frames.split_off(&vpn);
+        another.swapped = self.swapped.split_off(&vpn);
+        another.file = self.file.as_ref().map(|file| file.pages_from(pages));
+        self.vpn_range = VPNRange::new(self.vpn_range.get_start(), vpn);
+        another
+    }
+    /// Change the permission of the area and its mapped pages,
+    /// which stay dirty if they have been written.
+    pub fn set_perm(&mut self, page_table: &mut PageTable, map_perm: MapPermission) {
+        self.map_perm = map_perm;
+        for (&vpn, frame) in self.data_frames.iter() {
+            let dirty = page_table.translate(vpn).unwrap().flags() & PTEFlags::D;
+            let mut pte_flags = PTEFlags::from_bits(map_perm.bits as u16).unwrap() | dirty;
+            // frames still shared with forked processes are copied on write
+            if !self.shared && Arc::strong_count(frame) > 1 && pte_flags.contains(PTEFlags::W) {
+                pte_flags = (pte_flags - PTEFlags::W) | PTEFlags::COW;
+            }
+            page_table.remap(vpn, frame.ppn, pte_flags);
+        }
+    }
+    /// Pages of a shared file mapping written through `page_table`.
+    pub fn shared_file_pages(&self, page_table: &PageTable) -> Vec<FilePage> {
+        let file = match &self.file {
+            Some(file) if self.shared => file,
+            _ => return Vec::new(),
+        };
+        let start = self.vpn_range.get_start().0;
+        self.data_frames
+            .iter()
+            .filter(|&(&vpn, _)| {
+                page_table
+                    .translate(vpn)
+                    .is_some_and(|pte| pte.flags().contains(PTEFlags::D))
+            })
+            .map(|(vpn, frame)| FilePage {
+                file: file.page(vpn.0 - start),
+                frame: frame.clone(),
+            })
+            .collect()
+    }
     /// data: start-aligned but maybe with shorter length
     /// assume that all frames were cleared before
     pub fn copy_data(&mut self, page_table: &PageTable, data: &[u8]) {
@@ -338,6 +825,105 @@ impl MapArea {
     }
 }
 
+impl Drop for MapArea {
+    fn drop(&mut self) {
+        for &slot in self.swapped.values() {
+            swap_free(slot);
+        }
+    }
+}
+
+/// How a page which is not in memory is filled when it is accessed.
+pub enum LazyPage {
+    /// a page of the file range of the area
+    File(MapFile),
+    /// a page of a shared file mapping, whose frame is shared by all
+    /// processes mapping the page
+    Shared(MapFile),
+    /// a page swapped out to the slot
+    Swap(usize),
+    /// a zero-filled page
+    Zero,
+}
+
+impl LazyPage {
+    /// Fill a zeroed frame with the page, which may block.
+    pub fn load(&self, ppn: PhysPageNum) {
+        match self {
+            LazyPage::File(file) | LazyPage::Shared(file) => file.load(ppn),
+            LazyPage::Swap(slot) => swap_in(*slot, ppn),
+            LazyPage::Zero => {}
+        }
+    }
+    /// The frame of a shared file page already mapped by some process.
+    pub fn cached_frame(&self) -> Option<Arc<FrameTracker>> {
+        match self {
+            LazyPage::Shared(file) => cached_frame(file.page_key()),
+            _ => None,
+        }
+    }
+    /// The frame to be mapped for the page loaded into `frame`. A shared file
+    /// page is cached, or replaced by the one cached in the meantime.
+    pub fn cache(&self, frame: FrameTracker) -> Arc<FrameTracker> {
+        match self {
+            LazyPage::Shared(file) => cache_frame(file.page_key(), frame),
+            _ => Arc::new(frame),
+        }
+    }
+}
+
+/// A range of a file holding the initial content of an area,
+/// which starts from the beginning of the first page of the area.
+#[derive(Clone)]
+pub struct MapFile {
+    pub inode: Arc<Inode>,
+    pub offset: usize,
+    pub len: usize,
+}
+
+impl MapFile {
+    /// The part of the range from the `page_index`-th page of the area.
+    pub fn pages_from(&self, page_index: usize) -> MapFile {
+        let start = page_index * PAGE_SIZE;
+        MapFile {
+            inode: self.inode.clone(),
+            offset: self.offset + start,
+            len: self.len.saturating_sub(start),
+        }
+    }
+    /// The part of the range in the `page_index`-th page of the area,
+    /// the rest of the page is zero-filled.
+    pub fn page(&self, page_index: usize) -> MapFile {
+        let mut file = self.pages_from(page_index);
+        file.len = file.len.min(PAGE_SIZE);
+        file
+    }
+    /// The page of the file the range starts in.
+    fn page_key(&self) -> PageKey {
+        (self.inode.inode_id(), self.offset / PAGE_SIZE)
+    }
+    /// Copy the range into the beginning of a zeroed frame.
+    pub fn load(&self, ppn: PhysPageNum) {
+        self.inode
+            .read_at(self.offset, &mut ppn.get_bytes_array()[..self.len]);
+    }
+}
+
+/// A page of a shared file mapping and the frame holding its content.
+pub struct FilePage {
+    file: MapFile,
+    frame: Arc<FrameTracker>,
+}
+
+impl FilePage {
+    pub fn write_back(&self) {
+        self.file.inode.write_at(
+            self.file.offset,
+            &self.frame.ppn.get_bytes_array()[..self.file.len],
+        );
+    }
+}
+
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum MapType {
     Identical,
@@ -2,17 +2,24 @@ mod address;
 mod frame_allocator;
 mod heap_allocator;
 mod memory_set;
+mod page_cache;
 mod page_table;
+mod swap;
 
 pub use address::VPNRange;
 pub use address::{PhysAddr, PhysPageNum, StepByOne, VirtAddr, VirtPageNum};
-pub use frame_allocator::{FrameTracker, frame_alloc, frame_alloc_more, frame_dealloc};
-pub use memory_set::{KERNEL_SPACE, MapArea, MapPermission, MapType, MemorySet, kernel_token};
+pub use frame_allocator::{
+    FrameTracker, frame_alloc, frame_alloc_more, frame_dealloc, free_frame_count,
+};
+pub use memory_set::{
+    FilePage, KERNEL_SPACE, MapArea, MapFile, MapPermission, MapType, MemorySet, kernel_token,
+};
 use page_table::PTEFlags;
 pub use page_table::{
     PageTable, PageTableEntry, UserBuffer, translated_byte_buffer, translated_ref,
-    translated_refmut, translated_str,
+    translated_refmut, translated_str, user_range_accessible,
 };
+pub use swap::swap_flush;
 
 pub fn init() {
     heap_allocator::init_heap();
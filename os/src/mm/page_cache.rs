@@ -0,0 +1,36 @@
+//! Frames holding the pages of files mapped shared, so that all processes
+//! mapping the same page of a file see the same frame.
+
+use super::FrameTracker;
+use crate::sync::SpinIntrFreeCell;
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use lazy_static::*;
+
+/// The inode id of a file and the index of a page in it.
+pub type PageKey = (u32, usize);
+
+lazy_static! {
+    /// A frame is cached as long as some process maps it.
+    static ref PAGE_CACHE: SpinIntrFreeCell<BTreeMap<PageKey, Weak<FrameTracker>>> =
+        unsafe { SpinIntrFreeCell::new(BTreeMap::new()) };
+}
+
+/// The frame of a page mapped by some process.
+pub fn cached_frame(key: PageKey) -> Option<Arc<FrameTracker>> {
+    PAGE_CACHE.exclusive_access().get(&key)?.upgrade()
+}
+
+/// Cache `frame` loaded with a page, or return the frame of the page
+/// cached by another process in the meantime.
+pub fn cache_frame(key: PageKey, frame: FrameTracker) -> Arc<FrameTracker> {
+    let mut cache = PAGE_CACHE.exclusive_access();
+    if let Some(cached) = cache.get(&key).and_then(Weak::upgrade) {
+        return cached;
+    }
+    // forget the pages no longer mapped by anyone
+    cache.retain(|_, frame| frame.strong_count() > 0);
+    let frame = Arc::new(frame);
+    cache.insert(key, Arc::downgrade(&frame));
+    frame
+}
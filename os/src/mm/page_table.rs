@@ -1,11 +1,12 @@
 use super::{FrameTracker, PhysAddr, PhysPageNum, StepByOne, VirtAddr, VirtPageNum, frame_alloc};
+use crate::task::current_process;
 use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
 use bitflags::*;
 
 bitflags! {
-    pub struct PTEFlags: u8 {
+    pub struct PTEFlags: u16 {
         const V = 1 << 0;
         const R = 1 << 1;
         const W = 1 << 2;
@@ -14,6 +15,9 @@ bitflags! {
         const G = 1 << 5;
         const A = 1 << 6;
         const D = 1 << 7;
+        /// software bit: a read-only page shared by `fork` which becomes
+        /// writable again after being copied
+        const COW = 1 << 8;
     }
 }
 
@@ -36,7 +40,7 @@ impl PageTableEntry {
         (self.bits >> 10 & ((1usize << 44) - 1)).into()
     }
     pub fn flags(&self) -> PTEFlags {
-        PTEFlags::from_bits(self.bits as u8).unwrap()
+        PTEFlags::from_bits_truncate(self.bits as u16)
     }
     pub fn is_valid(&self) -> bool {
         (self.flags() & PTEFlags::V) != PTEFlags::empty()
@@ -50,6 +54,9 @@ impl PageTableEntry {
     pub fn executable(&self) -> bool {
         (self.flags() & PTEFlags::X) != PTEFlags::empty()
     }
+    pub fn is_cow(&self) -> bool {
+        (self.flags() & PTEFlags::COW) != PTEFlags::empty()
+    }
 }
 
 pub struct PageTable {
@@ -115,6 +122,24 @@ impl PageTable {
         assert!(!pte.is_valid(), "vpn {:?} is mapped before mapping", vpn);
         *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
     }
+    /// Replace the mapping of an already mapped `vpn`.
+    pub fn remap(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) {
+        let pte = self.find_pte(vpn).unwrap();
+        assert!(pte.is_valid(), "vpn {:?} is invalid before remapping", vpn);
+        *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
+    }
+    /// Clear the accessed bit of a mapped `vpn`.
+    pub fn clear_accessed(&mut self, vpn: VirtPageNum) {
+        let pte = self.find_pte(vpn).unwrap();
+        assert!(pte.is_valid(), "vpn {:?} is invalid before clearing", vpn);
+        pte.bits &= !(PTEFlags::A.bits as usize);
+    }
+    /// Mark `vpn` accessed and dirty if it is mapped, for a page written by the kernel.
+    pub fn set_dirty(&self, vpn: VirtPageNum) {
+        if let Some(pte) = self.find_pte(vpn).filter(|pte| pte.is_valid()) {
+            pte.bits |= (PTEFlags::A | PTEFlags::D).bits as usize;
+        }
+    }
     #[allow(unused)]
     pub fn unmap(&mut self, vpn: VirtPageNum) {
         let pte = self.find_pte(vpn).unwrap();
@@ -137,15 +162,65 @@ impl PageTable {
     }
 }
 
-pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&'static mut [u8]> {
+/// Find the frame behind `vpn` for the kernel to access for the user, None if
+/// the user can not access it so. Pages of the current process which are not
+/// loaded yet, or shared copy-on-write when `write` is set, are resolved first.
+fn translated_ppn(page_table: &PageTable, vpn: VirtPageNum, write: bool) -> Option<PhysPageNum> {
+    // a page being loaded may be changed in the meantime, so resolve it until it is mapped
+    loop {
+        match page_table.translate(vpn) {
+            Some(pte) if pte.is_valid() && !(write && pte.is_cow()) => {
+                let accessible = pte.flags().contains(PTEFlags::U) && (!write || pte.writable());
+                if accessible && write {
+                    // written back like a page written by the user
+                    page_table.set_dirty(vpn);
+                }
+                return accessible.then_some(pte.ppn());
+            }
+            _ => {
+                if !current_process().handle_page_fault(vpn, write) {
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+/// Whether [ptr, ptr + len) of the user space can be accessed by the kernel
+/// for the user, the pages which have not been loaded are loaded.
+pub fn user_range_accessible(token: usize, ptr: usize, len: usize, write: bool) -> bool {
+    let end = match ptr.checked_add(len) {
+        Some(end) => VirtAddr::from(end).ceil(),
+        None => return false,
+    };
+    let page_table = PageTable::from_token(token);
+    let mut vpn = VirtAddr::from(ptr).floor();
+    while vpn < end {
+        if translated_ppn(&page_table, vpn, write).is_none() {
+            return false;
+        }
+        vpn.step();
+    }
+    true
+}
+
+/// The pages of a user buffer, None if a part of it can not be accessed.
+/// A buffer to be written by the kernel is never shared copy-on-write,
+/// while one only read stays shared.
+pub fn translated_byte_buffer(
+    token: usize,
+    ptr: *const u8,
+    len: usize,
+    write: bool,
+) -> Option<Vec<&'static mut [u8]>> {
     let page_table = PageTable::from_token(token);
     let mut start = ptr as usize;
-    let end = start + len;
+    let end = start.checked_add(len)?;
     let mut v = Vec::new();
     while start < end {
         let start_va = VirtAddr::from(start);
         let mut vpn = start_va.floor();
-        let ppn = page_table.translate(vpn).unwrap().ppn();
+        let ppn = translated_ppn(&page_table, vpn, write)?;
         vpn.step();
         let mut end_va: VirtAddr = vpn.into();
         end_va = end_va.min(VirtAddr::from(end));
@@ -156,43 +231,40 @@ pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&
         }
         start = end_va.into();
     }
-    v
+    Some(v)
 }
 
-/// Load a string from other address spaces into kernel space without an end `\0`.
-pub fn translated_str(token: usize, ptr: *const u8) -> String {
+/// Load a string from other address spaces into kernel space without an end `\0`,
+/// None if it runs into a page which can not be read.
+pub fn translated_str(token: usize, ptr: *const u8) -> Option<String> {
     let page_table = PageTable::from_token(token);
     let mut string = String::new();
     let mut va = ptr as usize;
     loop {
-        let ch: u8 = *(page_table
-            .translate_va(VirtAddr::from(va))
-            .unwrap()
-            .get_mut());
+        let ch: u8 = *translated_pa(&page_table, VirtAddr::from(va), false)?.get_ref();
         if ch == 0 {
             break;
         }
         string.push(ch as char);
         va += 1;
     }
-    string
+    Some(string)
+}
+
+fn translated_pa(page_table: &PageTable, va: VirtAddr, write: bool) -> Option<PhysAddr> {
+    let aligned_pa: PhysAddr = translated_ppn(page_table, va.floor(), write)?.into();
+    let aligned_pa_usize: usize = aligned_pa.into();
+    Some((aligned_pa_usize + va.page_offset()).into())
 }
 
-pub fn translated_ref<T>(token: usize, ptr: *const T) -> &'static T {
+pub fn translated_ref<T>(token: usize, ptr: *const T) -> Option<&'static T> {
     let page_table = PageTable::from_token(token);
-    page_table
-        .translate_va(VirtAddr::from(ptr as usize))
-        .unwrap()
-        .get_ref()
+    translated_pa(&page_table, VirtAddr::from(ptr as usize), false).map(|pa| pa.get_ref())
 }
 
-pub fn translated_refmut<T>(token: usize, ptr: *mut T) -> &'static mut T {
+pub fn translated_refmut<T>(token: usize, ptr: *mut T) -> Option<&'static mut T> {
     let page_table = PageTable::from_token(token);
-    let va = ptr as usize;
-    page_table
-        .translate_va(VirtAddr::from(va))
-        .unwrap()
-        .get_mut()
+    translated_pa(&page_table, VirtAddr::from(ptr as usize), true).map(|pa| pa.get_mut())
 }
 
 pub struct UserBuffer {
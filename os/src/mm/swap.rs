@@ -0,0 +1,124 @@
+//! Slots of the swap area holding swapped out user pages.
+
+use super::{FrameTracker, PhysPageNum};
+use crate::config::{PAGE_SIZE, SWAP_PAGES, SWAP_START_BLOCK};
+use crate::drivers::BLOCK_DEVICE;
+use crate::sync::SpinIntrFreeCell;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+const BLOCK_SZ: usize = 512;
+
+pub struct SwapManager {
+    /// number of references to each slot, the slot is free if it is 0
+    refs: Vec<usize>,
+    /// free slots below `refs.len()`
+    recycled: Vec<usize>,
+    /// frames swapped out but not written to their slots yet
+    writing: BTreeMap<usize, Arc<FrameTracker>>,
+    /// slots in `writing` which no task has started to write
+    queue: VecDeque<usize>,
+}
+
+impl SwapManager {
+    pub fn new() -> Self {
+        Self {
+            refs: Vec::new(),
+            recycled: Vec::new(),
+            writing: BTreeMap::new(),
+            queue: VecDeque::new(),
+        }
+    }
+    fn alloc(&mut self) -> Option<usize> {
+        let slot = if let Some(slot) = self.recycled.pop() {
+            slot
+        } else if self.refs.len() < SWAP_PAGES {
+            self.refs.push(0);
+            self.refs.len() - 1
+        } else {
+            return None;
+        };
+        self.refs[slot] = 1;
+        Some(slot)
+    }
+    fn dup(&mut self, slot: usize) {
+        assert!(self.refs[slot] > 0, "swap slot {} is not allocated", slot);
+        self.refs[slot] += 1;
+    }
+    fn dealloc(&mut self, slot: usize) {
+        assert!(self.refs[slot] > 0, "swap slot {} is not allocated", slot);
+        self.refs[slot] -= 1;
+        if self.refs[slot] == 0 {
+            self.recycled.push(slot);
+        }
+    }
+}
+
+lazy_static! {
+    pub static ref SWAP_MANAGER: SpinIntrFreeCell<SwapManager> =
+        unsafe { SpinIntrFreeCell::new(SwapManager::new()) };
+}
+
+fn slot_blocks(slot: usize) -> impl Iterator<Item = usize> {
+    let start = SWAP_START_BLOCK + slot * (PAGE_SIZE / BLOCK_SZ);
+    start..start + PAGE_SIZE / BLOCK_SZ
+}
+
+/// Allocate a slot for a swapped out page whose content is in `frame`,
+/// the frame is written to the slot by `swap_flush`.
+/// Return None if the swap area is full.
+pub fn swap_out(frame: Arc<FrameTracker>) -> Option<usize> {
+    let mut swap = SWAP_MANAGER.exclusive_access();
+    let slot = swap.alloc()?;
+    // the slot is kept until it is written
+    swap.dup(slot);
+    swap.writing.insert(slot, frame);
+    swap.queue.push_back(slot);
+    Some(slot)
+}
+
+/// Write the frames swapped out by `swap_out` to their slots and free them.
+/// This may block, so it must be called without holding any PCB.
+pub fn swap_flush() {
+    loop {
+        let (slot, frame) = {
+            let mut swap = SWAP_MANAGER.exclusive_access();
+            match swap.queue.pop_front() {
+                Some(slot) => (slot, swap.writing.get(&slot).unwrap().clone()),
+                None => return,
+            }
+        };
+        let bytes = frame.ppn.get_bytes_array();
+        for (block_id, block) in slot_blocks(slot).zip(bytes.chunks(BLOCK_SZ)) {
+            BLOCK_DEVICE.write_block(block_id, block);
+        }
+        let mut swap = SWAP_MANAGER.exclusive_access();
+        swap.writing.remove(&slot);
+        swap.dealloc(slot);
+    }
+}
+
+/// Read the page in `slot` into the frame `ppn`, which may block.
+pub fn swap_in(slot: usize, ppn: PhysPageNum) {
+    let bytes = ppn.get_bytes_array();
+    // the page may not have been written yet
+    let writing = SWAP_MANAGER.exclusive_access().writing.get(&slot).cloned();
+    if let Some(frame) = writing {
+        bytes.copy_from_slice(frame.ppn.get_bytes_array());
+        return;
+    }
+    for (block_id, block) in slot_blocks(slot).zip(bytes.chunks_mut(BLOCK_SZ)) {
+        BLOCK_DEVICE.read_block(block_id, block);
+    }
+}
+
+/// Share `slot` with a forked process.
+pub fn swap_dup(slot: usize) {
+    SWAP_MANAGER.exclusive_access().dup(slot);
+}
+
+pub fn swap_free(slot: usize) {
+    SWAP_MANAGER.exclusive_access().dealloc(slot);
+}
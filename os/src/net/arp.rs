@@ -0,0 +1,203 @@
+//! The ARP table, which maps the addresses of neighbours to their MACs.
+//! It is filled from ARP packets and the IPv4 packets of neighbours, and
+//! the packets to a neighbour being resolved wait in its pending queue.
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec::Vec;
+use lazy_static::lazy_static;
+
+use super::{is_local_address, local_ip, local_mac, next_hop};
+use crate::drivers::{LOOPBACK_DEVICE, NET_DEVICE};
+use crate::sync::SpinIntrFreeCell;
+use crate::timer::get_time_ms;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_ARP: u16 = 0x0806;
+const ARP_REQUEST: u16 = 1;
+const ARP_REPLY: u16 = 2;
+const BROADCAST_MAC: [u8; 6] = [0xff; 6];
+/// Shorter frames are padded by the sender.
+const MIN_FRAME_SIZE: usize = 60;
+
+/// A resolved entry is trusted for so long without being refreshed.
+const REACHABLE_MS: usize = 60_000;
+const REQUEST_INTERVAL_MS: usize = 1000;
+/// The pending packets are dropped if so many requests are not answered.
+const MAX_REQUESTS: usize = 3;
+/// The oldest pending packet is dropped beyond this.
+const MAX_PENDING: usize = 8;
+
+enum Neighbour {
+    Reachable {
+        mac: [u8; 6],
+        expires_at: usize,
+    },
+    Incomplete {
+        pending: VecDeque<Vec<u8>>,
+        requests: usize,
+        request_at: usize,
+    },
+}
+
+lazy_static! {
+    /// Neighbours by their addresses.
+    static ref ARP_TABLE: SpinIntrFreeCell<BTreeMap<u32, Neighbour>> =
+        unsafe { SpinIntrFreeCell::new(BTreeMap::new()) };
+}
+
+fn ethertype(frame: &[u8]) -> Option<u16> {
+    Some(u16::from_be_bytes(frame.get(12..14)?.try_into().unwrap()))
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes(bytes.try_into().unwrap())
+}
+
+fn arp_packet(operation: u16, dest_mac: [u8; 6], target_mac: [u8; 6], target_ip: u32) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(MIN_FRAME_SIZE);
+    frame.extend_from_slice(&dest_mac);
+    frame.extend_from_slice(&local_mac());
+    frame.extend_from_slice(&ETHERTYPE_ARP.to_be_bytes());
+    // ethernet hardware addresses and IPv4 protocol addresses
+    frame.extend_from_slice(&1u16.to_be_bytes());
+    frame.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+    frame.extend_from_slice(&[6, 4]);
+    frame.extend_from_slice(&operation.to_be_bytes());
+    frame.extend_from_slice(&local_mac());
+    frame.extend_from_slice(&local_ip().to_u32().to_be_bytes());
+    frame.extend_from_slice(&target_mac);
+    frame.extend_from_slice(&target_ip.to_be_bytes());
+    frame.resize(MIN_FRAME_SIZE, 0);
+    frame
+}
+
+fn send_request(ip: u32) {
+    NET_DEVICE.transmit(&arp_packet(ARP_REQUEST, BROADCAST_MAC, [0; 6], ip));
+}
+
+/// Record the MAC of a neighbour, and send the packets waiting for it.
+fn learn(ip: u32, mac: [u8; 6]) {
+    let entry = Neighbour::Reachable {
+        mac,
+        expires_at: get_time_ms() + REACHABLE_MS,
+    };
+    let old = ARP_TABLE.exclusive_access().insert(ip, entry);
+    if let Some(Neighbour::Incomplete { pending, .. }) = old {
+        for mut frame in pending {
+            frame[0..6].copy_from_slice(&mac);
+            NET_DEVICE.transmit(&frame);
+        }
+    }
+}
+
+/// Send an IPv4 packet built with any destination MAC, it is addressed
+/// to the next hop once its MAC is resolved, or looped back if it is for
+/// this host.
+pub fn transmit_ip(mut frame: Vec<u8>) {
+    let dest = read_u32(&frame[30..34]);
+    if is_local_address(dest) {
+        frame[0..6].copy_from_slice(&local_mac());
+        LOOPBACK_DEVICE.transmit(&frame);
+        return;
+    }
+    let hop = match next_hop(dest) {
+        Some(hop) => hop,
+        None => {
+            frame[0..6].copy_from_slice(&BROADCAST_MAC);
+            NET_DEVICE.transmit(&frame);
+            return;
+        }
+    };
+    let mut table = ARP_TABLE.exclusive_access();
+    match table.get_mut(&hop) {
+        Some(Neighbour::Reachable { mac, .. }) => {
+            frame[0..6].copy_from_slice(mac);
+            drop(table);
+            NET_DEVICE.transmit(&frame);
+        }
+        Some(Neighbour::Incomplete { pending, .. }) => {
+            if pending.len() == MAX_PENDING {
+                pending.pop_front();
+            }
+            pending.push_back(frame);
+        }
+        None => {
+            let entry = Neighbour::Incomplete {
+                pending: VecDeque::from([frame]),
+                requests: 1,
+                request_at: get_time_ms() + REQUEST_INTERVAL_MS,
+            };
+            table.insert(hop, entry);
+            drop(table);
+            send_request(hop);
+        }
+    }
+}
+
+/// Handle a received ARP packet, requests for this host are answered.
+pub fn handle_arp(frame: &[u8]) {
+    if frame.len() < 42 || frame[14..20] != [0, 1, 8, 0, 6, 4] {
+        return;
+    }
+    let operation = u16::from_be_bytes([frame[20], frame[21]]);
+    let sender_mac: [u8; 6] = frame[22..28].try_into().unwrap();
+    let sender_ip = read_u32(&frame[28..32]);
+    let target_ip = read_u32(&frame[38..42]);
+    // nothing is answered before an address is configured
+    let for_us = target_ip != 0 && target_ip == local_ip().to_u32();
+    // as RFC 826, the sender is recorded if it asks for this host,
+    // or updated if it is known already
+    if sender_ip != 0 && (for_us || ARP_TABLE.exclusive_access().contains_key(&sender_ip)) {
+        learn(sender_ip, sender_mac);
+    }
+    if for_us && operation == ARP_REQUEST {
+        NET_DEVICE.transmit(&arp_packet(ARP_REPLY, sender_mac, sender_mac, sender_ip));
+    }
+}
+
+/// Learn the MAC of the neighbour which sent an IPv4 packet.
+pub fn snoop_ip(frame: &[u8]) {
+    if frame.len() < 34 || ethertype(frame) != Some(ETHERTYPE_IPV4) {
+        return;
+    }
+    let source_ip = read_u32(&frame[26..30]);
+    // packets from other networks come from the gateway's MAC, and those
+    // looped back come from no neighbour
+    if !is_local_address(source_ip) && next_hop(source_ip) == Some(source_ip) {
+        learn(source_ip, frame[6..12].try_into().unwrap());
+    }
+}
+
+pub fn is_arp(frame: &[u8]) -> bool {
+    ethertype(frame) == Some(ETHERTYPE_ARP)
+}
+
+/// Repeat unanswered requests, and forget neighbours not heard from for long.
+pub fn check_arp_timers() {
+    let now = get_time_ms();
+    let mut requests = Vec::new();
+    ARP_TABLE.exclusive_session(|table| {
+        table.retain(|ip, neighbour| match neighbour {
+            Neighbour::Reachable { expires_at, .. } => now < *expires_at,
+            Neighbour::Incomplete {
+                requests: count,
+                request_at,
+                ..
+            } => {
+                if now < *request_at {
+                    true
+                } else if *count < MAX_REQUESTS {
+                    *count += 1;
+                    *request_at = now + REQUEST_INTERVAL_MS;
+                    requests.push(*ip);
+                    true
+                } else {
+                    false
+                }
+            }
+        });
+    });
+    for ip in requests {
+        send_request(ip);
+    }
+}
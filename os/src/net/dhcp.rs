@@ -0,0 +1,300 @@
+//! A DHCP client of RFC 2131, which configures the address of the interface
+//! at boot and renews its lease. It is driven by the replies received and
+//! by the timer, and falls back to the address of QEMU user networking if
+//! no server answers.
+
+use alloc::vec::Vec;
+use lazy_static::lazy_static;
+use log::info;
+use lose_net_stack::IPv4;
+
+use super::arp::transmit_ip;
+use super::ipv4::{IPPROTO_UDP, ipv4_frame};
+use super::{FALLBACK_CONFIG, NetConfig, configure, local_mac, net_config};
+use crate::sync::SpinIntrFreeCell;
+use crate::timer::get_time_ms;
+
+const SERVER_PORT: u16 = 67;
+const CLIENT_PORT: u16 = 68;
+const BOOTREQUEST: u8 = 1;
+const BOOTREPLY: u8 = 2;
+/// Replies are broadcast, as no address is configured to receive them.
+const FLAG_BROADCAST: u16 = 0x8000;
+const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+/// The fixed part of a message, the options come after the magic cookie.
+const BOOTP_SIZE: usize = 236;
+
+const DHCPDISCOVER: u8 = 1;
+const DHCPOFFER: u8 = 2;
+const DHCPREQUEST: u8 = 3;
+const DHCPACK: u8 = 5;
+const DHCPNAK: u8 = 6;
+
+const OPTION_PAD: u8 = 0;
+const OPTION_SUBNET_MASK: u8 = 1;
+const OPTION_ROUTER: u8 = 3;
+const OPTION_REQUESTED_IP: u8 = 50;
+const OPTION_LEASE_TIME: u8 = 51;
+const OPTION_MESSAGE_TYPE: u8 = 53;
+const OPTION_SERVER_ID: u8 = 54;
+const OPTION_PARAMETER_LIST: u8 = 55;
+const OPTION_END: u8 = 255;
+
+const RETRY_MS: usize = 2000;
+/// The client gives up if a message is not answered after so many tries.
+const MAX_TRIES: usize = 4;
+/// Used if a server does not tell the lease time, in seconds.
+const DEFAULT_LEASE: usize = 86400;
+
+#[derive(Clone, Copy)]
+enum State {
+    /// the address is configured statically, or no server answered
+    Stopped,
+    /// a discover is sent, waiting for an offer
+    Selecting,
+    /// the offered address is requested, waiting for an ack
+    Requesting { ip: u32, server: u32 },
+    /// the address is leased, and renewed at half of the lease
+    Bound { server: u32, renew_at: usize },
+}
+
+struct Client {
+    state: State,
+    /// the transaction id of the messages sent
+    xid: u32,
+    tries: usize,
+    retry_at: usize,
+}
+
+lazy_static! {
+    static ref CLIENT: SpinIntrFreeCell<Client> = unsafe {
+        SpinIntrFreeCell::new(Client {
+            state: State::Stopped,
+            xid: 0,
+            tries: 0,
+            retry_at: 0,
+        })
+    };
+}
+
+/// The options of a reply which the client cares about.
+#[derive(Default)]
+struct Reply {
+    message_type: u8,
+    your_ip: u32,
+    netmask: Option<u32>,
+    router: Option<u32>,
+    server: Option<u32>,
+    lease: Option<u32>,
+}
+
+fn read_u32(bytes: &[u8]) -> Option<u32> {
+    Some(u32::from_be_bytes(bytes.get(..4)?.try_into().unwrap()))
+}
+
+/// Build a message of `message_type`, which requests `ip` from `server`
+/// if they are given.
+fn message(xid: u32, message_type: u8, request: Option<(u32, u32)>) -> Vec<u8> {
+    let mut message = Vec::with_capacity(BOOTP_SIZE + 64);
+    // ethernet hardware addresses of 6 bytes, no hop
+    message.extend_from_slice(&[BOOTREQUEST, 1, 6, 0]);
+    message.extend_from_slice(&xid.to_be_bytes());
+    message.extend_from_slice(&0u16.to_be_bytes());
+    message.extend_from_slice(&FLAG_BROADCAST.to_be_bytes());
+    // ciaddr, yiaddr, siaddr and giaddr are all zero
+    message.resize(28, 0);
+    message.extend_from_slice(&local_mac());
+    // the rest of chaddr, sname and file
+    message.resize(BOOTP_SIZE, 0);
+    message.extend_from_slice(&MAGIC_COOKIE);
+    message.extend_from_slice(&[OPTION_MESSAGE_TYPE, 1, message_type]);
+    if let Some((ip, server)) = request {
+        message.extend_from_slice(&[OPTION_REQUESTED_IP, 4]);
+        message.extend_from_slice(&ip.to_be_bytes());
+        message.extend_from_slice(&[OPTION_SERVER_ID, 4]);
+        message.extend_from_slice(&server.to_be_bytes());
+    }
+    message.extend_from_slice(&[
+        OPTION_PARAMETER_LIST,
+        2,
+        OPTION_SUBNET_MASK,
+        OPTION_ROUTER,
+        OPTION_END,
+    ]);
+    message
+}
+
+/// Broadcast a message from the client port to the server port.
+fn send(message: &[u8]) {
+    let mut datagram = Vec::with_capacity(8 + message.len());
+    datagram.extend_from_slice(&CLIENT_PORT.to_be_bytes());
+    datagram.extend_from_slice(&SERVER_PORT.to_be_bytes());
+    datagram.extend_from_slice(&((8 + message.len()) as u16).to_be_bytes());
+    // no checksum
+    datagram.extend_from_slice(&[0, 0]);
+    datagram.extend_from_slice(message);
+    transmit_ip(ipv4_frame(IPv4::from_u32(u32::MAX), IPPROTO_UDP, &datagram));
+}
+
+/// The message to be sent in a state, if any.
+fn message_of(client: &Client) -> Option<Vec<u8>> {
+    match client.state {
+        State::Selecting => Some(message(client.xid, DHCPDISCOVER, None)),
+        State::Requesting { ip, server } => {
+            Some(message(client.xid, DHCPREQUEST, Some((ip, server))))
+        }
+        _ => None,
+    }
+}
+
+/// Enter a state which sends a message, the message is returned to be sent.
+fn enter(client: &mut Client, state: State) -> Option<Vec<u8>> {
+    client.state = state;
+    client.tries = 1;
+    client.retry_at = get_time_ms() + RETRY_MS;
+    message_of(client)
+}
+
+/// Start to look for a server. The current address is kept until a lease
+/// is acquired.
+pub fn start() {
+    let mac = local_mac();
+    let message = CLIENT.exclusive_session(|client| {
+        client.xid = u32::from_be_bytes(mac[2..6].try_into().unwrap()) ^ get_time_ms() as u32;
+        enter(client, State::Selecting)
+    });
+    if let Some(message) = message {
+        send(&message);
+    }
+}
+
+/// Stop the client, the address is not renewed any more.
+pub fn stop() {
+    CLIENT.exclusive_access().state = State::Stopped;
+}
+
+pub fn is_dhcp(frame: &[u8]) -> bool {
+    if frame.len() < 34 || frame[12..14] != [0x08, 0x00] || frame[23] != IPPROTO_UDP {
+        return false;
+    }
+    let header_len = (frame[14] & 0xf) as usize * 4;
+    frame.len() >= 14 + header_len + 8
+        && frame[14 + header_len + 2..14 + header_len + 4] == CLIENT_PORT.to_be_bytes()
+}
+
+/// Parse a reply to this client, None if it is not.
+fn parse_reply(message: &[u8], xid: u32) -> Option<Reply> {
+    if message.len() < BOOTP_SIZE + 4
+        || message[0] != BOOTREPLY
+        || read_u32(&message[4..])? != xid
+        || message[28..34] != local_mac()
+        || message[BOOTP_SIZE..BOOTP_SIZE + 4] != MAGIC_COOKIE
+    {
+        return None;
+    }
+    let mut reply = Reply {
+        your_ip: read_u32(&message[16..])?,
+        ..Default::default()
+    };
+    let mut options = &message[BOOTP_SIZE + 4..];
+    while let Some(&code) = options.first() {
+        match code {
+            OPTION_PAD => {
+                options = &options[1..];
+                continue;
+            }
+            OPTION_END => break,
+            _ => {}
+        }
+        let len = *options.get(1)? as usize;
+        let value = options.get(2..2 + len)?;
+        match code {
+            OPTION_MESSAGE_TYPE => reply.message_type = *value.first()?,
+            OPTION_SUBNET_MASK => reply.netmask = read_u32(value),
+            OPTION_ROUTER => reply.router = read_u32(value),
+            OPTION_SERVER_ID => reply.server = read_u32(value),
+            OPTION_LEASE_TIME => reply.lease = read_u32(value),
+            _ => {}
+        }
+        options = &options[2 + len..];
+    }
+    Some(reply)
+}
+
+/// Handle a datagram to the client port.
+pub fn handle_dhcp(frame: &[u8]) {
+    let header_len = (frame[14] & 0xf) as usize * 4;
+    let datagram = &frame[14 + header_len..];
+    let len = (u16::from_be_bytes([datagram[4], datagram[5]]) as usize).min(datagram.len());
+    if len < 8 {
+        return;
+    }
+    let message = CLIENT.exclusive_session(|client| {
+        let reply = parse_reply(&datagram[8..len], client.xid)?;
+        match (client.state, reply.message_type) {
+            (State::Selecting, DHCPOFFER) => {
+                let server = reply.server?;
+                enter(
+                    client,
+                    State::Requesting {
+                        ip: reply.your_ip,
+                        server,
+                    },
+                )
+            }
+            (State::Requesting { ip, server }, DHCPACK) if reply.your_ip == ip => {
+                let config = NetConfig {
+                    ip,
+                    netmask: reply.netmask.unwrap_or(FALLBACK_CONFIG.netmask),
+                    gateway: reply.router.unwrap_or(0),
+                };
+                configure(config);
+                let lease = reply.lease.map_or(DEFAULT_LEASE, |lease| lease as usize);
+                client.state = State::Bound {
+                    server,
+                    renew_at: get_time_ms() + lease.saturating_mul(500),
+                };
+                let [a, b, c, d] = ip.to_be_bytes();
+                info!("KERN: dhcp: leased {}.{}.{}.{} for {}s", a, b, c, d, lease);
+                None
+            }
+            (State::Requesting { .. }, DHCPNAK) => enter(client, State::Selecting),
+            _ => None,
+        }
+    });
+    if let Some(message) = message {
+        send(&message);
+    }
+}
+
+/// Repeat unanswered messages and renew the lease. If no server answers
+/// and no address is configured, the fallback address is used.
+pub fn check_dhcp_timers() {
+    let now = get_time_ms();
+    let message = CLIENT.exclusive_session(|client| match client.state {
+        State::Selecting | State::Requesting { .. } if now >= client.retry_at => {
+            if client.tries == MAX_TRIES {
+                client.state = State::Stopped;
+                if net_config().ip == 0 {
+                    configure(FALLBACK_CONFIG);
+                    info!("KERN: dhcp: no server answers, use the fallback address");
+                }
+                return None;
+            }
+            client.tries += 1;
+            client.retry_at = now + RETRY_MS;
+            message_of(client)
+        }
+        State::Bound { server, renew_at } if now >= renew_at => enter(
+            client,
+            State::Requesting {
+                ip: net_config().ip,
+                server,
+            },
+        ),
+        _ => None,
+    });
+    if let Some(message) = message {
+        send(&message);
+    }
+}
@@ -0,0 +1,220 @@
+//! ICMP: echo requests to this host are answered, and raw ICMP sockets
+//! receive every ICMP packet with its IPv4 header and send ICMP messages.
+
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use lazy_static::lazy_static;
+use lose_net_stack::IPv4;
+
+use super::arp::transmit_ip;
+use super::ipv4::{IPPROTO_ICMP, IPV4_HEADER_SIZE, checksum, ipv4_frame};
+use super::{MTU, is_local_address};
+use crate::fs::{File, PollEvents};
+use crate::mm::UserBuffer;
+use crate::sync::{Condvar, PollWaiter, SpinIntrFreeCell};
+use crate::task::schedule;
+
+const ICMP_ECHO_REPLY: u8 = 0;
+const ICMP_ECHO_REQUEST: u8 = 8;
+/// Packets beyond this are dropped until the socket is read.
+const MAX_QUEUED: usize = 64;
+
+pub fn is_icmp(frame: &[u8]) -> bool {
+    frame.len() >= 14 + IPV4_HEADER_SIZE
+        && frame[12..14] == [0x08, 0x00]
+        && frame[23] == IPPROTO_ICMP
+}
+
+/// Handle an ICMP packet for this host, it is given to all raw sockets and
+/// an echo request is answered.
+pub fn handle_icmp(frame: &[u8]) {
+    let header_len = (frame[14] & 0xf) as usize * 4;
+    let total_len = u16::from_be_bytes([frame[16], frame[17]]) as usize;
+    if header_len < IPV4_HEADER_SIZE
+        || total_len < header_len + 8
+        || frame.len() < 14 + total_len
+        || !is_local_address(u32::from_be_bytes(frame[30..34].try_into().unwrap()))
+    {
+        return;
+    }
+    let packet = &frame[14..14 + total_len];
+    let message = &packet[header_len..];
+    if checksum(message) != 0 {
+        return;
+    }
+    let source = IPv4::from_u32(u32::from_be_bytes(packet[12..16].try_into().unwrap()));
+    RAW_TABLE.exclusive_session(|table| {
+        for socket in table.iter_mut().flatten() {
+            if socket.shutdown || socket.remote.is_some_and(|remote| remote != source) {
+                continue;
+            }
+            if socket.buffers.len() < MAX_QUEUED {
+                socket.buffers.push_back((packet.to_vec(), source));
+                socket.condvar.signal();
+            }
+        }
+    });
+    if message[0] == ICMP_ECHO_REQUEST {
+        let mut reply = message.to_vec();
+        reply[0] = ICMP_ECHO_REPLY;
+        reply[2..4].copy_from_slice(&[0, 0]);
+        let sum = checksum(&reply).to_be_bytes();
+        reply[2..4].copy_from_slice(&sum);
+        transmit_ip(ipv4_frame(source, IPPROTO_ICMP, &reply));
+    }
+}
+
+struct RawSocket {
+    /// the connected remote, packets from others are not received then
+    remote: Option<IPv4>,
+    /// packets with their IPv4 headers and their sources
+    buffers: VecDeque<(Vec<u8>, IPv4)>,
+    shutdown: bool,
+    condvar: Condvar,
+}
+
+lazy_static! {
+    static ref RAW_TABLE: SpinIntrFreeCell<Vec<Option<RawSocket>>> =
+        unsafe { SpinIntrFreeCell::new(Vec::new()) };
+}
+
+/// A raw ICMP socket, by its index in the raw socket table.
+pub struct ICMP {
+    pub socket_index: usize,
+}
+
+impl ICMP {
+    pub fn new() -> Self {
+        let socket = RawSocket {
+            remote: None,
+            buffers: VecDeque::new(),
+            shutdown: false,
+            condvar: Condvar::new(),
+        };
+        let mut table = RAW_TABLE.exclusive_access();
+        let socket_index = match table.iter().position(|slot| slot.is_none()) {
+            Some(index) => {
+                table[index] = Some(socket);
+                index
+            }
+            None => {
+                table.push(Some(socket));
+                table.len() - 1
+            }
+        };
+        Self { socket_index }
+    }
+
+    pub fn connect(&self, target: IPv4) {
+        RAW_TABLE.exclusive_access()[self.socket_index]
+            .as_mut()
+            .unwrap()
+            .remote = Some(target);
+    }
+
+    pub fn remote(&self) -> Option<IPv4> {
+        RAW_TABLE.exclusive_access()[self.socket_index]
+            .as_ref()
+            .unwrap()
+            .remote
+    }
+
+    /// Stop reading the socket, blocked readers return.
+    pub fn shutdown(&self) {
+        let mut table = RAW_TABLE.exclusive_access();
+        let socket = table[self.socket_index].as_mut().unwrap();
+        socket.shutdown = true;
+        socket.buffers.clear();
+        socket.condvar.broadcast();
+    }
+
+    /// Receive an ICMP packet with its IPv4 header, return its length and
+    /// source. The packet is truncated if the buffer is too small, and 0 is
+    /// returned once the socket is shut down.
+    pub fn recv_from(&self, buf: UserBuffer) -> (usize, Option<IPv4>) {
+        let (packet, source) = loop {
+            let mut table = RAW_TABLE.exclusive_access();
+            let socket = table[self.socket_index].as_mut().unwrap();
+            if let Some(packet) = socket.buffers.pop_front() {
+                break packet;
+            }
+            if socket.shutdown {
+                return (0, None);
+            }
+            let task_cx_ptr = socket.condvar.wait_no_sched();
+            drop(table);
+            schedule(task_cx_ptr);
+        };
+        let mut len = 0;
+        for (dst, byte) in buf.into_iter().zip(packet) {
+            unsafe {
+                *dst = byte;
+            }
+            len += 1;
+        }
+        (len, Some(source))
+    }
+
+    /// Send an ICMP message, whose checksum is filled by the user.
+    /// Return None if it does not fit in a frame.
+    pub fn send_to(&self, buf: UserBuffer, target: IPv4) -> Option<usize> {
+        let len = buf.len();
+        if IPV4_HEADER_SIZE + len > MTU {
+            return None;
+        }
+        let mut message = Vec::with_capacity(len);
+        for buffer in buf.buffers.iter() {
+            message.extend_from_slice(buffer);
+        }
+        transmit_ip(ipv4_frame(target, IPPROTO_ICMP, &message));
+        Some(len)
+    }
+}
+
+impl Drop for ICMP {
+    fn drop(&mut self) {
+        RAW_TABLE.exclusive_access()[self.socket_index] = None;
+    }
+}
+
+impl File for ICMP {
+    fn readable(&self) -> bool {
+        true
+    }
+
+    fn writable(&self) -> bool {
+        true
+    }
+
+    fn read(&self, buf: UserBuffer) -> usize {
+        self.recv_from(buf).0
+    }
+
+    /// Send to the connected remote, nothing is sent if it is not connected.
+    fn write(&self, buf: UserBuffer) -> usize {
+        match self.remote() {
+            Some(target) => self.send_to(buf, target).unwrap_or(0),
+            None => 0,
+        }
+    }
+
+    /// Sending never waits.
+    fn poll_ready(&self) -> PollEvents {
+        let table = RAW_TABLE.exclusive_access();
+        let socket = table[self.socket_index].as_ref().unwrap();
+        if !socket.buffers.is_empty() || socket.shutdown {
+            PollEvents::IN | PollEvents::OUT
+        } else {
+            PollEvents::OUT
+        }
+    }
+
+    fn register_waiter(&self, waiter: &Arc<PollWaiter>) {
+        RAW_TABLE.exclusive_access()[self.socket_index]
+            .as_ref()
+            .unwrap()
+            .condvar
+            .register_poller(waiter);
+    }
+}
@@ -0,0 +1,352 @@
+//! Sockets of the internet domain, on top of the TCP connections, the
+//! listening ports, the UDP sockets and the raw ICMP sockets.
+
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicU16, Ordering};
+use lose_net_stack::IPv4;
+
+use super::icmp::ICMP;
+use super::port_table::{PortFd, listen, pop_accepted};
+use super::tcp::{TCP, shutdown_connection};
+use super::udp::UDP;
+use super::{MTU, Shutdown, SockAddr, Socket, is_local_address, local_ip, source_ip};
+use crate::fs::{File, PollEvents};
+use crate::mm::UserBuffer;
+use crate::sync::{PollWaiter, SpinIntrFreeCell};
+
+/// Ports picked for sockets which are not bound explicitly.
+const EPHEMERAL_PORT_START: u16 = 49152;
+const EPHEMERAL_PORT_COUNT: usize = (u16::MAX - EPHEMERAL_PORT_START) as usize + 1;
+/// IPv4 and UDP headers.
+const UDP_HEADERS_SIZE: usize = 28;
+
+static NEXT_EPHEMERAL_PORT: AtomicU16 = AtomicU16::new(EPHEMERAL_PORT_START);
+
+fn ephemeral_port() -> u16 {
+    NEXT_EPHEMERAL_PORT
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |port| {
+            Some(port.checked_add(1).unwrap_or(EPHEMERAL_PORT_START))
+        })
+        .unwrap()
+}
+
+/// Bind a UDP port, an unused ephemeral port is picked for port 0.
+fn bind_udp(port: u16) -> Option<UDP> {
+    if port != 0 {
+        return UDP::bind(port);
+    }
+    (0..EPHEMERAL_PORT_COUNT).find_map(|_| UDP::bind(ephemeral_port()))
+}
+
+fn is_local(ip: IPv4) -> bool {
+    ip == IPv4::new(0, 0, 0, 0) || is_local_address(ip.to_u32())
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SocketType {
+    Stream,
+    Datagram,
+    /// ICMP messages
+    Raw,
+}
+
+#[derive(Clone)]
+enum InetState {
+    /// neither listening, connected nor bound to a UDP port
+    Idle,
+    Listening(Arc<PortFd>),
+    Connected(Arc<TCP>),
+    /// a datagram socket bound to a local port
+    Bound(Arc<UDP>),
+    Raw(Arc<ICMP>),
+}
+
+struct InetSocketInner {
+    /// the local port of a stream socket, set by bind, listen or connect
+    local_port: Option<u16>,
+    state: InetState,
+    write_shutdown: bool,
+}
+
+pub struct InetSocket {
+    socket_type: SocketType,
+    inner: SpinIntrFreeCell<InetSocketInner>,
+}
+
+impl InetSocket {
+    pub fn new(socket_type: SocketType) -> Self {
+        let state = match socket_type {
+            SocketType::Raw => InetState::Raw(Arc::new(ICMP::new())),
+            _ => InetState::Idle,
+        };
+        Self::with_state(socket_type, None, state)
+    }
+
+    fn with_state(socket_type: SocketType, local_port: Option<u16>, state: InetState) -> Self {
+        Self {
+            socket_type,
+            inner: unsafe {
+                SpinIntrFreeCell::new(InetSocketInner {
+                    local_port,
+                    state,
+                    write_shutdown: false,
+                })
+            },
+        }
+    }
+
+    fn state(&self) -> InetState {
+        self.inner.exclusive_access().state.clone()
+    }
+
+    /// The UDP port of a datagram socket, which is bound to an ephemeral
+    /// port if it is not bound yet.
+    fn udp(&self) -> Option<Arc<UDP>> {
+        let mut inner = self.inner.exclusive_access();
+        match &inner.state {
+            InetState::Bound(udp) => Some(udp.clone()),
+            InetState::Idle => {
+                let udp = Arc::new(bind_udp(0)?);
+                inner.state = InetState::Bound(udp.clone());
+                Some(udp)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Socket for InetSocket {
+    fn bind(&self, addr: SockAddr) -> bool {
+        let SockAddr::Inet(ip, port) = addr else {
+            return false;
+        };
+        if !is_local(ip) {
+            return false;
+        }
+        let mut inner = self.inner.exclusive_access();
+        if let InetState::Raw(_) = inner.state {
+            // raw sockets have no port, and only local addresses are checked
+            return true;
+        }
+        if !matches!(inner.state, InetState::Idle) || inner.local_port.is_some() {
+            return false;
+        }
+        match self.socket_type {
+            SocketType::Stream => {
+                inner.local_port = Some(if port == 0 { ephemeral_port() } else { port });
+            }
+            SocketType::Datagram => match bind_udp(port) {
+                Some(udp) => inner.state = InetState::Bound(Arc::new(udp)),
+                None => return false,
+            },
+            SocketType::Raw => unreachable!(),
+        }
+        true
+    }
+
+    fn listen(&self, backlog: usize) -> bool {
+        let mut inner = self.inner.exclusive_access();
+        if self.socket_type != SocketType::Stream || !matches!(inner.state, InetState::Idle) {
+            return false;
+        }
+        let port = *inner.local_port.get_or_insert_with(ephemeral_port);
+        match listen(port, backlog) {
+            Some(listen_index) => {
+                inner.state = InetState::Listening(Arc::new(PortFd::new(listen_index)));
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn accept(&self) -> Option<(Arc<dyn File>, SockAddr)> {
+        let port = match self.state() {
+            InetState::Listening(port) => port,
+            _ => return None,
+        };
+        let tcp = TCP::accepted(pop_accepted(port.index())?);
+        let peer = SockAddr::Inet(tcp.target, tcp.dport);
+        let socket = Self::with_state(
+            SocketType::Stream,
+            Some(tcp.sport),
+            InetState::Connected(Arc::new(tcp)),
+        );
+        Some((Arc::new(socket), peer))
+    }
+
+    fn connect(&self, addr: SockAddr) -> bool {
+        let SockAddr::Inet(ip, port) = addr else {
+            return false;
+        };
+        match self.socket_type {
+            SocketType::Stream => {
+                let local_port = {
+                    let mut inner = self.inner.exclusive_access();
+                    if !matches!(inner.state, InetState::Idle) {
+                        return false;
+                    }
+                    *inner.local_port.get_or_insert_with(ephemeral_port)
+                };
+                // the handshake blocks, the socket is not locked meanwhile
+                match TCP::connect(ip, local_port, port) {
+                    Some(tcp) => {
+                        self.inner.exclusive_access().state = InetState::Connected(Arc::new(tcp));
+                        true
+                    }
+                    None => false,
+                }
+            }
+            SocketType::Datagram => match self.udp() {
+                Some(udp) => {
+                    udp.connect(ip, port);
+                    true
+                }
+                None => false,
+            },
+            SocketType::Raw => match self.state() {
+                InetState::Raw(icmp) => {
+                    icmp.connect(ip);
+                    true
+                }
+                _ => unreachable!(),
+            },
+        }
+    }
+
+    fn send_to(&self, buf: UserBuffer, addr: Option<SockAddr>) -> Option<usize> {
+        if self.inner.exclusive_access().write_shutdown {
+            return None;
+        }
+        match self.socket_type {
+            SocketType::Stream => match self.state() {
+                InetState::Connected(tcp) => Some(tcp.write(buf)),
+                _ => None,
+            },
+            SocketType::Datagram => {
+                if buf.len() + UDP_HEADERS_SIZE > MTU {
+                    return None;
+                }
+                let udp = self.udp()?;
+                let (target, dport) = match addr {
+                    Some(SockAddr::Inet(ip, port)) => (ip, port),
+                    Some(SockAddr::Unix(_)) => return None,
+                    None => udp.remote()?,
+                };
+                Some(udp.send_to(buf, target, dport))
+            }
+            SocketType::Raw => match self.state() {
+                InetState::Raw(icmp) => {
+                    let target = match addr {
+                        Some(SockAddr::Inet(ip, _)) => ip,
+                        Some(SockAddr::Unix(_)) => return None,
+                        None => icmp.remote()?,
+                    };
+                    icmp.send_to(buf, target)
+                }
+                _ => unreachable!(),
+            },
+        }
+    }
+
+    fn recv_from(&self, buf: UserBuffer) -> Option<(usize, Option<SockAddr>)> {
+        match self.state() {
+            InetState::Connected(tcp) => {
+                Some((tcp.read(buf), Some(SockAddr::Inet(tcp.target, tcp.dport))))
+            }
+            InetState::Bound(udp) => {
+                let (len, source) = udp.recv_from(buf);
+                Some((len, source.map(|(ip, port)| SockAddr::Inet(ip, port))))
+            }
+            InetState::Raw(icmp) => {
+                let (len, source) = icmp.recv_from(buf);
+                Some((len, source.map(|ip| SockAddr::Inet(ip, 0))))
+            }
+            _ => None,
+        }
+    }
+
+    fn shutdown(&self, how: Shutdown) -> bool {
+        let read = matches!(how, Shutdown::Read | Shutdown::Both);
+        let write = matches!(how, Shutdown::Write | Shutdown::Both);
+        let mut inner = self.inner.exclusive_access();
+        match &inner.state {
+            InetState::Connected(tcp) => shutdown_connection(tcp.tcb_index, read, write),
+            InetState::Bound(udp) if read => udp.shutdown(),
+            InetState::Bound(_) => {}
+            InetState::Raw(icmp) if read => icmp.shutdown(),
+            InetState::Raw(_) => {}
+            _ => return false,
+        }
+        inner.write_shutdown |= write;
+        true
+    }
+
+    fn local_addr(&self) -> Option<SockAddr> {
+        let inner = self.inner.exclusive_access();
+        let (ip, port) = match &inner.state {
+            InetState::Connected(tcp) => (source_ip(tcp.target), tcp.sport),
+            InetState::Bound(udp) => (local_ip(), udp.sport),
+            _ => (local_ip(), inner.local_port.unwrap_or(0)),
+        };
+        Some(SockAddr::Inet(ip, port))
+    }
+
+    fn peer_addr(&self) -> Option<SockAddr> {
+        match self.state() {
+            InetState::Connected(tcp) => Some(SockAddr::Inet(tcp.target, tcp.dport)),
+            InetState::Bound(udp) => udp.remote().map(|(ip, port)| SockAddr::Inet(ip, port)),
+            InetState::Raw(icmp) => icmp.remote().map(|ip| SockAddr::Inet(ip, 0)),
+            _ => None,
+        }
+    }
+}
+
+impl File for InetSocket {
+    fn readable(&self) -> bool {
+        true
+    }
+
+    fn writable(&self) -> bool {
+        true
+    }
+
+    fn read(&self, buf: UserBuffer) -> usize {
+        self.recv_from(buf).map_or(0, |(len, _)| len)
+    }
+
+    fn write(&self, buf: UserBuffer) -> usize {
+        self.send_to(buf, None).unwrap_or(0)
+    }
+
+    /// A stream not connected is hung up, a datagram socket not bound yet
+    /// may send.
+    fn poll_ready(&self) -> PollEvents {
+        let write_shutdown = self.inner.exclusive_access().write_shutdown;
+        let mut events = match self.state() {
+            InetState::Idle if self.socket_type == SocketType::Stream => PollEvents::HUP,
+            InetState::Idle => PollEvents::OUT,
+            InetState::Listening(port) => port.poll_ready(),
+            InetState::Connected(tcp) => tcp.poll_ready(),
+            InetState::Bound(udp) => udp.poll_ready(),
+            InetState::Raw(icmp) => icmp.poll_ready(),
+        };
+        if write_shutdown {
+            events.remove(PollEvents::OUT);
+        }
+        events
+    }
+
+    fn register_waiter(&self, waiter: &Arc<PollWaiter>) {
+        match self.state() {
+            InetState::Idle => {}
+            InetState::Listening(port) => port.register_waiter(waiter),
+            InetState::Connected(tcp) => tcp.register_waiter(waiter),
+            InetState::Bound(udp) => udp.register_waiter(waiter),
+            InetState::Raw(icmp) => icmp.register_waiter(waiter),
+        }
+    }
+
+    fn socket(&self) -> Option<&dyn Socket> {
+        Some(self)
+    }
+}
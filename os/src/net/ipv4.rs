@@ -0,0 +1,58 @@
+//! IPv4 packets built by hand, for the protocols lose_net_stack does not
+//! know of.
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU16, Ordering};
+use lose_net_stack::IPv4;
+
+use super::{local_mac, source_ip};
+
+pub const IPPROTO_ICMP: u8 = 1;
+pub const IPPROTO_UDP: u8 = 17;
+pub const IPV4_HEADER_SIZE: usize = 20;
+
+static NEXT_IP_ID: AtomicU16 = AtomicU16::new(0);
+
+/// The internet checksum of RFC 1071.
+pub fn checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data
+        .chunks(2)
+        .map(|word| u16::from_be_bytes([word[0], *word.get(1).unwrap_or(&0)]) as u32)
+        .sum();
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// Build an ethernet frame of an IPv4 packet, the destination MAC is
+/// filled in by `transmit_ip`.
+pub fn ipv4_frame(dest: IPv4, protocol: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(14 + IPV4_HEADER_SIZE + payload.len());
+    frame.extend_from_slice(&[0xff; 6]);
+    frame.extend_from_slice(&local_mac());
+    frame.extend_from_slice(&[0x08, 0x00]);
+    let total_len = ((IPV4_HEADER_SIZE + payload.len()) as u16).to_be_bytes();
+    let id = NEXT_IP_ID.fetch_add(1, Ordering::Relaxed).to_be_bytes();
+    let mut header = [0u8; IPV4_HEADER_SIZE];
+    // version 4, 5 words of header, don't fragment, TTL 64
+    header[..10].copy_from_slice(&[
+        0x45,
+        0,
+        total_len[0],
+        total_len[1],
+        id[0],
+        id[1],
+        0x40,
+        0,
+        64,
+        protocol,
+    ]);
+    header[12..16].copy_from_slice(&source_ip(dest).to_u32().to_be_bytes());
+    header[16..20].copy_from_slice(&dest.to_u32().to_be_bytes());
+    let sum = checksum(&header).to_be_bytes();
+    header[10..12].copy_from_slice(&sum);
+    frame.extend_from_slice(&header);
+    frame.extend_from_slice(payload);
+    frame
+}
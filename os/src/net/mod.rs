@@ -1,3 +1,8 @@
+pub mod arp;
+pub mod dhcp;
+pub mod icmp;
+pub mod inet;
+pub mod ipv4;
 pub mod port_table;
 pub mod socket;
 pub mod tcp;
@@ -5,25 +10,51 @@ pub mod udp;
 
 pub use lose_net_stack::IPv4;
 
-use alloc::{sync::Arc, vec};
-use lose_net_stack::{LoseStack, MacAddress, TcpFlags, results::Packet};
+use alloc::{string::String, sync::Arc, vec, vec::Vec};
+use core::sync::atomic::{AtomicBool, Ordering};
+use lose_net_stack::{LoseStack, MacAddress, results::Packet};
 
 use crate::{
-    drivers::NET_DEVICE,
+    drivers::{LOOPBACK_DEVICE, NET_DEVICE},
+    fs::File,
+    mm::UserBuffer,
     net::socket::{get_socket, push_data},
-    sync::UPIntrFreeCell,
+    sync::{SpinIntrFreeCell, UPSafeCellRaw},
 };
 
-use self::{port_table::check_accept, socket::set_s_a_by_index};
+/// The largest IPv4 packet sent in a frame.
+pub const MTU: usize = 1500;
 
-pub struct NetStack(UPIntrFreeCell<LoseStack>);
+/// 127.0.0.0/8 is looped back to this host.
+const LOOPBACK_NETWORK: u32 = 127 << 24;
+const LOOPBACK_NETMASK: u32 = 0xff00_0000;
+const LOOPBACK_IP: u32 = LOOPBACK_NETWORK | 1;
+
+/// The address configuration of the interface, an unconfigured one has
+/// the address 0.0.0.0.
+#[derive(Clone, Copy)]
+pub struct NetConfig {
+    pub ip: u32,
+    pub netmask: u32,
+    pub gateway: u32,
+}
+
+/// QEMU user networking puts the guest in 10.0.2.0/24 behind 10.0.2.2,
+/// which is used if no DHCP server answers.
+pub const FALLBACK_CONFIG: NetConfig = NetConfig {
+    ip: 10 << 24 | 2 << 8 | 15,
+    netmask: 0xffff_ff00,
+    gateway: 10 << 24 | 2 << 8 | 2,
+};
+
+pub struct NetStack(SpinIntrFreeCell<LoseStack>);
 
 impl NetStack {
     pub fn new() -> Self {
         unsafe {
-            NetStack(UPIntrFreeCell::new(LoseStack::new(
-                IPv4::new(10, 0, 2, 15),
-                MacAddress::new([0x52, 0x54, 0x00, 0x12, 0x34, 0x56]),
+            NetStack(SpinIntrFreeCell::new(LoseStack::new(
+                IPv4::from_u32(0),
+                MacAddress::new(NET_DEVICE.mac()),
             )))
         }
     }
@@ -31,72 +62,195 @@ impl NetStack {
 
 lazy_static::lazy_static! {
     static ref LOSE_NET_STACK: Arc<NetStack> = Arc::new(NetStack::new());
+    static ref NET_CONFIG: SpinIntrFreeCell<NetConfig> = unsafe {
+        SpinIntrFreeCell::new(NetConfig {
+            ip: 0,
+            netmask: 0,
+            gateway: 0,
+        })
+    };
+}
+
+pub fn net_config() -> NetConfig {
+    *NET_CONFIG.exclusive_access()
+}
+
+/// Set the address configuration, packets are sent from the new address
+/// at once.
+pub fn configure(config: NetConfig) {
+    *NET_CONFIG.exclusive_access() = config;
+    LOSE_NET_STACK.0.exclusive_access().ip = IPv4::from_u32(config.ip);
+}
+
+/// Whether `config` can be used: the netmask is contiguous, and the
+/// gateway, if any, is on the network.
+pub fn is_valid_config(config: &NetConfig) -> bool {
+    config.netmask.leading_ones() + config.netmask.trailing_zeros() == 32
+        && (config.gateway == 0 || config.gateway & config.netmask == config.ip & config.netmask)
+}
+
+/// The address of this host.
+pub fn local_ip() -> IPv4 {
+    IPv4::from_u32(NET_CONFIG.exclusive_access().ip)
 }
 
+pub fn local_mac() -> [u8; 6] {
+    NET_DEVICE.mac()
+}
+
+/// Whether `ip` is an address of this host, packets to it are looped back.
+pub fn is_local_address(ip: u32) -> bool {
+    ip & LOOPBACK_NETMASK == LOOPBACK_NETWORK || (ip != 0 && ip == net_config().ip)
+}
+
+/// The source address of packets to `dest`.
+pub fn source_ip(dest: IPv4) -> IPv4 {
+    if dest.to_u32() & LOOPBACK_NETMASK == LOOPBACK_NETWORK {
+        IPv4::from_u32(LOOPBACK_IP)
+    } else {
+        local_ip()
+    }
+}
+
+/// The neighbour a packet to `ip` is sent to, or None if it is broadcast.
+pub fn next_hop(ip: u32) -> Option<u32> {
+    let config = net_config();
+    let network = config.ip & config.netmask;
+    if ip == u32::MAX || ip == network | !config.netmask {
+        None
+    } else if ip & config.netmask == network || config.gateway == 0 {
+        Some(ip)
+    } else {
+        Some(config.gateway)
+    }
+}
+
+/// The address of a socket.
+#[derive(Clone)]
+pub enum SockAddr {
+    /// an IPv4 address and a port
+    Inet(IPv4, u16),
+    /// the absolute path of a socket file, empty for an unnamed socket
+    Unix(String),
+}
+
+/// The directions of a connection to be shut down.
+#[derive(Clone, Copy)]
+pub enum Shutdown {
+    Read,
+    Write,
+    Both,
+}
+
+/// Operations of BSD sockets, the socket of an fd is reached by
+/// `File::socket`. Operations not supported by a socket fail with false
+/// or None.
+pub trait Socket: Send + Sync {
+    fn bind(&self, addr: SockAddr) -> bool;
+    fn listen(&self, backlog: usize) -> bool;
+    /// Wait for a connection, return the socket of it and the peer address.
+    fn accept(&self) -> Option<(Arc<dyn File>, SockAddr)>;
+    fn connect(&self, addr: SockAddr) -> bool;
+    /// Send to `addr`, or to the connected peer if it is None.
+    fn send_to(&self, buf: UserBuffer, addr: Option<SockAddr>) -> Option<usize>;
+    /// Receive data, with the address of the sender if it is known.
+    fn recv_from(&self, buf: UserBuffer) -> Option<(usize, Option<SockAddr>)>;
+    /// Send with open files passed along, which only Unix sockets can do.
+    fn send_msg(
+        &self,
+        buf: UserBuffer,
+        addr: Option<SockAddr>,
+        files: Vec<Arc<dyn File>>,
+    ) -> Option<usize> {
+        if files.is_empty() {
+            self.send_to(buf, addr)
+        } else {
+            None
+        }
+    }
+    /// Receive with the open files passed along.
+    fn recv_msg(&self, buf: UserBuffer) -> Option<(usize, Option<SockAddr>, Vec<Arc<dyn File>>)> {
+        self.recv_from(buf)
+            .map(|(len, source)| (len, source, Vec::new()))
+    }
+    fn shutdown(&self, how: Shutdown) -> bool;
+    fn local_addr(&self) -> Option<SockAddr>;
+    fn peer_addr(&self) -> Option<SockAddr>;
+}
+
+/// Handle the interrupt of the net device, all frames received are processed
+/// and the sockets waiting for them are woken up.
 pub fn net_interrupt_handler() {
-    let mut recv_buf = vec![0u8; 1024];
+    NET_DEVICE.handle_irq();
+    // large enough for an ethernet frame of the full MTU
+    let mut recv_buf = vec![0u8; 2048];
+    while let Some(len) = NET_DEVICE.receive(&mut recv_buf) {
+        handle_frame(&recv_buf[..len]);
+    }
+}
+
+/// Set while a hart drains the loopback device, so frames are handled
+/// in the order they are sent.
+static LOOPBACK_DRAINING: AtomicBool = AtomicBool::new(false);
+/// Frames are received from the loopback device into it, only by the hart
+/// which sets `LOOPBACK_DRAINING`.
+static LOOPBACK_BUF: UPSafeCellRaw<[u8; 2048]> = unsafe { UPSafeCellRaw::new([0; 2048]) };
 
-    let len = NET_DEVICE.receive(&mut recv_buf);
+/// Handle the frames looped back to this host. It is called where no lock
+/// of the net stack is held: after a syscall, and by `check_net_timers`.
+pub fn handle_loopback() {
+    if LOOPBACK_DRAINING.swap(true, Ordering::Acquire) {
+        return;
+    }
+    let recv_buf = LOOPBACK_BUF.get_mut();
+    while let Some(len) = LOOPBACK_DEVICE.receive(recv_buf) {
+        handle_frame(&recv_buf[..len]);
+    }
+    LOOPBACK_DRAINING.store(false, Ordering::Release);
+}
 
-    let packet = LOSE_NET_STACK
-        .0
-        .exclusive_access()
-        .analysis(&recv_buf[..len]);
+/// Run the timers of the protocols and handle the frames looped back.
+/// They allocate and take the locks of the net stack, so this is not done
+/// in an interrupt of the kernel, but at each timer tick of the user space
+/// and on idle harts.
+pub fn check_net_timers() {
+    tcp::check_tcp_timers();
+    arp::check_arp_timers();
+    dhcp::check_dhcp_timers();
+    handle_loopback();
+}
+
+fn handle_frame(frame: &[u8]) {
+    if arp::is_arp(frame) {
+        arp::handle_arp(frame);
+        return;
+    }
+    arp::snoop_ip(frame);
+    if dhcp::is_dhcp(frame) {
+        dhcp::handle_dhcp(frame);
+        return;
+    }
+    if icmp::is_icmp(frame) {
+        icmp::handle_icmp(frame);
+        return;
+    }
+    let packet = LOSE_NET_STACK.0.exclusive_access().analysis(frame);
 
     // println!("[kernel] receive a packet");
-    // hexdump(&recv_buf[..len]);
+    // hexdump(frame);
 
     match packet {
-        Packet::ARP(arp_packet) => {
-            let lose_stack = LOSE_NET_STACK.0.exclusive_access();
-            let reply_packet = arp_packet
-                .reply_packet(lose_stack.ip, lose_stack.mac)
-                .expect("can't build reply");
-            let reply_data = reply_packet.build_data();
-            NET_DEVICE.transmit(&reply_data)
-        }
-
         Packet::UDP(udp_packet) => {
             let target = udp_packet.source_ip;
             let lport = udp_packet.dest_port;
             let rport = udp_packet.source_port;
 
             if let Some(socket_index) = get_socket(target, lport, rport) {
-                push_data(socket_index, udp_packet.data.to_vec());
+                push_data(socket_index, udp_packet.data.to_vec(), target, rport);
             }
         }
 
-        Packet::TCP(tcp_packet) => {
-            let target = tcp_packet.source_ip;
-            let lport = tcp_packet.dest_port;
-            let rport = tcp_packet.source_port;
-            let flags = tcp_packet.flags;
-
-            if flags.contains(TcpFlags::S) {
-                // if it has a port to accept, then response the request
-                if check_accept(lport, &tcp_packet).is_some() {
-                    let mut reply_packet = tcp_packet.ack();
-                    reply_packet.flags = TcpFlags::S | TcpFlags::A;
-                    NET_DEVICE.transmit(&reply_packet.build_data());
-                }
-                return;
-            } else if tcp_packet.flags.contains(TcpFlags::F) {
-                // tcp disconnected
-                let reply_packet = tcp_packet.ack();
-                NET_DEVICE.transmit(&reply_packet.build_data());
-
-                let mut end_packet = reply_packet.ack();
-                end_packet.flags |= TcpFlags::F;
-                NET_DEVICE.transmit(&end_packet.build_data());
-            } else if tcp_packet.flags.contains(TcpFlags::A) && tcp_packet.data_len == 0 {
-                return;
-            }
-
-            if let Some(socket_index) = get_socket(target, lport, rport) {
-                push_data(socket_index, tcp_packet.data.to_vec());
-                set_s_a_by_index(socket_index, tcp_packet.seq, tcp_packet.ack);
-            }
-        }
+        Packet::TCP(tcp_packet) => tcp::handle_segment(&tcp_packet),
         _ => {}
     }
 }
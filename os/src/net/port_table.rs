@@ -1,26 +1,42 @@
-use alloc::{sync::Arc, vec::Vec};
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
 use lazy_static::lazy_static;
-use lose_net_stack::packets::tcp::TCPPacket;
 
-use crate::fs::File;
-use crate::sync::UPIntrFreeCell;
-use crate::task::TaskControlBlock;
+use crate::fs::{File, PollEvents};
+use crate::sync::{Condvar, PollWaiter, SpinIntrFreeCell};
+use crate::task::schedule;
 
-use super::tcp::TCP;
+use super::tcp::close_connection;
+
+/// The most connections of a port which are being established or not
+/// accepted yet.
+pub const BACKLOG: usize = 16;
 
 pub struct Port {
     pub port: u16,
-    pub receivable: bool,
-    pub schedule: Option<Arc<TaskControlBlock>>,
+    /// the backlog given to listen, at most `BACKLOG`
+    pub backlog: usize,
+    /// established connections waiting to be accepted, by their TCP table indices
+    pub accepted: VecDeque<usize>,
+    /// tasks waiting in accept
+    pub condvar: Condvar,
 }
 
 lazy_static! {
-    static ref LISTEN_TABLE: UPIntrFreeCell<Vec<Option<Port>>> =
-        unsafe { UPIntrFreeCell::new(Vec::new()) };
+    static ref LISTEN_TABLE: SpinIntrFreeCell<Vec<Option<Port>>> =
+        unsafe { SpinIntrFreeCell::new(Vec::new()) };
 }
 
-pub fn listen(port: u16) -> Option<usize> {
+/// Listen a port, return None if it is listened already.
+pub fn listen(port: u16, backlog: usize) -> Option<usize> {
     let mut listen_table = LISTEN_TABLE.exclusive_access();
+    if listen_table
+        .iter()
+        .any(|x| x.as_ref().is_some_and(|t| t.port == port))
+    {
+        return None;
+    }
     let mut index = usize::MAX;
     for i in 0..listen_table.len() {
         if listen_table[i].is_none() {
@@ -31,8 +47,9 @@ pub fn listen(port: u16) -> Option<usize> {
 
     let listen_port = Port {
         port,
-        receivable: false,
-        schedule: None,
+        backlog: backlog.clamp(1, BACKLOG),
+        accepted: VecDeque::new(),
+        condvar: Condvar::new(),
     };
 
     if index == usize::MAX {
@@ -44,67 +61,55 @@ pub fn listen(port: u16) -> Option<usize> {
     }
 }
 
-// can accept request
-pub fn accept(listen_index: usize, task: Arc<TaskControlBlock>) {
-    let mut listen_table = LISTEN_TABLE.exclusive_access();
-    assert!(listen_index < listen_table.len());
-    let listen_port = listen_table[listen_index].as_mut();
-    assert!(listen_port.is_some());
-    let listen_port = listen_port.unwrap();
-    listen_port.receivable = true;
-    listen_port.schedule = Some(task);
+/// Index of the listen table entry of a port.
+pub fn listening_index(port: u16) -> Option<usize> {
+    let listen_table = LISTEN_TABLE.exclusive_access();
+    listen_table
+        .iter()
+        .position(|x| x.as_ref().is_some_and(|t| t.port == port))
 }
 
-pub fn port_acceptable(listen_index: usize) -> bool {
-    let mut listen_table = LISTEN_TABLE.exclusive_access();
-    assert!(listen_index < listen_table.len());
+pub fn accepted_count(listen_index: usize) -> usize {
+    let listen_table = LISTEN_TABLE.exclusive_access();
+    listen_table[listen_index]
+        .as_ref()
+        .map_or(0, |port| port.accepted.len())
+}
 
-    let listen_port = listen_table[listen_index].as_mut();
-    listen_port.map_or(false, |x| x.receivable)
+pub fn backlog(listen_index: usize) -> usize {
+    let listen_table = LISTEN_TABLE.exclusive_access();
+    listen_table[listen_index]
+        .as_ref()
+        .map_or(0, |port| port.backlog)
 }
 
-// check whether it can accept request
-pub fn check_accept(port: u16, tcp_packet: &TCPPacket) -> Option<()> {
-    LISTEN_TABLE.exclusive_session(|listen_table| {
-        let mut listen_ports: Vec<&mut Option<Port>> = listen_table
-            .iter_mut()
-            .filter(|x| match x {
-                Some(t) => t.port == port && t.receivable == true,
-                None => false,
-            })
-            .collect();
-        if listen_ports.len() == 0 {
-            None
-        } else {
-            let listen_port = listen_ports[0].as_mut().unwrap();
-            let task = listen_port.schedule.clone().unwrap();
-            // wakeup_task(Arc::clone(&listen_port.schedule.clone().unwrap()));
-            listen_port.schedule = None;
-            listen_port.receivable = false;
-
-            accept_connection(port, tcp_packet, task);
-            Some(())
+/// Queue an established connection to be accepted, return false
+/// if the port is not listened any more.
+pub fn push_accepted(listen_index: usize, port: u16, tcb_index: usize) -> bool {
+    let mut listen_table = LISTEN_TABLE.exclusive_access();
+    match listen_table.get_mut(listen_index) {
+        Some(Some(listen_port)) if listen_port.port == port => {
+            listen_port.accepted.push_back(tcb_index);
+            listen_port.condvar.signal();
+            true
         }
-    })
+        _ => false,
+    }
 }
 
-pub fn accept_connection(_port: u16, tcp_packet: &TCPPacket, task: Arc<TaskControlBlock>) {
-    let process = task.process.upgrade().unwrap();
-    let mut inner = process.inner_exclusive_access();
-    let fd = inner.alloc_fd();
-
-    let tcp_socket = TCP::new(
-        tcp_packet.source_ip,
-        tcp_packet.dest_port,
-        tcp_packet.source_port,
-        tcp_packet.seq,
-        tcp_packet.ack,
-    );
-
-    inner.fd_table[fd] = Some(Arc::new(tcp_socket));
-
-    let cx = task.inner_exclusive_access().get_trap_cx();
-    cx.x[10] = fd;
+/// Take an established connection of a listening port, waiting until one
+/// arrives. Return None if the port is not listened any more.
+pub fn pop_accepted(listen_index: usize) -> Option<usize> {
+    loop {
+        let mut listen_table = LISTEN_TABLE.exclusive_access();
+        let listen_port = listen_table.get_mut(listen_index)?.as_mut()?;
+        if let Some(tcb_index) = listen_port.accepted.pop_front() {
+            return Some(tcb_index);
+        }
+        let task_cx_ptr = listen_port.condvar.wait_no_sched();
+        drop(listen_table);
+        schedule(task_cx_ptr);
+    }
 }
 
 // store in the fd_table, delete the listen table when close the application.
@@ -114,11 +119,22 @@ impl PortFd {
     pub fn new(port_index: usize) -> Self {
         PortFd(port_index)
     }
+
+    pub fn index(&self) -> usize {
+        self.0
+    }
 }
 
 impl Drop for PortFd {
     fn drop(&mut self) {
-        LISTEN_TABLE.exclusive_access()[self.0] = None
+        let port = LISTEN_TABLE.exclusive_access()[self.0].take();
+        // connections not accepted yet are closed
+        if let Some(port) = port {
+            port.condvar.broadcast();
+            for tcb_index in port.accepted {
+                close_connection(tcb_index);
+            }
+        }
     }
 }
 
@@ -138,4 +154,19 @@ impl File for PortFd {
     fn write(&self, _buf: crate::mm::UserBuffer) -> usize {
         0
     }
+
+    /// Readable when a connection is waiting to be accepted.
+    fn poll_ready(&self) -> PollEvents {
+        let listen_table = LISTEN_TABLE.exclusive_access();
+        match &listen_table[self.0] {
+            Some(port) if !port.accepted.is_empty() => PollEvents::IN,
+            _ => PollEvents::empty(),
+        }
+    }
+
+    fn register_waiter(&self, waiter: &Arc<PollWaiter>) {
+        if let Some(port) = &LISTEN_TABLE.exclusive_access()[self.0] {
+            port.condvar.register_poller(waiter);
+        }
+    }
 }
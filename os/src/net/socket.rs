@@ -1,49 +1,28 @@
 use alloc::collections::VecDeque;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 use lazy_static::lazy_static;
 use lose_net_stack::IPv4;
 
-use crate::sync::UPIntrFreeCell;
+use crate::sync::{Condvar, PollWaiter, SpinIntrFreeCell};
+use crate::task::schedule;
 
-// TODO: specify the protocol, TCP or UDP
+// UDP sockets, TCP connections are kept in the TCP table
 pub struct Socket {
-    pub raddr: IPv4,                // remote address
-    pub lport: u16,                 // local port
-    pub rport: u16,                 // rempote port
-    pub buffers: VecDeque<Vec<u8>>, // datas
-    pub seq: u32,
-    pub ack: u32,
+    pub lport: u16,                              // local port
+    pub remote: Option<(IPv4, u16)>,             // connected remote address and port
+    pub buffers: VecDeque<(Vec<u8>, IPv4, u16)>, // datas with their sources
+    pub shutdown: bool,                          // no more datas are read
+    pub condvar: Condvar,                        // tasks waiting for datas
 }
 
 lazy_static! {
-    static ref SOCKET_TABLE: UPIntrFreeCell<Vec<Option<Socket>>> =
-        unsafe { UPIntrFreeCell::new(Vec::new()) };
-}
-
-/// get the seq and ack by socket index
-pub fn get_s_a_by_index(index: usize) -> Option<(u32, u32)> {
-    let socket_table = SOCKET_TABLE.exclusive_access();
-
-    assert!(index < socket_table.len());
-
-    socket_table.get(index).map_or(None, |x| match x {
-        Some(x) => Some((x.seq, x.ack)),
-        None => None,
-    })
-}
-
-pub fn set_s_a_by_index(index: usize, seq: u32, ack: u32) {
-    let mut socket_table = SOCKET_TABLE.exclusive_access();
-
-    assert!(socket_table.len() > index);
-    assert!(socket_table[index].is_some());
-
-    let sock = socket_table[index].as_mut().unwrap();
-
-    sock.ack = ack;
-    sock.seq = seq;
+    static ref SOCKET_TABLE: SpinIntrFreeCell<Vec<Option<Socket>>> =
+        unsafe { SpinIntrFreeCell::new(Vec::new()) };
 }
 
+/// Find the socket which receives a datagram, a connected socket only
+/// receives the datagrams of its remote.
 pub fn get_socket(raddr: IPv4, lport: u16, rport: u16) -> Option<usize> {
     let socket_table = SOCKET_TABLE.exclusive_access();
     for i in 0..socket_table.len() {
@@ -53,19 +32,24 @@ pub fn get_socket(raddr: IPv4, lport: u16, rport: u16) -> Option<usize> {
         }
 
         let sock = sock.as_ref().unwrap();
-        if sock.raddr == raddr && sock.lport == lport && sock.rport == rport {
+        if sock.lport == lport && sock.remote.is_none_or(|remote| remote == (raddr, rport)) {
             return Some(i);
         }
     }
     None
 }
 
-pub fn add_socket(raddr: IPv4, lport: u16, rport: u16) -> Option<usize> {
-    if get_socket(raddr, lport, rport).is_some() {
+/// Bind a local port, return None if it is in use.
+pub fn add_socket(lport: u16) -> Option<usize> {
+    let mut socket_table = SOCKET_TABLE.exclusive_access();
+    if socket_table
+        .iter()
+        .flatten()
+        .any(|sock| sock.lport == lport)
+    {
         return None;
     }
 
-    let mut socket_table = SOCKET_TABLE.exclusive_access();
     let mut index = usize::MAX;
     for i in 0..socket_table.len() {
         if socket_table[i].is_none() {
@@ -75,12 +59,11 @@ pub fn add_socket(raddr: IPv4, lport: u16, rport: u16) -> Option<usize> {
     }
 
     let socket = Socket {
-        raddr,
         lport,
-        rport,
+        remote: None,
         buffers: VecDeque::new(),
-        seq: 0,
-        ack: 0,
+        shutdown: false,
+        condvar: Condvar::new(),
     };
 
     if index == usize::MAX {
@@ -100,24 +83,84 @@ pub fn remove_socket(index: usize) {
     socket_table[index] = None;
 }
 
-pub fn push_data(index: usize, data: Vec<u8>) {
+pub fn set_remote(index: usize, remote: Option<(IPv4, u16)>) {
     let mut socket_table = SOCKET_TABLE.exclusive_access();
 
     assert!(socket_table.len() > index);
     assert!(socket_table[index].is_some());
 
-    socket_table[index]
-        .as_mut()
-        .unwrap()
-        .buffers
-        .push_back(data);
+    socket_table[index].as_mut().unwrap().remote = remote;
+}
+
+pub fn get_remote(index: usize) -> Option<(IPv4, u16)> {
+    let socket_table = SOCKET_TABLE.exclusive_access();
+
+    assert!(socket_table.len() > index);
+    assert!(socket_table[index].is_some());
+
+    socket_table[index].as_ref().unwrap().remote
 }
 
-pub fn pop_data(index: usize) -> Option<Vec<u8>> {
+/// Stop reading the socket, blocked readers return.
+pub fn shutdown_socket(index: usize) {
     let mut socket_table = SOCKET_TABLE.exclusive_access();
 
     assert!(socket_table.len() > index);
     assert!(socket_table[index].is_some());
 
-    socket_table[index].as_mut().unwrap().buffers.pop_front()
+    let socket = socket_table[index].as_mut().unwrap();
+    socket.shutdown = true;
+    socket.buffers.clear();
+    socket.condvar.broadcast();
+}
+
+pub fn push_data(index: usize, data: Vec<u8>, raddr: IPv4, rport: u16) {
+    let mut socket_table = SOCKET_TABLE.exclusive_access();
+
+    assert!(socket_table.len() > index);
+    assert!(socket_table[index].is_some());
+
+    let socket = socket_table[index].as_mut().unwrap();
+    if !socket.shutdown {
+        socket.buffers.push_back((data, raddr, rport));
+        socket.condvar.signal();
+    }
+}
+
+/// Take a datagram of the socket with its source, waiting until one arrives.
+/// Return None if the socket is shut down.
+pub fn pop_data(index: usize) -> Option<(Vec<u8>, IPv4, u16)> {
+    loop {
+        let mut socket_table = SOCKET_TABLE.exclusive_access();
+
+        assert!(socket_table.len() > index);
+        assert!(socket_table[index].is_some());
+
+        let socket = socket_table[index].as_mut().unwrap();
+        if let Some(data) = socket.buffers.pop_front() {
+            return Some(data);
+        }
+        if socket.shutdown {
+            return None;
+        }
+        let task_cx_ptr = socket.condvar.wait_no_sched();
+        drop(socket_table);
+        schedule(task_cx_ptr);
+    }
+}
+
+/// Whether a datagram is queued, or the socket is shut down.
+pub fn socket_readable(index: usize) -> bool {
+    let socket_table = SOCKET_TABLE.exclusive_access();
+    let socket = socket_table[index].as_ref().unwrap();
+    !socket.buffers.is_empty() || socket.shutdown
+}
+
+pub fn register_socket_poller(index: usize, waiter: &Arc<PollWaiter>) {
+    let socket_table = SOCKET_TABLE.exclusive_access();
+    socket_table[index]
+        .as_ref()
+        .unwrap()
+        .condvar
+        .register_poller(waiter);
 }
@@ -1,41 +1,845 @@
+//! TCP connections following the state machine of RFC 793, with
+//! retransmission, reassembly of out-of-order segments and flow control.
+
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
 use alloc::vec;
+use alloc::vec::Vec;
+use lazy_static::lazy_static;
 use lose_net_stack::IPv4;
 use lose_net_stack::MacAddress;
 use lose_net_stack::TcpFlags;
 use lose_net_stack::packets::tcp::TCPPacket;
 
-use crate::{drivers::NET_DEVICE, fs::File};
+use crate::fs::{File, PollEvents};
+use crate::mm::UserBuffer;
+use crate::sync::{Condvar, PollWaiter, SpinIntrFreeCell};
+use crate::task::schedule;
+use crate::timer::get_time_ms;
+
+use super::arp::transmit_ip;
+use super::port_table::{accepted_count, backlog, listening_index, push_accepted};
+use super::{local_mac, source_ip};
+
+/// No option is sent or parsed, so segments are limited to the default MSS.
+const MSS: usize = 536;
+/// The window field is not scaled, so at most 65535 bytes are advertised.
+const RECV_BUFFER_SIZE: usize = 65535;
+const SEND_BUFFER_SIZE: usize = 65536;
+const INITIAL_RTO_MS: usize = 1000;
+const MIN_RTO_MS: usize = 200;
+const MAX_RTO_MS: usize = 60_000;
+/// The connection is aborted if a segment has been retransmitted so many times.
+const MAX_RETRIES: usize = 8;
+/// Maximum segment lifetime, a connection stays in TIME-WAIT for twice of it.
+const MSL_MS: usize = 1000;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum TcpState {
+    Closed,
+    SynSent,
+    SynReceived,
+    Established,
+    FinWait1,
+    FinWait2,
+    CloseWait,
+    Closing,
+    LastAck,
+    TimeWait,
+}
+
+/// Whether sequence number `a` is before `b`, sequence numbers wrap around.
+fn seq_lt(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+fn seq_le(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) <= 0
+}
+
+/// Initial sequence numbers are driven by the clock as suggested by RFC 793.
+fn initial_sequence() -> u32 {
+    (get_time_ms() as u32).wrapping_mul(250)
+}
+
+/// A segment which is sent but not acknowledged yet.
+struct Segment {
+    seq: u32,
+    /// SYN or FIN if it carries one
+    flags: TcpFlags,
+    data: Vec<u8>,
+    sent_ms: usize,
+    /// retransmitted segments are not used to measure the round-trip time
+    retransmitted: bool,
+}
+
+impl Segment {
+    /// Length in sequence space, SYN and FIN take one each.
+    fn len(&self) -> u32 {
+        let mut len = self.data.len() as u32;
+        if self.flags.contains(TcpFlags::S) {
+            len += 1;
+        }
+        if self.flags.contains(TcpFlags::F) {
+            len += 1;
+        }
+        len
+    }
+}
+
+/// Fields of an arriving segment.
+struct IncomingSegment<'a> {
+    seq: u32,
+    ack: u32,
+    flags: TcpFlags,
+    win: u16,
+    data: &'a [u8],
+}
+
+impl IncomingSegment<'_> {
+    fn len(&self) -> u32 {
+        let mut len = self.data.len() as u32;
+        if self.flags.contains(TcpFlags::S) {
+            len += 1;
+        }
+        if self.flags.contains(TcpFlags::F) {
+            len += 1;
+        }
+        len
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn build_segment(
+    remote_ip: IPv4,
+    local_port: u16,
+    remote_port: u16,
+    seq: u32,
+    ack: u32,
+    flags: TcpFlags,
+    win: u16,
+    data: &[u8],
+) -> Vec<u8> {
+    TCPPacket {
+        source_ip: source_ip(remote_ip),
+        source_mac: MacAddress::new(local_mac()),
+        source_port: local_port,
+        dest_ip: remote_ip,
+        // filled in once the MAC of the next hop is resolved
+        dest_mac: MacAddress::new([0xff, 0xff, 0xff, 0xff, 0xff, 0xff]),
+        dest_port: remote_port,
+        data_len: data.len(),
+        seq,
+        ack,
+        flags,
+        win,
+        urg: 0,
+        data,
+    }
+    .build_data()
+}
+
+/// RST answering a segment which does not belong to any connection.
+fn reset_reply(packet: &TCPPacket, out: &mut Vec<Vec<u8>>) {
+    let segment = IncomingSegment {
+        seq: packet.seq,
+        ack: packet.ack,
+        flags: packet.flags,
+        win: packet.win,
+        data: packet.data,
+    };
+    let (seq, ack, flags) = if segment.flags.contains(TcpFlags::A) {
+        (segment.ack, 0, TcpFlags::R)
+    } else {
+        (
+            0,
+            segment.seq.wrapping_add(segment.len()),
+            TcpFlags::R | TcpFlags::A,
+        )
+    };
+    out.push(build_segment(
+        packet.source_ip,
+        packet.dest_port,
+        packet.source_port,
+        seq,
+        ack,
+        flags,
+        0,
+        &[],
+    ));
+}
+
+fn transmit_all(packets: Vec<Vec<u8>>) {
+    for packet in packets {
+        transmit_ip(packet);
+    }
+}
+
+pub struct TcpControlBlock {
+    pub state: TcpState,
+    pub local_port: u16,
+    pub remote_ip: IPv4,
+    pub remote_port: u16,
+    /// the listening port which opened it passively, until it is established
+    listen_index: Option<usize>,
+    /// whether a socket or an accept queue refers to it, otherwise
+    /// it is freed once closed
+    owned: bool,
+    /// the user has closed the socket or shut down writing, FIN is sent
+    /// after the send buffer drains
+    close_requested: bool,
+    /// the user has closed the socket or shut down reading, data received
+    /// is dropped
+    read_closed: bool,
+    fin_sent: bool,
+    fin_received: bool,
+    /// the connection is reset by the peer or aborted
+    reset: bool,
+    // send sequence space
+    snd_una: u32,
+    snd_nxt: u32,
+    snd_wnd: u32,
+    // receive sequence space
+    rcv_nxt: u32,
+    /// bytes received in order but not read yet
+    recv_buffer: VecDeque<u8>,
+    /// segments received ahead of `rcv_nxt`, with whether they carry FIN
+    out_of_order: Vec<(u32, Vec<u8>, bool)>,
+    /// bytes written but not sent yet
+    send_buffer: VecDeque<u8>,
+    unacked: VecDeque<Segment>,
+    srtt_ms: Option<usize>,
+    rttvar_ms: usize,
+    rto_ms: usize,
+    retransmit_at: Option<usize>,
+    retries: usize,
+    time_wait_until: usize,
+    /// tasks waiting for data, buffer space or the connection state to change
+    wait_queue: Condvar,
+}
+
+impl TcpControlBlock {
+    fn new(remote_ip: IPv4, local_port: u16, remote_port: u16) -> Self {
+        let iss = initial_sequence();
+        Self {
+            state: TcpState::Closed,
+            local_port,
+            remote_ip,
+            remote_port,
+            listen_index: None,
+            owned: false,
+            close_requested: false,
+            read_closed: false,
+            fin_sent: false,
+            fin_received: false,
+            reset: false,
+            snd_una: iss,
+            snd_nxt: iss,
+            snd_wnd: 0,
+            rcv_nxt: 0,
+            recv_buffer: VecDeque::new(),
+            out_of_order: Vec::new(),
+            send_buffer: VecDeque::new(),
+            unacked: VecDeque::new(),
+            srtt_ms: None,
+            rttvar_ms: 0,
+            rto_ms: INITIAL_RTO_MS,
+            retransmit_at: None,
+            retries: 0,
+            time_wait_until: 0,
+            wait_queue: Condvar::new(),
+        }
+    }
+
+    fn recv_window(&self) -> usize {
+        RECV_BUFFER_SIZE.saturating_sub(self.recv_buffer.len())
+    }
+
+    fn send_segment(&self, seq: u32, flags: TcpFlags, data: &[u8], out: &mut Vec<Vec<u8>>) {
+        // everything but the first SYN acknowledges what has been received
+        let flags = if self.state == TcpState::SynSent {
+            flags
+        } else {
+            flags | TcpFlags::A
+        };
+        out.push(build_segment(
+            self.remote_ip,
+            self.local_port,
+            self.remote_port,
+            seq,
+            self.rcv_nxt,
+            flags,
+            self.recv_window() as u16,
+            data,
+        ));
+    }
+
+    fn send_ack(&self, out: &mut Vec<Vec<u8>>) {
+        self.send_segment(self.snd_nxt, TcpFlags::empty(), &[], out);
+    }
+
+    fn send_reset(&self, out: &mut Vec<Vec<u8>>) {
+        self.send_segment(self.snd_nxt, TcpFlags::R, &[], out);
+    }
+
+    /// Send a segment carrying new data, SYN or FIN, which is kept until acknowledged.
+    fn send_new(&mut self, flags: TcpFlags, data: Vec<u8>, out: &mut Vec<Vec<u8>>) {
+        let now = get_time_ms();
+        let segment = Segment {
+            seq: self.snd_nxt,
+            flags,
+            data,
+            sent_ms: now,
+            retransmitted: false,
+        };
+        let data_flags = if segment.data.is_empty() {
+            flags
+        } else {
+            flags | TcpFlags::P
+        };
+        self.send_segment(segment.seq, data_flags, &segment.data, out);
+        self.snd_nxt = self.snd_nxt.wrapping_add(segment.len());
+        self.unacked.push_back(segment);
+        if self.retransmit_at.is_none() {
+            self.retransmit_at = Some(now + self.rto_ms);
+        }
+    }
+
+    /// Send buffered data as the window allows, and FIN after all of it.
+    fn output(&mut self, out: &mut Vec<Vec<u8>>) {
+        if !matches!(self.state, TcpState::Established | TcpState::CloseWait) {
+            return;
+        }
+        loop {
+            let in_flight = self.snd_nxt.wrapping_sub(self.snd_una) as usize;
+            // a zero window is probed with one byte, which is retransmitted
+            // until the window opens
+            let window = if self.snd_wnd == 0 && self.unacked.is_empty() {
+                1
+            } else {
+                self.snd_wnd as usize
+            };
+            let len = MSS
+                .min(window.saturating_sub(in_flight))
+                .min(self.send_buffer.len());
+            if len == 0 {
+                break;
+            }
+            let data: Vec<u8> = self.send_buffer.drain(..len).collect();
+            self.send_new(TcpFlags::empty(), data, out);
+        }
+        if self.close_requested && self.send_buffer.is_empty() && !self.fin_sent {
+            self.send_new(TcpFlags::F, Vec::new(), out);
+            self.fin_sent = true;
+            self.state = match self.state {
+                TcpState::Established => TcpState::FinWait1,
+                _ => TcpState::LastAck,
+            };
+        }
+    }
+
+    fn abort(&mut self) {
+        self.state = TcpState::Closed;
+        self.reset = true;
+        self.unacked.clear();
+        self.send_buffer.clear();
+        self.out_of_order.clear();
+        self.retransmit_at = None;
+    }
+
+    fn enter_time_wait(&mut self) {
+        self.state = TcpState::TimeWait;
+        self.time_wait_until = get_time_ms() + 2 * MSL_MS;
+        self.unacked.clear();
+        self.retransmit_at = None;
+    }
+
+    /// Update the retransmission timeout with a round-trip time sample as in RFC 6298.
+    fn update_rto(&mut self, rtt_ms: usize) {
+        match self.srtt_ms {
+            None => {
+                self.srtt_ms = Some(rtt_ms);
+                self.rttvar_ms = rtt_ms / 2;
+            }
+            Some(srtt_ms) => {
+                self.rttvar_ms = (3 * self.rttvar_ms + srtt_ms.abs_diff(rtt_ms)) / 4;
+                self.srtt_ms = Some((7 * srtt_ms + rtt_ms) / 8);
+            }
+        }
+        self.rto_ms =
+            (self.srtt_ms.unwrap() + (4 * self.rttvar_ms).max(1)).clamp(MIN_RTO_MS, MAX_RTO_MS);
+    }
+
+    /// Remove the segments acknowledged by `ack` from the retransmission queue.
+    fn acknowledge(&mut self, ack: u32) {
+        let now = get_time_ms();
+        self.snd_una = ack;
+        while let Some(segment) = self.unacked.front_mut() {
+            if seq_le(segment.seq.wrapping_add(segment.len()), ack) {
+                let segment = self.unacked.pop_front().unwrap();
+                if !segment.retransmitted {
+                    self.update_rto(now - segment.sent_ms);
+                }
+            } else {
+                if seq_lt(segment.seq, ack) {
+                    // partially acknowledged
+                    let acked = ack.wrapping_sub(segment.seq) as usize;
+                    segment.data.drain(..acked);
+                    segment.seq = ack;
+                }
+                break;
+            }
+        }
+        self.retries = 0;
+        self.retransmit_at = if self.unacked.is_empty() {
+            None
+        } else {
+            Some(now + self.rto_ms)
+        };
+    }
+
+    fn retransmit(&mut self, out: &mut Vec<Vec<u8>>) {
+        let now = get_time_ms();
+        let (seq, flags, data) = match self.unacked.front_mut() {
+            Some(segment) => {
+                segment.retransmitted = true;
+                segment.sent_ms = now;
+                (segment.seq, segment.flags, segment.data.clone())
+            }
+            None => return,
+        };
+        let flags = if data.is_empty() {
+            flags
+        } else {
+            flags | TcpFlags::P
+        };
+        self.send_segment(seq, flags, &data, out);
+    }
+
+    fn on_retransmit_timeout(&mut self, out: &mut Vec<Vec<u8>>) {
+        self.retries += 1;
+        if self.retries > MAX_RETRIES {
+            self.send_reset(out);
+            self.abort();
+            return;
+        }
+        self.retransmit(out);
+        // back off exponentially
+        self.rto_ms = (self.rto_ms * 2).min(MAX_RTO_MS);
+        self.retransmit_at = Some(get_time_ms() + self.rto_ms);
+    }
+
+    /// Whether any part of a segment falls in the receive window.
+    fn acceptable(&self, seq: u32, len: u32) -> bool {
+        let window = self.recv_window() as u32;
+        let in_window =
+            |x: u32| seq_le(self.rcv_nxt, x) && seq_lt(x, self.rcv_nxt.wrapping_add(window));
+        match (len, window) {
+            (0, 0) => seq == self.rcv_nxt,
+            (0, _) => in_window(seq),
+            (_, 0) => false,
+            _ => in_window(seq) || in_window(seq.wrapping_add(len - 1)),
+        }
+    }
 
-use super::socket::get_s_a_by_index;
-use super::{
-    LOSE_NET_STACK, net_interrupt_handler,
-    socket::{add_socket, pop_data, remove_socket},
-};
+    fn receive_fin(&mut self) {
+        self.fin_received = true;
+        match self.state {
+            TcpState::SynReceived | TcpState::Established => self.state = TcpState::CloseWait,
+            TcpState::FinWait1 => self.state = TcpState::Closing,
+            TcpState::FinWait2 => self.enter_time_wait(),
+            _ => {}
+        }
+    }
+
+    /// Append a segment starting at or before `rcv_nxt` to the received data.
+    fn receive_in_order(&mut self, seq: u32, data: &[u8], fin: bool) {
+        let skip = self.rcv_nxt.wrapping_sub(seq) as usize;
+        if skip < data.len() {
+            self.recv_buffer.extend(data[skip..].iter());
+            self.rcv_nxt = seq.wrapping_add(data.len() as u32);
+        }
+        if fin && skip <= data.len() && !self.fin_received {
+            self.rcv_nxt = self.rcv_nxt.wrapping_add(1);
+            self.receive_fin();
+        }
+    }
+
+    fn receive_data(&mut self, seq: u32, data: &[u8], fin: bool) {
+        // what does not fit in the window is dropped
+        let window_end = self.rcv_nxt.wrapping_add(self.recv_window() as u32);
+        let (data, fin) = if seq_lt(window_end, seq.wrapping_add(data.len() as u32)) {
+            (&data[..window_end.wrapping_sub(seq) as usize], false)
+        } else {
+            (data, fin)
+        };
+        if seq_lt(self.rcv_nxt, seq) {
+            // kept until the gap before it is filled
+            if !self.out_of_order.iter().any(|(s, _, _)| *s == seq) {
+                self.out_of_order.push((seq, data.to_vec(), fin));
+            }
+            return;
+        }
+        self.receive_in_order(seq, data, fin);
+        while let Some(pos) = self
+            .out_of_order
+            .iter()
+            .position(|(seq, _, _)| seq_le(*seq, self.rcv_nxt))
+        {
+            let (seq, data, fin) = self.out_of_order.swap_remove(pos);
+            self.receive_in_order(seq, &data, fin);
+        }
+        // nobody reads the data of a closed socket
+        if self.read_closed {
+            self.recv_buffer.clear();
+        }
+    }
+
+    fn receive_in_syn_sent(&mut self, segment: &IncomingSegment, out: &mut Vec<Vec<u8>>) {
+        let ack = segment.flags.contains(TcpFlags::A);
+        let ack_acceptable =
+            ack && seq_lt(self.snd_una, segment.ack) && seq_le(segment.ack, self.snd_nxt);
+        if ack && !ack_acceptable {
+            if !segment.flags.contains(TcpFlags::R) {
+                out.push(build_segment(
+                    self.remote_ip,
+                    self.local_port,
+                    self.remote_port,
+                    segment.ack,
+                    0,
+                    TcpFlags::R,
+                    0,
+                    &[],
+                ));
+            }
+            return;
+        }
+        if segment.flags.contains(TcpFlags::R) {
+            if ack_acceptable {
+                self.abort();
+            }
+            return;
+        }
+        if !segment.flags.contains(TcpFlags::S) {
+            return;
+        }
+        self.rcv_nxt = segment.seq.wrapping_add(1);
+        self.snd_wnd = segment.win as u32;
+        if ack_acceptable {
+            self.acknowledge(segment.ack);
+            self.state = TcpState::Established;
+            self.send_ack(out);
+            self.output(out);
+        } else {
+            // simultaneous open, our SYN is sent again with ACK
+            self.state = TcpState::SynReceived;
+            self.retransmit(out);
+        }
+    }
+
+    /// Process an arriving segment as described in "SEGMENT ARRIVES" of RFC 793.
+    fn receive(&mut self, index: usize, segment: &IncomingSegment, out: &mut Vec<Vec<u8>>) {
+        if self.state == TcpState::SynSent {
+            self.receive_in_syn_sent(segment, out);
+            return;
+        }
+        if !self.acceptable(segment.seq, segment.len()) {
+            if !segment.flags.contains(TcpFlags::R) {
+                self.send_ack(out);
+            }
+            return;
+        }
+        if segment.flags.contains(TcpFlags::R) {
+            self.abort();
+            return;
+        }
+        if segment.flags.contains(TcpFlags::S) {
+            // a SYN in the window is an error
+            self.send_reset(out);
+            self.abort();
+            return;
+        }
+        if !segment.flags.contains(TcpFlags::A) {
+            return;
+        }
+        if self.state == TcpState::SynReceived {
+            if !(seq_lt(self.snd_una, segment.ack) && seq_le(segment.ack, self.snd_nxt)) {
+                out.push(build_segment(
+                    self.remote_ip,
+                    self.local_port,
+                    self.remote_port,
+                    segment.ack,
+                    0,
+                    TcpFlags::R,
+                    0,
+                    &[],
+                ));
+                return;
+            }
+            self.state = TcpState::Established;
+            if let Some(listen_index) = self.listen_index.take() {
+                if !push_accepted(listen_index, self.local_port, index) {
+                    // the port is not listened any more
+                    self.send_reset(out);
+                    self.abort();
+                    return;
+                }
+                self.owned = true;
+            }
+        }
+        if seq_lt(self.snd_nxt, segment.ack) {
+            // it acknowledges something not sent yet
+            self.send_ack(out);
+            return;
+        }
+        if seq_le(self.snd_una, segment.ack) {
+            if seq_lt(self.snd_una, segment.ack) {
+                self.acknowledge(segment.ack);
+            }
+            self.snd_wnd = segment.win as u32;
+            // the peer is alive, e.g. it answers window probes
+            self.retries = 0;
+        }
+        let fin_acked = self.fin_sent && self.snd_una == self.snd_nxt;
+        match self.state {
+            TcpState::FinWait1 if fin_acked => self.state = TcpState::FinWait2,
+            TcpState::Closing if fin_acked => self.enter_time_wait(),
+            TcpState::LastAck if fin_acked => {
+                self.state = TcpState::Closed;
+                return;
+            }
+            _ => {}
+        }
+        let fin = segment.flags.contains(TcpFlags::F);
+        match self.state {
+            TcpState::Established | TcpState::FinWait1 | TcpState::FinWait2
+                if !segment.data.is_empty() || fin =>
+            {
+                self.receive_data(segment.seq, segment.data, fin);
+                self.send_ack(out);
+            }
+            TcpState::TimeWait if fin => {
+                // our ACK of FIN is lost
+                self.send_ack(out);
+                self.enter_time_wait();
+            }
+            _ => {}
+        }
+        self.output(out);
+    }
+}
+
+lazy_static! {
+    static ref TCP_TABLE: SpinIntrFreeCell<Vec<Option<TcpControlBlock>>> =
+        unsafe { SpinIntrFreeCell::new(Vec::new()) };
+}
+
+fn insert_tcb(table: &mut Vec<Option<TcpControlBlock>>, tcb: TcpControlBlock) -> usize {
+    match table.iter().position(|slot| slot.is_none()) {
+        Some(index) => {
+            table[index] = Some(tcb);
+            index
+        }
+        None => {
+            table.push(Some(tcb));
+            table.len() - 1
+        }
+    }
+}
+
+/// Handle a TCP segment received from the network.
+pub fn handle_segment(packet: &TCPPacket) {
+    let segment = IncomingSegment {
+        seq: packet.seq,
+        ack: packet.ack,
+        flags: packet.flags,
+        win: packet.win,
+        data: packet.data,
+    };
+    let mut out = Vec::new();
+    let mut table = TCP_TABLE.exclusive_access();
+    let index = table.iter().position(|slot| {
+        slot.as_ref().is_some_and(|tcb| {
+            tcb.remote_ip == packet.source_ip
+                && tcb.local_port == packet.dest_port
+                && tcb.remote_port == packet.source_port
+        })
+    });
+    match index {
+        Some(index) => {
+            let tcb = table[index].as_mut().unwrap();
+            tcb.receive(index, &segment, &mut out);
+            tcb.wait_queue.broadcast();
+            if tcb.state == TcpState::Closed && !tcb.owned {
+                table[index] = None;
+            }
+        }
+        None if segment.flags.contains(TcpFlags::R) => {}
+        None if segment.flags.contains(TcpFlags::S) && !segment.flags.contains(TcpFlags::A) => {
+            match listening_index(packet.dest_port) {
+                Some(listen_index) => open_passively(&mut table, listen_index, packet, &mut out),
+                None => reset_reply(packet, &mut out),
+            }
+        }
+        None => reset_reply(packet, &mut out),
+    }
+    drop(table);
+    transmit_all(out);
+}
+
+/// Answer a SYN to a listening port with SYN|ACK.
+fn open_passively(
+    table: &mut Vec<Option<TcpControlBlock>>,
+    listen_index: usize,
+    packet: &TCPPacket,
+    out: &mut Vec<Vec<u8>>,
+) {
+    let half_open = table
+        .iter()
+        .flatten()
+        .filter(|tcb| tcb.listen_index == Some(listen_index))
+        .count();
+    // SYNs beyond the backlog are dropped, and the peer tries again later
+    if half_open + accepted_count(listen_index) >= backlog(listen_index) {
+        return;
+    }
+    let mut tcb = TcpControlBlock::new(packet.source_ip, packet.dest_port, packet.source_port);
+    tcb.state = TcpState::SynReceived;
+    tcb.listen_index = Some(listen_index);
+    tcb.rcv_nxt = packet.seq.wrapping_add(1);
+    tcb.snd_wnd = packet.win as u32;
+    tcb.send_new(TcpFlags::S, Vec::new(), out);
+    insert_tcb(table, tcb);
+}
+
+/// Retransmit segments which are not acknowledged in time, and free
+/// connections which have stayed in TIME-WAIT long enough.
+pub fn check_tcp_timers() {
+    let now = get_time_ms();
+    let mut out = Vec::new();
+    TCP_TABLE.exclusive_session(|table| {
+        for slot in table.iter_mut() {
+            let tcb = match slot {
+                Some(tcb) => tcb,
+                None => continue,
+            };
+            if tcb.state == TcpState::TimeWait && now >= tcb.time_wait_until {
+                tcb.state = TcpState::Closed;
+            } else if tcb.retransmit_at.is_some_and(|time| now >= time) {
+                tcb.on_retransmit_timeout(&mut out);
+                // the connection may be aborted
+                tcb.wait_queue.broadcast();
+            }
+            if tcb.state == TcpState::Closed && !tcb.owned {
+                *slot = None;
+            }
+        }
+    });
+    transmit_all(out);
+}
+
+/// Close a connection when its socket or accept queue is dropped,
+/// it stays in the table until the closing handshake finishes.
+pub fn close_connection(index: usize) {
+    let mut out = Vec::new();
+    let mut table = TCP_TABLE.exclusive_access();
+    let tcb = table[index].as_mut().unwrap();
+    tcb.owned = false;
+    tcb.close_requested = true;
+    tcb.read_closed = true;
+    tcb.recv_buffer.clear();
+    match tcb.state {
+        TcpState::SynSent => tcb.state = TcpState::Closed,
+        TcpState::Established | TcpState::CloseWait => tcb.output(&mut out),
+        _ => {}
+    }
+    if tcb.state == TcpState::Closed {
+        table[index] = None;
+    }
+    drop(table);
+    transmit_all(out);
+}
+
+/// Shut down reading, writing or both of a connection, the socket
+/// still refers to it.
+pub fn shutdown_connection(index: usize, read: bool, write: bool) {
+    let mut out = Vec::new();
+    let mut table = TCP_TABLE.exclusive_access();
+    let tcb = table[index].as_mut().unwrap();
+    if read {
+        tcb.read_closed = true;
+        tcb.recv_buffer.clear();
+    }
+    if write {
+        tcb.close_requested = true;
+        tcb.output(&mut out);
+    }
+    // blocked readers and writers return
+    tcb.wait_queue.broadcast();
+    drop(table);
+    transmit_all(out);
+}
 
-// add tcp packet info to this structure
 pub struct TCP {
     pub target: IPv4,
     pub sport: u16,
     pub dport: u16,
-    #[allow(unused)]
-    pub seq: u32,
-    #[allow(unused)]
-    pub ack: u32,
-    pub socket_index: usize,
+    /// index of the connection in the TCP table
+    pub tcb_index: usize,
 }
 
 impl TCP {
-    pub fn new(target: IPv4, sport: u16, dport: u16, seq: u32, ack: u32) -> Self {
-        let index = add_socket(target, sport, dport).expect("can't add socket");
-
+    /// The socket of an accepted connection.
+    pub fn accepted(tcb_index: usize) -> Self {
+        let table = TCP_TABLE.exclusive_access();
+        let tcb = table[tcb_index].as_ref().unwrap();
         Self {
+            target: tcb.remote_ip,
+            sport: tcb.local_port,
+            dport: tcb.remote_port,
+            tcb_index,
+        }
+    }
+
+    /// Open a connection actively, return None if it is refused or times out.
+    pub fn connect(target: IPv4, sport: u16, dport: u16) -> Option<Self> {
+        let mut out = Vec::new();
+        let mut table = TCP_TABLE.exclusive_access();
+        if table.iter().flatten().any(|tcb| {
+            tcb.remote_ip == target && tcb.local_port == sport && tcb.remote_port == dport
+        }) {
+            return None;
+        }
+        let mut tcb = TcpControlBlock::new(target, sport, dport);
+        tcb.state = TcpState::SynSent;
+        tcb.owned = true;
+        tcb.send_new(TcpFlags::S, Vec::new(), &mut out);
+        let tcb_index = insert_tcb(&mut table, tcb);
+        drop(table);
+        transmit_all(out);
+        loop {
+            let mut table = TCP_TABLE.exclusive_access();
+            let tcb = table[tcb_index].as_ref().unwrap();
+            let state = tcb.state;
+            match state {
+                TcpState::SynSent | TcpState::SynReceived => {
+                    let task_cx_ptr = tcb.wait_queue.wait_no_sched();
+                    drop(table);
+                    schedule(task_cx_ptr);
+                }
+                TcpState::Closed => {
+                    table[tcb_index] = None;
+                    return None;
+                }
+                _ => break,
+            }
+        }
+        Some(Self {
             target,
             sport,
             dport,
-            seq,
-            ack,
-            socket_index: index,
-        }
+            tcb_index,
+        })
     }
 }
 
@@ -48,32 +852,51 @@ impl File for TCP {
         true
     }
 
-    fn read(&self, mut buf: crate::mm::UserBuffer) -> usize {
+    /// Return 0 once the peer has closed its side and all data is read,
+    /// or reading is shut down.
+    fn read(&self, mut buf: UserBuffer) -> usize {
         loop {
-            if let Some(data) = pop_data(self.socket_index) {
-                let data_len = data.len();
-                let mut left = 0;
-                for i in 0..buf.buffers.len() {
-                    let buffer_i_len = buf.buffers[i].len().min(data_len - left);
-
-                    buf.buffers[i][..buffer_i_len]
-                        .copy_from_slice(&data[left..(left + buffer_i_len)]);
-
-                    left += buffer_i_len;
-                    if left == data_len {
+            let mut out = Vec::new();
+            let mut table = TCP_TABLE.exclusive_access();
+            let tcb = table[self.tcb_index].as_mut().unwrap();
+            if tcb.recv_buffer.is_empty() && !tcb.fin_received && !tcb.reset && !tcb.read_closed {
+                // woken up when a segment arrives or the connection is aborted
+                let task_cx_ptr = tcb.wait_queue.wait_no_sched();
+                drop(table);
+                schedule(task_cx_ptr);
+                continue;
+            }
+            let read_size = if !tcb.recv_buffer.is_empty() {
+                let window = tcb.recv_window();
+                let mut read_size = 0;
+                for buffer in buf.buffers.iter_mut() {
+                    let len = buffer.len().min(tcb.recv_buffer.len());
+                    for (dst, src) in buffer.iter_mut().zip(tcb.recv_buffer.drain(..len)) {
+                        *dst = src;
+                    }
+                    read_size += len;
+                    if tcb.recv_buffer.is_empty() {
                         break;
                     }
                 }
-                return left;
+                // tell the peer that the window opens again
+                if window < MSS && tcb.recv_window() >= MSS {
+                    tcb.send_ack(&mut out);
+                }
+                read_size
             } else {
-                net_interrupt_handler();
-            }
+                0
+            };
+            drop(table);
+            transmit_all(out);
+            return read_size;
         }
     }
 
-    fn write(&self, buf: crate::mm::UserBuffer) -> usize {
-        let lose_net_stack = LOSE_NET_STACK.0.exclusive_access();
-
+    /// Data is buffered and sent as the peer's window allows, this waits
+    /// only when the send buffer is full. Return the length written
+    /// before the connection is closed or reset.
+    fn write(&self, buf: UserBuffer) -> usize {
         let mut data = vec![0u8; buf.len()];
 
         let mut left = 0;
@@ -82,33 +905,70 @@ impl File for TCP {
             left += buf.buffers[i].len();
         }
 
-        let len = data.len();
+        let mut written = 0;
+        while written < data.len() {
+            let mut out = Vec::new();
+            let mut table = TCP_TABLE.exclusive_access();
+            let tcb = table[self.tcb_index].as_mut().unwrap();
+            if !matches!(tcb.state, TcpState::Established | TcpState::CloseWait)
+                || tcb.close_requested
+            {
+                break;
+            }
+            let len = (SEND_BUFFER_SIZE - tcb.send_buffer.len()).min(data.len() - written);
+            if len == 0 {
+                // woken up when the peer acknowledges data and the buffer drains
+                let task_cx_ptr = tcb.wait_queue.wait_no_sched();
+                drop(table);
+                schedule(task_cx_ptr);
+                continue;
+            }
+            tcb.send_buffer.extend(data[written..written + len].iter());
+            written += len;
+            tcb.output(&mut out);
+            drop(table);
+            transmit_all(out);
+        }
+        written
+    }
 
-        // get sock and sequence
-        let (ack, seq) = get_s_a_by_index(self.socket_index).map_or((0, 0), |x| x);
+    /// Readable as a read does not wait, writable while the connection
+    /// may send and the send buffer has room. Hung up once it is reset or
+    /// closed both ways.
+    fn poll_ready(&self) -> PollEvents {
+        let table = TCP_TABLE.exclusive_access();
+        let tcb = table[self.tcb_index].as_ref().unwrap();
+        let mut events = PollEvents::empty();
+        events.set(
+            PollEvents::IN,
+            !tcb.recv_buffer.is_empty() || tcb.fin_received || tcb.reset || tcb.read_closed,
+        );
+        events.set(
+            PollEvents::OUT,
+            matches!(tcb.state, TcpState::Established | TcpState::CloseWait)
+                && !tcb.close_requested
+                && tcb.send_buffer.len() < SEND_BUFFER_SIZE,
+        );
+        events.set(PollEvents::ERR, tcb.reset);
+        events.set(
+            PollEvents::HUP,
+            tcb.reset || tcb.state == TcpState::Closed || (tcb.fin_received && tcb.close_requested),
+        );
+        events
+    }
 
-        let tcp_packet = TCPPacket {
-            source_ip: lose_net_stack.ip,
-            source_mac: lose_net_stack.mac,
-            source_port: self.sport,
-            dest_ip: self.target,
-            dest_mac: MacAddress::new([0xff, 0xff, 0xff, 0xff, 0xff, 0xff]),
-            dest_port: self.dport,
-            data_len: len,
-            seq,
-            ack,
-            flags: TcpFlags::A,
-            win: 65535,
-            urg: 0,
-            data: data.as_ref(),
-        };
-        NET_DEVICE.transmit(&tcp_packet.build_data());
-        len
+    fn register_waiter(&self, waiter: &Arc<PollWaiter>) {
+        let table = TCP_TABLE.exclusive_access();
+        table[self.tcb_index]
+            .as_ref()
+            .unwrap()
+            .wait_queue
+            .register_poller(waiter);
     }
 }
 
 impl Drop for TCP {
     fn drop(&mut self) {
-        remove_socket(self.socket_index)
+        close_connection(self.tcb_index)
     }
 }
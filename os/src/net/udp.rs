@@ -1,68 +1,71 @@
-use super::LOSE_NET_STACK;
-use super::NET_DEVICE;
-use super::net_interrupt_handler;
-use super::socket::{add_socket, pop_data, remove_socket};
-use crate::fs::File;
+use super::arp::transmit_ip;
+use super::socket::{
+    add_socket, get_remote, pop_data, register_socket_poller, remove_socket, set_remote,
+    shutdown_socket, socket_readable,
+};
+use super::{local_mac, source_ip};
+use crate::fs::{File, PollEvents};
+use crate::mm::UserBuffer;
+use crate::sync::PollWaiter;
+use alloc::sync::Arc;
 use alloc::vec;
 use lose_net_stack::IPv4;
 use lose_net_stack::MacAddress;
 use lose_net_stack::packets::udp::UDPPacket;
 
 pub struct UDP {
-    pub target: IPv4,
     pub sport: u16,
-    pub dport: u16,
     pub socket_index: usize,
 }
 
 impl UDP {
-    pub fn new(target: IPv4, sport: u16, dport: u16) -> Self {
-        let index = add_socket(target, sport, dport).expect("can't add socket");
+    /// Bind a local port, return None if it is in use.
+    pub fn bind(sport: u16) -> Option<Self> {
+        let index = add_socket(sport)?;
 
-        Self {
-            target,
+        Some(Self {
             sport,
-            dport,
             socket_index: index,
-        }
+        })
     }
-}
 
-impl File for UDP {
-    fn readable(&self) -> bool {
-        true
+    /// Set the default destination, datagrams from others are not received then.
+    pub fn connect(&self, target: IPv4, dport: u16) {
+        set_remote(self.socket_index, Some((target, dport)));
     }
 
-    fn writable(&self) -> bool {
-        true
+    pub fn remote(&self) -> Option<(IPv4, u16)> {
+        get_remote(self.socket_index)
     }
 
-    fn read(&self, mut buf: crate::mm::UserBuffer) -> usize {
-        loop {
-            if let Some(data) = pop_data(self.socket_index) {
-                let data_len = data.len();
-                let mut left = 0;
-                for i in 0..buf.buffers.len() {
-                    let buffer_i_len = buf.buffers[i].len().min(data_len - left);
-
-                    buf.buffers[i][..buffer_i_len]
-                        .copy_from_slice(&data[left..(left + buffer_i_len)]);
-
-                    left += buffer_i_len;
-                    if left == data_len {
-                        break;
-                    }
-                }
-                return left;
-            } else {
-                net_interrupt_handler();
+    pub fn shutdown(&self) {
+        shutdown_socket(self.socket_index)
+    }
+
+    /// Receive a datagram, return its length and source. The datagram is
+    /// truncated if the buffer is too small, and 0 is returned once the
+    /// socket is shut down.
+    pub fn recv_from(&self, mut buf: UserBuffer) -> (usize, Option<(IPv4, u16)>) {
+        let (data, raddr, rport) = match pop_data(self.socket_index) {
+            Some(datagram) => datagram,
+            None => return (0, None),
+        };
+        let data_len = data.len();
+        let mut left = 0;
+        for i in 0..buf.buffers.len() {
+            let buffer_i_len = buf.buffers[i].len().min(data_len - left);
+
+            buf.buffers[i][..buffer_i_len].copy_from_slice(&data[left..(left + buffer_i_len)]);
+
+            left += buffer_i_len;
+            if left == data_len {
+                break;
             }
         }
+        (left, Some((raddr, rport)))
     }
 
-    fn write(&self, buf: crate::mm::UserBuffer) -> usize {
-        let lose_net_stack = LOSE_NET_STACK.0.exclusive_access();
-
+    pub fn send_to(&self, buf: UserBuffer, target: IPv4, dport: u16) -> usize {
         let mut data = vec![0u8; buf.len()];
 
         let mut left = 0;
@@ -74,20 +77,56 @@ impl File for UDP {
         let len = data.len();
 
         let udp_packet = UDPPacket::new(
-            lose_net_stack.ip,
-            lose_net_stack.mac,
+            source_ip(target),
+            MacAddress::new(local_mac()),
             self.sport,
-            self.target,
+            target,
+            // filled in once the MAC of the next hop is resolved
             MacAddress::new([0xff, 0xff, 0xff, 0xff, 0xff, 0xff]),
-            self.dport,
+            dport,
             len,
             data.as_ref(),
         );
-        NET_DEVICE.transmit(&udp_packet.build_data());
+        transmit_ip(udp_packet.build_data());
         len
     }
 }
 
+impl File for UDP {
+    fn readable(&self) -> bool {
+        true
+    }
+
+    fn writable(&self) -> bool {
+        true
+    }
+
+    fn read(&self, buf: UserBuffer) -> usize {
+        self.recv_from(buf).0
+    }
+
+    /// Send to the connected remote, nothing is sent if it is not connected.
+    fn write(&self, buf: UserBuffer) -> usize {
+        match self.remote() {
+            Some((target, dport)) => self.send_to(buf, target, dport),
+            None => 0,
+        }
+    }
+
+    /// Sending never waits.
+    fn poll_ready(&self) -> PollEvents {
+        if socket_readable(self.socket_index) {
+            PollEvents::IN | PollEvents::OUT
+        } else {
+            PollEvents::OUT
+        }
+    }
+
+    fn register_waiter(&self, waiter: &Arc<PollWaiter>) {
+        register_socket_poller(self.socket_index, waiter);
+    }
+}
+
 impl Drop for UDP {
     fn drop(&mut self) {
         remove_socket(self.socket_index)
@@ -3,6 +3,22 @@ pub fn set_timer(timer: usize) {
     sbi_rt::set_timer(timer as _);
 }
 
+/// use sbi call to start a hart at `start_addr` with its hart id in a0
+pub fn hart_start(hart_id: usize, start_addr: usize) -> bool {
+    sbi_rt::hart_start(hart_id, start_addr, 0).error == 0
+}
+
+/// use sbi call to send an inter-processor interrupt to a hart
+pub fn send_ipi(hart_id: usize) {
+    sbi_rt::send_ipi(1, hart_id);
+}
+
+/// use sbi call to flush the TLB of all harts
+pub fn remote_sfence_vma_all() {
+    // a hart mask base of -1 stands for all harts, and a size of -1 for all addresses
+    sbi_rt::remote_sfence_vma(0, usize::MAX, 0, usize::MAX);
+}
+
 /// use sbi call to shutdown the kernel
 pub fn shutdown(failure: bool) -> ! {
     use sbi_rt::{NoReason, Shutdown, SystemFailure, system_reset};
@@ -1,24 +1,28 @@
-use crate::sync::{Mutex, UPIntrFreeCell};
+use crate::sync::{Mutex, SpinIntrFreeCell};
 use crate::task::{
     TaskContext, TaskControlBlock, block_current_and_run_next, block_current_task, current_task,
-    wakeup_task,
+    schedule, wakeup_task,
 };
-use alloc::{collections::VecDeque, sync::Arc};
+use alloc::sync::{Arc, Weak};
+use alloc::{collections::VecDeque, vec::Vec};
 
 pub struct Condvar {
-    pub inner: UPIntrFreeCell<CondvarInner>,
+    pub inner: SpinIntrFreeCell<CondvarInner>,
 }
 
 pub struct CondvarInner {
     pub wait_queue: VecDeque<Arc<TaskControlBlock>>,
+    /// tasks polling the object, woken by every signal
+    pub pollers: Vec<Weak<PollWaiter>>,
 }
 
 impl Condvar {
     pub fn new() -> Self {
         Self {
             inner: unsafe {
-                UPIntrFreeCell::new(CondvarInner {
+                SpinIntrFreeCell::new(CondvarInner {
                     wait_queue: VecDeque::new(),
+                    pollers: Vec::new(),
                 })
             },
         }
@@ -29,6 +33,22 @@ impl Condvar {
         if let Some(task) = inner.wait_queue.pop_front() {
             wakeup_task(task);
         }
+        wake_pollers(&mut inner);
+    }
+
+    pub fn broadcast(&self) {
+        let mut inner = self.inner.exclusive_access();
+        while let Some(task) = inner.wait_queue.pop_front() {
+            wakeup_task(task);
+        }
+        wake_pollers(&mut inner);
+    }
+
+    /// Have `waiter` woken by the next signal or broadcast.
+    pub fn register_poller(&self, waiter: &Arc<PollWaiter>) {
+        let mut inner = self.inner.exclusive_access();
+        inner.pollers.retain(|poller| poller.strong_count() > 0);
+        inner.pollers.push(Arc::downgrade(waiter));
     }
 
     /*
@@ -56,3 +76,60 @@ impl Condvar {
         mutex.lock();
     }
 }
+
+fn wake_pollers(inner: &mut CondvarInner) {
+    for poller in inner.pollers.drain(..) {
+        if let Some(poller) = poller.upgrade() {
+            poller.wake();
+        }
+    }
+}
+
+struct PollWaiterInner {
+    woken: bool,
+    /// the task blocked in `wait`
+    task: Option<Arc<TaskControlBlock>>,
+}
+
+/// A task waiting for any of several objects, registered on each of their
+/// condvars and maybe on a timer. It is woken only once, by whichever
+/// comes first.
+pub struct PollWaiter {
+    inner: SpinIntrFreeCell<PollWaiterInner>,
+}
+
+impl PollWaiter {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            inner: unsafe {
+                SpinIntrFreeCell::new(PollWaiterInner {
+                    woken: false,
+                    task: None,
+                })
+            },
+        })
+    }
+
+    pub fn wake(&self) {
+        let task = self.inner.exclusive_session(|inner| {
+            inner.woken = true;
+            inner.task.take()
+        });
+        if let Some(task) = task {
+            wakeup_task(task);
+        }
+    }
+
+    /// Block the current task until the waiter is woken, it returns at
+    /// once if the waiter has been woken already.
+    pub fn wait(&self) {
+        let mut inner = self.inner.exclusive_access();
+        if inner.woken {
+            return;
+        }
+        inner.task = Some(current_task().unwrap());
+        let task_cx_ptr = block_current_task();
+        drop(inner);
+        schedule(task_cx_ptr);
+    }
+}
@@ -1,9 +1,11 @@
 mod condvar;
 mod mutex;
 mod semaphore;
+mod spin;
 mod up;
 
-pub use condvar::Condvar;
+pub use condvar::{Condvar, PollWaiter};
 pub use mutex::{Mutex, MutexBlocking, MutexSpin};
 pub use semaphore::Semaphore;
-pub use up::{UPIntrFreeCell, UPIntrRefMut};
+pub use spin::{SpinIntrFreeCell, SpinIntrRefMut};
+pub use up::{UPIntrFreeCell, UPSafeCellRaw};
@@ -1,9 +1,9 @@
-use crate::sync::UPIntrFreeCell;
+use crate::sync::SpinIntrFreeCell;
 use crate::task::{TaskControlBlock, block_current_and_run_next, current_task, wakeup_task};
 use alloc::{collections::VecDeque, sync::Arc};
 
 pub struct Semaphore {
-    pub inner: UPIntrFreeCell<SemaphoreInner>,
+    pub inner: SpinIntrFreeCell<SemaphoreInner>,
 }
 
 pub struct SemaphoreInner {
@@ -15,7 +15,7 @@ impl Semaphore {
     pub fn new(res_count: usize) -> Self {
         Self {
             inner: unsafe {
-                UPIntrFreeCell::new(SemaphoreInner {
+                SpinIntrFreeCell::new(SemaphoreInner {
                     count: res_count as isize,
                     wait_queue: VecDeque::new(),
                 })
@@ -0,0 +1,71 @@
+use super::up::intr_masking_info;
+use crate::task::hart_id;
+use core::cell::UnsafeCell;
+use core::hint::spin_loop;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+const NO_HART: usize = usize::MAX;
+
+/// Data shared among harts. A hart spins until the data is released by
+/// others, with its interrupts disabled so that interrupt handlers on it
+/// never spin for the data it holds.
+pub struct SpinIntrFreeCell<T> {
+    /// the hart accessing the data or NO_HART
+    owner: AtomicUsize,
+    inner: UnsafeCell<T>,
+}
+
+unsafe impl<T> Sync for SpinIntrFreeCell<T> {}
+
+pub struct SpinIntrRefMut<'a, T>(&'a SpinIntrFreeCell<T>);
+
+impl<T> SpinIntrFreeCell<T> {
+    pub unsafe fn new(value: T) -> Self {
+        Self {
+            owner: AtomicUsize::new(NO_HART),
+            inner: UnsafeCell::new(value),
+        }
+    }
+
+    /// Panic if the data has been borrowed on the current hart.
+    pub fn exclusive_access(&self) -> SpinIntrRefMut<'_, T> {
+        intr_masking_info().enter();
+        let hart_id = hart_id();
+        while let Err(owner) =
+            self.owner
+                .compare_exchange_weak(NO_HART, hart_id, Ordering::Acquire, Ordering::Relaxed)
+        {
+            assert_ne!(owner, hart_id, "already borrowed");
+            spin_loop();
+        }
+        SpinIntrRefMut(self)
+    }
+
+    pub fn exclusive_session<F, V>(&self, f: F) -> V
+    where
+        F: FnOnce(&mut T) -> V,
+    {
+        let mut inner = self.exclusive_access();
+        f(inner.deref_mut())
+    }
+}
+
+impl<'a, T> Drop for SpinIntrRefMut<'a, T> {
+    fn drop(&mut self) {
+        self.0.owner.store(NO_HART, Ordering::Release);
+        intr_masking_info().exit();
+    }
+}
+
+impl<'a, T> Deref for SpinIntrRefMut<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.0.inner.get() }
+    }
+}
+impl<'a, T> DerefMut for SpinIntrRefMut<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.0.inner.get() }
+    }
+}
@@ -1,6 +1,7 @@
+use crate::config::MAX_HARTS;
+use crate::task::hart_id;
 use core::cell::{RefCell, RefMut, UnsafeCell};
 use core::ops::{Deref, DerefMut};
-use lazy_static::*;
 use riscv::register::sstatus;
 
 /*
@@ -40,7 +41,7 @@ pub struct UPSafeCellRaw<T> {
 unsafe impl<T> Sync for UPSafeCellRaw<T> {}
 
 impl<T> UPSafeCellRaw<T> {
-    pub unsafe fn new(value: T) -> Self {
+    pub const unsafe fn new(value: T) -> Self {
         Self {
             inner: UnsafeCell::new(value),
         }
@@ -55,13 +56,17 @@ pub struct IntrMaskingInfo {
     sie_before_masking: bool,
 }
 
-lazy_static! {
-    static ref INTR_MASKING_INFO: UPSafeCellRaw<IntrMaskingInfo> =
-        unsafe { UPSafeCellRaw::new(IntrMaskingInfo::new()) };
+/// Interrupts are masked on each hart independently.
+static INTR_MASKING_INFO: [UPSafeCellRaw<IntrMaskingInfo>; MAX_HARTS] =
+    [const { unsafe { UPSafeCellRaw::new(IntrMaskingInfo::new()) } }; MAX_HARTS];
+
+/// Masking info of the current hart.
+pub fn intr_masking_info() -> &'static mut IntrMaskingInfo {
+    INTR_MASKING_INFO[hart_id()].get_mut()
 }
 
 impl IntrMaskingInfo {
-    pub fn new() -> Self {
+    pub const fn new() -> Self {
         Self {
             nested_level: 0,
             sie_before_masking: false,
@@ -89,6 +94,7 @@ impl IntrMaskingInfo {
     }
 }
 
+/// Data only accessed by the hart it belongs to.
 pub struct UPIntrFreeCell<T> {
     /// inner data
     inner: RefCell<T>,
@@ -107,7 +113,7 @@ impl<T> UPIntrFreeCell<T> {
 
     /// Panic if the data has been borrowed.
     pub fn exclusive_access(&self) -> UPIntrRefMut<'_, T> {
-        INTR_MASKING_INFO.get_mut().enter();
+        intr_masking_info().enter();
         UPIntrRefMut(Some(self.inner.borrow_mut()))
     }
 
@@ -123,7 +129,7 @@ impl<T> UPIntrFreeCell<T> {
 impl<'a, T> Drop for UPIntrRefMut<'a, T> {
     fn drop(&mut self) {
         self.0 = None;
-        INTR_MASKING_INFO.get_mut().exit();
+        intr_masking_info().exit();
     }
 }
 
@@ -1,8 +1,36 @@
-use crate::fs::{OpenFlags, make_pipe, open_file};
+use super::net::write_user_bytes;
+use crate::fs::{
+    File, OpenFlags, SeekFrom, Stat, absolute_path, link, make_pipe, mkdir, open_file, readlink,
+    real_dir_path, rename, rmdir, stat, symlink, truncate, unlink,
+};
 use crate::mm::{UserBuffer, translated_byte_buffer, translated_refmut, translated_str};
 use crate::task::{current_process, current_user_token};
+use alloc::string::String;
 use alloc::sync::Arc;
 
+/// Remove a directory instead of a file in `sys_unlink`.
+const AT_REMOVEDIR: u32 = 0x200;
+/// Tell of a symlink itself instead of its target in `sys_stat`.
+const AT_SYMLINK_NOFOLLOW: u32 = 0x100;
+/// Where the offset of `sys_lseek` counts from.
+const SEEK_SET: usize = 0;
+const SEEK_CUR: usize = 1;
+const SEEK_END: usize = 2;
+
+/// Load a path from user space and resolve it against the current working directory.
+pub fn translated_path(token: usize, path: *const u8) -> Option<String> {
+    let path = translated_str(token, path)?;
+    let cwd = current_process().inner_exclusive_access().cwd.clone();
+    Some(absolute_path(&cwd, &path))
+}
+
+/// The file opened at `fd`.
+pub fn get_file(fd: usize) -> Option<Arc<dyn File>> {
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    inner.fd_table.get(fd)?.clone()
+}
+
 pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
     let token = current_user_token();
     let process = current_process();
@@ -17,7 +45,10 @@ pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
         let file = file.clone();
         // release current task TCB manually to avoid multi-borrow
         drop(inner);
-        file.write(UserBuffer::new(translated_byte_buffer(token, buf, len))) as isize
+        match translated_byte_buffer(token, buf, len, false) {
+            Some(buffers) => file.write(UserBuffer::new(buffers)) as isize,
+            None => -1,
+        }
     } else {
         -1
     }
@@ -37,16 +68,110 @@ pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
         }
         // release current task TCB manually to avoid multi-borrow
         drop(inner);
-        file.read(UserBuffer::new(translated_byte_buffer(token, buf, len))) as isize
+        match translated_byte_buffer(token, buf, len, true) {
+            Some(buffers) => file.read(UserBuffer::new(buffers)) as isize,
+            None => -1,
+        }
     } else {
         -1
     }
 }
 
+/// Move the offset of `fd` and return it. Files such as pipes, sockets
+/// and stdin are not seekable.
+pub fn sys_lseek(fd: usize, offset: isize, whence: usize) -> isize {
+    let pos = match whence {
+        SEEK_SET if offset >= 0 => SeekFrom::Start(offset as usize),
+        SEEK_CUR => SeekFrom::Current(offset),
+        SEEK_END => SeekFrom::End(offset),
+        _ => return -1,
+    };
+    get_file(fd)
+        .and_then(|file| file.seek(pos))
+        .map_or(-1, |offset| offset as isize)
+}
+
+/// Read at `offset` of `fd` without moving its offset.
+pub fn sys_pread(fd: usize, buf: *const u8, len: usize, offset: usize) -> isize {
+    let token = current_user_token();
+    match (get_file(fd), translated_byte_buffer(token, buf, len, true)) {
+        (Some(file), Some(buffers)) if file.readable() => file
+            .read_at(offset, UserBuffer::new(buffers))
+            .map_or(-1, |size| size as isize),
+        _ => -1,
+    }
+}
+
+/// Write at `offset` of `fd` without moving its offset.
+pub fn sys_pwrite(fd: usize, buf: *const u8, len: usize, offset: usize) -> isize {
+    let token = current_user_token();
+    match (get_file(fd), translated_byte_buffer(token, buf, len, false)) {
+        (Some(file), Some(buffers)) if file.writable() => file
+            .write_at(offset, UserBuffer::new(buffers))
+            .map_or(-1, |size| size as isize),
+        _ => -1,
+    }
+}
+
+/// Store `stat` to user space, where it may cross a page.
+/// Return false if it can not be written there.
+fn write_stat(token: usize, st: *mut Stat, stat: &Stat) -> bool {
+    let bytes = unsafe {
+        core::slice::from_raw_parts(
+            stat as *const Stat as *const u8,
+            core::mem::size_of::<Stat>(),
+        )
+    };
+    write_user_bytes(token, st as *mut u8, bytes)
+}
+
+pub fn sys_truncate(path: *const u8, len: isize) -> isize {
+    let token = current_user_token();
+    let Some(path) = translated_path(token, path) else {
+        return -1;
+    };
+    match u32::try_from(len) {
+        Ok(len) if truncate(path.as_str(), len) => 0,
+        _ => -1,
+    }
+}
+/// Truncate a file opened for writing, which can not be a directory.
+pub fn sys_ftruncate(fd: usize, len: isize) -> isize {
+    let (Some(file), Ok(len)) = (get_file(fd), u32::try_from(len)) else {
+        return -1;
+    };
+    match file.inode() {
+        Some(inode) if file.writable() && inode.truncate(len) => 0,
+        _ => -1,
+    }
+}
+/// Store the metadata of `fd`, files without an inode only tell their type.
+pub fn sys_fstat(fd: usize, st: *mut Stat) -> isize {
+    let token = current_user_token();
+    match get_file(fd) {
+        Some(file) if write_stat(token, st, &file.stat()) => 0,
+        _ => -1,
+    }
+}
+
+/// Store the metadata of the inode at `path`.
+pub fn sys_stat(path: *const u8, st: *mut Stat, flags: u32) -> isize {
+    let token = current_user_token();
+    let Some(path) = translated_path(token, path) else {
+        return -1;
+    };
+    match stat(path.as_str(), flags & AT_SYMLINK_NOFOLLOW == 0) {
+        Some(stat) if write_stat(token, st, &stat) => 0,
+        _ => -1,
+    }
+}
+
 pub fn sys_open(path: *const u8, flags: u32) -> isize {
     let process = current_process();
     let token = current_user_token();
-    let path = translated_str(token, path);
+    let Some(path) = translated_path(token, path) else {
+        return -1;
+    };
     if let Some(inode) = open_file(path.as_str(), OpenFlags::from_bits(flags).unwrap()) {
         let mut inner = process.inner_exclusive_access();
         let fd = inner.alloc_fd();
@@ -73,14 +198,22 @@ pub fn sys_close(fd: usize) -> isize {
 pub fn sys_pipe(pipe: *mut usize) -> isize {
     let process = current_process();
     let token = current_user_token();
+    // the fds are only allocated if they can be stored
+    let (Some(read_fd_ref), Some(write_fd_ref)) = (
+        translated_refmut(token, pipe),
+        translated_refmut(token, unsafe { pipe.add(1) }),
+    ) else {
+        return -1;
+    };
     let mut inner = process.inner_exclusive_access();
     let (pipe_read, pipe_write) = make_pipe();
     let read_fd = inner.alloc_fd();
     inner.fd_table[read_fd] = Some(pipe_read);
     let write_fd = inner.alloc_fd();
     inner.fd_table[write_fd] = Some(pipe_write);
-    *translated_refmut(token, pipe) = read_fd;
-    *translated_refmut(token, unsafe { pipe.add(1) }) = write_fd;
+    drop(inner);
+    *read_fd_ref = read_fd;
+    *write_fd_ref = write_fd;
     0
 }
 
@@ -97,3 +230,124 @@ pub fn sys_dup(fd: usize) -> isize {
     inner.fd_table[new_fd] = Some(Arc::clone(inner.fd_table[fd].as_ref().unwrap()));
     new_fd as isize
 }
+
+pub fn sys_mkdir(path: *const u8) -> isize {
+    let token = current_user_token();
+    let Some(path) = translated_path(token, path) else {
+        return -1;
+    };
+    if mkdir(path.as_str()) { 0 } else { -1 }
+}
+
+pub fn sys_unlink(path: *const u8, flags: u32) -> isize {
+    let token = current_user_token();
+    let Some(path) = translated_path(token, path) else {
+        return -1;
+    };
+    let removed = if flags & AT_REMOVEDIR != 0 {
+        rmdir(path.as_str())
+    } else {
+        unlink(path.as_str())
+    };
+    if removed { 0 } else { -1 }
+}
+
+pub fn sys_link(old_path: *const u8, new_path: *const u8) -> isize {
+    let token = current_user_token();
+    let Some(old_path) = translated_path(token, old_path) else {
+        return -1;
+    };
+    let Some(new_path) = translated_path(token, new_path) else {
+        return -1;
+    };
+    if link(old_path.as_str(), new_path.as_str()) {
+        0
+    } else {
+        -1
+    }
+}
+
+/// Create a symlink at `path` to `target`, which is kept as it is given.
+pub fn sys_symlink(target: *const u8, path: *const u8) -> isize {
+    let token = current_user_token();
+    let Some(target) = translated_str(token, target) else {
+        return -1;
+    };
+    let Some(path) = translated_path(token, path) else {
+        return -1;
+    };
+    if symlink(target.as_str(), path.as_str()) {
+        0
+    } else {
+        -1
+    }
+}
+
+/// Store the target of the symlink at `path` to `buf` without a
+/// terminating nul, truncated to `len`, and return its length.
+pub fn sys_readlink(path: *const u8, buf: *mut u8, len: usize) -> isize {
+    let token = current_user_token();
+    let Some(path) = translated_path(token, path) else {
+        return -1;
+    };
+    match readlink(path.as_str()) {
+        Some(target) => {
+            let size = target.len().min(len);
+            if write_user_bytes(token, buf, &target.as_bytes()[..size]) {
+                size as isize
+            } else {
+                -1
+            }
+        }
+        None => -1,
+    }
+}
+
+pub fn sys_rename(old_path: *const u8, new_path: *const u8) -> isize {
+    let token = current_user_token();
+    let Some(old_path) = translated_path(token, old_path) else {
+        return -1;
+    };
+    let Some(new_path) = translated_path(token, new_path) else {
+        return -1;
+    };
+    if rename(old_path.as_str(), new_path.as_str()) {
+        0
+    } else {
+        -1
+    }
+}
+
+pub fn sys_chdir(path: *const u8) -> isize {
+    let token = current_user_token();
+    let Some(path) = translated_path(token, path) else {
+        return -1;
+    };
+    match real_dir_path(path.as_str()) {
+        Some(cwd) => {
+            current_process().inner_exclusive_access().cwd = cwd;
+            0
+        }
+        None => -1,
+    }
+}
+
+/// Copy the working directory with a trailing `\0` into `buf`,
+/// return its length or -1 if `buf` is too small.
+pub fn sys_getcwd(buf: *mut u8, len: usize) -> isize {
+    let token = current_user_token();
+    let cwd = current_process().inner_exclusive_access().cwd.clone();
+    if cwd.len() + 1 > len {
+        return -1;
+    }
+    let Some(buffers) = translated_byte_buffer(token, buf, cwd.len() + 1, true) else {
+        return -1;
+    };
+    let user_buf = UserBuffer::new(buffers);
+    for (byte_ref, byte) in user_buf.into_iter().zip(cwd.bytes().chain([0u8])) {
+        unsafe {
+            *byte_ref = byte;
+        }
+    }
+    cwd.len() as isize
+}
@@ -1,5 +1,8 @@
 //use crate::drivers::{KEYBOARD_DEVICE,MOUSE_DEVICE,INPUT_CONDVAR,read_input_event};
 use crate::drivers::{KEYBOARD_DEVICE, MOUSE_DEVICE};
+use crate::fs::InputEvents;
+use crate::task::current_process;
+use alloc::sync::Arc;
 
 pub fn sys_event_get() -> isize {
     let kb = KEYBOARD_DEVICE.clone();
@@ -22,3 +25,13 @@ pub fn sys_key_pressed() -> isize {
     let res = !UART.read_buffer_is_empty();
     if res { 1 } else { 0 }
 }
+
+/// Open the events of the keyboard and the mouse as a file, which can be
+/// polled along with others.
+pub fn sys_event_open() -> isize {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let fd = inner.alloc_fd();
+    inner.fd_table[fd] = Some(Arc::new(InputEvents));
+    fd as isize
+}
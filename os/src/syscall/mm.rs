@@ -0,0 +1,185 @@
+use crate::config::PAGE_SIZE;
+use crate::mm::{FrameTracker, MapFile, MapPermission, MemorySet, VirtAddr, VirtPageNum};
+use crate::task::{current_process, frame_alloc_or_swap};
+use alloc::vec::Vec;
+
+const PROT_READ: usize = 1 << 0;
+const PROT_WRITE: usize = 1 << 1;
+const PROT_EXEC: usize = 1 << 2;
+
+const MAP_SHARED: usize = 0x01;
+const MAP_PRIVATE: usize = 0x02;
+const MAP_FIXED: usize = 0x10;
+const MAP_ANONYMOUS: usize = 0x20;
+
+fn prot_to_permission(prot: usize) -> Option<MapPermission> {
+    if prot & !(PROT_READ | PROT_WRITE | PROT_EXEC) != 0 {
+        return None;
+    }
+    let mut permission = MapPermission::U;
+    // writable pages have to be readable on riscv
+    if prot & (PROT_READ | PROT_WRITE) != 0 {
+        permission |= MapPermission::R;
+    }
+    if prot & PROT_WRITE != 0 {
+        permission |= MapPermission::W;
+    }
+    if prot & PROT_EXEC != 0 {
+        permission |= MapPermission::X;
+    }
+    // a mapping without any access cannot be expressed by a valid pte
+    (permission != MapPermission::U).then_some(permission)
+}
+
+/// The first page of a free range of `pages` pages for `mmap` at `addr`,
+/// which is only a hint unless `fixed` is set.
+fn mmap_start(
+    memory_set: &MemorySet,
+    addr: usize,
+    pages: usize,
+    fixed: bool,
+) -> Option<VirtPageNum> {
+    let hint = VirtAddr::from(addr).floor();
+    if fixed {
+        let end = VirtPageNum(hint.0.checked_add(pages)?);
+        memory_set.is_free_for_mmap(hint, end).then_some(hint)
+    } else {
+        memory_set.find_free_area(hint, pages)
+    }
+}
+
+pub fn sys_mmap(
+    addr: usize,
+    len: usize,
+    prot: usize,
+    flags: usize,
+    fd: usize,
+    offset: usize,
+) -> isize {
+    let permission = match prot_to_permission(prot) {
+        Some(permission) => permission,
+        None => return -1,
+    };
+    let shared = match flags & (MAP_SHARED | MAP_PRIVATE) {
+        MAP_SHARED => true,
+        MAP_PRIVATE => false,
+        _ => return -1,
+    };
+    if len == 0 || offset % PAGE_SIZE != 0 || addr % PAGE_SIZE != 0 {
+        return -1;
+    }
+    let pages = len.div_ceil(PAGE_SIZE);
+    let process = current_process();
+    // the range is checked before any frame is loaded
+    let start_vpn = match mmap_start(
+        &process.inner_exclusive_access().memory_set,
+        addr,
+        pages,
+        flags & MAP_FIXED != 0,
+    ) {
+        Some(start_vpn) => start_vpn,
+        None => return -1,
+    };
+    let file = if flags & MAP_ANONYMOUS != 0 {
+        None
+    } else {
+        let inner = process.inner_exclusive_access();
+        let file = match inner.fd_table.get(fd) {
+            Some(Some(file)) => file.clone(),
+            _ => return -1,
+        };
+        drop(inner);
+        let inode = match file.inode() {
+            Some(inode) if !inode.is_dir() => inode,
+            _ => return -1,
+        };
+        if !file.readable() || (shared && permission.contains(MapPermission::W) && !file.writable())
+        {
+            return -1;
+        }
+        let len = inode.size().saturating_sub(offset).min(pages * PAGE_SIZE);
+        Some(MapFile { inode, offset, len })
+    };
+    // anonymous shared mappings are allocated at once so that forked processes
+    // see the same frames, pages of files are shared through the page cache
+    let mut frames: Vec<FrameTracker> = Vec::new();
+    if shared && file.is_none() {
+        for _ in 0..pages {
+            match frame_alloc_or_swap() {
+                Some(frame) => frames.push(frame),
+                None => return -1,
+            }
+        }
+    }
+    let mut inner = process.inner_exclusive_access();
+    // another thread may have mapped the range while the PCB is released
+    let end_vpn = VirtPageNum(start_vpn.0 + pages);
+    if !inner.memory_set.is_free_for_mmap(start_vpn, end_vpn) {
+        return -1;
+    }
+    let start_va: VirtAddr = start_vpn.into();
+    let end_va: VirtAddr = end_vpn.into();
+    inner
+        .memory_set
+        .insert_mmap_area(start_va, end_va, permission, shared, file, frames);
+    usize::from(start_va) as isize
+}
+
+pub fn sys_munmap(addr: usize, len: usize) -> isize {
+    if len == 0 || addr % PAGE_SIZE != 0 {
+        return -1;
+    }
+    let Some(end) = addr.checked_add(len) else {
+        return -1;
+    };
+    let start_vpn = VirtAddr::from(addr).floor();
+    let end_vpn = VirtAddr::from(end).ceil();
+    let process = current_process();
+    let file_pages = process
+        .inner_exclusive_access()
+        .memory_set
+        .munmap(start_vpn, end_vpn);
+    match file_pages {
+        Some(file_pages) => {
+            // the PCB is released while writing files since it may block
+            for file_page in file_pages {
+                file_page.write_back();
+            }
+            0
+        }
+        None => -1,
+    }
+}
+
+pub fn sys_mprotect(addr: usize, len: usize, prot: usize) -> isize {
+    if len == 0 || addr % PAGE_SIZE != 0 {
+        return -1;
+    }
+    let permission = match prot_to_permission(prot) {
+        Some(permission) => permission,
+        None => return -1,
+    };
+    let Some(end) = addr.checked_add(len) else {
+        return -1;
+    };
+    let start_vpn = VirtAddr::from(addr).floor();
+    let end_vpn = VirtAddr::from(end).ceil();
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if inner.memory_set.mprotect(start_vpn, end_vpn, permission) {
+        0
+    } else {
+        -1
+    }
+}
+
+/// Move the program break to `addr` and return the new break,
+/// the break is left unchanged if `addr` is 0 or invalid.
+pub fn sys_brk(addr: usize) -> isize {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if addr != 0 {
+        inner.memory_set.set_brk(addr);
+    }
+    inner.memory_set.brk() as isize
+}
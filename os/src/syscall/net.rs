@@ -1,53 +1,556 @@
-use crate::net::port_table::{PortFd, accept, listen, port_acceptable};
-use crate::net::udp::UDP;
-use crate::net::{IPv4, net_interrupt_handler};
-use crate::task::{current_process, current_task, current_trap_cx};
+use crate::fs::{File, UnixSocket, UnixType, absolute_path, make_socket_pair};
+use crate::mm::{UserBuffer, translated_byte_buffer, translated_ref, translated_refmut};
+use crate::net::inet::{InetSocket, SocketType};
+use crate::net::{
+    IPv4, NetConfig, Shutdown, SockAddr, configure, dhcp, is_valid_config, local_mac, net_config,
+};
+use crate::task::{current_process, current_user_token};
 use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
 
-// just support udp
-pub fn sys_connect(raddr: u32, lport: u16, rport: u16) -> isize {
+const AF_UNIX: u16 = 1;
+const AF_INET: u16 = 2;
+const SOCK_STREAM: usize = 1;
+const SOCK_DGRAM: usize = 2;
+const SOCK_RAW: usize = 3;
+const IPPROTO_ICMP: usize = 1;
+/// SOCK_NONBLOCK and SOCK_CLOEXEC may be or-ed into the type, they are ignored.
+const SOCK_TYPE_MASK: usize = 0xf;
+const SHUT_RD: usize = 0;
+const SHUT_WR: usize = 1;
+const SHUT_RDWR: usize = 2;
+const SOL_SOCKET: i32 = 1;
+const SCM_RIGHTS: i32 = 1;
+/// `msg_flags` of `recvmsg`: some files passed did not fit in the control buffer.
+const MSG_CTRUNC: i32 = 8;
+
+/// `struct sockaddr_in`: the family, the port and the address in network
+/// byte order, and 8 bytes of padding.
+const SOCKADDR_IN_SIZE: usize = 16;
+/// `struct sockaddr_un`: the family and a path ended by 0.
+const SOCKADDR_UN_SIZE: usize = 110;
+/// Files passed by a message at most, as SCM_MAX_FD of Linux.
+const MAX_PASSED_FILES: usize = 253;
+
+/// `struct msghdr`.
+#[repr(C)]
+pub struct MsgHdr {
+    name: *mut u8,
+    namelen: u32,
+    iov: *const IoVec,
+    iovlen: usize,
+    control: *mut u8,
+    controllen: usize,
+    flags: i32,
+}
+
+/// `struct iovec`.
+#[repr(C)]
+struct IoVec {
+    base: *mut u8,
+    len: usize,
+}
+
+/// `struct cmsghdr` is followed by its data, and the next one starts
+/// at a multiple of 8 bytes.
+const CMSG_HEADER_SIZE: usize = 16;
+
+fn cmsg_align(len: usize) -> usize {
+    (len + 7) & !7
+}
+
+/// The file of a socket fd.
+fn socket_file(fd: usize) -> Option<Arc<dyn File>> {
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let file = inner.fd_table.get(fd)?.as_ref()?.clone();
+    file.socket()?;
+    Some(file)
+}
+
+fn read_user_bytes(token: usize, ptr: *const u8, len: usize) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(len);
+    for buffer in translated_byte_buffer(token, ptr, len, false)? {
+        bytes.extend_from_slice(buffer);
+    }
+    Some(bytes)
+}
+
+/// Return false if the bytes can not be written to `ptr`.
+pub fn write_user_bytes(token: usize, ptr: *mut u8, bytes: &[u8]) -> bool {
+    let Some(buffers) = translated_byte_buffer(token, ptr, bytes.len(), true) else {
+        return false;
+    };
+    for (dst, byte) in UserBuffer::new(buffers).into_iter().zip(bytes) {
+        unsafe {
+            *dst = *byte;
+        }
+    }
+    true
+}
+
+/// Load a socket address from user space, the path of a Unix socket is
+/// resolved against the current working directory.
+fn read_sockaddr(token: usize, addr: *const u8, addrlen: usize) -> Option<SockAddr> {
+    if addr.is_null() || addrlen < 2 {
+        return None;
+    }
+    let bytes = read_user_bytes(token, addr, addrlen.min(SOCKADDR_UN_SIZE))?;
+    match u16::from_ne_bytes([bytes[0], bytes[1]]) {
+        AF_INET if bytes.len() >= SOCKADDR_IN_SIZE => {
+            let port = u16::from_be_bytes([bytes[2], bytes[3]]);
+            let ip = u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+            Some(SockAddr::Inet(IPv4::from_u32(ip), port))
+        }
+        AF_UNIX => {
+            let path = &bytes[2..];
+            let len = path
+                .iter()
+                .position(|&byte| byte == 0)
+                .unwrap_or(path.len());
+            let path = core::str::from_utf8(&path[..len]).ok()?;
+            if path.is_empty() {
+                return None;
+            }
+            let cwd = current_process().inner_exclusive_access().cwd.clone();
+            Some(SockAddr::Unix(absolute_path(&cwd, path)))
+        }
+        _ => None,
+    }
+}
+
+/// Store a socket address to user space, truncated to `*addrlen` bytes,
+/// and set `*addrlen` to its full size. Nothing is stored if `addr` is null.
+/// Return false if they can not be accessed.
+fn write_sockaddr(token: usize, addr: *mut u8, addrlen: *mut u32, sockaddr: SockAddr) -> bool {
+    if addr.is_null() || addrlen.is_null() {
+        return true;
+    }
+    let bytes = match sockaddr {
+        SockAddr::Inet(ip, port) => {
+            let mut bytes = vec![0u8; SOCKADDR_IN_SIZE];
+            bytes[0..2].copy_from_slice(&AF_INET.to_ne_bytes());
+            bytes[2..4].copy_from_slice(&port.to_be_bytes());
+            bytes[4..8].copy_from_slice(&ip.to_u32().to_be_bytes());
+            bytes
+        }
+        SockAddr::Unix(path) => {
+            // an unnamed socket has the family only
+            let mut bytes = AF_UNIX.to_ne_bytes().to_vec();
+            if !path.is_empty() {
+                bytes.extend_from_slice(&path.as_bytes()[..path.len().min(SOCKADDR_UN_SIZE - 3)]);
+                bytes.push(0);
+            }
+            bytes
+        }
+    };
+    let Some(addrlen) = translated_refmut(token, addrlen) else {
+        return false;
+    };
+    let len = (*addrlen as usize).min(bytes.len());
+    if !write_user_bytes(token, addr, &bytes[..len]) {
+        return false;
+    }
+    *addrlen = bytes.len() as u32;
+    true
+}
+
+/// The buffers of an array of `struct iovec` in user space, which are
+/// written if `write` is set.
+fn iov_buffer(token: usize, iov: *const IoVec, iovlen: usize, write: bool) -> Option<UserBuffer> {
+    let mut buffers = Vec::new();
+    for i in 0..iovlen {
+        let iov = translated_ref(token, unsafe { iov.add(i) })?;
+        buffers.extend(translated_byte_buffer(token, iov.base, iov.len, write)?);
+    }
+    Some(UserBuffer::new(buffers))
+}
+
+fn unix_type(socket_type: usize, protocol: usize) -> Option<UnixType> {
+    match (socket_type & SOCK_TYPE_MASK, protocol) {
+        (SOCK_STREAM, 0) => Some(UnixType::Stream),
+        (SOCK_DGRAM, 0) => Some(UnixType::Datagram),
+        _ => None,
+    }
+}
+
+/// Raw sockets are supported for ICMP only.
+pub fn sys_socket(domain: usize, socket_type: usize, protocol: usize) -> isize {
+    let socket: Arc<dyn File> = match domain as u16 {
+        AF_INET => {
+            let socket_type = match socket_type & SOCK_TYPE_MASK {
+                SOCK_STREAM => SocketType::Stream,
+                SOCK_DGRAM => SocketType::Datagram,
+                SOCK_RAW if protocol == IPPROTO_ICMP => SocketType::Raw,
+                _ => return -1,
+            };
+            Arc::new(InetSocket::new(socket_type))
+        }
+        AF_UNIX => match unix_type(socket_type, protocol) {
+            Some(socket_type) => Arc::new(UnixSocket::new(socket_type)),
+            None => return -1,
+        },
+        _ => return -1,
+    };
     let process = current_process();
     let mut inner = process.inner_exclusive_access();
     let fd = inner.alloc_fd();
-    let udp_node = UDP::new(IPv4::from_u32(raddr), lport, rport);
-    inner.fd_table[fd] = Some(Arc::new(udp_node));
+    inner.fd_table[fd] = Some(socket);
     fd as isize
 }
 
-// listen a port
-pub fn sys_listen(port: u16) -> isize {
-    match listen(port) {
-        Some(port_index) => {
-            let process = current_process();
-            let mut inner = process.inner_exclusive_access();
-            let fd = inner.alloc_fd();
-            let port_fd = PortFd::new(port_index);
-            inner.fd_table[fd] = Some(Arc::new(port_fd));
+/// Create a pair of connected Unix sockets, and store their fds to `sv`.
+pub fn sys_socketpair(domain: usize, socket_type: usize, protocol: usize, sv: *mut i32) -> isize {
+    if domain != AF_UNIX as usize {
+        return -1;
+    }
+    let socket_type = match unix_type(socket_type, protocol) {
+        Some(socket_type) => socket_type,
+        None => return -1,
+    };
+    let token = current_user_token();
+    // the fds are only allocated if they can be stored
+    let (Some(fd_a_ref), Some(fd_b_ref)) = (
+        translated_refmut(token, sv),
+        translated_refmut(token, unsafe { sv.add(1) }),
+    ) else {
+        return -1;
+    };
+    let (a, b) = make_socket_pair(socket_type);
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let fd_a = inner.alloc_fd();
+    inner.fd_table[fd_a] = Some(a);
+    let fd_b = inner.alloc_fd();
+    inner.fd_table[fd_b] = Some(b);
+    drop(inner);
+    *fd_a_ref = fd_a as i32;
+    *fd_b_ref = fd_b as i32;
+    0
+}
+
+pub fn sys_bind(fd: usize, addr: *const u8, addrlen: usize) -> isize {
+    let token = current_user_token();
+    let (file, addr) = match (socket_file(fd), read_sockaddr(token, addr, addrlen)) {
+        (Some(file), Some(addr)) => (file, addr),
+        _ => return -1,
+    };
+    if file.socket().unwrap().bind(addr) {
+        0
+    } else {
+        -1
+    }
+}
 
-            // NOTICE: this return the port index, not the fd
-            port_index as isize
+pub fn sys_listen(fd: usize, backlog: usize) -> isize {
+    match socket_file(fd) {
+        Some(file) if file.socket().unwrap().listen(backlog) => 0,
+        _ => -1,
+    }
+}
+
+/// Wait for a connection of a listening socket, return the fd of it.
+pub fn sys_accept(fd: usize, addr: *mut u8, addrlen: *mut u32) -> isize {
+    let token = current_user_token();
+    let file = match socket_file(fd) {
+        Some(file) => file,
+        None => return -1,
+    };
+    let (connection, peer) = match file.socket().unwrap().accept() {
+        Some(accepted) => accepted,
+        None => return -1,
+    };
+    if !write_sockaddr(token, addr, addrlen, peer) {
+        return -1;
+    }
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let fd = inner.alloc_fd();
+    inner.fd_table[fd] = Some(connection);
+    fd as isize
+}
+
+pub fn sys_connect(fd: usize, addr: *const u8, addrlen: usize) -> isize {
+    let token = current_user_token();
+    let (file, addr) = match (socket_file(fd), read_sockaddr(token, addr, addrlen)) {
+        (Some(file), Some(addr)) => (file, addr),
+        _ => return -1,
+    };
+    if file.socket().unwrap().connect(addr) {
+        0
+    } else {
+        -1
+    }
+}
+
+pub fn sys_getsockname(fd: usize, addr: *mut u8, addrlen: *mut u32) -> isize {
+    let token = current_user_token();
+    match socket_file(fd).and_then(|file| file.socket().unwrap().local_addr()) {
+        Some(local) if write_sockaddr(token, addr, addrlen, local) => 0,
+        _ => -1,
+    }
+}
+
+pub fn sys_getpeername(fd: usize, addr: *mut u8, addrlen: *mut u32) -> isize {
+    let token = current_user_token();
+    match socket_file(fd).and_then(|file| file.socket().unwrap().peer_addr()) {
+        Some(peer) if write_sockaddr(token, addr, addrlen, peer) => 0,
+        _ => -1,
+    }
+}
+
+/// Send to `addr`, or to the connected peer if `addr` is null. No flag is supported.
+pub fn sys_sendto(
+    fd: usize,
+    buf: *const u8,
+    len: usize,
+    _flags: usize,
+    addr: *const u8,
+    addrlen: usize,
+) -> isize {
+    let token = current_user_token();
+    let file = match socket_file(fd) {
+        Some(file) => file,
+        None => return -1,
+    };
+    let addr = if addr.is_null() {
+        None
+    } else {
+        match read_sockaddr(token, addr, addrlen) {
+            Some(addr) => Some(addr),
+            None => return -1,
         }
+    };
+    let Some(buffers) = translated_byte_buffer(token, buf, len, false) else {
+        return -1;
+    };
+    match file
+        .socket()
+        .unwrap()
+        .send_to(UserBuffer::new(buffers), addr)
+    {
+        Some(len) => len as isize,
         None => -1,
     }
 }
 
-// accept a tcp connection
-pub fn sys_accept(port_index: usize) -> isize {
-    println!("accepting port {}", port_index);
-
-    let task = current_task().unwrap();
-    accept(port_index, task);
-    // block_current_and_run_next();
+/// Receive data and store the address of the sender to `addr` if it is
+/// not null. No flag is supported.
+pub fn sys_recvfrom(
+    fd: usize,
+    buf: *mut u8,
+    len: usize,
+    _flags: usize,
+    addr: *mut u8,
+    addrlen: *mut u32,
+) -> isize {
+    let token = current_user_token();
+    let file = match socket_file(fd) {
+        Some(file) => file,
+        None => return -1,
+    };
+    let Some(buffers) = translated_byte_buffer(token, buf, len, true) else {
+        return -1;
+    };
+    match file.socket().unwrap().recv_from(UserBuffer::new(buffers)) {
+        Some((_, Some(source))) if !write_sockaddr(token, addr, addrlen, source) => -1,
+        Some((len, _)) => len as isize,
+        None => -1,
+    }
+}
 
-    // NOTICE: There does not have interrupt handler, just call it munually.
-    loop {
-        net_interrupt_handler();
+/// Send the data of the buffers in `msg`, to the address in it if there is
+/// one. The files of SCM_RIGHTS messages in its control buffer are passed
+/// along. No flag is supported.
+pub fn sys_sendmsg(fd: usize, msg: *const MsgHdr, _flags: usize) -> isize {
+    let token = current_user_token();
+    let file = match socket_file(fd) {
+        Some(file) => file,
+        None => return -1,
+    };
+    let Some(msg) = translated_ref(token, msg) else {
+        return -1;
+    };
+    let addr = if msg.name.is_null() {
+        None
+    } else {
+        match read_sockaddr(token, msg.name, msg.namelen as usize) {
+            Some(addr) => Some(addr),
+            None => return -1,
+        }
+    };
+    let mut files = Vec::new();
+    if !msg.control.is_null() {
+        let Some(control) = read_user_bytes(token, msg.control, msg.controllen) else {
+            return -1;
+        };
+        let mut offset = 0;
+        while offset + CMSG_HEADER_SIZE <= control.len() {
+            let header = &control[offset..offset + CMSG_HEADER_SIZE];
+            let len = usize::from_ne_bytes(header[0..8].try_into().unwrap());
+            let level = i32::from_ne_bytes(header[8..12].try_into().unwrap());
+            let cmsg_type = i32::from_ne_bytes(header[12..16].try_into().unwrap());
+            if len < CMSG_HEADER_SIZE || offset + len > control.len() {
+                return -1;
+            }
+            if level == SOL_SOCKET && cmsg_type == SCM_RIGHTS {
+                let process = current_process();
+                let inner = process.inner_exclusive_access();
+                for fd in control[offset + CMSG_HEADER_SIZE..offset + len].chunks_exact(4) {
+                    let fd = i32::from_ne_bytes(fd.try_into().unwrap()) as usize;
+                    match inner.fd_table.get(fd) {
+                        Some(Some(file)) => files.push(file.clone()),
+                        _ => return -1,
+                    }
+                }
+            }
+            offset += cmsg_align(len);
+        }
+        if files.len() > MAX_PASSED_FILES {
+            return -1;
+        }
+    }
+    let Some(buf) = iov_buffer(token, msg.iov, msg.iovlen, false) else {
+        return -1;
+    };
+    match file.socket().unwrap().send_msg(buf, addr, files) {
+        Some(len) => len as isize,
+        None => -1,
+    }
+}
 
-        if !port_acceptable(port_index) {
-            break;
+/// Receive into the buffers in `msg`, and store the address of the sender.
+/// Files passed along get new fds, which are stored to the control buffer
+/// as an SCM_RIGHTS message; those which do not fit are closed and
+/// MSG_CTRUNC is set. No flag is supported.
+pub fn sys_recvmsg(fd: usize, msg: *mut MsgHdr, _flags: usize) -> isize {
+    let token = current_user_token();
+    let file = match socket_file(fd) {
+        Some(file) => file,
+        None => return -1,
+    };
+    let Some((name, iov, iovlen, control, controllen)) = translated_ref(token, msg)
+        .map(|msg| (msg.name, msg.iov, msg.iovlen, msg.control, msg.controllen))
+    else {
+        return -1;
+    };
+    let Some(buf) = iov_buffer(token, iov, iovlen, true) else {
+        return -1;
+    };
+    let (len, source, mut files) = match file.socket().unwrap().recv_msg(buf) {
+        Some(received) => received,
+        None => return -1,
+    };
+    let namelen = unsafe { &raw mut (*msg).namelen };
+    let name_stored = match source {
+        Some(source) => write_sockaddr(token, name, namelen, source),
+        None => match translated_refmut(token, namelen) {
+            Some(namelen) => {
+                *namelen = 0;
+                true
+            }
+            None => false,
+        },
+    };
+    if !name_stored {
+        return -1;
+    }
+    let mut flags = 0;
+    let room = if control.is_null() {
+        0
+    } else {
+        controllen.saturating_sub(CMSG_HEADER_SIZE) / 4
+    };
+    if files.len() > room {
+        files.truncate(room);
+        flags |= MSG_CTRUNC;
+    }
+    let mut used = 0;
+    if !files.is_empty() {
+        let process = current_process();
+        let mut inner = process.inner_exclusive_access();
+        let mut cmsg = Vec::with_capacity(CMSG_HEADER_SIZE + 4 * files.len());
+        cmsg.extend_from_slice(&(CMSG_HEADER_SIZE + 4 * files.len()).to_ne_bytes());
+        cmsg.extend_from_slice(&SOL_SOCKET.to_ne_bytes());
+        cmsg.extend_from_slice(&SCM_RIGHTS.to_ne_bytes());
+        for file in files {
+            let fd = inner.alloc_fd();
+            inner.fd_table[fd] = Some(file);
+            cmsg.extend_from_slice(&(fd as i32).to_ne_bytes());
+        }
+        drop(inner);
+        if !write_user_bytes(token, control, &cmsg) {
+            return -1;
         }
+        used = cmsg.len();
     }
+    match (
+        translated_refmut(token, unsafe { &raw mut (*msg).controllen }),
+        translated_refmut(token, unsafe { &raw mut (*msg).flags }),
+    ) {
+        (Some(controllen), Some(msg_flags)) => {
+            *controllen = used;
+            *msg_flags = flags;
+            len as isize
+        }
+        _ => -1,
+    }
+}
+
+pub fn sys_shutdown(fd: usize, how: usize) -> isize {
+    let how = match how {
+        SHUT_RD => Shutdown::Read,
+        SHUT_WR => Shutdown::Write,
+        SHUT_RDWR => Shutdown::Both,
+        _ => return -1,
+    };
+    match socket_file(fd) {
+        Some(file) if file.socket().unwrap().shutdown(how) => 0,
+        _ => -1,
+    }
+}
 
-    let cx = current_trap_cx();
-    cx.x[10] as isize
+/// The configuration of the interface seen by user space, the addresses
+/// are in host byte order.
+#[repr(C)]
+pub struct NetConfigInfo {
+    pub ip: u32,
+    pub netmask: u32,
+    pub gateway: u32,
+    pub mac: [u8; 6],
+}
+
+pub fn sys_net_config_get(info: *mut NetConfigInfo) -> isize {
+    let Some(info) = translated_refmut(current_user_token(), info) else {
+        return -1;
+    };
+    let config = net_config();
+    *info = NetConfigInfo {
+        ip: config.ip,
+        netmask: config.netmask,
+        gateway: config.gateway,
+        mac: local_mac(),
+    };
+    0
+}
+
+/// Configure the interface statically, a gateway of 0 means none. The
+/// address 0.0.0.0 restarts DHCP instead.
+pub fn sys_net_config_set(ip: u32, netmask: u32, gateway: u32) -> isize {
+    if ip == 0 {
+        dhcp::start();
+        return 0;
+    }
+    let config = NetConfig {
+        ip,
+        netmask,
+        gateway,
+    };
+    if !is_valid_config(&config) {
+        return -1;
+    }
+    dhcp::stop();
+    configure(config);
+    0
 }
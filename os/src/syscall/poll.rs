@@ -0,0 +1,252 @@
+use super::fs::get_file;
+use crate::fs::{Epoll, PollEvents, wait_ready};
+use crate::mm::{translated_ref, translated_refmut, user_range_accessible};
+use crate::task::{current_process, current_user_token};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+const EPOLL_CTL_ADD: usize = 1;
+const EPOLL_CTL_DEL: usize = 2;
+const EPOLL_CTL_MOD: usize = 3;
+/// The bits of epoll events beyond those of poll, which ask for edge-triggered
+/// or one-shot notification, and are not supported.
+const EPOLL_UNSUPPORTED: u32 = !0xffff;
+/// Fds a select looks at, as FD_SETSIZE of Linux.
+const FD_SETSIZE: usize = 1024;
+
+/// `struct pollfd`.
+#[repr(C)]
+pub struct PollFd {
+    fd: i32,
+    events: u16,
+    revents: u16,
+}
+
+/// `struct epoll_event`, which is not packed on RISC-V.
+#[repr(C)]
+pub struct EpollEvent {
+    events: u32,
+    data: u64,
+}
+
+/// Whether an array of `len` items at `ptr` can be written by the kernel,
+/// which is checked before waiting.
+fn array_writable<T>(token: usize, ptr: *mut T, len: usize) -> bool {
+    len.checked_mul(core::mem::size_of::<T>())
+        .is_some_and(|size| user_range_accessible(token, ptr as usize, size, true))
+}
+
+/// A negative timeout waits forever.
+fn timeout(timeout_ms: isize) -> Option<usize> {
+    (timeout_ms >= 0).then_some(timeout_ms as usize)
+}
+
+/// Wait for the events of `fds`, and store the events ready to each.
+/// Return the number of fds with events ready, 0 if the timeout passes.
+/// Fds which are not open get POLLNVAL, negative fds are skipped.
+pub fn sys_poll(fds: *mut PollFd, nfds: usize, timeout_ms: isize) -> isize {
+    let token = current_user_token();
+    if !array_writable(token, fds, nfds) {
+        return -1;
+    }
+    let mut polled = Vec::with_capacity(nfds);
+    for i in 0..nfds {
+        let Some(poll_fd) = translated_ref(token, unsafe { fds.add(i) }) else {
+            return -1;
+        };
+        let events = PollEvents::from_bits_truncate(poll_fd.events) | PollEvents::always();
+        let file = if poll_fd.fd < 0 {
+            None
+        } else {
+            Some(get_file(poll_fd.fd as usize))
+        };
+        polled.push((file, events));
+    }
+    let files: Vec<_> = polled
+        .iter()
+        .filter_map(|(file, _)| file.clone().flatten())
+        .collect();
+    let check = || {
+        let revents: Vec<_> = polled
+            .iter()
+            .map(|(file, events)| match file {
+                None => PollEvents::empty(),
+                Some(None) => PollEvents::NVAL,
+                Some(Some(file)) => file.poll_ready() & *events,
+            })
+            .collect();
+        revents
+            .iter()
+            .any(|revents| !revents.is_empty())
+            .then_some(revents)
+    };
+    let revents = wait_ready(&files, timeout(timeout_ms), check);
+    let mut count = 0;
+    for i in 0..nfds {
+        // it may have been unmapped by another thread while waiting
+        let Some(poll_fd) = translated_refmut(token, unsafe { fds.add(i) }) else {
+            return -1;
+        };
+        poll_fd.revents = revents.as_ref().map_or(0, |revents| revents[i].bits());
+        if poll_fd.revents != 0 {
+            count += 1;
+        }
+    }
+    count
+}
+
+/// Select among the first `nfds` fds those ready to be read, written, or
+/// with exceptional conditions, the sets are bitmaps of u64 words which
+/// may be null. The sets are overwritten by the fds ready, and the number
+/// of them is returned.
+pub fn sys_select(
+    nfds: usize,
+    readfds: *mut u64,
+    writefds: *mut u64,
+    exceptfds: *mut u64,
+    timeout_ms: isize,
+) -> isize {
+    if nfds > FD_SETSIZE {
+        return -1;
+    }
+    let token = current_user_token();
+    let words = nfds.div_ceil(64);
+    let load = |set: *mut u64| -> Option<Vec<u64>> {
+        if set.is_null() {
+            return Some(alloc::vec![0; words]);
+        }
+        if !array_writable(token, set, words) {
+            return None;
+        }
+        (0..words)
+            .map(|i| translated_ref(token, unsafe { set.add(i) }).copied())
+            .collect()
+    };
+    let (Some(read_set), Some(write_set), Some(except_set)) =
+        (load(readfds), load(writefds), load(exceptfds))
+    else {
+        return -1;
+    };
+    let sets = [
+        (read_set, PollEvents::IN | PollEvents::HUP | PollEvents::ERR),
+        (write_set, PollEvents::OUT | PollEvents::ERR),
+        (except_set, PollEvents::PRI),
+    ];
+    let mut polled = Vec::new();
+    for fd in 0..nfds {
+        let asked = sets
+            .iter()
+            .any(|(set, _)| set[fd / 64] & (1 << (fd % 64)) != 0);
+        if asked {
+            match get_file(fd) {
+                Some(file) => polled.push((fd, file)),
+                None => return -1,
+            }
+        }
+    }
+    let files: Vec<_> = polled.iter().map(|(_, file)| file.clone()).collect();
+    let check = || {
+        let mut ready: [Vec<u64>; 3] = core::array::from_fn(|_| alloc::vec![0; words]);
+        let mut count = 0;
+        for (fd, file) in polled.iter() {
+            let events = file.poll_ready();
+            for ((set, wanted), ready) in sets.iter().zip(ready.iter_mut()) {
+                let bit = 1 << (fd % 64);
+                if set[fd / 64] & bit != 0 && events.intersects(*wanted) {
+                    ready[fd / 64] |= bit;
+                    count += 1;
+                }
+            }
+        }
+        (count > 0).then_some((ready, count))
+    };
+    let (ready, count) = wait_ready(&files, timeout(timeout_ms), check)
+        .unwrap_or_else(|| (core::array::from_fn(|_| alloc::vec![0; words]), 0));
+    for (set, ready) in [readfds, writefds, exceptfds].into_iter().zip(ready) {
+        if set.is_null() {
+            continue;
+        }
+        for (i, word) in ready.into_iter().enumerate() {
+            match translated_refmut(token, unsafe { set.add(i) }) {
+                Some(set_word) => *set_word = word,
+                None => return -1,
+            }
+        }
+    }
+    count as isize
+}
+
+pub fn sys_epoll_create(_flags: usize) -> isize {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let fd = inner.alloc_fd();
+    inner.fd_table[fd] = Some(Arc::new(Epoll::new()));
+    fd as isize
+}
+
+/// Add, modify or delete the interest of `fd` in the epoll instance
+/// `epfd`. Only level-triggered notification is supported.
+pub fn sys_epoll_ctl(epfd: usize, op: usize, fd: usize, event: *const EpollEvent) -> isize {
+    let token = current_user_token();
+    let (epoll_file, file) = match (get_file(epfd), get_file(fd)) {
+        (Some(epoll_file), Some(file)) => (epoll_file, file),
+        _ => return -1,
+    };
+    let epoll = match epoll_file.epoll() {
+        Some(epoll) => epoll,
+        None => return -1,
+    };
+    let done = if op == EPOLL_CTL_DEL {
+        epoll.delete(fd, &file)
+    } else {
+        let Some(event) = translated_ref(token, event) else {
+            return -1;
+        };
+        if event.events & EPOLL_UNSUPPORTED != 0 {
+            return -1;
+        }
+        let events = PollEvents::from_bits_truncate(event.events as u16);
+        match op {
+            EPOLL_CTL_ADD => epoll.add(fd, &file, events, event.data),
+            EPOLL_CTL_MOD => epoll.modify(fd, &file, events, event.data),
+            _ => false,
+        }
+    };
+    if done { 0 } else { -1 }
+}
+
+/// Wait for at most `maxevents` events of the epoll instance `epfd`, and
+/// store them to `events`. Return their number, 0 if the timeout passes.
+pub fn sys_epoll_wait(
+    epfd: usize,
+    events: *mut EpollEvent,
+    maxevents: usize,
+    timeout_ms: isize,
+) -> isize {
+    let token = current_user_token();
+    let epoll_file = match get_file(epfd) {
+        Some(epoll_file) if maxevents > 0 && array_writable(token, events, maxevents) => epoll_file,
+        _ => return -1,
+    };
+    let epoll = match epoll_file.epoll() {
+        Some(epoll) => epoll,
+        None => return -1,
+    };
+    // the epoll instance registers the waiter on every file it watches
+    let files = [epoll_file.clone()];
+    let check = || {
+        let ready = epoll.ready(maxevents);
+        (!ready.is_empty()).then_some(ready)
+    };
+    let ready = wait_ready(&files, timeout(timeout_ms), check).unwrap_or_default();
+    for (i, (ready_events, data)) in ready.iter().enumerate() {
+        let Some(event) = translated_refmut(token, unsafe { events.add(i) }) else {
+            return -1;
+        };
+        *event = EpollEvent {
+            events: ready_events.bits() as u32,
+            data: *data,
+        };
+    }
+    ready.len() as isize
+}
@@ -1,8 +1,12 @@
-use crate::fs::{OpenFlags, open_file};
-use crate::mm::{translated_ref, translated_refmut, translated_str};
+use super::fs::translated_path;
+use crate::fs::{File, OpenFlags, open_file};
+use crate::mm::{translated_ref, translated_refmut, translated_str, user_range_accessible};
 use crate::task::{
-    SignalFlags, current_process, current_task, current_user_token, exit_current_and_run_next,
-    pid2process, suspend_current_and_run_next,
+    CONTINUED_STATUS, MAX_NICE, MIN_NICE, MIN_PRIORITY, SIG_BLOCK, SIG_IGN, SIG_SETMASK,
+    SIG_UNBLOCK, SignalAction, SignalFlags, WCONTINUED, WNOHANG, WUNTRACED, current_force_signal,
+    current_process, current_task, current_user_token, exit_current_and_run_next,
+    is_stopped_status, pid2process, pop_signal_frame, schedule, suspend_current_and_run_next,
+    wait_switched_out,
 };
 use crate::timer::get_time_ms;
 use alloc::string::String;
@@ -19,6 +23,25 @@ pub fn sys_yield() -> isize {
     0
 }
 
+/// Set the priority of the current thread used by the static priority
+/// and stride policies, return the priority or -1 if it is too small.
+pub fn sys_set_priority(prio: isize) -> isize {
+    if prio < MIN_PRIORITY as isize {
+        return -1;
+    }
+    current_task().unwrap().sched.exclusive_access().priority = prio as usize;
+    prio
+}
+
+/// Add `inc` to the nice value of the current thread used by the
+/// CFS-like policy, return the new nice value.
+pub fn sys_nice(inc: isize) -> isize {
+    let task = current_task().unwrap();
+    let mut sched = task.sched.exclusive_access();
+    sched.nice = sched.nice.saturating_add(inc).clamp(MIN_NICE, MAX_NICE);
+    sched.nice
+}
+
 pub fn sys_get_time() -> isize {
     get_time_ms() as isize
 }
@@ -30,36 +53,36 @@ pub fn sys_getpid() -> isize {
 pub fn sys_fork() -> isize {
     let current_process = current_process();
     let new_process = current_process.fork();
-    let new_pid = new_process.getpid();
-    // modify trap context of new_task, because it returns immediately after switching
-    let new_process_inner = new_process.inner_exclusive_access();
-    let task = new_process_inner.tasks[0].as_ref().unwrap();
-    let trap_cx = task.inner_exclusive_access().get_trap_cx();
-    // we do not have to move to next instruction since we have done it before
-    // for child process, fork returns 0
-    trap_cx.x[10] = 0;
-    new_pid as isize
+    // the trap context of the new task is set before it may run on another hart
+    new_process.getpid() as isize
 }
 
 pub fn sys_exec(path: *const u8, mut args: *const usize) -> isize {
     let token = current_user_token();
-    let path = translated_str(token, path);
+    let Some(path) = translated_path(token, path) else {
+        return -1;
+    };
     let mut args_vec: Vec<String> = Vec::new();
     loop {
-        let arg_str_ptr = *translated_ref(token, args);
+        let Some(&arg_str_ptr) = translated_ref(token, args) else {
+            return -1;
+        };
         if arg_str_ptr == 0 {
             break;
         }
-        args_vec.push(translated_str(token, arg_str_ptr as *const u8));
+        let Some(arg) = translated_str(token, arg_str_ptr as *const u8) else {
+            return -1;
+        };
+        args_vec.push(arg);
         unsafe {
             args = args.add(1);
         }
     }
     if let Some(app_inode) = open_file(path.as_str(), OpenFlags::RDONLY) {
-        let all_data = app_inode.read_all();
         let process = current_process();
         let argc = args_vec.len();
-        process.exec(all_data.as_slice(), args_vec);
+        process.sync_shared_mappings();
+        process.exec(&app_inode.inode().unwrap(), args_vec);
         // return argc because cx.x[10] will be covered with it later
         argc as isize
     } else {
@@ -67,51 +90,220 @@ pub fn sys_exec(path: *const u8, mut args: *const usize) -> isize {
     }
 }
 
-/// If there is not a child process whose pid is same as given, return -1.
-/// Else if there is a child process but it is still running, return -2.
-pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
+/// Wait for a child to exit, or to be stopped or continued if `WUNTRACED` or
+/// `WCONTINUED` is set in `options`. Return its pid and write its status word,
+/// 0 if `WNOHANG` is set and no child has changed its state, or -1 if there is
+/// no such child or the wait is interrupted by a signal.
+pub fn sys_waitpid(pid: isize, status_ptr: *mut i32, options: usize) -> isize {
     let process = current_process();
-    // find a child process
-
-    let mut inner = process.inner_exclusive_access();
-    if !inner
-        .children
-        .iter()
-        .any(|p| pid == -1 || pid as usize == p.getpid())
+    // the status word is checked before a child is reaped
+    if !status_ptr.is_null()
+        && !user_range_accessible(
+            current_user_token(),
+            status_ptr as usize,
+            core::mem::size_of::<i32>(),
+            true,
+        )
     {
         return -1;
-        // ---- release current PCB
     }
-    let pair = inner.children.iter().enumerate().find(|(_, p)| {
-        // ++++ temporarily access child PCB exclusively
-        p.inner_exclusive_access().is_zombie && (pid == -1 || pid as usize == p.getpid())
-        // ++++ release child PCB
-    });
-    if let Some((idx, _)) = pair {
-        let child = inner.children.remove(idx);
-        // confirm that child will be deallocated after being removed from children list
-        assert_eq!(Arc::strong_count(&child), 1);
-        let found_pid = child.getpid();
-        // ++++ temporarily access child PCB exclusively
-        let exit_code = child.inner_exclusive_access().exit_code;
-        // ++++ release child PCB
-        *translated_refmut(inner.memory_set.token(), exit_code_ptr) = exit_code;
-        found_pid as isize
+    loop {
+        let mut inner = process.inner_exclusive_access();
+        if !inner
+            .children
+            .iter()
+            .any(|p| pid == -1 || pid as usize == p.getpid())
+        {
+            return -1;
+            // ---- release current PCB
+        }
+        let found = inner
+            .children
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| pid == -1 || pid as usize == p.getpid())
+            .find_map(|(idx, p)| {
+                // ++++ temporarily access child PCB exclusively
+                let mut child_inner = p.inner_exclusive_access();
+                if let Some(status) = child_inner.exit_status {
+                    return Some((idx, status, true));
+                }
+                let status = child_inner.status_change?;
+                let reported = if is_stopped_status(status) {
+                    options & WUNTRACED != 0
+                } else {
+                    status == CONTINUED_STATUS && options & WCONTINUED != 0
+                };
+                if reported {
+                    child_inner.status_change = None;
+                    Some((idx, status, false))
+                } else {
+                    None
+                }
+                // ++++ release child PCB
+            });
+        let (idx, status, exited) = match found {
+            Some(found) => found,
+            None if options & WNOHANG != 0 => return 0,
+            None if inner.signal_interrupts() => return -1,
+            None => {
+                // woken up by a child changing its state, or by a signal
+                let task_cx_ptr = inner.wait_queue.wait_no_sched();
+                drop(inner);
+                schedule(task_cx_ptr);
+                continue;
+            }
+        };
+        let child = if exited {
+            inner.children.remove(idx)
+        } else {
+            inner.children[idx].clone()
+        };
+        let token = inner.memory_set.token();
+        // writing to user memory may copy a copy-on-write page of the current process
+        drop(inner);
+        if exited {
+            // the main thread may still be switching out on its own kernel stack
+            let main_task = child.inner_exclusive_access().get_task(0);
+            wait_switched_out(&main_task);
+            drop(main_task);
+            // confirm that child will be deallocated after being removed from children list
+            assert_eq!(Arc::strong_count(&child), 1);
+        }
+        // unless it has been unmapped by another thread in the meantime
+        if let Some(status_ref) = translated_refmut(token, status_ptr) {
+            *status_ref = status;
+        }
+        return child.getpid() as isize;
+    }
+}
+
+pub fn sys_kill(pid: usize, signum: usize) -> isize {
+    let process = match pid2process(pid) {
+        Some(process) => process,
+        None => return -1,
+    };
+    // signal 0 only checks whether the process exists
+    if signum == 0 {
+        return 0;
+    }
+    let signal = match SignalFlags::from_signum(signum) {
+        Some(signal) => signal,
+        None => return -1,
+    };
+    let mut process_inner = process.inner_exclusive_access();
+    let mut continued = false;
+    if signal == SignalFlags::SIGCONT {
+        // a stopped process continues even if SIGCONT is blocked or ignored
+        continued = process_inner.stopped;
+        process_inner.stopped = false;
+        process_inner.signals.remove(SignalFlags::stop_signals());
+        if continued {
+            process_inner.status_change = Some(CONTINUED_STATUS);
+        }
+    } else if signal.intersects(SignalFlags::stop_signals()) {
+        process_inner.signals.remove(SignalFlags::SIGCONT);
+    }
+    process_inner.signals |= signal;
+    // threads blocked in waitpid check whether they are interrupted
+    process_inner.wait_queue.broadcast();
+    if signal.intersects(SignalFlags::SIGCONT | SignalFlags::SIGKILL) {
+        process_inner.stop_queue.broadcast();
+    }
+    drop(process_inner);
+    if continued {
+        process.notify_parent();
+    }
+    0
+}
+
+pub fn sys_sigaction(
+    signum: usize,
+    action: *const SignalAction,
+    old_action: *mut SignalAction,
+) -> isize {
+    let signal = match SignalFlags::from_signum(signum) {
+        Some(signal) => signal,
+        None => return -1,
+    };
+    if signal.intersects(SignalFlags::unmaskable()) && !action.is_null() {
+        return -1;
+    }
+    let token = current_user_token();
+    let process = current_process();
+    // both are checked before anything is changed
+    let old_action = if old_action.is_null() {
+        None
+    } else {
+        match translated_refmut(token, old_action) {
+            Some(old_action) => Some(old_action),
+            None => return -1,
+        }
+    };
+    let action = if action.is_null() {
+        None
     } else {
-        -2
+        match translated_ref(token, action) {
+            Some(&action) => Some(action),
+            None => return -1,
+        }
+    };
+    let old = process.inner_exclusive_access().signal_actions[signum];
+    if let Some(old_action) = old_action {
+        *old_action = old;
+    }
+    if let Some(mut action) = action {
+        action.mask =
+            SignalFlags::from_bits_truncate(action.mask.bits()) - SignalFlags::unmaskable();
+        let mut process_inner = process.inner_exclusive_access();
+        process_inner.signal_actions[signum] = action;
+        // pending signals are discarded once they are ignored
+        if action.handler == SIG_IGN {
+            process_inner.signals.remove(signal);
+        }
     }
-    // ---- release current PCB automatically
+    0
 }
 
-pub fn sys_kill(pid: usize, signal: u32) -> isize {
-    if let Some(process) = pid2process(pid) {
-        if let Some(flag) = SignalFlags::from_bits(signal) {
-            process.inner_exclusive_access().signals |= flag;
-            0
-        } else {
+pub fn sys_sigprocmask(how: usize, set: *const u32, old_set: *mut u32) -> isize {
+    let token = current_user_token();
+    let process = current_process();
+    let old_set = if old_set.is_null() {
+        None
+    } else {
+        match translated_refmut(token, old_set) {
+            Some(old_set) => Some(old_set),
+            None => return -1,
+        }
+    };
+    let old_mask = process.inner_exclusive_access().signal_mask;
+    if !set.is_null() {
+        let Some(&set) = translated_ref(token, set) else {
+            return -1;
+        };
+        let set = SignalFlags::from_bits_truncate(set);
+        let mask = match how {
+            SIG_BLOCK => old_mask | set,
+            SIG_UNBLOCK => old_mask - set,
+            SIG_SETMASK => set,
+            _ => return -1,
+        };
+        process.inner_exclusive_access().signal_mask = mask - SignalFlags::unmaskable();
+    }
+    if let Some(old_set) = old_set {
+        *old_set = old_mask.bits();
+    }
+    0
+}
+
+/// Return from a signal handler to the context saved on the user stack.
+pub fn sys_sigreturn() -> isize {
+    match pop_signal_frame() {
+        // a0 of the restored context is kept
+        Some(a0) => a0 as isize,
+        None => {
+            current_force_signal(SignalFlags::SIGSEGV);
             -1
         }
-    } else {
-        -1
     }
 }
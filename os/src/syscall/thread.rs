@@ -18,8 +18,10 @@ pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
             .ustack_base,
         true,
     ));
-    // add new task to scheduler
-    add_task(Arc::clone(&new_task));
+    new_task
+        .sched
+        .exclusive_access()
+        .inherit(&task.sched.exclusive_access());
     let new_task_inner = new_task.inner_exclusive_access();
     let new_task_res = new_task_inner.res.as_ref().unwrap();
     let new_task_tid = new_task_res.tid;
@@ -39,6 +41,10 @@ pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
         trap_handler as usize,
     );
     (*new_task_trap_cx).x[10] = arg;
+    drop(process_inner);
+    drop(new_task_inner);
+    // add new task to scheduler after it is ready to run on another hart
+    add_task(new_task);
     new_task_tid as isize
 }
 
@@ -1,37 +1,39 @@
-use super::{ProcessControlBlock, TaskControlBlock, TaskStatus};
-use crate::sync::UPIntrFreeCell;
-use alloc::collections::{BTreeMap, VecDeque};
+use super::scheduler::{Scheduler, SchedulerImpl};
+use super::{ProcessControlBlock, TaskControlBlock, TaskStatus, wake_idle_hart};
+use crate::sync::SpinIntrFreeCell;
+use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use lazy_static::*;
 
 pub struct TaskManager {
-    ready_queue: VecDeque<Arc<TaskControlBlock>>,
+    scheduler: SchedulerImpl,
 }
 
-/// A simple FIFO scheduler.
 impl TaskManager {
     pub fn new() -> Self {
         Self {
-            ready_queue: VecDeque::new(),
+            scheduler: SchedulerImpl::new(),
         }
     }
     pub fn add(&mut self, task: Arc<TaskControlBlock>) {
-        self.ready_queue.push_back(task);
+        self.scheduler.add(task);
     }
     pub fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
-        self.ready_queue.pop_front()
+        self.scheduler.fetch()
     }
 }
 
 lazy_static! {
-    pub static ref TASK_MANAGER: UPIntrFreeCell<TaskManager> =
-        unsafe { UPIntrFreeCell::new(TaskManager::new()) };
-    pub static ref PID2PCB: UPIntrFreeCell<BTreeMap<usize, Arc<ProcessControlBlock>>> =
-        unsafe { UPIntrFreeCell::new(BTreeMap::new()) };
+    pub static ref TASK_MANAGER: SpinIntrFreeCell<TaskManager> =
+        unsafe { SpinIntrFreeCell::new(TaskManager::new()) };
+    pub static ref PID2PCB: SpinIntrFreeCell<BTreeMap<usize, Arc<ProcessControlBlock>>> =
+        unsafe { SpinIntrFreeCell::new(BTreeMap::new()) };
 }
 
 pub fn add_task(task: Arc<TaskControlBlock>) {
     TASK_MANAGER.exclusive_access().add(task);
+    wake_idle_hart();
 }
 
 pub fn wakeup_task(task: Arc<TaskControlBlock>) {
@@ -45,11 +47,20 @@ pub fn fetch_task() -> Option<Arc<TaskControlBlock>> {
     TASK_MANAGER.exclusive_access().fetch()
 }
 
+pub fn has_ready_task() -> bool {
+    !TASK_MANAGER.exclusive_access().scheduler.is_empty()
+}
+
 pub fn pid2process(pid: usize) -> Option<Arc<ProcessControlBlock>> {
     let map = PID2PCB.exclusive_access();
     map.get(&pid).map(Arc::clone)
 }
 
+/// All processes which have not exited, in the order of pids.
+pub fn all_processes() -> Vec<Arc<ProcessControlBlock>> {
+    PID2PCB.exclusive_access().values().cloned().collect()
+}
+
 pub fn insert_into_pid2process(pid: usize, process: Arc<ProcessControlBlock>) {
     PID2PCB.exclusive_access().insert(pid, process);
 }
@@ -3,29 +3,39 @@ mod id;
 mod manager;
 mod process;
 mod processor;
+mod scheduler;
 mod signal;
+mod swap;
 mod switch;
 #[allow(clippy::module_inception)]
 mod task;
 
 use self::id::TaskUserRes;
-use crate::fs::{OpenFlags, open_file};
+use crate::fs::{File, OpenFlags, open_file};
 use crate::sbi::shutdown;
 use alloc::{sync::Arc, vec::Vec};
+use core::sync::atomic::Ordering;
 use lazy_static::*;
-use manager::fetch_task;
-use process::ProcessControlBlock;
+use manager::{fetch_task, has_ready_task};
+use process::{ProcessControlBlock, exited_status, signaled_status};
 use switch::__switch;
 
 pub use context::TaskContext;
 pub use id::{IDLE_PID, KernelStack, PidHandle, kstack_alloc, pid_alloc};
 pub use manager::{add_task, pid2process, remove_from_pid2process, wakeup_task};
+pub use process::{CONTINUED_STATUS, WCONTINUED, WNOHANG, WUNTRACED, is_stopped_status};
 pub use processor::{
     current_kstack_top, current_process, current_task, current_trap_cx, current_trap_cx_user_va,
-    current_user_token, run_tasks, schedule, take_current_task,
+    current_user_token, hart_id, run_tasks, schedule, take_current_task, wait_switched_out,
+    wake_idle_hart,
 };
-pub use signal::SignalFlags;
-pub use task::{TaskControlBlock, TaskStatus};
+pub use scheduler::{MAX_NICE, MIN_NICE, MIN_PRIORITY};
+pub use signal::{
+    SIG_BLOCK, SIG_IGN, SIG_SETMASK, SIG_UNBLOCK, SignalAction, SignalFlags,
+    handle_signals_of_current, pop_signal_frame,
+};
+pub use swap::{frame_alloc_or_swap, reclaim_frames, set_current_in_syscall};
+pub use task::{NO_HART, TaskControlBlock, TaskStatus};
 
 pub fn suspend_current_and_run_next() {
     // There must be an application running.
@@ -60,6 +70,25 @@ pub fn block_current_and_run_next() {
 
 /// Exit the current 'Running' task and run the next task in task list.
 pub fn exit_current_and_run_next(exit_code: i32) {
+    exit_current(exit_code, exited_status(exit_code));
+}
+
+/// Terminate the current task by a signal, its process is reported
+/// as terminated by the signal if this is the main thread.
+pub fn kill_current_and_run_next(signum: usize) {
+    exit_current(-(signum as i32), signaled_status(signum));
+}
+
+/// Exit the current task with `exit_code`, and with `status` reported
+/// to the parent if the process exits together with it.
+fn exit_current(exit_code: i32, status: i32) {
+    // shared file mappings are written back however the process exits, and
+    // before the task is taken since writing files may block it
+    let task = current_task().unwrap();
+    if task.inner_exclusive_access().res.as_ref().unwrap().tid == 0 {
+        task.process.upgrade().unwrap().sync_shared_mappings();
+    }
+    drop(task);
     let task = take_current_task().unwrap();
     let mut task_inner = task.inner_exclusive_access();
     let process = task.process.upgrade().unwrap();
@@ -67,6 +96,8 @@ pub fn exit_current_and_run_next(exit_code: i32) {
     // record exit code
     task_inner.exit_code = Some(exit_code);
     task_inner.res = None;
+    // the exited thread does not hold any user page
+    task.in_syscall.store(false, Ordering::SeqCst);
     // here we do not remove the thread since we are still using the kstack
     // it will be deallocated when sys_waittid is called
     drop(task_inner);
@@ -92,17 +123,34 @@ pub fn exit_current_and_run_next(exit_code: i32) {
         let mut process_inner = process.inner_exclusive_access();
         // mark this process as a zombie process
         process_inner.is_zombie = true;
-        // record exit code of main process
-        process_inner.exit_code = exit_code;
+        let children = core::mem::take(&mut process_inner.children);
 
-        {
-            // move all child processes under init process
-            let mut initproc_inner = INITPROC.inner_exclusive_access();
-            for child in process_inner.children.iter() {
+        // other threads may be running on other harts, they are never
+        // scheduled again after being switched out since the process is a zombie
+        let other_tasks: Vec<_> = process_inner
+            .tasks
+            .iter()
+            .skip(1)
+            .flatten()
+            .cloned()
+            .collect();
+        drop(process_inner);
+        for task in other_tasks {
+            wait_switched_out(&task);
+        }
+
+        // move all child processes under init process, a parent is never
+        // locked while holding its children
+        if !children.is_empty() {
+            for child in children.iter() {
                 child.inner_exclusive_access().parent = Some(Arc::downgrade(&INITPROC));
-                initproc_inner.children.push(child.clone());
             }
+            let mut initproc_inner = INITPROC.inner_exclusive_access();
+            initproc_inner.children.extend(children);
+            // some of them may have exited already
+            initproc_inner.wait_queue.broadcast();
         }
+        let mut process_inner = process.inner_exclusive_access();
 
         // deallocate user res (including tid/trap_cx/ustack) of all threads
         // it has to be done before we dealloc the whole memory_set
@@ -122,7 +170,6 @@ pub fn exit_current_and_run_next(exit_code: i32) {
         recycle_res.clear();
 
         let mut process_inner = process.inner_exclusive_access();
-        process_inner.children.clear();
         // deallocate other data in user space i.e. program code/data section
         process_inner.memory_set.recycle_data_pages();
         // drop file descriptors
@@ -134,6 +181,10 @@ pub fn exit_current_and_run_next(exit_code: i32) {
         while process_inner.tasks.len() > 1 {
             process_inner.tasks.pop();
         }
+        // the process can be reaped from now on
+        process_inner.exit_status = Some(status);
+        drop(process_inner);
+        process.notify_parent();
     }
     drop(process);
     // we do not have to save task context
@@ -144,8 +195,7 @@ pub fn exit_current_and_run_next(exit_code: i32) {
 lazy_static! {
     pub static ref INITPROC: Arc<ProcessControlBlock> = {
         let inode = open_file("initproc", OpenFlags::RDONLY).unwrap();
-        let v = inode.read_all();
-        ProcessControlBlock::new(v.as_slice())
+        ProcessControlBlock::new(&inode.inode().unwrap())
     };
 }
 
@@ -153,14 +203,15 @@ pub fn add_initproc() {
     let _initproc = INITPROC.clone();
 }
 
-pub fn check_signals_of_current() -> Option<(i32, &'static str)> {
-    let process = current_process();
-    let process_inner = process.inner_exclusive_access();
-    process_inner.signals.check_error()
-}
-
-pub fn current_add_signal(signal: SignalFlags) {
+/// Post a signal caused by the current instruction, which would be executed
+/// again if the signal were blocked or ignored, so its default action is taken.
+pub fn current_force_signal(signal: SignalFlags) {
     let process = current_process();
     let mut process_inner = process.inner_exclusive_access();
+    let action = &mut process_inner.signal_actions[signal.signum()];
+    if action.handler == SIG_IGN {
+        *action = SignalAction::default();
+    }
+    process_inner.signal_mask.remove(signal);
     process_inner.signals |= signal;
 }
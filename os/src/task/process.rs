@@ -1,22 +1,55 @@
 use super::TaskControlBlock;
 use super::id::RecycleAllocator;
 use super::manager::insert_into_pid2process;
+use super::signal::{DefaultAction, MAX_SIG, SIG_DFL, SIG_IGN, SignalAction};
 use super::{PidHandle, pid_alloc};
-use super::{SignalFlags, add_task};
+use super::{SignalFlags, add_task, frame_alloc_or_swap};
 use crate::fs::{File, Stdin, Stdout};
-use crate::mm::{KERNEL_SPACE, MemorySet, translated_refmut};
-use crate::sync::{Condvar, Mutex, Semaphore, UPIntrFreeCell, UPIntrRefMut};
+use crate::mm::{KERNEL_SPACE, MemorySet, VirtPageNum, translated_refmut};
+use crate::sync::{Condvar, Mutex, Semaphore, SpinIntrFreeCell, SpinIntrRefMut};
 use crate::trap::{TrapContext, trap_handler};
 use alloc::string::String;
 use alloc::sync::{Arc, Weak};
 use alloc::vec;
 use alloc::vec::Vec;
+use core::sync::atomic::Ordering;
+use easy_fs::Inode;
+
+/// `options` of `sys_waitpid`: return at once if no child has changed its state.
+pub const WNOHANG: usize = 1;
+/// `options` of `sys_waitpid`: report children which are stopped.
+pub const WUNTRACED: usize = 2;
+/// `options` of `sys_waitpid`: report stopped children which are continued.
+pub const WCONTINUED: usize = 8;
+
+/// Status word of a child which continues, encoded as in Linux.
+pub const CONTINUED_STATUS: i32 = 0xffff;
+
+/// Status word of a child which has exited by itself.
+pub fn exited_status(exit_code: i32) -> i32 {
+    (exit_code & 0xff) << 8
+}
+
+/// Status word of a child which is terminated by a signal.
+pub fn signaled_status(signum: usize) -> i32 {
+    signum as i32
+}
+
+/// Status word of a child which is stopped by a signal.
+pub fn stopped_status(signum: usize) -> i32 {
+    ((signum as i32) << 8) | 0x7f
+}
+
+/// Whether the status word is reported for a stopped child.
+pub fn is_stopped_status(status: i32) -> bool {
+    status & 0xff == 0x7f
+}
 
 pub struct ProcessControlBlock {
     // immutable
     pub pid: PidHandle,
     // mutable
-    inner: UPIntrFreeCell<ProcessControlBlockInner>,
+    inner: SpinIntrFreeCell<ProcessControlBlockInner>,
 }
 
 pub struct ProcessControlBlockInner {
@@ -24,9 +57,25 @@ pub struct ProcessControlBlockInner {
     pub memory_set: MemorySet,
     pub parent: Option<Weak<ProcessControlBlock>>,
     pub children: Vec<Arc<ProcessControlBlock>>,
-    pub exit_code: i32,
+    /// status word reported by `sys_waitpid`, which is set once the process
+    /// has exited and released its resources so that it can be reaped
+    pub exit_status: Option<i32>,
+    /// a stop or continue which has not been reported to the parent yet
+    pub status_change: Option<i32>,
+    /// threads waiting for a child to change its state in `sys_waitpid`
+    pub wait_queue: Condvar,
     pub fd_table: Vec<Option<Arc<dyn File + Send + Sync>>>,
+    /// absolute path of the working directory
+    pub cwd: String,
+    /// pending signals
     pub signals: SignalFlags,
+    /// blocked signals
+    pub signal_mask: SignalFlags,
+    pub signal_actions: [SignalAction; MAX_SIG + 1],
+    /// stopped by a signal until SIGCONT arrives
+    pub stopped: bool,
+    /// threads of a stopped process, woken up by SIGCONT or SIGKILL
+    pub stop_queue: Condvar,
     pub tasks: Vec<Option<Arc<TaskControlBlock>>>,
     pub task_res_allocator: RecycleAllocator,
     pub mutex_list: Vec<Option<Arc<dyn Mutex>>>,
@@ -64,27 +113,56 @@ impl ProcessControlBlockInner {
     pub fn get_task(&self, tid: usize) -> Arc<TaskControlBlock> {
         self.tasks[tid].as_ref().unwrap().clone()
     }
+
+    /// Whether a pending signal should interrupt a blocking syscall,
+    /// which is the case if it is neither blocked nor ignored.
+    pub fn signal_interrupts(&self) -> bool {
+        let pending = self.signals - self.signal_mask;
+        (1..=MAX_SIG)
+            .filter(|signum| pending.bits() & (1 << signum) != 0)
+            .any(|signum| match self.signal_actions[signum].handler {
+                SIG_IGN => false,
+                SIG_DFL => matches!(
+                    SignalFlags::default_action(signum),
+                    DefaultAction::Terminate | DefaultAction::Stop
+                ),
+                _ => true,
+            })
+    }
+
+    /// Whether user pages of the process can be swapped out, which is not
+    /// the case while any of its threads is in a syscall.
+    pub fn swappable(&self) -> bool {
+        !self.is_zombie
+            && self
+                .tasks
+                .iter()
+                .flatten()
+                .all(|task| !task.in_syscall.load(Ordering::SeqCst))
+    }
 }
 
 impl ProcessControlBlock {
-    pub fn inner_exclusive_access(&self) -> UPIntrRefMut<'_, ProcessControlBlockInner> {
+    pub fn inner_exclusive_access(&self) -> SpinIntrRefMut<'_, ProcessControlBlockInner> {
         self.inner.exclusive_access()
     }
 
-    pub fn new(elf_data: &[u8]) -> Arc<Self> {
+    pub fn new(elf_inode: &Arc<Inode>) -> Arc<Self> {
         // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, ustack_base, entry_point) = MemorySet::from_elf(elf_data);
+        let (memory_set, ustack_base, entry_point) = MemorySet::from_elf(elf_inode);
         // allocate a pid
         let pid_handle = pid_alloc();
         let process = Arc::new(Self {
             pid: pid_handle,
             inner: unsafe {
-                UPIntrFreeCell::new(ProcessControlBlockInner {
+                SpinIntrFreeCell::new(ProcessControlBlockInner {
                     is_zombie: false,
                     memory_set,
                     parent: None,
                     children: Vec::new(),
-                    exit_code: 0,
+                    exit_status: None,
+                    status_change: None,
+                    wait_queue: Condvar::new(),
                     fd_table: vec![
                         // 0 -> stdin
                         Some(Arc::new(Stdin)),
@@ -93,7 +171,12 @@ impl ProcessControlBlock {
                         // 2 -> stderr
                         Some(Arc::new(Stdout)),
                     ],
+                    cwd: String::from("/"),
                     signals: SignalFlags::empty(),
+                    signal_mask: SignalFlags::empty(),
+                    signal_actions: [SignalAction::default(); MAX_SIG + 1],
+                    stopped: false,
+                    stop_queue: Condvar::new(),
                     tasks: Vec::new(),
                     task_res_allocator: RecycleAllocator::new(),
                     mutex_list: Vec::new(),
@@ -132,13 +215,21 @@ impl ProcessControlBlock {
     }
 
     /// Only support processes with a single thread.
-    pub fn exec(self: &Arc<Self>, elf_data: &[u8], args: Vec<String>) {
+    pub fn exec(self: &Arc<Self>, elf_inode: &Arc<Inode>, args: Vec<String>) {
         assert_eq!(self.inner_exclusive_access().thread_count(), 1);
         // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, ustack_base, entry_point) = MemorySet::from_elf(elf_data);
+        let (memory_set, ustack_base, entry_point) = MemorySet::from_elf(elf_inode);
         let new_token = memory_set.token();
         // substitute memory_set
-        self.inner_exclusive_access().memory_set = memory_set;
+        let mut inner = self.inner_exclusive_access();
+        inner.memory_set = memory_set;
+        // handlers are gone with the old image, while ignored signals stay ignored
+        for action in inner.signal_actions.iter_mut() {
+            if action.handler != SIG_IGN {
+                *action = SignalAction::default();
+            }
+        }
+        drop(inner);
         // then we alloc user resource for main thread again
         // since memory_set has been changed
         let task = self.inner_exclusive_access().get_task(0);
@@ -146,7 +237,7 @@ impl ProcessControlBlock {
         task_inner.res.as_mut().unwrap().ustack_base = ustack_base;
         task_inner.res.as_mut().unwrap().alloc_user_res();
         task_inner.trap_cx_ppn = task_inner.res.as_mut().unwrap().trap_cx_ppn();
-        // push arguments on user stack
+        // push arguments on user stack, which is mapped for the user
         let mut user_sp = task_inner.res.as_mut().unwrap().ustack_top();
         user_sp -= (args.len() + 1) * core::mem::size_of::<usize>();
         let argv_base = user_sp;
@@ -156,6 +247,7 @@ impl ProcessControlBlock {
                     new_token,
                     (argv_base + arg * core::mem::size_of::<usize>()) as *mut usize,
                 )
+                .unwrap()
             })
             .collect();
         *argv[args.len()] = 0;
@@ -164,10 +256,10 @@ impl ProcessControlBlock {
             *argv[i] = user_sp;
             let mut p = user_sp;
             for c in args[i].as_bytes() {
-                *translated_refmut(new_token, p as *mut u8) = *c;
+                *translated_refmut(new_token, p as *mut u8).unwrap() = *c;
                 p += 1;
             }
-            *translated_refmut(new_token, p as *mut u8) = 0;
+            *translated_refmut(new_token, p as *mut u8).unwrap() = 0;
         }
         // make the user_sp aligned to 8B for k210 platform
         user_sp -= user_sp % core::mem::size_of::<usize>();
@@ -184,12 +276,69 @@ impl ProcessControlBlock {
         *task_inner.get_trap_cx() = trap_cx;
     }
 
+    /// Load a lazy or swapped out page or copy a copy-on-write page at `vpn`
+    /// of the user space, return false if the access is invalid or there is
+    /// no memory for the page.
+    pub fn handle_page_fault(&self, vpn: VirtPageNum, write: bool) -> bool {
+        let lazy_page = self.inner_exclusive_access().memory_set.lazy_page(vpn);
+        if let Some(lazy_page) = lazy_page {
+            // a page of a file mapped shared may be in memory for another process
+            let frame = match lazy_page.cached_frame() {
+                Some(frame) => frame,
+                None => {
+                    // the PCB is released while loading the page since it may block
+                    let frame = match frame_alloc_or_swap() {
+                        Some(frame) => frame,
+                        None => return false,
+                    };
+                    lazy_page.load(frame.ppn);
+                    lazy_page.cache(frame)
+                }
+            };
+            return self
+                .inner_exclusive_access()
+                .memory_set
+                .map_lazy_page(vpn, frame, &lazy_page);
+        }
+        write
+            && self
+                .inner_exclusive_access()
+                .memory_set
+                .handle_cow_fault(vpn)
+    }
+
+    /// Wake up the threads of the parent waiting for children and post
+    /// SIGCHLD to it, after the state of the process has changed.
+    /// The PCB should not be held since the parent is locked before it.
+    pub fn notify_parent(&self) {
+        let parent = self
+            .inner_exclusive_access()
+            .parent
+            .as_ref()
+            .and_then(|parent| parent.upgrade());
+        if let Some(parent) = parent {
+            let mut parent_inner = parent.inner_exclusive_access();
+            parent_inner.signals |= SignalFlags::SIGCHLD;
+            parent_inner.wait_queue.broadcast();
+        }
+    }
+
+    /// Write the dirty pages of shared file mappings back to their files.
+    pub fn sync_shared_mappings(&self) {
+        let file_pages = self.inner_exclusive_access().memory_set.shared_file_pages();
+        // the PCB is released while writing files since it may block
+        for file_page in file_pages {
+            file_page.write_back();
+        }
+    }
+
     /// Only support processes with a single thread.
     pub fn fork(self: &Arc<Self>) -> Arc<Self> {
         let mut parent = self.inner_exclusive_access();
         assert_eq!(parent.thread_count(), 1);
-        // clone parent's memory_set completely including trampoline/ustacks/trap_cxs
-        let memory_set = MemorySet::from_existed_user(&parent.memory_set);
+        // clone parent's memory_set including trampoline/ustacks/trap_cxs,
+        // user pages are shared copy-on-write
+        let memory_set = MemorySet::from_existed_user(&mut parent.memory_set);
         // alloc a pid
         let pid = pid_alloc();
         // copy fd table
@@ -205,14 +354,21 @@ impl ProcessControlBlock {
         let child = Arc::new(Self {
             pid,
             inner: unsafe {
-                UPIntrFreeCell::new(ProcessControlBlockInner {
+                SpinIntrFreeCell::new(ProcessControlBlockInner {
                     is_zombie: false,
                     memory_set,
                     parent: Some(Arc::downgrade(self)),
                     children: Vec::new(),
-                    exit_code: 0,
+                    exit_status: None,
+                    status_change: None,
+                    wait_queue: Condvar::new(),
                     fd_table: new_fd_table,
+                    cwd: parent.cwd.clone(),
                     signals: SignalFlags::empty(),
+                    signal_mask: parent.signal_mask,
+                    signal_actions: parent.signal_actions,
+                    stopped: false,
+                    stop_queue: Condvar::new(),
                     tasks: Vec::new(),
                     task_res_allocator: RecycleAllocator::new(),
                     mutex_list: Vec::new(),
@@ -237,6 +393,9 @@ impl ProcessControlBlock {
             // but mention that we allocate a new kstack here
             false,
         ));
+        task.sched
+            .exclusive_access()
+            .inherit(&parent.get_task(0).sched.exclusive_access());
         // attach task to child process
         let mut child_inner = child.inner_exclusive_access();
         child_inner.tasks.push(Some(Arc::clone(&task)));
@@ -245,6 +404,9 @@ impl ProcessControlBlock {
         let task_inner = task.inner_exclusive_access();
         let trap_cx = task_inner.get_trap_cx();
         trap_cx.kernel_sp = task.kstack.get_top();
+        // we do not have to move to next instruction since we have done it before
+        // for child process, fork returns 0
+        trap_cx.x[10] = 0;
         drop(task_inner);
         insert_into_pid2process(child.getpid(), Arc::clone(&child));
         // add this thread to scheduler
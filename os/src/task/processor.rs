@@ -1,14 +1,23 @@
 use super::__switch;
-use super::{ProcessControlBlock, TaskContext, TaskControlBlock};
-use super::{TaskStatus, fetch_task};
+use super::{NO_HART, ProcessControlBlock, TaskContext, TaskControlBlock};
+use super::{TaskStatus, fetch_task, has_ready_task};
+use crate::config::{BOOT_STACK_SIZE, MAX_HARTS};
+use crate::net::check_net_timers;
+use crate::sbi::send_ipi;
 use crate::sync::UPIntrFreeCell;
 use crate::trap::TrapContext;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use core::arch::asm;
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use lazy_static::*;
+use riscv::register::sstatus;
 
 pub struct Processor {
     current: Option<Arc<TaskControlBlock>>,
+    /// the task switched out last, which is released after the switch
+    prev: Option<Arc<TaskControlBlock>>,
     idle_task_cx: TaskContext,
 }
 
@@ -16,6 +25,7 @@ impl Processor {
     pub fn new() -> Self {
         Self {
             current: None,
+            prev: None,
             idle_task_cx: TaskContext::zero_init(),
         }
     }
@@ -23,6 +33,11 @@ impl Processor {
         &mut self.idle_task_cx as *mut _
     }
     pub fn take_current(&mut self) -> Option<Arc<TaskControlBlock>> {
+        if let Some(task) = self.current.as_ref() {
+            task.sched.exclusive_access().stop_running();
+        }
+        // the task is still running on its kernel stack until it is switched out
+        self.prev = self.current.clone();
         self.current.take()
     }
     pub fn current(&self) -> Option<Arc<TaskControlBlock>> {
@@ -31,38 +46,119 @@ impl Processor {
 }
 
 lazy_static! {
-    pub static ref PROCESSOR: UPIntrFreeCell<Processor> =
-        unsafe { UPIntrFreeCell::new(Processor::new()) };
+    pub static ref PROCESSORS: Vec<UPIntrFreeCell<Processor>> = (0..MAX_HARTS)
+        .map(|_| unsafe { UPIntrFreeCell::new(Processor::new()) })
+        .collect();
+}
+
+/// Harts waiting for tasks, one bit for each hart.
+static IDLE_HARTS: AtomicUsize = AtomicUsize::new(0);
+
+/// Id of the current hart, which is kept in `tp` in the kernel.
+pub fn hart_id() -> usize {
+    let hart_id;
+    unsafe { asm!("mv {}, tp", out(reg) hart_id) };
+    hart_id
+}
+
+fn processor() -> &'static UPIntrFreeCell<Processor> {
+    &PROCESSORS[hart_id()]
 }
 
 pub fn run_tasks() {
     loop {
-        let mut processor = PROCESSOR.exclusive_access();
+        let mut processor = processor().exclusive_access();
         if let Some(task) = fetch_task() {
+            // the hart the task ran on may not have switched away from it yet
+            while task
+                .on_hart
+                .compare_exchange(NO_HART, hart_id(), Ordering::SeqCst, Ordering::SeqCst)
+                .is_err()
+            {
+                spin_loop();
+            }
+            // threads left by an exited process are never run again
+            if task
+                .process
+                .upgrade()
+                .is_none_or(|process| process.inner_exclusive_access().is_zombie)
+            {
+                task.on_hart.store(NO_HART, Ordering::SeqCst);
+                continue;
+            }
             let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
             // access coming task TCB exclusively
             let next_task_cx_ptr = task.inner.exclusive_session(|task_inner| {
                 task_inner.task_status = TaskStatus::Running;
                 &task_inner.task_cx as *const TaskContext
             });
+            task.sched.exclusive_access().start_running();
             processor.current = Some(task);
             // release processor manually
             drop(processor);
             unsafe {
                 __switch(idle_task_cx_ptr, next_task_cx_ptr);
             }
+            // the context of the previous task has been saved
+            if let Some(prev) = processor().exclusive_access().prev.take() {
+                prev.on_hart.store(NO_HART, Ordering::SeqCst);
+            }
         } else {
-            println!("no tasks available in run_tasks");
+            drop(processor);
+            wait_for_task();
+        }
+    }
+}
+
+/// Sleep until an interrupt arrives, e.g. an IPI sent by `wake_idle_hart`.
+fn wait_for_task() {
+    let mask = 1 << hart_id();
+    IDLE_HARTS.fetch_or(mask, Ordering::SeqCst);
+    if !has_ready_task() {
+        unsafe {
+            // a pending interrupt wakes up the hart even if it is masked
+            asm!("wfi");
+            // let the interrupt be handled
+            sstatus::set_sie();
+            sstatus::clear_sie();
+        }
+    }
+    IDLE_HARTS.fetch_and(!mask, Ordering::SeqCst);
+    // the net stack is not run in the timer interrupt of the kernel
+    check_net_timers();
+}
+
+/// Send a rescheduling IPI to an idle hart, if any, after a task is added.
+pub fn wake_idle_hart() {
+    let idle_harts = IDLE_HARTS.load(Ordering::SeqCst);
+    if idle_harts != 0 {
+        send_ipi(idle_harts.trailing_zeros() as usize);
+    }
+}
+
+/// Wait until `task` is switched out, it is asked to reschedule by an IPI
+/// if it is running on another hart.
+pub fn wait_switched_out(task: &TaskControlBlock) {
+    let mut kicked = NO_HART;
+    loop {
+        let hart = task.on_hart.load(Ordering::SeqCst);
+        if hart == NO_HART {
+            return;
+        }
+        if hart != kicked {
+            send_ipi(hart);
+            kicked = hart;
         }
+        spin_loop();
     }
 }
 
 pub fn take_current_task() -> Option<Arc<TaskControlBlock>> {
-    PROCESSOR.exclusive_access().take_current()
+    processor().exclusive_access().take_current()
 }
 
 pub fn current_task() -> Option<Arc<TaskControlBlock>> {
-    PROCESSOR.exclusive_access().current()
+    processor().exclusive_access().current()
 }
 
 pub fn current_process() -> Arc<ProcessControlBlock> {
@@ -95,16 +191,16 @@ pub fn current_kstack_top() -> usize {
     if let Some(task) = current_task() {
         task.kstack.get_top()
     } else {
-        let mut boot_stack_top;
-        unsafe { asm!("la {},boot_stack_top",out(reg) boot_stack_top) };
-        boot_stack_top
+        let boot_stack_lower_bound: usize;
+        unsafe { asm!("la {},boot_stack_lower_bound",out(reg) boot_stack_lower_bound) };
+        boot_stack_lower_bound + (hart_id() + 1) * BOOT_STACK_SIZE
     }
     // current_task().unwrap().kstack.get_top()
 }
 
 pub fn schedule(switched_task_cx_ptr: *mut TaskContext) {
     let idle_task_cx_ptr =
-        PROCESSOR.exclusive_session(|processor| processor.get_idle_task_cx_ptr());
+        processor().exclusive_session(|processor| processor.get_idle_task_cx_ptr());
     unsafe {
         __switch(switched_task_cx_ptr, idle_task_cx_ptr);
     }
@@ -0,0 +1,261 @@
+//! Scheduling policies picking the next ready task, one of which
+//! is chosen by the `sched-*` features at build time.
+
+use super::TaskControlBlock;
+use crate::timer::get_time;
+#[cfg(any(
+    feature = "sched-priority",
+    feature = "sched-stride",
+    feature = "sched-cfs"
+))]
+use alloc::collections::BTreeMap;
+#[cfg(any(
+    feature = "sched-rr",
+    feature = "sched-priority",
+    not(any(feature = "sched-stride", feature = "sched-cfs"))
+))]
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+
+pub const DEFAULT_PRIORITY: usize = 16;
+/// Smaller priorities would give strides too long to be fair.
+pub const MIN_PRIORITY: usize = 2;
+pub const MIN_NICE: isize = -20;
+pub const MAX_NICE: isize = 19;
+
+#[cfg(feature = "sched-stride")]
+const BIG_STRIDE: u64 = 1 << 20;
+const NICE_0_WEIGHT: u64 = 1024;
+/// Weights of nice values from -20 to 19 as in Linux,
+/// each nice level gives about 10% more or less cpu time.
+const NICE_TO_WEIGHT: [u64; 40] = [
+    88761, 71755, 56483, 46273, 36291, 29154, 23254, 18705, 14949, 11916, 9548, 7620, 6100, 4904,
+    3906, 3121, 2501, 1991, 1586, 1277, 1024, 820, 655, 526, 423, 335, 272, 215, 172, 137, 110, 87,
+    70, 56, 45, 36, 29, 23, 18, 15,
+];
+
+/// Scheduling parameters and statistics of a task.
+pub struct SchedEntity {
+    /// tasks with larger priorities run first in the static priority
+    /// policy, and more often in the stride policy
+    pub priority: usize,
+    /// tasks with smaller nice values get more cpu time in the CFS-like policy
+    pub nice: isize,
+    /// pass of the stride policy
+    #[cfg(feature = "sched-stride")]
+    pass: u64,
+    /// runtime weighted by nice in clock ticks
+    vruntime: u64,
+    /// when the task was switched to last time
+    run_start: usize,
+}
+
+impl SchedEntity {
+    pub fn new() -> Self {
+        Self {
+            priority: DEFAULT_PRIORITY,
+            nice: 0,
+            #[cfg(feature = "sched-stride")]
+            pass: 0,
+            vruntime: 0,
+            run_start: 0,
+        }
+    }
+    /// Parameters are inherited by forked processes and new threads.
+    pub fn inherit(&mut self, parent: &SchedEntity) {
+        self.priority = parent.priority;
+        self.nice = parent.nice;
+    }
+    #[cfg(feature = "sched-stride")]
+    fn stride(&self) -> u64 {
+        BIG_STRIDE / self.priority as u64
+    }
+    fn weight(&self) -> u64 {
+        NICE_TO_WEIGHT[(self.nice - MIN_NICE) as usize]
+    }
+    pub fn start_running(&mut self) {
+        self.run_start = get_time();
+    }
+    pub fn stop_running(&mut self) {
+        let runtime = (get_time() - self.run_start) as u64;
+        self.vruntime += runtime * NICE_0_WEIGHT / self.weight();
+    }
+}
+
+pub trait Scheduler {
+    fn new() -> Self;
+    fn add(&mut self, task: Arc<TaskControlBlock>);
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>>;
+    fn is_empty(&self) -> bool;
+}
+
+// The policy of the `sched-*` feature enabled, round-robin if there is none.
+// Enabling several of them defines `SchedulerImpl` more than once.
+#[cfg(any(
+    feature = "sched-rr",
+    not(any(
+        feature = "sched-priority",
+        feature = "sched-stride",
+        feature = "sched-cfs"
+    ))
+))]
+pub type SchedulerImpl = RoundRobinScheduler;
+#[cfg(feature = "sched-priority")]
+pub type SchedulerImpl = PriorityScheduler;
+#[cfg(feature = "sched-stride")]
+pub type SchedulerImpl = StrideScheduler;
+#[cfg(feature = "sched-cfs")]
+pub type SchedulerImpl = CfsScheduler;
+
+/// Ready tasks run in FIFO order.
+#[cfg(any(
+    feature = "sched-rr",
+    not(any(
+        feature = "sched-priority",
+        feature = "sched-stride",
+        feature = "sched-cfs"
+    ))
+))]
+pub struct RoundRobinScheduler {
+    ready_queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+#[cfg(any(
+    feature = "sched-rr",
+    not(any(
+        feature = "sched-priority",
+        feature = "sched-stride",
+        feature = "sched-cfs"
+    ))
+))]
+impl Scheduler for RoundRobinScheduler {
+    fn new() -> Self {
+        Self {
+            ready_queue: VecDeque::new(),
+        }
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        self.ready_queue.push_back(task);
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.ready_queue.pop_front()
+    }
+    fn is_empty(&self) -> bool {
+        self.ready_queue.is_empty()
+    }
+}
+
+/// Tasks with the highest priority run round-robin,
+/// tasks with lower priorities starve until they block.
+#[cfg(feature = "sched-priority")]
+pub struct PriorityScheduler {
+    ready_queues: BTreeMap<usize, VecDeque<Arc<TaskControlBlock>>>,
+}
+
+#[cfg(feature = "sched-priority")]
+impl Scheduler for PriorityScheduler {
+    fn new() -> Self {
+        Self {
+            ready_queues: BTreeMap::new(),
+        }
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        let priority = task.sched.exclusive_access().priority;
+        self.ready_queues
+            .entry(priority)
+            .or_default()
+            .push_back(task);
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        let mut queue = self.ready_queues.last_entry()?;
+        let task = queue.get_mut().pop_front();
+        if queue.get().is_empty() {
+            queue.remove();
+        }
+        task
+    }
+    fn is_empty(&self) -> bool {
+        self.ready_queues.is_empty()
+    }
+}
+
+/// The task with the smallest pass runs next, and its pass is increased
+/// by a stride inversely proportional to its priority.
+#[cfg(feature = "sched-stride")]
+pub struct StrideScheduler {
+    /// ready tasks ordered by pass, and then by when they were added
+    ready_tasks: BTreeMap<(u64, usize), Arc<TaskControlBlock>>,
+    /// pass of the task fetched last
+    min_pass: u64,
+    seq: usize,
+}
+
+#[cfg(feature = "sched-stride")]
+impl Scheduler for StrideScheduler {
+    fn new() -> Self {
+        Self {
+            ready_tasks: BTreeMap::new(),
+            min_pass: 0,
+            seq: 0,
+        }
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        let pass = task.sched.exclusive_session(|sched| {
+            // tasks which have been blocked or just created do not
+            // take all the cpu time to catch up with others
+            sched.pass = sched.pass.max(self.min_pass);
+            sched.pass
+        });
+        self.ready_tasks.insert((pass, self.seq), task);
+        self.seq += 1;
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        let ((pass, _), task) = self.ready_tasks.pop_first()?;
+        self.min_pass = pass;
+        task.sched
+            .exclusive_session(|sched| sched.pass += sched.stride());
+        Some(task)
+    }
+    fn is_empty(&self) -> bool {
+        self.ready_tasks.is_empty()
+    }
+}
+
+/// Completely-fair-like policy: the task with the smallest virtual runtime
+/// runs next, and the runtime of a task is weighted by its nice value.
+#[cfg(feature = "sched-cfs")]
+pub struct CfsScheduler {
+    /// ready tasks ordered by virtual runtime, and then by when they were added
+    ready_tasks: BTreeMap<(u64, usize), Arc<TaskControlBlock>>,
+    /// virtual runtime of the task fetched last
+    min_vruntime: u64,
+    seq: usize,
+}
+
+#[cfg(feature = "sched-cfs")]
+impl Scheduler for CfsScheduler {
+    fn new() -> Self {
+        Self {
+            ready_tasks: BTreeMap::new(),
+            min_vruntime: 0,
+            seq: 0,
+        }
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        let vruntime = task.sched.exclusive_session(|sched| {
+            // sleeping does not earn cpu time
+            sched.vruntime = sched.vruntime.max(self.min_vruntime);
+            sched.vruntime
+        });
+        self.ready_tasks.insert((vruntime, self.seq), task);
+        self.seq += 1;
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        let ((vruntime, _), task) = self.ready_tasks.pop_first()?;
+        self.min_vruntime = vruntime;
+        Some(task)
+    }
+    fn is_empty(&self) -> bool {
+        self.ready_tasks.is_empty()
+    }
+}
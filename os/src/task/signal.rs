@@ -1,29 +1,301 @@
+use super::process::stopped_status;
+use super::{current_process, current_trap_cx, current_user_token, schedule};
+use crate::mm::translated_byte_buffer;
+use alloc::format;
+use alloc::string::String;
 use bitflags::*;
+use core::mem::size_of;
+
+pub const MAX_SIG: usize = 31;
+
+/// The default action is taken on the signal.
+pub const SIG_DFL: usize = 0;
+/// The signal is ignored.
+pub const SIG_IGN: usize = 1;
+
+/// `how` of `sys_sigprocmask`
+pub const SIG_BLOCK: usize = 0;
+pub const SIG_UNBLOCK: usize = 1;
+pub const SIG_SETMASK: usize = 2;
 
 bitflags! {
+    /// A set of signals, signal `n` is represented by bit `n`.
     pub struct SignalFlags: u32 {
+        const SIGHUP    = 1 << 1;
         const SIGINT    = 1 << 2;
+        const SIGQUIT   = 1 << 3;
         const SIGILL    = 1 << 4;
+        const SIGTRAP   = 1 << 5;
         const SIGABRT   = 1 << 6;
+        const SIGBUS    = 1 << 7;
         const SIGFPE    = 1 << 8;
+        const SIGKILL   = 1 << 9;
+        const SIGUSR1   = 1 << 10;
         const SIGSEGV   = 1 << 11;
+        const SIGUSR2   = 1 << 12;
+        const SIGPIPE   = 1 << 13;
+        const SIGALRM   = 1 << 14;
+        const SIGTERM   = 1 << 15;
+        const SIGSTKFLT = 1 << 16;
+        const SIGCHLD   = 1 << 17;
+        const SIGCONT   = 1 << 18;
+        const SIGSTOP   = 1 << 19;
+        const SIGTSTP   = 1 << 20;
+        const SIGTTIN   = 1 << 21;
+        const SIGTTOU   = 1 << 22;
+        const SIGURG    = 1 << 23;
+        const SIGXCPU   = 1 << 24;
+        const SIGXFSZ   = 1 << 25;
+        const SIGVTALRM = 1 << 26;
+        const SIGPROF   = 1 << 27;
+        const SIGWINCH  = 1 << 28;
+        const SIGIO     = 1 << 29;
+        const SIGPWR    = 1 << 30;
+        const SIGSYS    = 1 << 31;
     }
 }
 
+/// Names and descriptions of signals from 1 to `MAX_SIG`.
+const SIGNAL_INFO: [(&str, &str); MAX_SIG] = [
+    ("SIGHUP", "Hangup"),
+    ("SIGINT", "Killed"),
+    ("SIGQUIT", "Quit"),
+    ("SIGILL", "Illegal Instruction"),
+    ("SIGTRAP", "Trace/Breakpoint Trap"),
+    ("SIGABRT", "Aborted"),
+    ("SIGBUS", "Bus Error"),
+    ("SIGFPE", "Erroneous Arithmetic Operation"),
+    ("SIGKILL", "Killed"),
+    ("SIGUSR1", "User Defined Signal 1"),
+    ("SIGSEGV", "Segmentation Fault"),
+    ("SIGUSR2", "User Defined Signal 2"),
+    ("SIGPIPE", "Broken Pipe"),
+    ("SIGALRM", "Alarm Clock"),
+    ("SIGTERM", "Terminated"),
+    ("SIGSTKFLT", "Stack Fault"),
+    ("SIGCHLD", "Child Status Changed"),
+    ("SIGCONT", "Continued"),
+    ("SIGSTOP", "Stopped"),
+    ("SIGTSTP", "Stopped"),
+    ("SIGTTIN", "Stopped"),
+    ("SIGTTOU", "Stopped"),
+    ("SIGURG", "Urgent I/O Condition"),
+    ("SIGXCPU", "CPU Time Limit Exceeded"),
+    ("SIGXFSZ", "File Size Limit Exceeded"),
+    ("SIGVTALRM", "Virtual Timer Expired"),
+    ("SIGPROF", "Profiling Timer Expired"),
+    ("SIGWINCH", "Window Changed"),
+    ("SIGIO", "I/O Possible"),
+    ("SIGPWR", "Power Failure"),
+    ("SIGSYS", "Bad System Call"),
+];
+
+/// What the default action of a signal does.
+#[derive(Copy, Clone, PartialEq)]
+pub enum DefaultAction {
+    Terminate,
+    Ignore,
+    Stop,
+    Continue,
+}
+
 impl SignalFlags {
-    pub fn check_error(&self) -> Option<(i32, &'static str)> {
-        if self.contains(Self::SIGINT) {
-            Some((-2, "Killed, SIGINT=2"))
-        } else if self.contains(Self::SIGILL) {
-            Some((-4, "Illegal Instruction, SIGILL=4"))
-        } else if self.contains(Self::SIGABRT) {
-            Some((-6, "Aborted, SIGABRT=6"))
-        } else if self.contains(Self::SIGFPE) {
-            Some((-8, "Erroneous Arithmetic Operation, SIGFPE=8"))
-        } else if self.contains(Self::SIGSEGV) {
-            Some((-11, "Segmentation Fault, SIGSEGV=11"))
+    pub fn from_signum(signum: usize) -> Option<Self> {
+        if (1..=MAX_SIG).contains(&signum) {
+            Self::from_bits(1 << signum)
         } else {
             None
         }
     }
+    /// Signals which can never be caught, blocked or ignored.
+    pub fn unmaskable() -> Self {
+        Self::SIGKILL | Self::SIGSTOP
+    }
+    pub fn stop_signals() -> Self {
+        Self::SIGSTOP | Self::SIGTSTP | Self::SIGTTIN | Self::SIGTTOU
+    }
+    /// Number of a single signal.
+    pub fn signum(&self) -> usize {
+        self.bits().trailing_zeros() as usize
+    }
+    /// The signal with the smallest number in the set.
+    pub fn first(&self) -> Option<usize> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(self.bits().trailing_zeros() as usize)
+        }
+    }
+    pub fn default_action(signum: usize) -> DefaultAction {
+        let signal = Self::from_bits_truncate(1 << signum);
+        if signal.intersects(Self::stop_signals()) {
+            DefaultAction::Stop
+        } else if signal == Self::SIGCONT {
+            DefaultAction::Continue
+        } else if signal.intersects(Self::SIGCHLD | Self::SIGURG | Self::SIGWINCH) {
+            DefaultAction::Ignore
+        } else {
+            DefaultAction::Terminate
+        }
+    }
+    /// Message printed when a process is terminated by the signal.
+    pub fn describe(signum: usize) -> (&'static str, &'static str) {
+        SIGNAL_INFO[signum - 1]
+    }
+}
+
+/// How a process handles a signal, which is shared with the user space.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct SignalAction {
+    /// `SIG_DFL`, `SIG_IGN` or the address of the handler
+    pub handler: usize,
+    /// signals blocked while the handler is running
+    pub mask: SignalFlags,
+    /// where the handler returns to, which calls `sys_sigreturn`
+    pub restorer: usize,
+}
+
+impl SignalAction {
+    pub const fn default() -> Self {
+        Self {
+            handler: SIG_DFL,
+            mask: SignalFlags::empty(),
+            restorer: 0,
+        }
+    }
+}
+
+/// Saved on the user stack when a handler is called,
+/// and restored from there by `sys_sigreturn`.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct SignalFrame {
+    pub x: [usize; 32],
+    pub sepc: usize,
+    /// the signal mask before the handler is called
+    pub mask: SignalFlags,
+}
+
+impl SignalFrame {
+    fn as_bytes(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self as *const _ as *const u8, size_of::<Self>()) }
+    }
+    fn as_bytes_mut(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self as *mut _ as *mut u8, size_of::<Self>()) }
+    }
+}
+
+fn terminated_by(signum: usize) -> (usize, String) {
+    let (name, description) = SignalFlags::describe(signum);
+    (signum, format!("{}, {}={}", description, name, signum))
+}
+
+/// Take the action of a pending signal which is not blocked. If it is caught,
+/// the current thread runs its handler after returning to the user space.
+/// Return the signal and the message if the process is terminated by it.
+pub fn handle_signals_of_current() -> Option<(usize, String)> {
+    loop {
+        let process = current_process();
+        let mut process_inner = process.inner_exclusive_access();
+        if process_inner.signals.contains(SignalFlags::SIGKILL) {
+            return Some(terminated_by(SignalFlags::SIGKILL.signum()));
+        }
+        if process_inner.stopped {
+            // sleep until SIGCONT or SIGKILL arrives
+            let task_cx_ptr = process_inner.stop_queue.wait_no_sched();
+            drop(process_inner);
+            schedule(task_cx_ptr);
+            continue;
+        }
+        let signum = (process_inner.signals - process_inner.signal_mask).first()?;
+        let signal = SignalFlags::from_signum(signum).unwrap();
+        let action = process_inner.signal_actions[signum];
+        match action.handler {
+            SIG_IGN => process_inner.signals.remove(signal),
+            SIG_DFL => match SignalFlags::default_action(signum) {
+                // the signal is kept so that the other threads exit as well
+                DefaultAction::Terminate => return Some(terminated_by(signum)),
+                DefaultAction::Stop => {
+                    process_inner.signals.remove(signal);
+                    process_inner.stopped = true;
+                    process_inner.status_change = Some(stopped_status(signum));
+                    drop(process_inner);
+                    process.notify_parent();
+                }
+                DefaultAction::Ignore | DefaultAction::Continue => {
+                    process_inner.signals.remove(signal)
+                }
+            },
+            handler => {
+                process_inner.signals.remove(signal);
+                let mask = process_inner.signal_mask;
+                process_inner.signal_mask |= (action.mask | signal) - SignalFlags::unmaskable();
+                drop(process_inner);
+                if !push_signal_frame(signum, handler, action.restorer, mask) {
+                    // there is no room for the frame, the process is killed
+                    let mut process_inner = process.inner_exclusive_access();
+                    process_inner.signal_actions[SignalFlags::SIGSEGV.signum()] =
+                        SignalAction::default();
+                    process_inner.signal_mask = mask - SignalFlags::SIGSEGV;
+                    process_inner.signals |= SignalFlags::SIGSEGV;
+                    continue;
+                }
+                return None;
+            }
+        }
+    }
+}
+
+/// Save the user context on the user stack and direct the current thread
+/// to `handler`, which returns to `restorer` and calls `sys_sigreturn`.
+fn push_signal_frame(signum: usize, handler: usize, restorer: usize, mask: SignalFlags) -> bool {
+    let trap_cx = current_trap_cx();
+    let frame = SignalFrame {
+        x: trap_cx.x,
+        sepc: trap_cx.sepc,
+        mask,
+    };
+    let token = current_user_token();
+    let sp = (trap_cx.x[2].wrapping_sub(size_of::<SignalFrame>())) & !0xf;
+    let Some(buffers) =
+        translated_byte_buffer(token, sp as *const u8, size_of::<SignalFrame>(), true)
+    else {
+        return false;
+    };
+    let mut bytes = frame.as_bytes();
+    for buffer in buffers {
+        buffer.copy_from_slice(&bytes[..buffer.len()]);
+        bytes = &bytes[buffer.len()..];
+    }
+    trap_cx.x[2] = sp;
+    trap_cx.x[10] = signum;
+    trap_cx.x[1] = restorer;
+    trap_cx.sepc = handler;
+    true
+}
+
+/// Restore the user context saved by the handler frame on the user stack,
+/// return the restored a0 or None if the frame is invalid.
+pub fn pop_signal_frame() -> Option<usize> {
+    let trap_cx = current_trap_cx();
+    let token = current_user_token();
+    let sp = trap_cx.x[2];
+    let buffers = translated_byte_buffer(token, sp as *const u8, size_of::<SignalFrame>(), false)?;
+    let mut frame = SignalFrame {
+        x: [0; 32],
+        sepc: 0,
+        mask: SignalFlags::empty(),
+    };
+    let mut bytes = frame.as_bytes_mut();
+    for buffer in buffers {
+        let (head, tail) = bytes.split_at_mut(buffer.len());
+        head.copy_from_slice(buffer);
+        bytes = tail;
+    }
+    trap_cx.x = frame.x;
+    trap_cx.sepc = frame.sepc;
+    current_process().inner_exclusive_access().signal_mask =
+        SignalFlags::from_bits_truncate(frame.mask.bits()) - SignalFlags::unmaskable();
+    Some(trap_cx.x[10])
 }
@@ -0,0 +1,87 @@
+//! Reclaim frames by swapping out user pages of processes.
+
+use super::manager::all_processes;
+use super::{current_process, current_task};
+use crate::mm::{FrameTracker, frame_alloc, free_frame_count, swap_flush};
+use crate::sync::SpinIntrFreeCell;
+use alloc::sync::Arc;
+use core::sync::atomic::Ordering;
+use lazy_static::*;
+
+/// Pages are swapped out when fewer frames are free, since the kernel
+/// cannot swap out pages while holding a PCB and needs frames there.
+const FREE_FRAMES_LOW: usize = 256;
+/// Pages are swapped out until this many frames are free.
+const FREE_FRAMES_HIGH: usize = 512;
+
+lazy_static! {
+    /// pid of the process whose page was swapped out last
+    static ref CLOCK_HAND: SpinIntrFreeCell<usize> = unsafe { SpinIntrFreeCell::new(0) };
+}
+
+/// Swap out at most `pages` user pages, the processes are scanned like
+/// a clock starting after the last one swapped out. The current process
+/// is skipped unless `with_current` is set, since its threads may hold
+/// its pages in a syscall. Return the number of pages swapped out.
+/// This may block, so no PCB should be held.
+fn swap_out_pages(pages: usize, with_current: bool) -> usize {
+    let mut processes = all_processes();
+    if !with_current {
+        let current = current_process();
+        processes.retain(|process| !Arc::ptr_eq(process, &current));
+    }
+    let hand = *CLOCK_HAND.exclusive_access();
+    let start = processes.partition_point(|process| process.getpid() <= hand);
+    processes.rotate_left(start);
+    let mut count = 0;
+    for round in 0..4 {
+        for process in processes.iter() {
+            if count == pages {
+                break;
+            }
+            let swapped = {
+                let mut process_inner = process.inner_exclusive_access();
+                if !process_inner.swappable() {
+                    continue;
+                }
+                process_inner
+                    .memory_set
+                    .swap_out_pages(round, pages - count)
+            };
+            if swapped > 0 {
+                *CLOCK_HAND.exclusive_access() = process.getpid();
+                count += swapped;
+            }
+        }
+    }
+    // frames are freed after being written to the swap area
+    swap_flush();
+    count
+}
+
+/// Mark whether the current task is in a syscall, with its process locked
+/// so that no page is being swapped out by other harts after it returns.
+pub fn set_current_in_syscall(in_syscall: bool) {
+    let task = current_task().unwrap();
+    let process = task.process.upgrade().unwrap();
+    let _process_inner = process.inner_exclusive_access();
+    task.in_syscall.store(in_syscall, Ordering::SeqCst);
+}
+
+/// Keep some frames free. Called on traps from the user space,
+/// where the current task does not hold any user page.
+pub fn reclaim_frames() {
+    let free_frames = free_frame_count();
+    if free_frames < FREE_FRAMES_LOW {
+        swap_out_pages(FREE_FRAMES_HIGH - free_frames, true);
+    }
+}
+
+/// Allocate a frame, pages of other processes are swapped out if there is
+/// no free frame. No PCB should be held since this may block.
+pub fn frame_alloc_or_swap() -> Option<FrameTracker> {
+    frame_alloc().or_else(|| {
+        swap_out_pages(1, false);
+        frame_alloc()
+    })
+}
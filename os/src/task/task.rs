@@ -1,22 +1,36 @@
 use super::id::TaskUserRes;
+use super::scheduler::SchedEntity;
 use super::{KernelStack, ProcessControlBlock, TaskContext, kstack_alloc};
 use crate::trap::TrapContext;
 use crate::{
     mm::PhysPageNum,
-    sync::{UPIntrFreeCell, UPIntrRefMut},
+    sync::{SpinIntrFreeCell, SpinIntrRefMut},
 };
 use alloc::sync::{Arc, Weak};
+use core::sync::atomic::{AtomicBool, AtomicUsize};
+
+/// `on_hart` of a task not running on any hart.
+pub const NO_HART: usize = usize::MAX;
 
 pub struct TaskControlBlock {
     // immutable
     pub process: Weak<ProcessControlBlock>,
     pub kstack: KernelStack,
+    /// the hart running the task, which is set before switching to the task
+    /// and cleared after switching away from it
+    pub on_hart: AtomicUsize,
+    /// user pages may be accessed by the kernel for the running syscall,
+    /// which is changed with the process locked
+    pub in_syscall: AtomicBool,
+    /// scheduling parameters, which are accessed by the scheduler
+    /// without borrowing the inner TCB
+    pub sched: SpinIntrFreeCell<SchedEntity>,
     // mutable
-    pub inner: UPIntrFreeCell<TaskControlBlockInner>,
+    pub inner: SpinIntrFreeCell<TaskControlBlockInner>,
 }
 
 impl TaskControlBlock {
-    pub fn inner_exclusive_access(&self) -> UPIntrRefMut<'_, TaskControlBlockInner> {
+    pub fn inner_exclusive_access(&self) -> SpinIntrRefMut<'_, TaskControlBlockInner> {
         self.inner.exclusive_access()
     }
 
@@ -59,8 +73,11 @@ impl TaskControlBlock {
         Self {
             process: Arc::downgrade(&process),
             kstack,
+            on_hart: AtomicUsize::new(NO_HART),
+            in_syscall: AtomicBool::new(false),
+            sched: unsafe { SpinIntrFreeCell::new(SchedEntity::new()) },
             inner: unsafe {
-                UPIntrFreeCell::new(TaskControlBlockInner {
+                SpinIntrFreeCell::new(TaskControlBlockInner {
                     res: Some(res),
                     trap_cx_ppn,
                     task_cx: TaskContext::goto_trap_return(kstack_top),
@@ -1,8 +1,9 @@
 use core::cmp::Ordering;
 
+use crate::board::VIRT_RTC;
 use crate::config::CLOCK_FREQ;
 use crate::sbi::set_timer;
-use crate::sync::UPIntrFreeCell;
+use crate::sync::{PollWaiter, SpinIntrFreeCell};
 use crate::task::{TaskControlBlock, wakeup_task};
 use alloc::collections::BinaryHeap;
 use alloc::sync::Arc;
@@ -20,13 +21,29 @@ pub fn get_time_ms() -> usize {
     time::read() / (CLOCK_FREQ / MSEC_PER_SEC)
 }
 
+/// Seconds since the epoch, from the RTC.
+pub fn get_real_time_sec() -> u32 {
+    let rtc = VIRT_RTC as *const u32;
+    // reading the low word latches the high word
+    let low = unsafe { rtc.read_volatile() } as u64;
+    let high = unsafe { rtc.add(1).read_volatile() } as u64;
+    ((high << 32 | low) / 1_000_000_000) as u32
+}
+
 pub fn set_next_trigger() {
     set_timer(get_time() + CLOCK_FREQ / TICKS_PER_SEC);
 }
 
+/// What a timer wakes when it expires.
+pub enum TimerWaker {
+    Task(Arc<TaskControlBlock>),
+    /// a poll with a timeout, which may have been woken already
+    Poller(Arc<PollWaiter>),
+}
+
 pub struct TimerCondVar {
     pub expire_ms: usize,
-    pub task: Arc<TaskControlBlock>,
+    pub waker: TimerWaker,
 }
 
 impl PartialEq for TimerCondVar {
@@ -50,13 +67,25 @@ impl Ord for TimerCondVar {
 }
 
 lazy_static! {
-    static ref TIMERS: UPIntrFreeCell<BinaryHeap<TimerCondVar>> =
-        unsafe { UPIntrFreeCell::new(BinaryHeap::<TimerCondVar>::new()) };
+    static ref TIMERS: SpinIntrFreeCell<BinaryHeap<TimerCondVar>> =
+        unsafe { SpinIntrFreeCell::new(BinaryHeap::<TimerCondVar>::new()) };
 }
 
 pub fn add_timer(expire_ms: usize, task: Arc<TaskControlBlock>) {
     let mut timers = TIMERS.exclusive_access();
-    timers.push(TimerCondVar { expire_ms, task });
+    timers.push(TimerCondVar {
+        expire_ms,
+        waker: TimerWaker::Task(task),
+    });
+}
+
+/// Wake a poll at `expire_ms` if nothing else wakes it before.
+pub fn add_poll_timer(expire_ms: usize, waiter: Arc<PollWaiter>) {
+    let mut timers = TIMERS.exclusive_access();
+    timers.push(TimerCondVar {
+        expire_ms,
+        waker: TimerWaker::Poller(waiter),
+    });
 }
 
 pub fn check_timer() {
@@ -64,8 +93,10 @@ pub fn check_timer() {
     TIMERS.exclusive_session(|timers| {
         while let Some(timer) = timers.peek() {
             if timer.expire_ms <= current_ms {
-                wakeup_task(Arc::clone(&timer.task));
-                timers.pop();
+                match timers.pop().unwrap().waker {
+                    TimerWaker::Task(task) => wakeup_task(task),
+                    TimerWaker::Poller(waiter) => waiter.wake(),
+                }
             } else {
                 break;
             }
@@ -9,6 +9,8 @@ pub struct TrapContext {
     pub kernel_satp: usize,
     pub kernel_sp: usize,
     pub trap_handler: usize,
+    /// hart the task runs on, loaded into `tp` on traps
+    pub hart_id: usize,
 }
 
 impl TrapContext {
@@ -32,6 +34,7 @@ impl TrapContext {
             kernel_satp,
             kernel_sp,
             trap_handler,
+            hart_id: 0,
         };
         cx.set_sp(sp);
         cx
@@ -1,18 +1,20 @@
 mod context;
 
 use crate::config::TRAMPOLINE;
+use crate::mm::VirtAddr;
+use crate::net::{check_net_timers, handle_loopback};
 use crate::syscall::syscall;
 use crate::task::{
-    SignalFlags, check_signals_of_current, current_add_signal, current_trap_cx,
-    current_trap_cx_user_va, current_user_token, exit_current_and_run_next,
-    suspend_current_and_run_next,
+    SignalFlags, current_force_signal, current_process, current_trap_cx, current_trap_cx_user_va,
+    current_user_token, handle_signals_of_current, hart_id, kill_current_and_run_next,
+    reclaim_frames, set_current_in_syscall, suspend_current_and_run_next,
 };
 use crate::timer::{check_timer, set_next_trigger};
 use core::arch::{asm, global_asm};
 use riscv::register::{
     mtvec::TrapMode,
     scause::{self, Exception, Interrupt, Trap},
-    sie, sscratch, sstatus, stval, stvec,
+    sie, sip, sscratch, sstatus, stval, stvec,
 };
 
 global_asm!(include_str!("trap.S"));
@@ -45,6 +47,19 @@ pub fn enable_timer_interrupt() {
     }
 }
 
+/// IPIs arrive as supervisor software interrupts.
+pub fn enable_software_interrupt() {
+    unsafe {
+        sie::set_ssoft();
+    }
+}
+
+fn clear_software_interrupt() {
+    unsafe {
+        sip::clear_ssoft();
+    }
+}
+
 fn enable_supervisor_interrupt() {
     unsafe {
         sstatus::set_sie();
@@ -70,19 +85,37 @@ pub fn trap_handler() -> ! {
             cx.sepc += 4;
 
             enable_supervisor_interrupt();
+            reclaim_frames();
 
+            // user pages used by the syscall must not be swapped out until it returns
+            set_current_in_syscall(true);
             // get system call return value
-            let result = syscall(cx.x[17], [cx.x[10], cx.x[11], cx.x[12]]);
+            let result = syscall(
+                cx.x[17],
+                [cx.x[10], cx.x[11], cx.x[12], cx.x[13], cx.x[14], cx.x[15]],
+            );
+            set_current_in_syscall(false);
+            // packets sent to this host by the syscall are handled at once
+            handle_loopback();
             // cx is changed during sys_exec, so we have to call it again
             cx = current_trap_cx();
             cx.x[10] = result as usize;
         }
-        Trap::Exception(Exception::StoreFault)
-        | Trap::Exception(Exception::StorePageFault)
-        | Trap::Exception(Exception::InstructionFault)
+        Trap::Exception(Exception::StorePageFault)
         | Trap::Exception(Exception::InstructionPageFault)
-        | Trap::Exception(Exception::LoadFault)
         | Trap::Exception(Exception::LoadPageFault) => {
+            // loading a page may block on the block device
+            enable_supervisor_interrupt();
+            reclaim_frames();
+            let write = matches!(scause.cause(), Trap::Exception(Exception::StorePageFault));
+            // the access is retried after the page is resolved
+            if !current_process().handle_page_fault(VirtAddr::from(stval).floor(), write) {
+                current_force_signal(SignalFlags::SIGSEGV);
+            }
+        }
+        Trap::Exception(Exception::StoreFault)
+        | Trap::Exception(Exception::InstructionFault)
+        | Trap::Exception(Exception::LoadFault) => {
             /*
             println!(
                 "[kernel] {:?} in application, bad addr = {:#x}, bad instruction = {:#x}, kernel killed it.",
@@ -91,19 +124,25 @@ pub fn trap_handler() -> ! {
                 current_trap_cx().sepc,
             );
             */
-            current_add_signal(SignalFlags::SIGSEGV);
+            current_force_signal(SignalFlags::SIGSEGV);
         }
         Trap::Exception(Exception::IllegalInstruction) => {
-            current_add_signal(SignalFlags::SIGILL);
+            current_force_signal(SignalFlags::SIGILL);
         }
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
             set_next_trigger();
             check_timer();
+            check_net_timers();
             suspend_current_and_run_next();
         }
         Trap::Interrupt(Interrupt::SupervisorExternal) => {
             crate::board::irq_handler();
         }
+        Trap::Interrupt(Interrupt::SupervisorSoft) => {
+            // another hart asks this one to reschedule
+            clear_software_interrupt();
+            suspend_current_and_run_next();
+        }
         _ => {
             panic!(
                 "Unsupported trap {:?}, stval = {:#x}!",
@@ -112,10 +151,10 @@ pub fn trap_handler() -> ! {
             );
         }
     }
-    // check signals
-    if let Some((errno, msg)) = check_signals_of_current() {
+    // handle signals
+    if let Some((signum, msg)) = handle_signals_of_current() {
         println!("[kernel] {}", msg);
-        exit_current_and_run_next(errno);
+        kill_current_and_run_next(signum);
     }
     trap_return();
 }
@@ -127,6 +166,8 @@ pub fn trap_handler() -> ! {
 pub fn trap_return() -> ! {
     disable_supervisor_interrupt();
     set_user_trap_entry();
+    // the task may have been moved to another hart
+    current_trap_cx().hart_id = hart_id();
     let trap_cx_user_va = current_trap_cx_user_va();
     let user_satp = current_user_token();
     unsafe extern "C" {
@@ -160,6 +201,11 @@ pub fn trap_from_kernel(_trap_cx: &TrapContext) {
             check_timer();
             // do not schedule now
         }
+        Trap::Interrupt(Interrupt::SupervisorSoft) => {
+            // an idle hart is woken up, a running task is switched
+            // out on the next timer interrupt from the user space
+            clear_software_interrupt();
+        }
         _ => {
             panic!(
                 "Unsupported trap from kernel: {:?}, stval = {:#x}!",
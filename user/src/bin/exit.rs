@@ -5,7 +5,9 @@
 extern crate user_lib;
 use user_lib::{exit, fork, wait, waitpid, yield_};
 
-const MAGIC: i32 = -0x10384;
+/// Only the low 8 bits of an exit code reach the parent, as in Linux, so
+/// the code is kept within an i8.
+const MAGIC: i32 = -0x38;
 
 #[unsafe(no_mangle)]
 pub fn main() -> i32 {
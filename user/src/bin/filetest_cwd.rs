@@ -0,0 +1,60 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{OpenFlags, chdir, close, fork, getcwd, mkdir, open, rmdir, unlink, waitpid};
+
+fn cwd_is(expected: &str) -> bool {
+    let mut buf = [0u8; 64];
+    let len = getcwd(&mut buf);
+    len >= 0 && &buf[..len as usize] == expected.as_bytes()
+}
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    assert!(cwd_is("/"));
+    assert_eq!(mkdir("/cwd_a\0"), 0);
+    assert_eq!(chdir("cwd_a\0"), 0);
+    assert!(cwd_is("/cwd_a"));
+    // relative paths are resolved against the working directory
+    assert_eq!(mkdir("cwd_b\0"), 0);
+    assert_eq!(chdir("./cwd_b/\0"), 0);
+    assert!(cwd_is("/cwd_a/cwd_b"));
+    let fd = open("file\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    close(fd as usize);
+    assert!(open("/cwd_a/cwd_b/file\0", OpenFlags::RDONLY) > 0);
+    // only directories can become the working directory
+    assert_eq!(chdir("file\0"), -1);
+    assert_eq!(chdir("missing\0"), -1);
+    assert!(cwd_is("/cwd_a/cwd_b"));
+    // a buffer without room for the trailing '\0' is rejected
+    let mut small = [0u8; 12];
+    assert_eq!(getcwd(&mut small), -1);
+
+    // children inherit the working directory
+    let pid = fork();
+    if pid == 0 {
+        assert!(cwd_is("/cwd_a/cwd_b"));
+        assert_eq!(chdir("..\0"), 0);
+        assert!(cwd_is("/cwd_a"));
+        return 0;
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    // but changes made by a child do not affect the parent
+    assert!(cwd_is("/cwd_a/cwd_b"));
+
+    assert_eq!(unlink("file\0"), 0);
+    assert_eq!(chdir("../..\0"), 0);
+    assert!(cwd_is("/"));
+    assert_eq!(chdir("..\0"), 0);
+    assert!(cwd_is("/"));
+    assert_eq!(rmdir("cwd_a/cwd_b\0"), 0);
+    assert_eq!(rmdir("cwd_a\0"), 0);
+    println!("filetest_cwd passed!");
+    0
+}
@@ -0,0 +1,47 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{OpenFlags, close, mkdir, open, read, rename, rmdir, unlink, write};
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    let test_str = "Hello, directories!";
+    assert_eq!(mkdir("/dir_a\0"), 0);
+    assert_eq!(mkdir("/dir_a\0"), -1);
+    assert_eq!(mkdir("/dir_a/dir_b\0"), 0);
+    let fd = open("/dir_a/dir_b/file\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    write(fd, test_str.as_bytes());
+    close(fd);
+    // a directory cannot be opened for writing
+    assert_eq!(open("/dir_a\0", OpenFlags::WRONLY), -1);
+
+    // "." and ".." are real directory entries
+    assert_eq!(
+        rename("/dir_a/dir_b/./file\0", "/dir_a/dir_b/../moved\0"),
+        0
+    );
+    assert_eq!(open("/dir_a/dir_b/file\0", OpenFlags::RDONLY), -1);
+    let fd = open("/dir_a/moved\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let mut buffer = [0u8; 100];
+    let read_len = read(fd, &mut buffer) as usize;
+    close(fd);
+    assert_eq!(test_str, core::str::from_utf8(&buffer[..read_len]).unwrap());
+
+    // only empty directories can be removed
+    assert_eq!(rmdir("/dir_a\0"), -1);
+    assert_eq!(unlink("/dir_a/dir_b\0"), -1);
+    assert_eq!(rmdir("/dir_a/dir_b\0"), 0);
+    assert_eq!(unlink("/dir_a/moved\0"), 0);
+    assert_eq!(unlink("/dir_a/moved\0"), -1);
+    assert_eq!(rmdir("/dir_a\0"), 0);
+    assert_eq!(open("/dir_a\0", OpenFlags::RDONLY), -1);
+    println!("filetest_dir passed!");
+    0
+}
@@ -0,0 +1,134 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    OpenFlags, Stat, chdir, close, exec, exit, fork, getcwd, link, lstat, mkdir, open, read,
+    readlink, rmdir, stat, symlink, unlink, waitpid, write,
+};
+
+fn read_file(path: &str, buf: &mut [u8]) -> isize {
+    let fd = open(path, OpenFlags::RDONLY);
+    if fd < 0 {
+        return fd;
+    }
+    let len = read(fd as usize, buf);
+    close(fd as usize);
+    len
+}
+
+/// Hard links share the inode, which lives until the last one is removed.
+fn hard_link_test() {
+    let fd = open("link_a\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, b"shared"), 6);
+    close(fd as usize);
+    assert_eq!(link("link_a\0", "link_b\0"), 0);
+    assert_eq!(link("link_a\0", "link_b\0"), -1);
+    assert_eq!(link("link_missing\0", "link_c\0"), -1);
+    let (mut a, mut b) = (Stat::default(), Stat::default());
+    assert_eq!(stat("link_a\0", &mut a), 0);
+    assert_eq!(stat("link_b\0", &mut b), 0);
+    assert_eq!((a.ino, a.nlink), (b.ino, 2));
+
+    assert_eq!(unlink("link_a\0"), 0);
+    let mut buf = [0u8; 16];
+    assert_eq!(read_file("link_b\0", &mut buf), 6);
+    assert_eq!(&buf[..6], b"shared");
+    assert_eq!(stat("link_b\0", &mut b), 0);
+    assert_eq!(b.nlink, 1);
+    assert_eq!(unlink("link_b\0"), 0);
+    assert_eq!(stat("link_b\0", &mut b), -1);
+
+    // directories can not be linked
+    assert_eq!(mkdir("link_dir\0"), 0);
+    assert_eq!(link("link_dir\0", "link_dir2\0"), -1);
+    assert_eq!(rmdir("link_dir\0"), 0);
+}
+
+fn symlink_test() {
+    assert_eq!(mkdir("/sym_dir\0"), 0);
+    let fd = open("/sym_dir/target\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, b"pointed"), 7);
+    close(fd as usize);
+
+    // relative targets are followed from the directory of the link
+    assert_eq!(symlink("target\0", "/sym_dir/rel\0"), 0);
+    assert_eq!(symlink("/sym_dir\0", "/sym_abs\0"), 0);
+    let mut buf = [0u8; 16];
+    assert_eq!(read_file("/sym_dir/rel\0", &mut buf), 7);
+    assert_eq!(read_file("/sym_abs/rel\0", &mut buf), 7);
+    assert_eq!(&buf[..7], b"pointed");
+    assert_eq!(readlink("/sym_abs\0", &mut buf), 8);
+    assert_eq!(&buf[..8], b"/sym_dir");
+    assert_eq!(readlink("/sym_dir/target\0", &mut buf), -1);
+
+    // stat follows the link, lstat tells of the link itself
+    let (mut st, mut lst) = (Stat::default(), Stat::default());
+    assert_eq!(stat("/sym_dir/rel\0", &mut st), 0);
+    assert_eq!(lstat("/sym_dir/rel\0", &mut lst), 0);
+    assert!(st.is_file() && lst.is_symlink());
+    assert_eq!((st.size, lst.size), (7, 6));
+    assert_ne!(st.ino, lst.ino);
+
+    // ".." after a link leads to the parent of its target
+    assert_eq!(mkdir("/sym_dir/sub\0"), 0);
+    assert_eq!(symlink("/sym_dir/sub\0", "/sym_sub\0"), 0);
+    assert_eq!(read_file("/sym_sub/../target\0", &mut buf), 7);
+    assert_eq!(chdir("/sym_sub\0"), 0);
+    let mut cwd = [0u8; 32];
+    assert_eq!(getcwd(&mut cwd), 12);
+    assert_eq!(&cwd[..12], b"/sym_dir/sub");
+    assert_eq!(chdir("..\0"), 0);
+    assert_eq!(getcwd(&mut cwd), 8);
+    assert_eq!(&cwd[..8], b"/sym_dir");
+    assert_eq!(chdir("/\0"), 0);
+
+    // dangling links and loops do not resolve
+    assert_eq!(symlink("nowhere\0", "/sym_dir/dangling\0"), 0);
+    assert_eq!(read_file("/sym_dir/dangling\0", &mut buf), -1);
+    assert_eq!(lstat("/sym_dir/dangling\0", &mut lst), 0);
+    assert_eq!(symlink("loop_b\0", "/sym_dir/loop_a\0"), 0);
+    assert_eq!(symlink("loop_a\0", "/sym_dir/loop_b\0"), 0);
+    assert_eq!(stat("/sym_dir/loop_a\0", &mut st), -1);
+    assert_eq!(symlink("x\0", "/sym_dir/rel\0"), -1);
+
+    // a program is run through a link to it
+    assert_eq!(symlink("hello_world\0", "/hello_link\0"), 0);
+    let pid = fork();
+    if pid == 0 {
+        exec("/hello_link\0", &[core::ptr::null::<u8>()]);
+        exit(1);
+    }
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+
+    // removing a link leaves its target
+    for path in [
+        "/hello_link\0",
+        "/sym_sub\0",
+        "/sym_abs\0",
+        "/sym_dir/rel\0",
+        "/sym_dir/dangling\0",
+        "/sym_dir/loop_a\0",
+        "/sym_dir/loop_b\0",
+    ] {
+        assert_eq!(unlink(path), 0);
+    }
+    assert_eq!(stat("/hello_world\0", &mut st), 0);
+    assert_eq!(unlink("/sym_dir/target\0"), 0);
+    assert_eq!(rmdir("/sym_dir/sub\0"), 0);
+    assert_eq!(rmdir("/sym_dir\0"), 0);
+}
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    hard_link_test();
+    symlink_test();
+    println!("filetest_link passed!");
+    0
+}
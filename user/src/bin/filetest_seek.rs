@@ -0,0 +1,69 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    OpenFlags, SEEK_CUR, SEEK_END, SEEK_SET, close, lseek, open, pipe, pread, pwrite, read, unlink,
+    write,
+};
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    let path = "seek_test\0";
+    let fd = open(path, OpenFlags::CREATE | OpenFlags::RDWR);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    assert_eq!(write(fd, b"header:0123456789"), 17);
+    assert_eq!(lseek(fd, 0, SEEK_CUR), 17);
+
+    // read the tail, and rewrite the header
+    assert_eq!(lseek(fd, -4, SEEK_END), 13);
+    let mut buf = [0u8; 16];
+    assert_eq!(read(fd, &mut buf), 4);
+    assert_eq!(&buf[..4], b"6789");
+    assert_eq!(lseek(fd, 0, SEEK_SET), 0);
+    assert_eq!(write(fd, b"HEADER"), 6);
+    assert_eq!(lseek(fd, 1, SEEK_CUR), 7);
+    assert_eq!(read(fd, &mut buf[..3]), 3);
+    assert_eq!(&buf[..3], b"012");
+    assert_eq!(lseek(fd, -1, SEEK_SET), -1);
+    assert_eq!(lseek(fd, -11, SEEK_CUR), -1);
+    assert_eq!(lseek(fd, 0, 3), -1);
+
+    // positional I/O leaves the offset alone
+    assert_eq!(pread(fd, &mut buf[..6], 0), 6);
+    assert_eq!(&buf[..6], b"HEADER");
+    assert_eq!(pwrite(fd, b"ab", 7), 2);
+    assert_eq!(pread(fd, &mut buf, 7), 10);
+    assert_eq!(&buf[..10], b"ab23456789");
+    assert_eq!(pread(fd, &mut buf, 17), 0);
+    assert_eq!(lseek(fd, 0, SEEK_CUR), 10);
+
+    // a write past the end grows the file, the gap reads as zeros
+    assert_eq!(lseek(fd, 20, SEEK_SET), 20);
+    assert_eq!(write(fd, b"!"), 1);
+    assert_eq!(lseek(fd, 0, SEEK_END), 21);
+    assert_eq!(pread(fd, &mut buf[..4], 17), 4);
+    assert_eq!(&buf[..4], b"\0\0\0!");
+    close(fd);
+
+    // a read-only file can not be written at an offset
+    let fd = open(path, OpenFlags::RDONLY) as usize;
+    assert_eq!(pwrite(fd, b"x", 0), -1);
+    close(fd);
+    assert_eq!(unlink(path), 0);
+
+    // pipes and stdin are not seekable
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    assert_eq!(lseek(fds[0], 0, SEEK_SET), -1);
+    assert_eq!(pwrite(fds[1], b"x", 0), -1);
+    assert_eq!(pread(fds[0], &mut buf, 0), -1);
+    close(fds[0]);
+    close(fds[1]);
+    assert_eq!(lseek(0, 0, SEEK_CUR), -1);
+    println!("filetest_seek passed!");
+    0
+}
@@ -0,0 +1,70 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    OpenFlags, S_IFCHR, S_IFDIR, S_IFIFO, S_IFREG, Stat, close, fstat, mkdir, open, pipe, rmdir,
+    stat, unlink, write,
+};
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    let path = "stat_test\0";
+    let fd = open(path, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let mut st = Stat::default();
+    assert_eq!(fstat(fd, &mut st), 0);
+    assert_eq!(st.file_type(), S_IFREG);
+    assert_eq!(
+        (st.permissions(), st.nlink, st.size, st.blocks),
+        (0o644, 1, 0, 0)
+    );
+    let created = st.ctime;
+    assert!(created > 0);
+    let ino = st.ino;
+
+    assert_eq!(write(fd, &[7u8; 1000]), 1000);
+    assert_eq!(fstat(fd, &mut st), 0);
+    assert_eq!((st.size, st.blocks, st.blksize), (1000, 2, 512));
+    assert!(st.mtime >= created && st.ctime >= created);
+    close(fd);
+
+    // stat finds the same inode by path
+    let mut by_path = Stat::default();
+    assert_eq!(stat(path, &mut by_path), 0);
+    assert_eq!((by_path.ino, by_path.size), (ino, 1000));
+    assert_eq!(stat("stat_test_missing\0", &mut by_path), -1);
+
+    // a subdirectory links its parent through ".."
+    assert_eq!(mkdir("stat_dir\0"), 0);
+    assert_eq!(stat("stat_dir\0", &mut st), 0);
+    assert!(st.is_dir());
+    assert_eq!(
+        (st.file_type(), st.permissions(), st.nlink),
+        (S_IFDIR, 0o755, 2)
+    );
+    assert_eq!(mkdir("stat_dir/sub\0"), 0);
+    assert_eq!(stat("stat_dir\0", &mut st), 0);
+    assert_eq!(st.nlink, 3);
+    assert_eq!(rmdir("stat_dir/sub\0"), 0);
+    assert_eq!(stat("stat_dir/.\0", &mut st), 0);
+    assert_eq!(st.nlink, 2);
+    assert_eq!(rmdir("stat_dir\0"), 0);
+    assert_eq!(unlink(path), 0);
+
+    // files without an inode tell their type
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    assert_eq!(fstat(fds[0], &mut st), 0);
+    assert_eq!(st.file_type(), S_IFIFO);
+    close(fds[0]);
+    close(fds[1]);
+    assert_eq!(fstat(1, &mut st), 0);
+    assert_eq!(st.file_type(), S_IFCHR);
+    assert_eq!(fstat(fds[0], &mut st), -1);
+    println!("filetest_stat passed!");
+    0
+}
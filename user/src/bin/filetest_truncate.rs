@@ -0,0 +1,64 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    OpenFlags, SEEK_SET, Stat, close, fstat, ftruncate, lseek, mkdir, open, pread, read, rmdir,
+    stat, truncate, unlink, write,
+};
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    let path = "truncate_test\0";
+    let fd = open(path, OpenFlags::CREATE | OpenFlags::RDWR);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let data = [0x5au8; 4096];
+    for _ in 0..32 {
+        assert_eq!(write(fd, &data), 4096);
+    }
+    let mut st = Stat::default();
+    assert_eq!(fstat(fd, &mut st), 0);
+    assert_eq!(st.size, 32 * 4096);
+
+    // shrinking frees the blocks past the new end
+    assert_eq!(ftruncate(fd, 1000), 0);
+    assert_eq!(fstat(fd, &mut st), 0);
+    assert_eq!((st.size, st.blocks), (1000, 2));
+    assert_eq!(truncate(path, 10), 0);
+    assert_eq!(stat(path, &mut st), 0);
+    assert_eq!(st.size, 10);
+    close(fd);
+
+    // growing again reads zeros past the old end
+    assert_eq!(truncate(path, 600), 0);
+    let fd = open(path, OpenFlags::RDONLY) as usize;
+    let mut buf = [0xffu8; 1024];
+    assert_eq!(read(fd, &mut buf), 600);
+    assert!(buf[..10].iter().all(|byte| *byte == 0x5a));
+    assert!(buf[10..600].iter().all(|byte| *byte == 0));
+    // a file must be opened for writing to be truncated
+    assert_eq!(ftruncate(fd, 0), -1);
+    close(fd);
+
+    // seeking past the end leaves a hole, which holds no blocks
+    let fd = open(path, OpenFlags::RDWR) as usize;
+    assert_eq!(lseek(fd, 1 << 20, SEEK_SET), 1 << 20);
+    assert_eq!(write(fd, b"x"), 1);
+    assert_eq!(fstat(fd, &mut st), 0);
+    assert_eq!((st.size, st.blocks), ((1 << 20) + 1, 4));
+    assert_eq!(pread(fd, &mut buf, 4096), 1024);
+    assert!(buf.iter().all(|byte| *byte == 0));
+    close(fd);
+
+    assert_eq!(truncate(path, -1), -1);
+    assert_eq!(truncate("truncate_missing\0", 0), -1);
+    assert_eq!(mkdir("truncate_dir\0"), 0);
+    assert_eq!(truncate("truncate_dir\0", 0), -1);
+    assert_eq!(rmdir("truncate_dir\0"), 0);
+    assert_eq!(unlink(path), 0);
+    println!("filetest_truncate passed!");
+    0
+}
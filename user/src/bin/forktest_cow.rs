@@ -0,0 +1,48 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{OpenFlags, close, fork, open, read, wait};
+
+static mut DATA: [u8; 8192] = [1; 8192];
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    let mut stack_value: usize = 1;
+    let pid = fork();
+    if pid == 0 {
+        // writes of the child must not be seen by the parent
+        unsafe {
+            DATA[0] = 2;
+            DATA[8191] = 2;
+        }
+        stack_value = 2;
+        // the kernel also writes into copy-on-write pages
+        let mut buf = [0u8; 16];
+        let fd = open("filea\0", OpenFlags::RDONLY);
+        if fd >= 0 {
+            read(fd as usize, &mut buf);
+            close(fd as usize);
+        }
+        assert_eq!(stack_value, 2);
+        0
+    } else {
+        let mut exit_code: i32 = 0;
+        assert_eq!(pid, wait(&mut exit_code));
+        assert_eq!(exit_code, 0);
+        let (first, last) = unsafe { (DATA[0], DATA[8191]) };
+        assert_eq!(first, 1);
+        assert_eq!(last, 1);
+        // the parent gets its own copy on the first store
+        let first = unsafe {
+            DATA[0] = 3;
+            DATA[0]
+        };
+        assert_eq!(first, 3);
+        assert_eq!(stack_value, 1);
+        println!("forktest_cow passed!");
+        0
+    }
+}
@@ -5,7 +5,7 @@ extern crate alloc;
 extern crate user_lib;
 
 use user_lib::console::getchar;
-use user_lib::{Display, VIRTGPU_XRES, VIRTGPU_YRES, key_pressed, sleep};
+use user_lib::{Display, PollEvents, PollFd, VIRTGPU_XRES, VIRTGPU_YRES, get_time, poll};
 
 use embedded_graphics::Pixel;
 use embedded_graphics::pixelcolor::*;
@@ -325,14 +325,18 @@ mod tests {
 
 const LF: u8 = 0x0au8;
 const CR: u8 = 0x0du8;
+const FRAME_MS: isize = 40;
 #[unsafe(no_mangle)]
 pub fn main() -> i32 {
     let mut disp = Display::new(Size::new(VIRTGPU_XRES, VIRTGPU_YRES));
     let mut game =
         SnakeGame::<20, Rgb888>::new(1280, 800, 20, 20, Rgb888::RED, Rgb888::YELLOW, 200);
     let _ = disp.clear(Rgb888::BLACK).unwrap();
+    let mut next_frame = get_time() + FRAME_MS;
     loop {
-        if key_pressed() {
+        // keys are handled as they come until the next frame is due
+        let mut stdin = [PollFd::new(0, PollEvents::IN)];
+        if poll(&mut stdin, (next_frame - get_time()).max(0)) > 0 {
             let c = getchar();
             match c {
                 LF => break,
@@ -343,11 +347,12 @@ pub fn main() -> i32 {
                 b'd' => game.set_direction(Direction::Right),
                 _ => (),
             }
+            continue;
         }
         let _ = disp.clear(Rgb888::BLACK).unwrap();
         game.draw(&mut disp);
         disp.flush();
-        sleep(40);
+        next_frame = get_time() + FRAME_MS;
     }
     0
 }
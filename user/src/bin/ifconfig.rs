@@ -0,0 +1,52 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+// usage: ifconfig                             show the configuration
+//        ifconfig a.b.c.d [netmask] [gateway] configure statically
+//        ifconfig dhcp                        configure by DHCP
+
+use user_lib::{net_config, parse_ip, set_net_config};
+
+fn print_ip(name: &str, ip: u32) {
+    let bytes = ip.to_be_bytes();
+    println!(
+        "{:<8}{}.{}.{}.{}",
+        name, bytes[0], bytes[1], bytes[2], bytes[3]
+    );
+}
+
+#[unsafe(no_mangle)]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc == 2 && argv[1] == "dhcp" {
+        return set_net_config(0, 0, 0) as i32;
+    }
+    if argc > 1 {
+        let mut addresses = [0u32, 0xffff_ff00, 0];
+        for (address, arg) in addresses.iter_mut().zip(&argv[1..argc]) {
+            match parse_ip(arg) {
+                Some(ip) => *address = ip,
+                None => {
+                    println!("ifconfig: bad address {}", arg);
+                    return -1;
+                }
+            }
+        }
+        if set_net_config(addresses[0], addresses[1], addresses[2]) < 0 {
+            println!("ifconfig: invalid configuration");
+            return -1;
+        }
+    }
+    let config = net_config();
+    let mac = config.mac;
+    println!(
+        "ether   {:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+        mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]
+    );
+    print_ip("inet", config.ip);
+    print_ip("netmask", config.netmask);
+    print_ip("gateway", config.gateway);
+    0
+}
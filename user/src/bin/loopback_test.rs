@@ -0,0 +1,80 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    AF_INET, SOCK_DGRAM, SOCK_STREAM, SockAddrIn, accept, bind, close, connect, exit, fork,
+    getsockname, listen, read, recvfrom, sendto, socket, waitpid, write,
+};
+
+const LOOPBACK: u32 = 127 << 24 | 1;
+
+/// A datagram sent to 127.0.0.1 comes back from 127.0.0.1.
+fn udp_test() {
+    let receiver = socket(AF_INET, SOCK_DGRAM, 0) as usize;
+    assert_eq!(bind(receiver, &SockAddrIn::new(LOOPBACK, 5000)), 0);
+    let sender = socket(AF_INET, SOCK_DGRAM, 0) as usize;
+    assert_eq!(
+        sendto(sender, b"hello", Some(&SockAddrIn::new(LOOPBACK, 5000))),
+        5
+    );
+    let mut local = SockAddrIn::default();
+    assert_eq!(getsockname(sender, &mut local), 0);
+    let mut from = SockAddrIn::default();
+    let mut buf = [0u8; 16];
+    assert_eq!(recvfrom(receiver, &mut buf, Some(&mut from)), 5);
+    assert_eq!(&buf[..5], b"hello");
+    assert_eq!((from.ip(), from.port()), (LOOPBACK, local.port()));
+    assert_eq!(close(sender), 0);
+    assert_eq!(close(receiver), 0);
+}
+
+/// A connection between two processes of this host.
+fn tcp_test() {
+    let server = socket(AF_INET, SOCK_STREAM, 0) as usize;
+    assert_eq!(bind(server, &SockAddrIn::new(0, 5001)), 0);
+    assert_eq!(listen(server, 1), 0);
+    let pid = fork();
+    if pid == 0 {
+        let client = socket(AF_INET, SOCK_STREAM, 0) as usize;
+        if connect(client, &SockAddrIn::new(LOOPBACK, 5001)) != 0 {
+            exit(1);
+        }
+        write(client, b"ping");
+        let mut buf = [0u8; 16];
+        let len = read(client, &mut buf);
+        close(client);
+        exit(if buf[..len.max(0) as usize] == *b"pong" {
+            0
+        } else {
+            2
+        });
+    }
+    let mut peer = SockAddrIn::default();
+    let connection = accept(server, Some(&mut peer));
+    assert!(connection >= 0);
+    let connection = connection as usize;
+    assert_eq!(peer.ip(), LOOPBACK);
+    let mut local = SockAddrIn::default();
+    assert_eq!(getsockname(connection, &mut local), 0);
+    assert_eq!((local.ip(), local.port()), (LOOPBACK, 5001));
+    let mut buf = [0u8; 16];
+    assert_eq!(read(connection, &mut buf), 4);
+    assert_eq!(&buf[..4], b"ping");
+    assert_eq!(write(connection, b"pong"), 4);
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(close(connection), 0);
+    assert_eq!(close(server), 0);
+}
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    udp_test();
+    tcp_test();
+    println!("loopback_test passed!");
+    0
+}
@@ -0,0 +1,152 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{
+    OpenFlags, S_IFCHR, S_IFDIR, S_IFIFO, S_IFLNK, S_IFSOCK, Stat, close, lstat, open, read,
+    readlink, stat,
+};
+
+/// A directory is read as entries of a name padded to 28 bytes and an
+/// inode number.
+const DIRENT_SZ: usize = 32;
+const NAME_SZ: usize = 28;
+
+fn names(dir: &str) -> Option<Vec<String>> {
+    let fd = open(format!("{}\0", dir).as_str(), OpenFlags::RDONLY);
+    if fd < 0 {
+        return None;
+    }
+    let fd = fd as usize;
+    let mut names = Vec::new();
+    let mut dirent = [0u8; DIRENT_SZ];
+    while read(fd, &mut dirent) == DIRENT_SZ as isize {
+        let len = dirent[..NAME_SZ]
+            .iter()
+            .position(|b| *b == 0)
+            .unwrap_or(NAME_SZ);
+        // an empty name marks a free slot
+        if len > 0 {
+            names.push(String::from(core::str::from_utf8(&dirent[..len]).unwrap()));
+        }
+    }
+    close(fd);
+    names.sort();
+    Some(names)
+}
+
+/// The mode as `ls -l` shows it, e.g. "drwxr-xr-x".
+fn mode_string(st: &Stat) -> String {
+    let mut mode = String::new();
+    mode.push(match st.file_type() {
+        S_IFDIR => 'd',
+        S_IFLNK => 'l',
+        S_IFSOCK => 's',
+        S_IFIFO => 'p',
+        S_IFCHR => 'c',
+        _ => '-',
+    });
+    for (i, c) in "rwxrwxrwx".chars().enumerate() {
+        mode.push(if st.permissions() & (1 << (8 - i)) != 0 {
+            c
+        } else {
+            '-'
+        });
+    }
+    mode
+}
+
+/// Seconds since the epoch as "YYYY-MM-DD hh:mm" in UTC.
+fn time_string(secs: i64) -> String {
+    let days = secs.div_euclid(86400);
+    let secs_of_day = secs.rem_euclid(86400);
+    // the civil date of a day count, from Howard Hinnant's algorithm
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60
+    )
+}
+
+fn print_entry(path: &str, name: &str, long: bool) {
+    if !long {
+        println!("{}", name);
+        return;
+    }
+    let c_path = format!("{}\0", path);
+    let mut st = Stat::default();
+    if lstat(c_path.as_str(), &mut st) != 0 {
+        println!("ls: cannot stat {}", path);
+        return;
+    }
+    let mut target = [0u8; 256];
+    let arrow = if st.is_symlink() {
+        let len = readlink(c_path.as_str(), &mut target).max(0) as usize;
+        format!(
+            " -> {}",
+            core::str::from_utf8(&target[..len]).unwrap_or("?")
+        )
+    } else {
+        String::new()
+    };
+    println!(
+        "{} {:>3} {:>3} {:>3} {:>8} {} {}{}",
+        mode_string(&st),
+        st.nlink,
+        st.uid,
+        st.gid,
+        st.size,
+        time_string(st.mtime),
+        name,
+        arrow
+    );
+}
+
+/// ls [-l] [path]...
+#[unsafe(no_mangle)]
+pub fn main(_argc: usize, argv: &[&str]) -> i32 {
+    let long = argv.get(1) == Some(&"-l");
+    let mut paths: Vec<&str> = argv
+        .iter()
+        .skip(if long { 2 } else { 1 })
+        .copied()
+        .collect();
+    if paths.is_empty() {
+        paths.push(".");
+    }
+    let mut exit_code = 0;
+    for path in paths {
+        let mut st = Stat::default();
+        if stat(format!("{}\0", path).as_str(), &mut st) != 0 {
+            println!("ls: cannot access {}", path);
+            exit_code = 1;
+            continue;
+        }
+        if !st.is_dir() {
+            print_entry(path, path, long);
+            continue;
+        }
+        for name in names(path).unwrap_or_default() {
+            print_entry(format!("{}/{}", path, name).as_str(), &name, long);
+        }
+    }
+    exit_code
+}
@@ -0,0 +1,135 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use user_lib::{
+    MmapFlags, MmapProt, OpenFlags, close, fork, mmap, mprotect, munmap, open, read, unlink, wait,
+    write,
+};
+
+const PAGE_SIZE: usize = 4096;
+
+fn anonymous(len: usize, flags: MmapFlags) -> isize {
+    mmap(
+        0,
+        len,
+        MmapProt::READ | MmapProt::WRITE,
+        flags | MmapFlags::ANONYMOUS,
+        0,
+        0,
+    )
+}
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    // anonymous private mapping is zero-filled and private after fork
+    let private = anonymous(2 * PAGE_SIZE, MmapFlags::PRIVATE);
+    assert!(private > 0);
+    let private = unsafe { core::slice::from_raw_parts_mut(private as *mut u8, 2 * PAGE_SIZE) };
+    assert!(private.iter().all(|&byte| byte == 0));
+    private[PAGE_SIZE] = 1;
+    // anonymous shared mapping is seen by the parent after fork
+    let shared = anonymous(PAGE_SIZE, MmapFlags::SHARED);
+    assert!(shared > 0);
+    let shared = unsafe { &mut *(shared as *mut usize) };
+    let pid = fork();
+    if pid == 0 {
+        private[PAGE_SIZE] = 2;
+        *shared = 42;
+        return 0;
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(pid, wait(&mut exit_code));
+    assert_eq!(private[PAGE_SIZE], 1);
+    assert_eq!(*shared, 42);
+
+    // overlapping fixed mappings and invalid arguments are rejected
+    let fixed = private.as_ptr() as usize;
+    let fixed_flags = MmapFlags::PRIVATE | MmapFlags::ANONYMOUS | MmapFlags::FIXED;
+    assert_eq!(
+        mmap(
+            fixed + PAGE_SIZE,
+            PAGE_SIZE,
+            MmapProt::READ,
+            fixed_flags,
+            0,
+            0
+        ),
+        -1
+    );
+    assert_eq!(anonymous(0, MmapFlags::PRIVATE), -1);
+    assert_eq!(anonymous(PAGE_SIZE, MmapFlags::empty()), -1);
+
+    // unmapping a part of a mapping leaves a hole
+    assert_eq!(munmap(fixed + PAGE_SIZE, PAGE_SIZE), 0);
+    assert_eq!(mprotect(fixed, 2 * PAGE_SIZE, MmapProt::READ), -1);
+    assert_eq!(
+        mmap(
+            fixed + PAGE_SIZE,
+            PAGE_SIZE,
+            MmapProt::READ,
+            fixed_flags,
+            0,
+            0
+        ),
+        (fixed + PAGE_SIZE) as isize
+    );
+    assert_eq!(mprotect(fixed, 2 * PAGE_SIZE, MmapProt::READ), 0);
+    assert_eq!(munmap(fixed, 2 * PAGE_SIZE), 0);
+
+    // file-backed mappings
+    let fd = open("mmap_file\0", OpenFlags::CREATE | OpenFlags::RDWR);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let content: Vec<u8> = (0..PAGE_SIZE + 100).map(|i| i as u8).collect();
+    write(fd, &content);
+    let file_private = mmap(
+        0,
+        2 * PAGE_SIZE,
+        MmapProt::READ | MmapProt::WRITE,
+        MmapFlags::PRIVATE,
+        fd,
+        0,
+    );
+    assert!(file_private > 0);
+    let file_private =
+        unsafe { core::slice::from_raw_parts_mut(file_private as *mut u8, 2 * PAGE_SIZE) };
+    assert_eq!(&file_private[..content.len()], content.as_slice());
+    // the rest of the last page is zero-filled
+    assert!(file_private[content.len()..].iter().all(|&byte| byte == 0));
+    file_private[0] = 0xff;
+    let file_shared = mmap(
+        0,
+        PAGE_SIZE,
+        MmapProt::READ | MmapProt::WRITE,
+        MmapFlags::SHARED,
+        fd,
+        PAGE_SIZE,
+    );
+    assert!(file_shared > 0);
+    let file_shared = unsafe { core::slice::from_raw_parts_mut(file_shared as *mut u8, 100) };
+    assert_eq!(file_shared, &content[PAGE_SIZE..]);
+    file_shared[0] = 0xee;
+    assert_eq!(munmap(file_shared.as_ptr() as usize, PAGE_SIZE), 0);
+    close(fd);
+
+    // only changes to the shared mapping reach the file
+    let fd = open("mmap_file\0", OpenFlags::RDONLY) as usize;
+    let mut buffer = [0u8; PAGE_SIZE + 100];
+    assert_eq!(read(fd, &mut buffer), (PAGE_SIZE + 100) as isize);
+    close(fd);
+    assert_eq!(buffer[0], 0);
+    assert_eq!(buffer[PAGE_SIZE], 0xee);
+    assert_eq!(unlink("mmap_file\0"), 0);
+
+    // the heap grows beyond its initial 32 KiB
+    let big: Vec<usize> = (0..0x10000).collect();
+    assert_eq!(big.iter().sum::<usize>(), 0x10000 * 0xffff / 2);
+    println!("mmap_test passed!");
+    0
+}
@@ -0,0 +1,123 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+// usage: ping [a.b.c.d] [count], the gateway is pinged by default
+
+use user_lib::{
+    AF_INET, IPPROTO_ICMP, SOCK_RAW, SockAddrIn, close, get_time, getpid, net_config, parse_ip,
+    recvfrom, sendto, sleep, socket,
+};
+
+const ICMP_ECHO_REPLY: u8 = 0;
+const ICMP_ECHO_REQUEST: u8 = 8;
+/// ICMP header, the send time and some padding.
+const ECHO_SIZE: usize = 8 + 8 + 24;
+
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data
+        .chunks(2)
+        .map(|word| u16::from_be_bytes([word[0], *word.get(1).unwrap_or(&0)]) as u32)
+        .sum();
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+fn echo_request(id: u16, seq: u16) -> [u8; ECHO_SIZE] {
+    let mut message = [0u8; ECHO_SIZE];
+    message[0] = ICMP_ECHO_REQUEST;
+    message[4..6].copy_from_slice(&id.to_be_bytes());
+    message[6..8].copy_from_slice(&seq.to_be_bytes());
+    message[8..16].copy_from_slice(&(get_time() as u64).to_be_bytes());
+    for (i, byte) in message[16..].iter_mut().enumerate() {
+        *byte = b'a' + i as u8;
+    }
+    let sum = checksum(&message);
+    message[2..4].copy_from_slice(&sum.to_be_bytes());
+    message
+}
+
+/// Wait for the reply of an echo request, return its TTL and round-trip time.
+fn wait_reply(fd: usize, id: u16, seq: u16) -> Option<(u8, isize)> {
+    let mut packet = [0u8; 1500];
+    loop {
+        let len = recvfrom(fd, &mut packet, None);
+        if len <= 0 {
+            return None;
+        }
+        let packet = &packet[..len as usize];
+        // the IPv4 header comes first
+        let header_len = (packet[0] & 0xf) as usize * 4;
+        let message = &packet[header_len..];
+        if message.len() < 16
+            || message[0] != ICMP_ECHO_REPLY
+            || message[4..6] != id.to_be_bytes()
+            || message[6..8] != seq.to_be_bytes()
+        {
+            continue;
+        }
+        let sent = u64::from_be_bytes(message[8..16].try_into().unwrap());
+        return Some((packet[8], get_time() - sent as isize));
+    }
+}
+
+#[unsafe(no_mangle)]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    let ip = if argc > 1 {
+        match parse_ip(argv[1]) {
+            Some(ip) => ip,
+            None => {
+                println!("ping: bad address {}", argv[1]);
+                return -1;
+            }
+        }
+    } else {
+        net_config().gateway
+    };
+    let count = if argc > 2 {
+        argv[2].parse::<u16>().unwrap_or(4)
+    } else {
+        4
+    };
+    let fd = socket(AF_INET, SOCK_RAW, IPPROTO_ICMP);
+    if fd < 0 {
+        println!("ping: failed to create a raw socket");
+        return -1;
+    }
+    let fd = fd as usize;
+    let target = SockAddrIn::new(ip, 0);
+    let bytes = ip.to_be_bytes();
+    println!(
+        "PING {}.{}.{}.{} {} bytes of data",
+        bytes[0],
+        bytes[1],
+        bytes[2],
+        bytes[3],
+        ECHO_SIZE - 8
+    );
+    let id = getpid() as u16;
+    let mut received = 0;
+    for seq in 0..count {
+        if sendto(fd, &echo_request(id, seq), Some(&target)) < 0 {
+            println!("ping: failed to send");
+            break;
+        }
+        if let Some((ttl, rtt)) = wait_reply(fd, id, seq) {
+            println!(
+                "{} bytes from {}.{}.{}.{}: icmp_seq={} ttl={} time={} ms",
+                ECHO_SIZE, bytes[0], bytes[1], bytes[2], bytes[3], seq, ttl, rtt
+            );
+            received += 1;
+        }
+        if seq + 1 < count {
+            sleep(1000);
+        }
+    }
+    println!("{} packets transmitted, {} received", count, received);
+    close(fd);
+    if received > 0 { 0 } else { 1 }
+}
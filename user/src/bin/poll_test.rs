@@ -0,0 +1,178 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    AF_INET, AF_UNIX, EPOLL_CTL_ADD, EPOLL_CTL_DEL, EPOLL_CTL_MOD, EpollEvent, FdSet, PollEvents,
+    PollFd, SOCK_DGRAM, SOCK_STREAM, SockAddrIn, bind, close, epoll_create, epoll_ctl, epoll_wait,
+    exit, fork, get_time, pipe, poll, read, select, sendto, sleep, socket, socketpair, waitpid,
+    write,
+};
+
+const LOOPBACK: u32 = 127 << 24 | 1;
+
+/// A pipe is ready as data and room come and go, and hung up once its
+/// write end is closed.
+fn pipe_test() {
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let [rx, tx] = fds;
+    let mut polled = [
+        PollFd::new(rx, PollEvents::IN),
+        PollFd::new(tx, PollEvents::OUT),
+    ];
+    assert_eq!(poll(&mut polled, 0), 1);
+    assert!(polled[0].revents().is_empty());
+    assert_eq!(polled[1].revents(), PollEvents::OUT);
+    assert_eq!(write(tx, b"x"), 1);
+    assert_eq!(poll(&mut polled, 0), 2);
+    assert_eq!(polled[0].revents(), PollEvents::IN);
+    let mut buf = [0u8; 1];
+    assert_eq!(read(rx, &mut buf), 1);
+
+    // nothing comes, so the timeout passes
+    let mut polled = [PollFd::new(rx, PollEvents::IN)];
+    let start = get_time();
+    assert_eq!(poll(&mut polled, 50), 0);
+    assert!(get_time() - start >= 50);
+
+    // a write of another process wakes the poll
+    let pid = fork();
+    if pid == 0 {
+        sleep(50);
+        write(tx, b"y");
+        exit(0);
+    }
+    assert_eq!(poll(&mut polled, -1), 1);
+    assert_eq!(polled[0].revents(), PollEvents::IN);
+    assert_eq!(read(rx, &mut buf), 1);
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+
+    assert_eq!(close(tx), 0);
+    assert_eq!(poll(&mut polled, -1), 1);
+    assert_eq!(polled[0].revents(), PollEvents::HUP);
+    assert_eq!(close(rx), 0);
+
+    // fds not open are reported, negative ones are skipped
+    let mut polled = [
+        PollFd::new(rx, PollEvents::IN),
+        PollFd::new(0, PollEvents::IN),
+    ];
+    polled[1].fd = -1;
+    assert_eq!(poll(&mut polled, 0), 1);
+    assert_eq!(polled[0].revents(), PollEvents::NVAL);
+    assert!(polled[1].revents().is_empty());
+}
+
+/// A datagram sent over the loopback device wakes a poll of the receiver.
+fn udp_test() {
+    let receiver = socket(AF_INET, SOCK_DGRAM, 0) as usize;
+    assert_eq!(bind(receiver, &SockAddrIn::new(LOOPBACK, 5100)), 0);
+    let mut polled = [PollFd::new(receiver, PollEvents::IN | PollEvents::OUT)];
+    assert_eq!(poll(&mut polled, 0), 1);
+    assert_eq!(polled[0].revents(), PollEvents::OUT);
+    let sender = socket(AF_INET, SOCK_DGRAM, 0) as usize;
+    assert_eq!(
+        sendto(sender, b"ping", Some(&SockAddrIn::new(LOOPBACK, 5100))),
+        4
+    );
+    let mut polled = [PollFd::new(receiver, PollEvents::IN)];
+    assert_eq!(poll(&mut polled, 1000), 1);
+    assert_eq!(polled[0].revents(), PollEvents::IN);
+    assert_eq!(close(sender), 0);
+    assert_eq!(close(receiver), 0);
+}
+
+fn select_test() {
+    let mut fds = [0usize; 2];
+    assert_eq!(socketpair(AF_UNIX, SOCK_STREAM, 0, &mut fds), 0);
+    let [a, b] = fds;
+    let nfds = a.max(b) + 1;
+    let mut readfds = FdSet::default();
+    readfds.set(a);
+    readfds.set(b);
+    let mut writefds = FdSet::default();
+    writefds.set(a);
+    assert_eq!(
+        select(nfds, Some(&mut readfds), Some(&mut writefds), None, 0),
+        1
+    );
+    assert!(!readfds.is_set(a) && !readfds.is_set(b));
+    assert!(writefds.is_set(a));
+    assert_eq!(write(a, b"hi"), 2);
+    let mut readfds = FdSet::default();
+    readfds.set(a);
+    readfds.set(b);
+    assert_eq!(select(nfds, Some(&mut readfds), None, None, -1), 1);
+    assert!(!readfds.is_set(a) && readfds.is_set(b));
+    assert_eq!(close(a), 0);
+    assert_eq!(close(b), 0);
+}
+
+fn epoll_test() {
+    let epfd = epoll_create() as usize;
+    let mut fds = [0usize; 2];
+    assert_eq!(socketpair(AF_UNIX, SOCK_DGRAM, 0, &mut fds), 0);
+    let [a, b] = fds;
+    let mut pipe_fds = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fds), 0);
+    let event = EpollEvent::new(PollEvents::IN, 1);
+    assert_eq!(epoll_ctl(epfd, EPOLL_CTL_ADD, b, Some(&event)), 0);
+    assert_eq!(epoll_ctl(epfd, EPOLL_CTL_ADD, b, Some(&event)), -1);
+    let event = EpollEvent::new(PollEvents::IN, 2);
+    assert_eq!(epoll_ctl(epfd, EPOLL_CTL_ADD, pipe_fds[0], Some(&event)), 0);
+    // an epoll instance does not watch itself, and only level-triggered
+    // notification is supported
+    assert_eq!(epoll_ctl(epfd, EPOLL_CTL_ADD, epfd, Some(&event)), -1);
+    let mut edge = EpollEvent::new(PollEvents::IN, 3);
+    edge.events |= 1 << 31;
+    assert_eq!(epoll_ctl(epfd, EPOLL_CTL_ADD, a, Some(&edge)), -1);
+
+    let mut events = [EpollEvent::default(); 4];
+    assert_eq!(epoll_wait(epfd, &mut events, 0), 0);
+    assert_eq!(write(a, b"one"), 3);
+    assert_eq!(epoll_wait(epfd, &mut events, -1), 1);
+    assert_eq!(events[0].data, 1);
+    assert_eq!(events[0].events(), PollEvents::IN);
+    // level-triggered, so it is reported until read
+    assert_eq!(epoll_wait(epfd, &mut events, 0), 1);
+    let mut buf = [0u8; 8];
+    assert_eq!(read(b, &mut buf), 3);
+    assert_eq!(epoll_wait(epfd, &mut events, 0), 0);
+
+    // the epoll instance can be polled as well
+    let mut polled = [PollFd::new(epfd, PollEvents::IN)];
+    assert_eq!(poll(&mut polled, 0), 0);
+    assert_eq!(write(pipe_fds[1], b"two"), 3);
+    assert_eq!(poll(&mut polled, -1), 1);
+    assert_eq!(epoll_wait(epfd, &mut events, -1), 1);
+    assert_eq!(events[0].data, 2);
+
+    let event = EpollEvent::new(PollEvents::OUT, 4);
+    assert_eq!(epoll_ctl(epfd, EPOLL_CTL_MOD, pipe_fds[0], Some(&event)), 0);
+    assert_eq!(epoll_wait(epfd, &mut events, 0), 0);
+    assert_eq!(epoll_ctl(epfd, EPOLL_CTL_DEL, pipe_fds[0], None), 0);
+    assert_eq!(epoll_ctl(epfd, EPOLL_CTL_DEL, pipe_fds[0], None), -1);
+
+    // closing a file drops its interest
+    assert_eq!(write(a, b"three"), 5);
+    assert_eq!(close(b), 0);
+    assert_eq!(epoll_wait(epfd, &mut events, 0), 0);
+    assert_eq!(close(a), 0);
+    assert_eq!(close(pipe_fds[0]), 0);
+    assert_eq!(close(pipe_fds[1]), 0);
+    assert_eq!(close(epfd), 0);
+}
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    pipe_test();
+    udp_test();
+    select_test();
+    epoll_test();
+    println!("poll_test passed!");
+    0
+}
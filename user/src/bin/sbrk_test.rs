@@ -0,0 +1,35 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{brk, sbrk};
+
+const PAGE_SIZE: usize = 4096;
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    let origin = brk(0);
+    assert!(origin > 0);
+    // grow by a page and a half, the new memory is zero-filled
+    assert_eq!(sbrk((PAGE_SIZE + PAGE_SIZE / 2) as isize), origin);
+    assert_eq!(brk(0), origin + (PAGE_SIZE + PAGE_SIZE / 2) as isize);
+    let heap =
+        unsafe { core::slice::from_raw_parts_mut(origin as *mut u8, PAGE_SIZE + PAGE_SIZE / 2) };
+    assert!(heap.iter().all(|&byte| byte == 0));
+    heap.fill(0x5a);
+    // shrink and grow again
+    assert_eq!(
+        sbrk(-((PAGE_SIZE / 2) as isize)),
+        origin + (PAGE_SIZE + PAGE_SIZE / 2) as isize
+    );
+    assert_eq!(sbrk(0), origin + PAGE_SIZE as isize);
+    assert_eq!(sbrk(PAGE_SIZE as isize), origin + PAGE_SIZE as isize);
+    // the break cannot move below the start of the heap
+    assert_eq!(brk(origin as usize - 1), brk(0));
+    assert_eq!(sbrk(-(4 * PAGE_SIZE as isize)), -1);
+    assert_eq!(brk(origin as usize), origin);
+    println!("sbrk_test passed!");
+    0
+}
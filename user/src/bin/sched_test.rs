@@ -0,0 +1,31 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, nice, set_priority, waitpid};
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    assert_eq!(set_priority(1), -1);
+    assert_eq!(set_priority(8), 8);
+    assert_eq!(nice(0), 0);
+    assert_eq!(nice(5), 5);
+    assert_eq!(nice(100), 19);
+    assert_eq!(nice(-100), -20);
+    assert_eq!(nice(25), 5);
+    // the forked process inherits the nice value
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(nice(0), 5);
+        assert_eq!(nice(-1), 4);
+        exit(0);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(nice(0), 5);
+    println!("sched_test passed!");
+    0
+}
@@ -0,0 +1,112 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    SIG_BLOCK, SIG_IGN, SIG_UNBLOCK, SIGINT, SIGKILL, SIGSTOP, SIGTERM, SIGUSR1, SIGUSR2,
+    SignalAction, SignalFlags, close, exit, fork, getpid, kill, pipe, read, sigaction, sigprocmask,
+    waitpid, write, yield_,
+};
+
+static CAUGHT: AtomicUsize = AtomicUsize::new(0);
+static INTERRUPTED: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn count_handler(signum: i32) {
+    assert_eq!(signum, SIGUSR1);
+    CAUGHT.fetch_add(1, Ordering::SeqCst);
+}
+
+extern "C" fn interrupt_handler(signum: i32) {
+    assert_eq!(signum, SIGINT);
+    INTERRUPTED.store(1, Ordering::SeqCst);
+}
+
+fn wait_exit_code(pid: isize) -> i32 {
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    exit_code
+}
+
+fn spin_forever() -> ! {
+    loop {
+        yield_();
+    }
+}
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    let pid = getpid() as usize;
+    // the handler runs before kill returns, and the context is restored after it
+    let action = SignalAction::new(count_handler as usize, SignalFlags::empty());
+    assert_eq!(sigaction(SIGUSR1, Some(&action), None), 0);
+    let before = [1usize, 2, 3];
+    assert_eq!(kill(pid, SIGUSR1), 0);
+    assert_eq!(CAUGHT.load(Ordering::SeqCst), 1);
+    assert_eq!(before, [1, 2, 3]);
+    let mut old_action = SignalAction::default();
+    assert_eq!(sigaction(SIGUSR1, None, Some(&mut old_action)), 0);
+    assert_eq!(old_action.handler, count_handler as usize);
+
+    // blocked signals stay pending until they are unblocked
+    assert_eq!(sigprocmask(SIG_BLOCK, Some(SignalFlags::SIGUSR1), None), 0);
+    assert_eq!(kill(pid, SIGUSR1), 0);
+    assert_eq!(CAUGHT.load(Ordering::SeqCst), 1);
+    let mut mask = SignalFlags::empty();
+    assert_eq!(
+        sigprocmask(SIG_UNBLOCK, Some(SignalFlags::SIGUSR1), Some(&mut mask)),
+        0
+    );
+    assert_eq!(mask, SignalFlags::SIGUSR1);
+    assert_eq!(CAUGHT.load(Ordering::SeqCst), 2);
+
+    // ignored signals are discarded, SIGKILL cannot be caught
+    let ignore = SignalAction::new(SIG_IGN, SignalFlags::empty());
+    assert_eq!(sigaction(SIGUSR2, Some(&ignore), None), 0);
+    assert_eq!(kill(pid, SIGUSR2), 0);
+    assert_eq!(sigaction(SIGKILL, Some(&ignore), None), -1);
+    assert_eq!(kill(pid, 64), -1);
+
+    // a process catching SIGINT cleans up and exits by itself
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let child = fork();
+    if child == 0 {
+        let action = SignalAction::new(interrupt_handler as usize, SignalFlags::empty());
+        assert_eq!(sigaction(SIGINT, Some(&action), None), 0);
+        // tell the parent that the handler is installed
+        assert_eq!(write(pipe_fd[1], b"r"), 1);
+        while INTERRUPTED.load(Ordering::SeqCst) == 0 {
+            yield_();
+        }
+        println!("child caught SIGINT and exits");
+        exit(0);
+    }
+    let mut buf = [0u8; 1];
+    assert_eq!(read(pipe_fd[0], &mut buf), 1);
+    close(pipe_fd[0]);
+    close(pipe_fd[1]);
+    assert_eq!(kill(child as usize, SIGINT), 0);
+    assert_eq!(wait_exit_code(child), 0);
+
+    // the default action of SIGTERM terminates the process
+    let child = fork();
+    if child == 0 {
+        spin_forever();
+    }
+    assert_eq!(kill(child as usize, SIGTERM), 0);
+    assert_eq!(wait_exit_code(child), -SIGTERM);
+
+    // a stopped process is still killed by SIGKILL
+    let child = fork();
+    if child == 0 {
+        spin_forever();
+    }
+    assert_eq!(kill(child as usize, SIGSTOP), 0);
+    assert_eq!(kill(child as usize, SIGKILL), 0);
+    assert_eq!(wait_exit_code(child), -SIGKILL);
+    println!("sig_test passed!");
+    0
+}
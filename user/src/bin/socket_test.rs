@@ -0,0 +1,107 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    AF_INET, IPPROTO_ICMP, SHUT_RD, SHUT_RDWR, SHUT_WR, SOCK_DGRAM, SOCK_RAW, SOCK_STREAM,
+    SockAddrIn, bind, close, connect, getpeername, getsockname, listen, net_config, read, recvfrom,
+    sendto, set_net_config, shutdown, socket,
+};
+
+const GATEWAY: u32 = 10 << 24 | 2 << 8 | 2;
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    // only stream, datagram and raw sockets of the internet domain are supported
+    assert_eq!(socket(AF_INET + 1, SOCK_STREAM, 0), -1);
+    assert_eq!(socket(AF_INET, 4, 0), -1);
+    // raw sockets are for ICMP only
+    assert_eq!(socket(AF_INET, SOCK_RAW, 0), -1);
+    // socket calls fail on other files
+    assert_eq!(bind(0, &SockAddrIn::new(0, 3000)), -1);
+
+    // a datagram socket is bound once, and a port is bound by one socket
+    let udp = socket(AF_INET, SOCK_DGRAM, 0) as usize;
+    let mut addr = SockAddrIn::default();
+    assert_eq!(getsockname(udp, &mut addr), 0);
+    assert_eq!(addr.port(), 0);
+    assert_eq!(bind(udp, &SockAddrIn::new(GATEWAY, 3000)), -1);
+    assert_eq!(bind(udp, &SockAddrIn::new(0, 3000)), 0);
+    assert_eq!(bind(udp, &SockAddrIn::new(0, 3001)), -1);
+    assert_eq!(getsockname(udp, &mut addr), 0);
+    assert_eq!(addr.port(), 3000);
+    let other = socket(AF_INET, SOCK_DGRAM, 0) as usize;
+    assert_eq!(bind(other, &SockAddrIn::new(0, 3000)), -1);
+
+    // connect sets the default destination
+    assert_eq!(getpeername(udp, &mut addr), -1);
+    assert_eq!(connect(udp, &SockAddrIn::new(GATEWAY, 9)), 0);
+    assert_eq!(getpeername(udp, &mut addr), 0);
+    assert_eq!((addr.ip(), addr.port()), (GATEWAY, 9));
+
+    // reads return at once after shutting down reading, and writes fail
+    // after shutting down writing
+    assert_eq!(shutdown(udp, SHUT_RD), 0);
+    let mut buf = [0u8; 16];
+    assert_eq!(recvfrom(udp, &mut buf, None), 0);
+    assert_eq!(read(udp, &mut buf), 0);
+    assert_eq!(shutdown(udp, SHUT_WR), 0);
+    assert_eq!(sendto(udp, b"discard", None), -1);
+
+    // sending from an unbound socket binds an ephemeral port
+    assert_eq!(sendto(other, b"discard", None), -1);
+    assert_eq!(
+        sendto(other, b"discard", Some(&SockAddrIn::new(GATEWAY, 9))),
+        7
+    );
+    assert_eq!(getsockname(other, &mut addr), 0);
+    assert!(addr.port() >= 49152);
+
+    // a raw socket has no port, and bind only checks the address
+    let raw = socket(AF_INET, SOCK_RAW, IPPROTO_ICMP) as usize;
+    assert_eq!(bind(raw, &SockAddrIn::new(0, 0)), 0);
+    assert_eq!(bind(raw, &SockAddrIn::new(GATEWAY, 0)), -1);
+    assert_eq!(getpeername(raw, &mut addr), -1);
+    assert_eq!(connect(raw, &SockAddrIn::new(GATEWAY, 0)), 0);
+    assert_eq!(getpeername(raw, &mut addr), 0);
+    assert_eq!(addr.ip(), GATEWAY);
+    assert_eq!(close(raw), 0);
+
+    // a port is listened by one socket
+    let server = socket(AF_INET, SOCK_STREAM, 0) as usize;
+    assert_eq!(bind(server, &SockAddrIn::new(0, 4000)), 0);
+    assert_eq!(listen(server, 4), 0);
+    assert_eq!(listen(server, 4), -1);
+    assert_eq!(getsockname(server, &mut addr), 0);
+    assert_eq!(addr.port(), 4000);
+    let another = socket(AF_INET, SOCK_STREAM, 0) as usize;
+    assert_eq!(bind(another, &SockAddrIn::new(0, 4000)), 0);
+    assert_eq!(listen(another, 4), -1);
+
+    // a listening socket has no peer and transfers no data
+    assert_eq!(getpeername(server, &mut addr), -1);
+    assert_eq!(shutdown(server, SHUT_RDWR), -1);
+    assert_eq!(sendto(server, b"data", None), -1);
+
+    // the port is free again once the listening socket is closed
+    assert_eq!(close(server), 0);
+    let server = socket(AF_INET, SOCK_STREAM, 0) as usize;
+    assert_eq!(bind(server, &SockAddrIn::new(0, 4000)), 0);
+    assert_eq!(listen(server, 4), 0);
+
+    // a static configuration needs a contiguous netmask and a gateway on
+    // the network, a bad one leaves the interface as it is
+    let config = net_config();
+    assert_ne!(config.mac, [0; 6]);
+    assert_eq!(set_net_config(10 << 24 | 15, 0xff00_ff00, 0), -1);
+    assert_eq!(set_net_config(10 << 24 | 15, 0xffff_ff00, GATEWAY), -1);
+    assert_eq!(net_config().ip, config.ip);
+
+    for fd in [udp, other, another, server] {
+        assert_eq!(close(fd), 0);
+    }
+    println!("socket_test passed!");
+    0
+}
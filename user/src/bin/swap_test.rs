@@ -0,0 +1,61 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{MmapFlags, MmapProt, exit, fork, mmap, munmap, waitpid};
+
+const PAGE_SIZE: usize = 4096;
+/// More than the physical memory, so that some pages must be swapped out.
+const PAGES: usize = 128 * 1024 * 1024 / PAGE_SIZE;
+const WORDS: usize = PAGE_SIZE / core::mem::size_of::<usize>();
+
+fn page(base: usize, i: usize) -> &'static mut [usize] {
+    unsafe { core::slice::from_raw_parts_mut((base + i * PAGE_SIZE) as *mut usize, WORDS) }
+}
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    let base = mmap(
+        0,
+        PAGES * PAGE_SIZE,
+        MmapProt::READ | MmapProt::WRITE,
+        MmapFlags::PRIVATE | MmapFlags::ANONYMOUS,
+        0,
+        0,
+    );
+    assert!(base > 0);
+    let base = base as usize;
+    for i in 0..PAGES {
+        let page = page(base, i);
+        page[0] = i;
+        page[WORDS - 1] = !i;
+    }
+    // the pages written last are checked first
+    for i in (0..PAGES).rev() {
+        let page = page(base, i);
+        assert_eq!(page[0], i);
+        assert_eq!(page[WORDS - 1], !i);
+        assert!(page[1..WORDS - 1].iter().all(|&word| word == 0));
+    }
+    // swapped out pages are not shared with the forked process
+    let pid = fork();
+    if pid == 0 {
+        for i in (0..PAGES).step_by(1024) {
+            let page = page(base, i);
+            assert_eq!(page[0], i);
+            page[0] = 0;
+        }
+        exit(0);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    for i in (0..PAGES).step_by(1024) {
+        assert_eq!(page(base, i)[0], i);
+    }
+    assert_eq!(munmap(base, PAGES * PAGE_SIZE), 0);
+    println!("swap_test passed!");
+    0
+}
@@ -10,7 +10,7 @@ extern crate alloc;
 
 // use http://localhost:6201/ to access the http server
 
-use user_lib::{accept, listen, read, write};
+use user_lib::{AF_INET, SOCK_STREAM, SockAddrIn, accept, bind, listen, read, socket, write};
 
 // get url from the tcp request list.
 fn get_url_from_tcp_request(req: &[u8]) -> String {
@@ -134,16 +134,29 @@ fn handle_tcp_client(client_fd: usize) -> bool {
 pub fn main() -> i32 {
     println!("This is a very simple http server");
 
-    let tcp_fd = listen(80);
+    let tcp_fd = socket(AF_INET, SOCK_STREAM, 0);
 
-    if tcp_fd < 0 {
+    if tcp_fd < 0
+        || bind(tcp_fd as usize, &SockAddrIn::new(0, 80)) < 0
+        || listen(tcp_fd as usize, 16) < 0
+    {
         println!("Failed to listen on port 80");
         return -1;
     }
 
     loop {
-        let client = accept(tcp_fd as usize);
-        println!("client connected: {}", client);
+        let mut peer = SockAddrIn::default();
+        let client = accept(tcp_fd as usize, Some(&mut peer));
+        let ip = peer.ip().to_be_bytes();
+        println!(
+            "client connected: {} from {}.{}.{}.{}:{}",
+            client,
+            ip[0],
+            ip[1],
+            ip[2],
+            ip[3],
+            peer.port()
+        );
 
         if client < 1 {
             println!("Failed to accept a client on port 80");
@@ -8,15 +8,21 @@ extern crate user_lib;
 #[macro_use]
 extern crate alloc;
 
-use user_lib::{connect, read, write};
+use user_lib::{AF_INET, SOCK_DGRAM, SockAddrIn, bind, connect, read, socket, write};
 
 #[unsafe(no_mangle)]
 pub fn main() -> i32 {
     println!("udp test open!");
 
-    let udp_fd = connect(10 << 24 | 0 << 16 | 2 << 8 | 2, 2001, 26099);
+    let udp_fd = socket(AF_INET, SOCK_DGRAM, 0);
 
-    if udp_fd < 0 {
+    if udp_fd < 0
+        || bind(udp_fd as usize, &SockAddrIn::new(0, 2001)) < 0
+        || connect(
+            udp_fd as usize,
+            &SockAddrIn::new(10 << 24 | 0 << 16 | 2 << 8 | 2, 26099),
+        ) < 0
+    {
         println!("failed to create udp connection.");
         return -1;
     }
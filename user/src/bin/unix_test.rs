@@ -0,0 +1,168 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    AF_UNIX, OpenFlags, SOCK_DGRAM, SOCK_STREAM, SockAddrUn, accept, bind, close, connect, exit,
+    fork, getpeername, getsockname, listen, open, pipe, read, recvfrom, recvmsg, sendmsg, sendto,
+    socket, socketpair, unlink, waitpid, write,
+};
+
+const STREAM_PATH: &str = "/unix_test.sock";
+const DGRAM_PATH: &str = "/unix_test.dgram";
+const CLIENT_PATH: &str = "/unix_test.client";
+
+fn remove_files() {
+    unlink("/unix_test.sock\0");
+    unlink("/unix_test.dgram\0");
+    unlink("/unix_test.client\0");
+}
+
+/// Both ends of a stream pair read what the other writes, and see the end
+/// of file once it is closed.
+fn stream_pair_test() {
+    let mut fds = [0usize; 2];
+    assert_eq!(socketpair(AF_UNIX, SOCK_STREAM, 0, &mut fds), 0);
+    let [a, b] = fds;
+    assert_eq!(write(a, b"hello"), 5);
+    assert_eq!(write(a, b" world"), 6);
+    let mut buf = [0u8; 32];
+    assert_eq!(read(b, &mut buf), 11);
+    assert_eq!(&buf[..11], b"hello world");
+    assert_eq!(write(b, b"back"), 4);
+    assert_eq!(read(a, &mut buf), 4);
+    assert_eq!(&buf[..4], b"back");
+    let mut addr = SockAddrUn::new("x");
+    assert_eq!(getpeername(a, &mut addr), 0);
+    assert_eq!(addr.path(), "");
+    assert_eq!(close(a), 0);
+    assert_eq!(read(b, &mut buf), 0);
+    assert!(write(b, b"lost") <= 0);
+    assert_eq!(close(b), 0);
+    assert_eq!(socketpair(AF_UNIX, SOCK_STREAM, 1, &mut fds), -1);
+}
+
+/// A datagram pair keeps the boundaries of messages.
+fn dgram_pair_test() {
+    let mut fds = [0usize; 2];
+    assert_eq!(socketpair(AF_UNIX, SOCK_DGRAM, 0, &mut fds), 0);
+    let [a, b] = fds;
+    assert_eq!(write(a, b"one"), 3);
+    assert_eq!(write(a, b"three"), 5);
+    let mut buf = [0u8; 32];
+    assert_eq!(read(b, &mut buf), 3);
+    assert_eq!(&buf[..3], b"one");
+    // the rest of a message which does not fit is discarded
+    assert_eq!(read(b, &mut buf[..2]), 2);
+    assert_eq!(&buf[..2], b"th");
+    assert_eq!(write(a, b"four"), 4);
+    assert_eq!(read(b, &mut buf), 4);
+    assert_eq!(&buf[..4], b"four");
+    assert_eq!(close(a), 0);
+    assert_eq!(close(b), 0);
+}
+
+/// A server bound to a path accepts a child, which passes it the write end
+/// of a pipe.
+fn bound_stream_test() {
+    let server = socket(AF_UNIX, SOCK_STREAM, 0) as usize;
+    assert_eq!(listen(server, 1), -1);
+    assert_eq!(bind(server, &SockAddrUn::new(STREAM_PATH)), 0);
+    assert_eq!(listen(server, 1), 0);
+    let mut addr = SockAddrUn::default();
+    assert_eq!(getsockname(server, &mut addr), 0);
+    assert_eq!(addr.path(), STREAM_PATH);
+    // the path is taken, and the socket file can not be opened
+    let another = socket(AF_UNIX, SOCK_STREAM, 0) as usize;
+    assert_eq!(bind(another, &SockAddrUn::new(STREAM_PATH)), -1);
+    assert_eq!(close(another), 0);
+    assert_eq!(open("/unix_test.sock\0", OpenFlags::RDONLY), -1);
+
+    let mut pipe_fds = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fds), 0);
+    let pid = fork();
+    if pid == 0 {
+        close(server);
+        close(pipe_fds[0]);
+        let client = socket(AF_UNIX, SOCK_STREAM, 0) as usize;
+        if connect(client, &SockAddrUn::new(STREAM_PATH)) != 0 {
+            exit(1);
+        }
+        if sendmsg(client, b"pipe", None, &[pipe_fds[1]]) != 4 {
+            exit(2);
+        }
+        close(pipe_fds[1]);
+        let mut buf = [0u8; 16];
+        let len = read(client, &mut buf);
+        close(client);
+        exit(if buf[..len.max(0) as usize] == *b"done" {
+            0
+        } else {
+            3
+        });
+    }
+    assert_eq!(close(pipe_fds[1]), 0);
+    let mut peer = SockAddrUn::default();
+    let connection = accept(server, Some(&mut peer));
+    assert!(connection >= 0);
+    let connection = connection as usize;
+    assert_eq!(peer.path(), "");
+    let mut buf = [0u8; 16];
+    let mut fds = [0usize; 4];
+    assert_eq!(recvmsg(connection, &mut buf, None, &mut fds), (4, 1));
+    assert_eq!(&buf[..4], b"pipe");
+    assert_eq!(write(fds[0], b"through"), 7);
+    assert_eq!(close(fds[0]), 0);
+    assert_eq!(read(pipe_fds[0], &mut buf), 7);
+    assert_eq!(&buf[..7], b"through");
+    assert_eq!(write(connection, b"done"), 4);
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(close(pipe_fds[0]), 0);
+    assert_eq!(close(connection), 0);
+    assert_eq!(close(server), 0);
+    // the socket file stays after the socket is closed
+    let again = socket(AF_UNIX, SOCK_STREAM, 0) as usize;
+    assert_eq!(bind(again, &SockAddrUn::new(STREAM_PATH)), -1);
+    assert_eq!(unlink("/unix_test.sock\0"), 0);
+    assert_eq!(bind(again, &SockAddrUn::new(STREAM_PATH)), 0);
+    assert_eq!(close(again), 0);
+}
+
+/// Datagrams sent to a bound path tell the path of the sender.
+fn bound_dgram_test() {
+    let receiver = socket(AF_UNIX, SOCK_DGRAM, 0) as usize;
+    assert_eq!(bind(receiver, &SockAddrUn::new(DGRAM_PATH)), 0);
+    let sender = socket(AF_UNIX, SOCK_DGRAM, 0) as usize;
+    assert_eq!(bind(sender, &SockAddrUn::new(CLIENT_PATH)), 0);
+    assert_eq!(sendto(sender, b"hi", Some(&SockAddrUn::new(DGRAM_PATH))), 2);
+    assert_eq!(
+        sendto(sender, b"hi", Some(&SockAddrUn::new("/nowhere"))),
+        -1
+    );
+    let mut from = SockAddrUn::default();
+    let mut buf = [0u8; 16];
+    assert_eq!(recvfrom(receiver, &mut buf, Some(&mut from)), 2);
+    assert_eq!(&buf[..2], b"hi");
+    assert_eq!(from.path(), CLIENT_PATH);
+    assert_eq!(sendto(receiver, b"ok", Some(&from)), 2);
+    assert_eq!(recvfrom(sender, &mut buf, None), 2);
+    assert_eq!(&buf[..2], b"ok");
+    assert_eq!(close(sender), 0);
+    assert_eq!(close(receiver), 0);
+}
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    remove_files();
+    stream_pair_test();
+    dgram_pair_test();
+    bound_stream_test();
+    bound_dgram_test();
+    remove_files();
+    println!("unix_test passed!");
+    0
+}
@@ -16,7 +16,7 @@ const LINE_START: &str = ">> ";
 use alloc::string::String;
 use alloc::vec::Vec;
 use user_lib::console::getchar;
-use user_lib::{OpenFlags, close, dup, exec, fork, open, pipe, waitpid};
+use user_lib::{OpenFlags, chdir, close, dup, exec, fork, getcwd, open, pipe, waitpid};
 
 #[derive(Debug)]
 struct ProcessArguments {
@@ -108,7 +108,26 @@ pub fn main() -> i32 {
                     if process_arguments_list.len() == 1 {
                         valid = true;
                     }
-                    if !valid {
+                    // `cd` must run in the shell itself to change its working directory
+                    let cd_target = match process_arguments_list.as_slice() {
+                        [process_args]
+                            if process_args.args_copy.first().map(|arg| arg.as_str())
+                                == Some("cd\0") =>
+                        {
+                            Some(
+                                process_args
+                                    .args_copy
+                                    .get(1)
+                                    .map_or("/\0", |arg| arg.as_str()),
+                            )
+                        }
+                        _ => None,
+                    };
+                    if let Some(target) = cd_target {
+                        if chdir(target) == -1 {
+                            println!("cd: {}: No such directory", target.trim_end_matches('\0'));
+                        }
+                    } else if !valid {
                         println!("Invalid command: Inputs/Outputs cannot be correctly binded!");
                     } else {
                         // create pipes
@@ -172,8 +191,29 @@ pub fn main() -> i32 {
                                     close(pipe_fd[0]);
                                     close(pipe_fd[1]);
                                 }
-                                // execute new application
-                                if exec(args_copy[0].as_str(), args_addr.as_slice()) == -1 {
+                                if args_copy[0].as_str() == "pwd\0" {
+                                    let mut cwd = [0u8; 256];
+                                    let len = getcwd(&mut cwd);
+                                    if len == -1 {
+                                        println!("pwd: path too long");
+                                        return -4;
+                                    }
+                                    println!(
+                                        "{}",
+                                        core::str::from_utf8(&cwd[..len as usize]).unwrap()
+                                    );
+                                    return 0;
+                                }
+                                // execute new application, bare names are also
+                                // looked up in the root directory
+                                let app = args_copy[0].as_str();
+                                if exec(app, args_addr.as_slice()) == -1
+                                    && (app.contains('/')
+                                        || exec(
+                                            (String::from("/") + app).as_str(),
+                                            args_addr.as_slice(),
+                                        ) == -1)
+                                {
                                     println!("Error when executing!");
                                     return -4;
                                 }
@@ -10,12 +10,30 @@ extern crate user_lib;
 // item of TESTS : app_name(argv_0), argv_1, argv_2, argv_3, exit_code
 static SUCC_TESTS: &[(&str, &str, &str, &str, i32)] = &[
     ("filetest_simple\0", "\0", "\0", "\0", 0),
+    ("filetest_dir\0", "\0", "\0", "\0", 0),
+    ("filetest_cwd\0", "\0", "\0", "\0", 0),
+    ("filetest_seek\0", "\0", "\0", "\0", 0),
+    ("filetest_stat\0", "\0", "\0", "\0", 0),
+    ("filetest_link\0", "\0", "\0", "\0", 0),
+    ("filetest_truncate\0", "\0", "\0", "\0", 0),
+    ("ls\0", "-l\0", "/\0", "\0", 0),
     ("cat\0", "filea\0", "\0", "\0", 0),
     ("cmdline_args\0", "1\0", "2\0", "3\0", 0),
     ("eisenberg\0", "\0", "\0", "\0", 0),
     ("exit\0", "\0", "\0", "\0", 0),
     ("fantastic_text\0", "\0", "\0", "\0", 0),
     ("forktest_simple\0", "\0", "\0", "\0", 0),
+    ("forktest_cow\0", "\0", "\0", "\0", 0),
+    ("mmap_test\0", "\0", "\0", "\0", 0),
+    ("sbrk_test\0", "\0", "\0", "\0", 0),
+    ("swap_test\0", "\0", "\0", "\0", 0),
+    ("sched_test\0", "\0", "\0", "\0", 0),
+    ("sig_test\0", "\0", "\0", "\0", 0),
+    ("wait_test\0", "\0", "\0", "\0", 0),
+    ("socket_test\0", "\0", "\0", "\0", 0),
+    ("loopback_test\0", "\0", "\0", "\0", 0),
+    ("unix_test\0", "\0", "\0", "\0", 0),
+    ("poll_test\0", "\0", "\0", "\0", 0),
     ("forktest\0", "\0", "\0", "\0", 0),
     ("forktest2\0", "\0", "\0", "\0", 0),
     ("forktree\0", "\0", "\0", "\0", 0),
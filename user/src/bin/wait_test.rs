@@ -0,0 +1,83 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    SIGCHLD, SIGCONT, SIGKILL, SIGSTOP, SignalAction, SignalFlags, WCONTINUED, WNOHANG, WUNTRACED,
+    close, exit, fork, kill, pipe, read, sigaction, sleep, waitpid, waitpid_options, wexitstatus,
+    wifcontinued, wifexited, wifsignaled, wifstopped, write, wstopsig, wtermsig, yield_,
+};
+
+static CHILD_SIGNALS: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn child_handler(signum: i32) {
+    assert_eq!(signum, SIGCHLD);
+    CHILD_SIGNALS.fetch_add(1, Ordering::SeqCst);
+}
+
+fn spin_forever() -> ! {
+    loop {
+        yield_();
+    }
+}
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    let action = SignalAction::new(child_handler as usize, SignalFlags::empty());
+    assert_eq!(sigaction(SIGCHLD, Some(&action), None), 0);
+
+    // the parent sleeps until the child exits, and SIGCHLD is posted to it
+    let child = fork();
+    if child == 0 {
+        sleep(50);
+        exit(7);
+    }
+    let mut status: i32 = 0;
+    assert_eq!(waitpid_options(child, &mut status, 0), child);
+    assert!(wifexited(status) && !wifsignaled(status));
+    assert_eq!(wexitstatus(status), 7);
+    assert!(CHILD_SIGNALS.load(Ordering::SeqCst) >= 1);
+
+    // WNOHANG returns at once while the child is running
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let child = fork();
+    if child == 0 {
+        close(pipe_fd[1]);
+        let mut buf = [0u8; 1];
+        assert_eq!(read(pipe_fd[0], &mut buf), 1);
+        exit(-3);
+    }
+    close(pipe_fd[0]);
+    assert_eq!(waitpid_options(child, &mut status, WNOHANG), 0);
+    assert_eq!(write(pipe_fd[1], b"x"), 1);
+    close(pipe_fd[1]);
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(child as usize, &mut exit_code), child);
+    assert_eq!(exit_code, -3);
+
+    // stops and continues are reported with WUNTRACED and WCONTINUED
+    let child = fork();
+    if child == 0 {
+        spin_forever();
+    }
+    assert_eq!(kill(child as usize, SIGSTOP), 0);
+    assert_eq!(waitpid_options(child, &mut status, WUNTRACED), child);
+    assert!(wifstopped(status));
+    assert_eq!(wstopsig(status), SIGSTOP);
+    assert_eq!(kill(child as usize, SIGCONT), 0);
+    assert_eq!(waitpid_options(child, &mut status, WCONTINUED), child);
+    assert!(wifcontinued(status));
+    assert_eq!(kill(child as usize, SIGKILL), 0);
+    assert_eq!(waitpid_options(child, &mut status, WUNTRACED), child);
+    assert!(wifsignaled(status));
+    assert_eq!(wtermsig(status), SIGKILL);
+
+    // there is no child left
+    assert_eq!(waitpid_options(-1, &mut status, WNOHANG), -1);
+    println!("wait_test passed!");
+    0
+}
@@ -10,6 +10,16 @@ bitflags! {
     }
 }
 
+/// Make `unlink` remove a directory, as `rmdir` does.
+const AT_REMOVEDIR: u32 = 0x200;
+/// Make `stat` tell of a symlink itself, as `lstat` does.
+const AT_SYMLINK_NOFOLLOW: u32 = 0x100;
+
+/// Where the offset given to `lseek` counts from.
+pub const SEEK_SET: usize = 0;
+pub const SEEK_CUR: usize = 1;
+pub const SEEK_END: usize = 2;
+
 pub fn dup(fd: usize) -> isize {
     sys_dup(fd)
 }
@@ -28,3 +38,258 @@ pub fn read(fd: usize, buf: &mut [u8]) -> isize {
 pub fn write(fd: usize, buf: &[u8]) -> isize {
     sys_write(fd, buf)
 }
+/// Move the offset of a file and return it. Pipes, sockets and stdin
+/// are not seekable.
+pub fn lseek(fd: usize, offset: isize, whence: usize) -> isize {
+    sys_lseek(fd, offset, whence)
+}
+/// Read at `offset` without moving the offset of the file.
+pub fn pread(fd: usize, buf: &mut [u8], offset: usize) -> isize {
+    sys_pread(fd, buf, offset)
+}
+/// Write at `offset` without moving the offset of the file.
+pub fn pwrite(fd: usize, buf: &[u8], offset: usize) -> isize {
+    sys_pwrite(fd, buf, offset)
+}
+/// Shrink or grow the file at `path` to `len` bytes, the part grown reads
+/// zeros.
+pub fn truncate(path: &str, len: isize) -> isize {
+    sys_truncate(path, len)
+}
+/// Like `truncate`, on a file opened for writing.
+pub fn ftruncate(fd: usize, len: isize) -> isize {
+    sys_ftruncate(fd, len)
+}
+/// Store the metadata of the file at `path`, symlinks are followed.
+pub fn stat(path: &str, st: &mut Stat) -> isize {
+    sys_stat(path, st, 0)
+}
+/// Store the metadata of the file at `path`, or of the symlink there.
+pub fn lstat(path: &str, st: &mut Stat) -> isize {
+    sys_stat(path, st, AT_SYMLINK_NOFOLLOW)
+}
+/// Store the metadata of `fd`, a pipe, socket or console only tells
+/// its type.
+pub fn fstat(fd: usize, st: &mut Stat) -> isize {
+    sys_fstat(fd, st)
+}
+pub fn mkdir(path: &str) -> isize {
+    sys_mkdir(path)
+}
+pub fn unlink(path: &str) -> isize {
+    sys_unlink(path, 0)
+}
+pub fn rmdir(path: &str) -> isize {
+    sys_unlink(path, AT_REMOVEDIR)
+}
+/// Add `new_path` referring to the same file as `old_path`, which may not
+/// be a directory.
+pub fn link(old_path: &str, new_path: &str) -> isize {
+    sys_link(old_path, new_path)
+}
+/// Create a symlink at `path` to `target`, a relative target is followed
+/// from the directory holding the link.
+pub fn symlink(target: &str, path: &str) -> isize {
+    sys_symlink(target, path)
+}
+/// Store the target of the symlink at `path` to `buf`, without a
+/// terminating nul, and return its length.
+pub fn readlink(path: &str, buf: &mut [u8]) -> isize {
+    sys_readlink(path, buf)
+}
+pub fn rename(old_path: &str, new_path: &str) -> isize {
+    sys_rename(old_path, new_path)
+}
+pub fn chdir(path: &str) -> isize {
+    sys_chdir(path)
+}
+pub fn getcwd(buf: &mut [u8]) -> isize {
+    sys_getcwd(buf)
+}
+
+/// The file types in `Stat::mode`, above the permission bits.
+pub const S_IFMT: u32 = 0o170000;
+pub const S_IFIFO: u32 = 0o010000;
+pub const S_IFCHR: u32 = 0o020000;
+pub const S_IFDIR: u32 = 0o040000;
+pub const S_IFREG: u32 = 0o100000;
+pub const S_IFLNK: u32 = 0o120000;
+pub const S_IFSOCK: u32 = 0o140000;
+
+/// `struct stat`, times are in seconds since the epoch and `blocks`
+/// counts 512-byte blocks.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Stat {
+    pub dev: u64,
+    pub ino: u64,
+    pub mode: u32,
+    pub nlink: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub rdev: u64,
+    __pad1: u64,
+    pub size: i64,
+    pub blksize: u32,
+    __pad2: u32,
+    pub blocks: u64,
+    pub atime: i64,
+    pub atime_nsec: u64,
+    pub mtime: i64,
+    pub mtime_nsec: u64,
+    pub ctime: i64,
+    pub ctime_nsec: u64,
+    __unused: [u32; 2],
+}
+
+impl Stat {
+    pub fn file_type(&self) -> u32 {
+        self.mode & S_IFMT
+    }
+    pub fn is_dir(&self) -> bool {
+        self.file_type() == S_IFDIR
+    }
+    pub fn is_file(&self) -> bool {
+        self.file_type() == S_IFREG
+    }
+    pub fn is_symlink(&self) -> bool {
+        self.file_type() == S_IFLNK
+    }
+    /// The permission bits.
+    pub fn permissions(&self) -> u32 {
+        self.mode & 0o777
+    }
+}
+
+bitflags! {
+    /// The events of `poll` and `epoll_wait`.
+    pub struct PollEvents: u16 {
+        const IN = 1 << 0;
+        const PRI = 1 << 1;
+        const OUT = 1 << 2;
+        /// reported whether it is asked or not
+        const ERR = 1 << 3;
+        /// reported whether it is asked or not
+        const HUP = 1 << 4;
+        /// the fd is not open
+        const NVAL = 1 << 5;
+    }
+}
+
+/// `struct pollfd`, a negative fd is skipped.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct PollFd {
+    pub fd: i32,
+    pub events: u16,
+    pub revents: u16,
+}
+
+impl PollFd {
+    pub fn new(fd: usize, events: PollEvents) -> Self {
+        Self {
+            fd: fd as i32,
+            events: events.bits(),
+            revents: 0,
+        }
+    }
+    /// The events ready, set by `poll`.
+    pub fn revents(&self) -> PollEvents {
+        PollEvents::from_bits_truncate(self.revents)
+    }
+}
+
+/// Wait until some of `fds` are ready, for at most `timeout_ms`, or
+/// forever if it is negative. Return the number of fds ready and set
+/// their `revents`, 0 once the timeout passes.
+pub fn poll(fds: &mut [PollFd], timeout_ms: isize) -> isize {
+    sys_poll(fds, timeout_ms)
+}
+
+/// A set of the fds below 1024 for `select`.
+#[derive(Clone, Copy, Default)]
+pub struct FdSet([u64; 16]);
+
+impl FdSet {
+    pub fn set(&mut self, fd: usize) {
+        self.0[fd / 64] |= 1 << (fd % 64);
+    }
+    pub fn clear(&mut self, fd: usize) {
+        self.0[fd / 64] &= !(1 << (fd % 64));
+    }
+    pub fn is_set(&self, fd: usize) -> bool {
+        self.0[fd / 64] & (1 << (fd % 64)) != 0
+    }
+}
+
+fn fd_set_ptr(set: Option<&mut FdSet>) -> *mut u64 {
+    set.map_or(core::ptr::null_mut(), |set| set.0.as_mut_ptr())
+}
+
+/// Wait until some fds below `nfds` in the sets are ready to be read,
+/// written or have exceptional conditions, for at most `timeout_ms`, or
+/// forever if it is negative. The sets keep only the fds ready, and
+/// their number is returned.
+pub fn select(
+    nfds: usize,
+    readfds: Option<&mut FdSet>,
+    writefds: Option<&mut FdSet>,
+    exceptfds: Option<&mut FdSet>,
+    timeout_ms: isize,
+) -> isize {
+    sys_select(
+        nfds,
+        fd_set_ptr(readfds),
+        fd_set_ptr(writefds),
+        fd_set_ptr(exceptfds),
+        timeout_ms,
+    )
+}
+
+pub const EPOLL_CTL_ADD: usize = 1;
+pub const EPOLL_CTL_DEL: usize = 2;
+pub const EPOLL_CTL_MOD: usize = 3;
+
+/// `struct epoll_event`, `data` is given back as it is.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct EpollEvent {
+    pub events: u32,
+    pub data: u64,
+}
+
+impl EpollEvent {
+    pub fn new(events: PollEvents, data: u64) -> Self {
+        Self {
+            events: events.bits() as u32,
+            data,
+        }
+    }
+    pub fn events(&self) -> PollEvents {
+        PollEvents::from_bits_truncate(self.events as u16)
+    }
+}
+
+impl Default for EpollEvent {
+    fn default() -> Self {
+        Self::new(PollEvents::empty(), 0)
+    }
+}
+
+pub fn epoll_create() -> isize {
+    sys_epoll_create(0)
+}
+/// Only level-triggered notification is supported.
+pub fn epoll_ctl(epfd: usize, op: usize, fd: usize, event: Option<&EpollEvent>) -> isize {
+    sys_epoll_ctl(
+        epfd,
+        op,
+        fd,
+        event.map_or(core::ptr::null(), |event| event as *const EpollEvent),
+    )
+}
+/// Wait for events as `poll` does, and store at most `events.len()` of
+/// them. Return their number.
+pub fn epoll_wait(epfd: usize, events: &mut [EpollEvent], timeout_ms: isize) -> isize {
+    sys_epoll_wait(epfd, events, timeout_ms)
+}
@@ -66,6 +66,12 @@ impl DrawTarget for Display {
     }
 }
 
+/// Open the events of the keyboard and the mouse as a file, each read
+/// gives whole events of 8 bytes, which `InputEvent::from` decodes.
+pub fn event_open() -> isize {
+    sys_event_open()
+}
+
 pub fn event_get() -> Option<InputEvent> {
     let raw_value = sys_event_get();
     if raw_value == 0 {
@@ -7,6 +7,7 @@ pub mod console;
 mod file;
 mod io;
 mod lang_items;
+mod mm;
 mod net;
 mod sync;
 mod syscall;
@@ -16,23 +17,56 @@ extern crate alloc;
 #[macro_use]
 extern crate bitflags;
 
-use core::ptr::addr_of_mut;
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::{NonNull, addr_of_mut};
 
 use alloc::vec::Vec;
 use buddy_system_allocator::LockedHeap;
 pub use file::*;
 pub use io::*;
+pub use mm::*;
 pub use net::*;
 pub use sync::*;
 use syscall::*;
 pub use task::*;
 
 const USER_HEAP_SIZE: usize = 32768;
+/// The heap maps at least this many bytes once it runs out of space.
+const USER_HEAP_GROW_SIZE: usize = 0x10000;
 
 static mut HEAP_SPACE: [u8; USER_HEAP_SIZE] = [0; USER_HEAP_SIZE];
 
+/// Start from `HEAP_SPACE` and grow by moving the program break on demand.
+struct GrowableHeap(LockedHeap);
+
+unsafe impl GlobalAlloc for GrowableHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut heap = self.0.lock();
+        if let Ok(ptr) = heap.alloc(layout) {
+            return ptr.as_ptr();
+        }
+        // a buddy block fitting the layout surely lies in twice its size
+        let size =
+            (layout.size().max(layout.align()).next_power_of_two() * 2).max(USER_HEAP_GROW_SIZE);
+        let start = sbrk(size as isize);
+        if start == -1 {
+            return core::ptr::null_mut();
+        }
+        unsafe {
+            heap.add_to_heap(start as usize, start as usize + size);
+        }
+        heap.alloc(layout)
+            .map_or(core::ptr::null_mut(), |ptr| ptr.as_ptr())
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.0
+            .lock()
+            .dealloc(unsafe { NonNull::new_unchecked(ptr) }, layout);
+    }
+}
+
 #[global_allocator]
-static HEAP: LockedHeap = LockedHeap::empty();
+static HEAP: GrowableHeap = GrowableHeap(LockedHeap::empty());
 
 #[alloc_error_handler]
 pub fn handle_alloc_error(layout: core::alloc::Layout) -> ! {
@@ -43,7 +77,8 @@ pub fn handle_alloc_error(layout: core::alloc::Layout) -> ! {
 #[unsafe(link_section = ".text.entry")]
 pub extern "C" fn _start(argc: usize, argv: usize) -> ! {
     unsafe {
-        HEAP.lock()
+        HEAP.0
+            .lock()
             .init(addr_of_mut!(HEAP_SPACE) as usize, USER_HEAP_SIZE);
     }
     let mut v: Vec<&'static str> = Vec::new();
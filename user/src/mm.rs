@@ -0,0 +1,49 @@
+use super::*;
+
+bitflags! {
+    pub struct MmapProt: usize {
+        const READ = 1 << 0;
+        const WRITE = 1 << 1;
+        const EXEC = 1 << 2;
+    }
+}
+
+bitflags! {
+    pub struct MmapFlags: usize {
+        const SHARED = 0x01;
+        const PRIVATE = 0x02;
+        const FIXED = 0x10;
+        const ANONYMOUS = 0x20;
+    }
+}
+
+/// Return the start address of the mapping or -1.
+pub fn mmap(
+    addr: usize,
+    len: usize,
+    prot: MmapProt,
+    flags: MmapFlags,
+    fd: usize,
+    offset: usize,
+) -> isize {
+    sys_mmap(addr, len, prot.bits, flags.bits, fd, offset)
+}
+pub fn munmap(addr: usize, len: usize) -> isize {
+    sys_munmap(addr, len)
+}
+pub fn mprotect(addr: usize, len: usize, prot: MmapProt) -> isize {
+    sys_mprotect(addr, len, prot.bits)
+}
+/// Move the program break to `addr`, return the new break or the current one if `addr` is 0.
+pub fn brk(addr: usize) -> isize {
+    sys_brk(addr)
+}
+/// Move the program break by `increment` bytes, return the old break or -1.
+pub fn sbrk(increment: isize) -> isize {
+    let old_brk = sys_brk(0);
+    let new_brk = old_brk + increment;
+    if increment != 0 && sys_brk(new_brk as usize) != new_brk {
+        return -1;
+    }
+    old_brk
+}
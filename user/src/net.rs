@@ -1,13 +1,323 @@
 use super::*;
 
-pub fn connect(ip: u32, sport: u16, dport: u16) -> isize {
-    sys_connect(ip, sport, dport)
+pub const AF_UNIX: usize = 1;
+pub const AF_INET: usize = 2;
+pub const SOCK_STREAM: usize = 1;
+pub const SOCK_DGRAM: usize = 2;
+pub const SOCK_RAW: usize = 3;
+pub const IPPROTO_ICMP: usize = 1;
+pub const SHUT_RD: usize = 0;
+pub const SHUT_WR: usize = 1;
+pub const SHUT_RDWR: usize = 2;
+pub const SOL_SOCKET: i32 = 1;
+pub const SCM_RIGHTS: i32 = 1;
+/// Set by `recvmsg` if some files passed did not fit.
+pub const MSG_CTRUNC: i32 = 8;
+
+/// A socket address of some family, which the kernel reads or stores.
+pub trait SockAddr {
+    fn addr_len(&self) -> usize;
+}
+
+/// `struct sockaddr_in`, the port and the address are in network byte order.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SockAddrIn {
+    pub family: u16,
+    pub port: u16,
+    pub addr: u32,
+    pub zero: [u8; 8],
+}
+
+impl SockAddrIn {
+    /// `ip` is like `10 << 24 | 2` for 10.0.0.2, in host byte order as `port`.
+    pub fn new(ip: u32, port: u16) -> Self {
+        Self {
+            family: AF_INET as u16,
+            port: port.to_be(),
+            addr: ip.to_be(),
+            zero: [0; 8],
+        }
+    }
+    pub fn ip(&self) -> u32 {
+        u32::from_be(self.addr)
+    }
+    pub fn port(&self) -> u16 {
+        u16::from_be(self.port)
+    }
+}
+
+impl SockAddr for SockAddrIn {
+    fn addr_len(&self) -> usize {
+        core::mem::size_of::<Self>()
+    }
+}
+
+/// `struct sockaddr_un`, the path is ended by 0. An unnamed socket has an
+/// empty path.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SockAddrUn {
+    pub family: u16,
+    pub path: [u8; 108],
+}
+
+impl SockAddrUn {
+    /// The path is truncated to 107 bytes.
+    pub fn new(path: &str) -> Self {
+        let mut addr = Self::default();
+        let len = path.len().min(addr.path.len() - 1);
+        addr.path[..len].copy_from_slice(&path.as_bytes()[..len]);
+        addr
+    }
+    pub fn path(&self) -> &str {
+        let len = self
+            .path
+            .iter()
+            .position(|&byte| byte == 0)
+            .unwrap_or(self.path.len());
+        core::str::from_utf8(&self.path[..len]).unwrap_or("")
+    }
+}
+
+impl Default for SockAddrUn {
+    fn default() -> Self {
+        Self {
+            family: AF_UNIX as u16,
+            path: [0; 108],
+        }
+    }
+}
+
+impl SockAddr for SockAddrUn {
+    fn addr_len(&self) -> usize {
+        core::mem::size_of::<Self>()
+    }
+}
+
+/// Parse an address like "10.0.2.15" to host byte order.
+pub fn parse_ip(s: &str) -> Option<u32> {
+    let mut ip = 0u32;
+    let mut parts = 0;
+    for part in s.split('.') {
+        ip = ip << 8 | part.parse::<u8>().ok()? as u32;
+        parts += 1;
+    }
+    (parts == 4).then_some(ip)
+}
+
+fn addr_in(addr: &dyn SockAddr) -> (*const u8, usize) {
+    (addr as *const dyn SockAddr as *const u8, addr.addr_len())
+}
+
+/// Where the kernel stores an address and its size, null if it is not wanted.
+fn addr_out(addr: Option<&mut dyn SockAddr>) -> (*mut u8, u32) {
+    addr.map_or((core::ptr::null_mut(), 0), |addr| {
+        let len = addr.addr_len() as u32;
+        (addr as *mut dyn SockAddr as *mut u8, len)
+    })
+}
+
+/// `protocol` is 0 for the default one of the type, raw sockets must be
+/// given `IPPROTO_ICMP`.
+pub fn socket(domain: usize, socket_type: usize, protocol: usize) -> isize {
+    sys_socket(domain, socket_type, protocol)
+}
+/// Create a pair of connected Unix sockets, their fds are stored to `fds`.
+pub fn socketpair(
+    domain: usize,
+    socket_type: usize,
+    protocol: usize,
+    fds: &mut [usize; 2],
+) -> isize {
+    let mut sv = [0i32; 2];
+    let ret = sys_socketpair(domain, socket_type, protocol, &mut sv);
+    if ret == 0 {
+        *fds = [sv[0] as usize, sv[1] as usize];
+    }
+    ret
+}
+pub fn bind(fd: usize, addr: &dyn SockAddr) -> isize {
+    let (addr, addrlen) = addr_in(addr);
+    sys_bind(fd, addr, addrlen)
+}
+pub fn listen(fd: usize, backlog: usize) -> isize {
+    sys_listen(fd, backlog)
+}
+/// Wait for a connection, return its fd and store the peer address to `addr`.
+pub fn accept(fd: usize, addr: Option<&mut dyn SockAddr>) -> isize {
+    let (addr, mut addrlen) = addr_out(addr);
+    sys_accept(fd, addr, &mut addrlen)
+}
+pub fn connect(fd: usize, addr: &dyn SockAddr) -> isize {
+    let (addr, addrlen) = addr_in(addr);
+    sys_connect(fd, addr, addrlen)
+}
+/// Send to `addr`, or to the connected peer if it is None.
+pub fn sendto(fd: usize, buf: &[u8], addr: Option<&dyn SockAddr>) -> isize {
+    let (addr, addrlen) = addr.map_or((core::ptr::null(), 0), addr_in);
+    sys_sendto(fd, buf, 0, addr, addrlen)
+}
+/// Receive data and store the address of the sender to `addr`.
+pub fn recvfrom(fd: usize, buf: &mut [u8], addr: Option<&mut dyn SockAddr>) -> isize {
+    let (addr, mut addrlen) = addr_out(addr);
+    sys_recvfrom(fd, buf, 0, addr, &mut addrlen)
+}
+pub fn shutdown(fd: usize, how: usize) -> isize {
+    sys_shutdown(fd, how)
+}
+pub fn getsockname(fd: usize, addr: &mut dyn SockAddr) -> isize {
+    let (addr, mut addrlen) = addr_out(Some(addr));
+    sys_getsockname(fd, addr, &mut addrlen)
+}
+pub fn getpeername(fd: usize, addr: &mut dyn SockAddr) -> isize {
+    let (addr, mut addrlen) = addr_out(Some(addr));
+    sys_getpeername(fd, addr, &mut addrlen)
+}
+
+/// `struct msghdr`.
+#[repr(C)]
+pub struct MsgHdr {
+    pub name: *mut u8,
+    pub namelen: u32,
+    pub iov: *const IoVec,
+    pub iovlen: usize,
+    pub control: *mut u8,
+    pub controllen: usize,
+    pub flags: i32,
 }
 
-pub fn listen(sport: u16) -> isize {
-    sys_listen(sport)
+/// `struct iovec`.
+#[repr(C)]
+pub struct IoVec {
+    pub base: *mut u8,
+    pub len: usize,
 }
 
-pub fn accept(socket_fd: usize) -> isize {
-    sys_accept(socket_fd)
+/// `struct cmsghdr`, followed by the fds of an SCM_RIGHTS message.
+#[repr(C)]
+struct CmsgHdr {
+    len: usize,
+    level: i32,
+    cmsg_type: i32,
+}
+
+/// Files passed by a message at most.
+const MAX_PASSED_FILES: usize = 16;
+
+/// A control buffer which holds an SCM_RIGHTS message of some fds.
+#[repr(C)]
+struct Rights {
+    header: CmsgHdr,
+    fds: [i32; MAX_PASSED_FILES],
+}
+
+/// Send `buf` to `addr` or to the connected peer, and pass the files of `fds`
+/// along, at most 16 of them.
+pub fn sendmsg(fd: usize, buf: &[u8], addr: Option<&dyn SockAddr>, fds: &[usize]) -> isize {
+    if fds.len() > MAX_PASSED_FILES {
+        return -1;
+    }
+    let (name, namelen) = addr.map_or((core::ptr::null(), 0), addr_in);
+    let iov = IoVec {
+        base: buf.as_ptr() as *mut u8,
+        len: buf.len(),
+    };
+    let mut rights = Rights {
+        header: CmsgHdr {
+            len: core::mem::size_of::<CmsgHdr>() + 4 * fds.len(),
+            level: SOL_SOCKET,
+            cmsg_type: SCM_RIGHTS,
+        },
+        fds: [0; MAX_PASSED_FILES],
+    };
+    for (dst, fd) in rights.fds.iter_mut().zip(fds) {
+        *dst = *fd as i32;
+    }
+    let msg = MsgHdr {
+        name: name as *mut u8,
+        namelen: namelen as u32,
+        iov: &iov,
+        iovlen: 1,
+        control: if fds.is_empty() {
+            core::ptr::null_mut()
+        } else {
+            &mut rights as *mut Rights as *mut u8
+        },
+        controllen: rights.header.len,
+        flags: 0,
+    };
+    sys_sendmsg(fd, &msg, 0)
+}
+/// Receive into `buf` and store the address of the sender to `addr`. The
+/// files passed along get new fds, which are stored to `fds`. Return the
+/// length received and the number of fds, which is `usize::MAX` if some
+/// files did not fit in `fds`.
+pub fn recvmsg(
+    fd: usize,
+    buf: &mut [u8],
+    addr: Option<&mut dyn SockAddr>,
+    fds: &mut [usize],
+) -> (isize, usize) {
+    let (name, namelen) = addr_out(addr);
+    let iov = IoVec {
+        base: buf.as_mut_ptr(),
+        len: buf.len(),
+    };
+    let room = fds.len().min(MAX_PASSED_FILES);
+    let mut rights = Rights {
+        header: CmsgHdr {
+            len: 0,
+            level: 0,
+            cmsg_type: 0,
+        },
+        fds: [0; MAX_PASSED_FILES],
+    };
+    let mut msg = MsgHdr {
+        name,
+        namelen,
+        iov: &iov,
+        iovlen: 1,
+        control: &mut rights as *mut Rights as *mut u8,
+        controllen: core::mem::size_of::<CmsgHdr>() + 4 * room,
+        flags: 0,
+    };
+    let len = sys_recvmsg(fd, &mut msg, 0);
+    if len < 0 {
+        return (len, 0);
+    }
+    let count = if msg.controllen == 0 {
+        0
+    } else {
+        (rights.header.len - core::mem::size_of::<CmsgHdr>()) / 4
+    };
+    for (dst, fd) in fds.iter_mut().zip(&rights.fds[..count]) {
+        *dst = *fd as usize;
+    }
+    if msg.flags & MSG_CTRUNC != 0 {
+        (len, usize::MAX)
+    } else {
+        (len, count)
+    }
+}
+
+/// The configuration of the interface, the addresses are in host byte order.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NetConfig {
+    pub ip: u32,
+    pub netmask: u32,
+    pub gateway: u32,
+    pub mac: [u8; 6],
+}
+
+pub fn net_config() -> NetConfig {
+    let mut config = NetConfig::default();
+    sys_net_config_get(&mut config as *mut NetConfig as *mut u8);
+    config
+}
+/// Configure the interface statically, a gateway of 0 means none.
+/// The address 0 asks for one by DHCP instead.
+pub fn set_net_config(ip: u32, netmask: u32, gateway: u32) -> isize {
+    sys_net_config_set(ip, netmask, gateway)
 }
@@ -1,24 +1,65 @@
+use super::{EpollEvent, MsgHdr, PollFd, SignalAction, Stat};
+
+const SYSCALL_GETCWD: usize = 17;
+const SYSCALL_EPOLL_CREATE: usize = 20;
+const SYSCALL_EPOLL_CTL: usize = 21;
+const SYSCALL_EPOLL_WAIT: usize = 22;
 const SYSCALL_DUP: usize = 24;
-const SYSCALL_CONNECT: usize = 29;
-const SYSCALL_LISTEN: usize = 30;
-const SYSCALL_ACCEPT: usize = 31;
+const SYSCALL_MKDIR: usize = 34;
+const SYSCALL_UNLINK: usize = 35;
+const SYSCALL_SYMLINK: usize = 36;
+const SYSCALL_LINK: usize = 37;
+const SYSCALL_RENAME: usize = 38;
+const SYSCALL_TRUNCATE: usize = 45;
+const SYSCALL_FTRUNCATE: usize = 46;
+const SYSCALL_CHDIR: usize = 49;
 const SYSCALL_OPEN: usize = 56;
 const SYSCALL_CLOSE: usize = 57;
 const SYSCALL_PIPE: usize = 59;
+const SYSCALL_LSEEK: usize = 62;
 const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
+const SYSCALL_PREAD: usize = 67;
+const SYSCALL_PWRITE: usize = 68;
+const SYSCALL_SELECT: usize = 72;
+const SYSCALL_POLL: usize = 73;
+const SYSCALL_READLINK: usize = 78;
+const SYSCALL_STAT: usize = 79;
+const SYSCALL_FSTAT: usize = 80;
 const SYSCALL_EXIT: usize = 93;
 const SYSCALL_SLEEP: usize = 101;
 const SYSCALL_YIELD: usize = 124;
 const SYSCALL_KILL: usize = 129;
+const SYSCALL_SIGACTION: usize = 134;
+const SYSCALL_SIGPROCMASK: usize = 135;
+const SYSCALL_SIGRETURN: usize = 139;
+const SYSCALL_SET_PRIORITY: usize = 140;
 const SYSCALL_GET_TIME: usize = 169;
 const SYSCALL_GETPID: usize = 172;
+const SYSCALL_SOCKET: usize = 198;
+const SYSCALL_SOCKETPAIR: usize = 199;
+const SYSCALL_BIND: usize = 200;
+const SYSCALL_LISTEN: usize = 201;
+const SYSCALL_ACCEPT: usize = 202;
+const SYSCALL_CONNECT: usize = 203;
+const SYSCALL_GETSOCKNAME: usize = 204;
+const SYSCALL_GETPEERNAME: usize = 205;
+const SYSCALL_SENDTO: usize = 206;
+const SYSCALL_RECVFROM: usize = 207;
+const SYSCALL_SHUTDOWN: usize = 210;
+const SYSCALL_SENDMSG: usize = 211;
+const SYSCALL_RECVMSG: usize = 212;
+const SYSCALL_BRK: usize = 214;
+const SYSCALL_MUNMAP: usize = 215;
 const SYSCALL_FORK: usize = 220;
 const SYSCALL_EXEC: usize = 221;
+const SYSCALL_MMAP: usize = 222;
+const SYSCALL_MPROTECT: usize = 226;
 const SYSCALL_WAITPID: usize = 260;
 const SYSCALL_THREAD_CREATE: usize = 1000;
 const SYSCALL_GETTID: usize = 1001;
 const SYSCALL_WAITTID: usize = 1002;
+const SYSCALL_NICE: usize = 1040;
 const SYSCALL_MUTEX_CREATE: usize = 1010;
 const SYSCALL_MUTEX_LOCK: usize = 1011;
 const SYSCALL_MUTEX_UNLOCK: usize = 1012;
@@ -32,6 +73,9 @@ const SYSCALL_FRAMEBUFFER: usize = 2000;
 const SYSCALL_FRAMEBUFFER_FLUSH: usize = 2001;
 const SYSCALL_EVENT_GET: usize = 3000;
 const SYSCALL_KEY_PRESSED: usize = 3001;
+const SYSCALL_EVENT_OPEN: usize = 3002;
+const SYSCALL_NET_CONFIG_GET: usize = 4000;
+const SYSCALL_NET_CONFIG_SET: usize = 4001;
 
 fn syscall(id: usize, args: [usize; 3]) -> isize {
     let mut ret: isize;
@@ -47,24 +91,219 @@ fn syscall(id: usize, args: [usize; 3]) -> isize {
     ret
 }
 
+fn syscall6(id: usize, args: [usize; 6]) -> isize {
+    let mut ret: isize;
+    unsafe {
+        core::arch::asm!(
+            "ecall",
+            inlateout("x10") args[0] => ret,
+            in("x11") args[1],
+            in("x12") args[2],
+            in("x13") args[3],
+            in("x14") args[4],
+            in("x15") args[5],
+            in("x17") id
+        );
+    }
+    ret
+}
+
+pub fn sys_getcwd(buf: &mut [u8]) -> isize {
+    syscall(SYSCALL_GETCWD, [buf.as_mut_ptr() as usize, buf.len(), 0])
+}
+
 pub fn sys_dup(fd: usize) -> isize {
     syscall(SYSCALL_DUP, [fd, 0, 0])
 }
 
-pub fn sys_connect(dest: u32, sport: u16, dport: u16) -> isize {
+pub fn sys_poll(fds: &mut [PollFd], timeout_ms: isize) -> isize {
     syscall(
-        SYSCALL_CONNECT,
-        [dest as usize, sport as usize, dport as usize],
+        SYSCALL_POLL,
+        [fds.as_mut_ptr() as usize, fds.len(), timeout_ms as usize],
+    )
+}
+
+pub fn sys_select(
+    nfds: usize,
+    readfds: *mut u64,
+    writefds: *mut u64,
+    exceptfds: *mut u64,
+    timeout_ms: isize,
+) -> isize {
+    syscall6(
+        SYSCALL_SELECT,
+        [
+            nfds,
+            readfds as usize,
+            writefds as usize,
+            exceptfds as usize,
+            timeout_ms as usize,
+            0,
+        ],
     )
 }
 
-// just listen for tcp connections now
-pub fn sys_listen(sport: u16) -> isize {
-    syscall(SYSCALL_LISTEN, [sport as usize, 0, 0])
+pub fn sys_epoll_create(flags: usize) -> isize {
+    syscall(SYSCALL_EPOLL_CREATE, [flags, 0, 0])
 }
 
-pub fn sys_accept(socket_fd: usize) -> isize {
-    syscall(SYSCALL_ACCEPT, [socket_fd, 0, 0])
+pub fn sys_epoll_ctl(epfd: usize, op: usize, fd: usize, event: *const EpollEvent) -> isize {
+    syscall6(SYSCALL_EPOLL_CTL, [epfd, op, fd, event as usize, 0, 0])
+}
+
+pub fn sys_epoll_wait(epfd: usize, events: &mut [EpollEvent], timeout_ms: isize) -> isize {
+    syscall6(
+        SYSCALL_EPOLL_WAIT,
+        [
+            epfd,
+            events.as_mut_ptr() as usize,
+            events.len(),
+            timeout_ms as usize,
+            0,
+            0,
+        ],
+    )
+}
+
+pub fn sys_socket(domain: usize, socket_type: usize, protocol: usize) -> isize {
+    syscall(SYSCALL_SOCKET, [domain, socket_type, protocol])
+}
+
+pub fn sys_socketpair(
+    domain: usize,
+    socket_type: usize,
+    protocol: usize,
+    sv: &mut [i32; 2],
+) -> isize {
+    syscall6(
+        SYSCALL_SOCKETPAIR,
+        [
+            domain,
+            socket_type,
+            protocol,
+            sv.as_mut_ptr() as usize,
+            0,
+            0,
+        ],
+    )
+}
+
+pub fn sys_bind(fd: usize, addr: *const u8, addrlen: usize) -> isize {
+    syscall(SYSCALL_BIND, [fd, addr as usize, addrlen])
+}
+
+pub fn sys_listen(fd: usize, backlog: usize) -> isize {
+    syscall(SYSCALL_LISTEN, [fd, backlog, 0])
+}
+
+pub fn sys_accept(fd: usize, addr: *mut u8, addrlen: *mut u32) -> isize {
+    syscall(SYSCALL_ACCEPT, [fd, addr as usize, addrlen as usize])
+}
+
+pub fn sys_connect(fd: usize, addr: *const u8, addrlen: usize) -> isize {
+    syscall(SYSCALL_CONNECT, [fd, addr as usize, addrlen])
+}
+
+pub fn sys_getsockname(fd: usize, addr: *mut u8, addrlen: *mut u32) -> isize {
+    syscall(SYSCALL_GETSOCKNAME, [fd, addr as usize, addrlen as usize])
+}
+
+pub fn sys_getpeername(fd: usize, addr: *mut u8, addrlen: *mut u32) -> isize {
+    syscall(SYSCALL_GETPEERNAME, [fd, addr as usize, addrlen as usize])
+}
+
+pub fn sys_sendto(fd: usize, buf: &[u8], flags: usize, addr: *const u8, addrlen: usize) -> isize {
+    syscall6(
+        SYSCALL_SENDTO,
+        [
+            fd,
+            buf.as_ptr() as usize,
+            buf.len(),
+            flags,
+            addr as usize,
+            addrlen,
+        ],
+    )
+}
+
+pub fn sys_recvfrom(
+    fd: usize,
+    buf: &mut [u8],
+    flags: usize,
+    addr: *mut u8,
+    addrlen: *mut u32,
+) -> isize {
+    syscall6(
+        SYSCALL_RECVFROM,
+        [
+            fd,
+            buf.as_mut_ptr() as usize,
+            buf.len(),
+            flags,
+            addr as usize,
+            addrlen as usize,
+        ],
+    )
+}
+
+pub fn sys_sendmsg(fd: usize, msg: *const MsgHdr, flags: usize) -> isize {
+    syscall(SYSCALL_SENDMSG, [fd, msg as usize, flags])
+}
+
+pub fn sys_recvmsg(fd: usize, msg: *mut MsgHdr, flags: usize) -> isize {
+    syscall(SYSCALL_RECVMSG, [fd, msg as usize, flags])
+}
+
+pub fn sys_shutdown(fd: usize, how: usize) -> isize {
+    syscall(SYSCALL_SHUTDOWN, [fd, how, 0])
+}
+
+pub fn sys_mkdir(path: &str) -> isize {
+    syscall(SYSCALL_MKDIR, [path.as_ptr() as usize, 0, 0])
+}
+
+pub fn sys_unlink(path: &str, flags: u32) -> isize {
+    syscall(SYSCALL_UNLINK, [path.as_ptr() as usize, flags as usize, 0])
+}
+
+pub fn sys_symlink(target: &str, path: &str) -> isize {
+    syscall(
+        SYSCALL_SYMLINK,
+        [target.as_ptr() as usize, path.as_ptr() as usize, 0],
+    )
+}
+
+pub fn sys_link(old_path: &str, new_path: &str) -> isize {
+    syscall(
+        SYSCALL_LINK,
+        [old_path.as_ptr() as usize, new_path.as_ptr() as usize, 0],
+    )
+}
+
+pub fn sys_readlink(path: &str, buf: &mut [u8]) -> isize {
+    syscall(
+        SYSCALL_READLINK,
+        [path.as_ptr() as usize, buf.as_mut_ptr() as usize, buf.len()],
+    )
+}
+
+pub fn sys_rename(old_path: &str, new_path: &str) -> isize {
+    syscall(
+        SYSCALL_RENAME,
+        [old_path.as_ptr() as usize, new_path.as_ptr() as usize, 0],
+    )
+}
+
+pub fn sys_truncate(path: &str, len: isize) -> isize {
+    syscall(SYSCALL_TRUNCATE, [path.as_ptr() as usize, len as usize, 0])
+}
+
+pub fn sys_ftruncate(fd: usize, len: isize) -> isize {
+    syscall(SYSCALL_FTRUNCATE, [fd, len as usize, 0])
+}
+
+pub fn sys_chdir(path: &str) -> isize {
+    syscall(SYSCALL_CHDIR, [path.as_ptr() as usize, 0, 0])
 }
 
 pub fn sys_open(path: &str, flags: u32) -> isize {
@@ -90,6 +329,39 @@ pub fn sys_write(fd: usize, buffer: &[u8]) -> isize {
     syscall(SYSCALL_WRITE, [fd, buffer.as_ptr() as usize, buffer.len()])
 }
 
+pub fn sys_lseek(fd: usize, offset: isize, whence: usize) -> isize {
+    syscall(SYSCALL_LSEEK, [fd, offset as usize, whence])
+}
+
+pub fn sys_pread(fd: usize, buffer: &mut [u8], offset: usize) -> isize {
+    syscall6(
+        SYSCALL_PREAD,
+        [fd, buffer.as_mut_ptr() as usize, buffer.len(), offset, 0, 0],
+    )
+}
+
+pub fn sys_pwrite(fd: usize, buffer: &[u8], offset: usize) -> isize {
+    syscall6(
+        SYSCALL_PWRITE,
+        [fd, buffer.as_ptr() as usize, buffer.len(), offset, 0, 0],
+    )
+}
+
+pub fn sys_stat(path: &str, st: &mut Stat, flags: u32) -> isize {
+    syscall(
+        SYSCALL_STAT,
+        [
+            path.as_ptr() as usize,
+            st as *mut Stat as usize,
+            flags as usize,
+        ],
+    )
+}
+
+pub fn sys_fstat(fd: usize, st: &mut Stat) -> isize {
+    syscall(SYSCALL_FSTAT, [fd, st as *mut Stat as usize, 0])
+}
+
 pub fn sys_exit(exit_code: i32) -> ! {
     syscall(SYSCALL_EXIT, [exit_code as usize, 0, 0]);
     panic!("sys_exit never returns!");
@@ -103,8 +375,35 @@ pub fn sys_yield() -> isize {
     syscall(SYSCALL_YIELD, [0, 0, 0])
 }
 
-pub fn sys_kill(pid: usize, signal: i32) -> isize {
-    syscall(SYSCALL_KILL, [pid, signal as usize, 0])
+pub fn sys_kill(pid: usize, signum: i32) -> isize {
+    syscall(SYSCALL_KILL, [pid, signum as usize, 0])
+}
+
+pub fn sys_sigaction(
+    signum: i32,
+    action: *const SignalAction,
+    old_action: *mut SignalAction,
+) -> isize {
+    syscall(
+        SYSCALL_SIGACTION,
+        [signum as usize, action as usize, old_action as usize],
+    )
+}
+
+pub fn sys_sigprocmask(how: usize, set: *const u32, old_set: *mut u32) -> isize {
+    syscall(SYSCALL_SIGPROCMASK, [how, set as usize, old_set as usize])
+}
+
+pub fn sys_sigreturn() -> isize {
+    syscall(SYSCALL_SIGRETURN, [0, 0, 0])
+}
+
+pub fn sys_set_priority(prio: isize) -> isize {
+    syscall(SYSCALL_SET_PRIORITY, [prio as usize, 0, 0])
+}
+
+pub fn sys_nice(inc: isize) -> isize {
+    syscall(SYSCALL_NICE, [inc as usize, 0, 0])
 }
 
 pub fn sys_get_time() -> isize {
@@ -126,8 +425,31 @@ pub fn sys_exec(path: &str, args: &[*const u8]) -> isize {
     )
 }
 
-pub fn sys_waitpid(pid: isize, exit_code: *mut i32) -> isize {
-    syscall(SYSCALL_WAITPID, [pid as usize, exit_code as usize, 0])
+pub fn sys_mmap(
+    addr: usize,
+    len: usize,
+    prot: usize,
+    flags: usize,
+    fd: usize,
+    offset: usize,
+) -> isize {
+    syscall6(SYSCALL_MMAP, [addr, len, prot, flags, fd, offset])
+}
+
+pub fn sys_brk(addr: usize) -> isize {
+    syscall(SYSCALL_BRK, [addr, 0, 0])
+}
+
+pub fn sys_munmap(addr: usize, len: usize) -> isize {
+    syscall(SYSCALL_MUNMAP, [addr, len, 0])
+}
+
+pub fn sys_mprotect(addr: usize, len: usize, prot: usize) -> isize {
+    syscall(SYSCALL_MPROTECT, [addr, len, prot])
+}
+
+pub fn sys_waitpid(pid: isize, status: *mut i32, options: usize) -> isize {
+    syscall(SYSCALL_WAITPID, [pid as usize, status as usize, options])
 }
 
 pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
@@ -193,3 +515,18 @@ pub fn sys_event_get() -> isize {
 pub fn sys_key_pressed() -> isize {
     syscall(SYSCALL_KEY_PRESSED, [0, 0, 0])
 }
+
+pub fn sys_event_open() -> isize {
+    syscall(SYSCALL_EVENT_OPEN, [0, 0, 0])
+}
+
+pub fn sys_net_config_get(config: *mut u8) -> isize {
+    syscall(SYSCALL_NET_CONFIG_GET, [config as usize, 0, 0])
+}
+
+pub fn sys_net_config_set(ip: u32, netmask: u32, gateway: u32) -> isize {
+    syscall(
+        SYSCALL_NET_CONFIG_SET,
+        [ip as usize, netmask as usize, gateway as usize],
+    )
+}
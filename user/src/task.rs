@@ -6,6 +6,14 @@ pub fn exit(exit_code: i32) -> ! {
 pub fn yield_() -> isize {
     sys_yield()
 }
+/// Set the priority of the current thread, which is at least 2.
+pub fn set_priority(prio: isize) -> isize {
+    sys_set_priority(prio)
+}
+/// Change the nice value of the current thread within [-20, 19].
+pub fn nice(inc: isize) -> isize {
+    sys_nice(inc)
+}
 pub fn get_time() -> isize {
     sys_get_time()
 }
@@ -19,46 +27,233 @@ pub fn exec(path: &str, args: &[*const u8]) -> isize {
     sys_exec(path, args)
 }
 
-pub fn wait(exit_code: &mut i32) -> isize {
-    loop {
-        match sys_waitpid(-1, exit_code as *mut _) {
-            -2 => {
-                yield_();
-            }
-            // -1 or a real pid
-            exit_pid => return exit_pid,
-        }
+/// `options` of `waitpid_options`: return 0 at once if no child has changed.
+pub const WNOHANG: usize = 1;
+/// `options` of `waitpid_options`: report children which are stopped.
+pub const WUNTRACED: usize = 2;
+/// `options` of `waitpid_options`: report stopped children which are continued.
+pub const WCONTINUED: usize = 8;
+
+pub fn wifexited(status: i32) -> bool {
+    status & 0x7f == 0
+}
+pub fn wexitstatus(status: i32) -> i32 {
+    (status >> 8) & 0xff
+}
+pub fn wifsignaled(status: i32) -> bool {
+    status & 0x7f != 0 && status & 0x7f != 0x7f
+}
+pub fn wtermsig(status: i32) -> i32 {
+    status & 0x7f
+}
+pub fn wifstopped(status: i32) -> bool {
+    status & 0xff == 0x7f
+}
+pub fn wstopsig(status: i32) -> i32 {
+    wexitstatus(status)
+}
+pub fn wifcontinued(status: i32) -> bool {
+    status == 0xffff
+}
+
+/// Exit code of a child from its status word, which is negative
+/// if the child is terminated by a signal.
+fn exit_code_of(status: i32) -> i32 {
+    if wifsignaled(status) {
+        -wtermsig(status)
+    } else {
+        wexitstatus(status) as i8 as i32
     }
 }
 
+/// Wait for a child to change its state and get its status word,
+/// return -1 if there is no such child or it is interrupted by a signal.
+pub fn waitpid_options(pid: isize, status: &mut i32, options: usize) -> isize {
+    sys_waitpid(pid, status as *mut _, options)
+}
+
+pub fn wait(exit_code: &mut i32) -> isize {
+    waitpid_exit_code(-1, exit_code, 0)
+}
+
 pub fn waitpid(pid: usize, exit_code: &mut i32) -> isize {
-    loop {
-        match sys_waitpid(pid as isize, exit_code as *mut _) {
-            -2 => {
-                yield_();
-            }
-            // -1 or a real pid
-            exit_pid => return exit_pid,
-        }
-    }
+    waitpid_exit_code(pid as isize, exit_code, 0)
 }
 
+/// Return 0 if the child is still running.
 pub fn waitpid_nb(pid: usize, exit_code: &mut i32) -> isize {
-    sys_waitpid(pid as isize, exit_code as *mut _)
+    waitpid_exit_code(pid as isize, exit_code, WNOHANG)
+}
+
+fn waitpid_exit_code(pid: isize, exit_code: &mut i32, options: usize) -> isize {
+    let mut status: i32 = 0;
+    let exit_pid = waitpid_options(pid, &mut status, options);
+    if exit_pid > 0 {
+        *exit_code = exit_code_of(status);
+    }
+    exit_pid
 }
 
+pub const SIGHUP: i32 = 1;
+pub const SIGINT: i32 = 2;
+pub const SIGQUIT: i32 = 3;
+pub const SIGILL: i32 = 4;
+pub const SIGTRAP: i32 = 5;
+pub const SIGABRT: i32 = 6;
+pub const SIGBUS: i32 = 7;
+pub const SIGFPE: i32 = 8;
+pub const SIGKILL: i32 = 9;
+pub const SIGUSR1: i32 = 10;
+pub const SIGSEGV: i32 = 11;
+pub const SIGUSR2: i32 = 12;
+pub const SIGPIPE: i32 = 13;
+pub const SIGALRM: i32 = 14;
+pub const SIGTERM: i32 = 15;
+pub const SIGSTKFLT: i32 = 16;
+pub const SIGCHLD: i32 = 17;
+pub const SIGCONT: i32 = 18;
+pub const SIGSTOP: i32 = 19;
+pub const SIGTSTP: i32 = 20;
+pub const SIGTTIN: i32 = 21;
+pub const SIGTTOU: i32 = 22;
+pub const SIGURG: i32 = 23;
+pub const SIGXCPU: i32 = 24;
+pub const SIGXFSZ: i32 = 25;
+pub const SIGVTALRM: i32 = 26;
+pub const SIGPROF: i32 = 27;
+pub const SIGWINCH: i32 = 28;
+pub const SIGIO: i32 = 29;
+pub const SIGPWR: i32 = 30;
+pub const SIGSYS: i32 = 31;
+
 bitflags! {
-    pub struct SignalFlags: i32 {
+    /// A set of signals, signal `n` is represented by bit `n`.
+    pub struct SignalFlags: u32 {
+        const SIGHUP    = 1 << 1;
         const SIGINT    = 1 << 2;
+        const SIGQUIT   = 1 << 3;
         const SIGILL    = 1 << 4;
+        const SIGTRAP   = 1 << 5;
         const SIGABRT   = 1 << 6;
+        const SIGBUS    = 1 << 7;
         const SIGFPE    = 1 << 8;
+        const SIGKILL   = 1 << 9;
+        const SIGUSR1   = 1 << 10;
         const SIGSEGV   = 1 << 11;
+        const SIGUSR2   = 1 << 12;
+        const SIGPIPE   = 1 << 13;
+        const SIGALRM   = 1 << 14;
+        const SIGTERM   = 1 << 15;
+        const SIGSTKFLT = 1 << 16;
+        const SIGCHLD   = 1 << 17;
+        const SIGCONT   = 1 << 18;
+        const SIGSTOP   = 1 << 19;
+        const SIGTSTP   = 1 << 20;
+        const SIGTTIN   = 1 << 21;
+        const SIGTTOU   = 1 << 22;
+        const SIGURG    = 1 << 23;
+        const SIGXCPU   = 1 << 24;
+        const SIGXFSZ   = 1 << 25;
+        const SIGVTALRM = 1 << 26;
+        const SIGPROF   = 1 << 27;
+        const SIGWINCH  = 1 << 28;
+        const SIGIO     = 1 << 29;
+        const SIGPWR    = 1 << 30;
+        const SIGSYS    = 1 << 31;
+    }
+}
+
+/// Take the default action on the signal.
+pub const SIG_DFL: usize = 0;
+/// Ignore the signal.
+pub const SIG_IGN: usize = 1;
+
+/// `how` of `sigprocmask`
+pub const SIG_BLOCK: usize = 0;
+pub const SIG_UNBLOCK: usize = 1;
+pub const SIG_SETMASK: usize = 2;
+
+/// How to handle a signal. A handler is an `extern "C" fn(signum: i32)`,
+/// and the signals in `mask` are blocked while it is running.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct SignalAction {
+    pub handler: usize,
+    pub mask: SignalFlags,
+    restorer: usize,
+}
+
+impl SignalAction {
+    pub fn new(handler: usize, mask: SignalFlags) -> Self {
+        Self {
+            handler,
+            mask,
+            restorer: __sigreturn_trampoline as usize,
+        }
+    }
+}
+
+impl Default for SignalAction {
+    fn default() -> Self {
+        Self::new(SIG_DFL, SignalFlags::empty())
+    }
+}
+
+// signal handlers return here, where the stack pointer points to the
+// frame saved by the kernel
+core::arch::global_asm!(
+    ".globl __sigreturn_trampoline",
+    "__sigreturn_trampoline:",
+    "li a7, 139",
+    "ecall",
+);
+
+unsafe extern "C" {
+    fn __sigreturn_trampoline();
+}
+
+pub fn kill(pid: usize, signum: i32) -> isize {
+    sys_kill(pid, signum)
+}
+
+pub fn sigaction(
+    signum: i32,
+    action: Option<&SignalAction>,
+    old_action: Option<&mut SignalAction>,
+) -> isize {
+    sys_sigaction(
+        signum,
+        action.map_or(core::ptr::null(), |action| action as *const _),
+        old_action.map_or(core::ptr::null_mut(), |old_action| old_action as *mut _),
+    )
+}
+
+pub fn sigprocmask(
+    how: usize,
+    set: Option<SignalFlags>,
+    old_set: Option<&mut SignalFlags>,
+) -> isize {
+    let set = set.map(|set| set.bits());
+    let mut old_bits = 0u32;
+    let ret = sys_sigprocmask(
+        how,
+        set.as_ref()
+            .map_or(core::ptr::null(), |set| set as *const _),
+        if old_set.is_some() {
+            &mut old_bits as *mut _
+        } else {
+            core::ptr::null_mut()
+        },
+    );
+    if let Some(old_set) = old_set {
+        *old_set = SignalFlags::from_bits_truncate(old_bits);
     }
+    ret
 }
 
-pub fn kill(pid: usize, signal: i32) -> isize {
-    sys_kill(pid, signal)
+/// Only called by the trampoline a signal handler returns to.
+pub fn sigreturn() -> isize {
+    sys_sigreturn()
 }
 
 pub fn sleep(sleep_ms: usize) {